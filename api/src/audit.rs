@@ -0,0 +1,184 @@
+use crate::error::AppError;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::error;
+
+/// Fields whose values never appear in the audit log. Changes to them are
+/// recorded, but with both values replaced by `REDACTED`.
+const REDACTED_FIELDS: &[&str] = &[
+    "password",
+    "password_hash",
+    "ssh_key",
+    "token",
+    "token_hash",
+    "secret",
+    "totp",
+];
+
+/// Placeholder for the value of a redacted field.
+pub const REDACTED: &str = "[redacted]";
+
+/// Value of a field before and after a change.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FieldChange {
+    pub before: serde_json::Value,
+    pub after: serde_json::Value,
+}
+
+/// An action recorded in the audit log.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AuditEntry {
+    pub id: String,
+    pub time: DateTime<Utc>,
+    /// User who performed the action
+    pub actor: String,
+    /// What was done, e.g. `repository.delete`
+    pub action: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// ID of the repository, credential, user, ... the action applies to
+    pub target: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Address of the client that sent the request
+    pub source_ip: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    /// Changed fields of the target, with secrets redacted
+    pub changes: BTreeMap<String, FieldChange>,
+}
+
+impl AuditEntry {
+    /// Creates an entry for an action performed now, without changes.
+    pub fn new(actor: &str, action: &str, target: Option<&str>, source_ip: Option<String>) -> Self {
+        AuditEntry {
+            id: uuid::Uuid::new_v4().to_string(),
+            time: Utc::now(),
+            actor: actor.to_string(),
+            action: action.to_string(),
+            target: target.map(str::to_string),
+            source_ip,
+            changes: BTreeMap::new(),
+        }
+    }
+
+    /// Records the fields that differ between the target before and after the action.
+    ///
+    /// `None` stands for a target that didn't exist before or doesn't exist after.
+    pub fn with_changes<T: Serialize>(mut self, before: Option<&T>, after: Option<&T>) -> Self {
+        self.changes = diff(before, after);
+        self
+    }
+}
+
+/// Returns the top-level fields that differ between two serialized values, with
+/// the values of `REDACTED_FIELDS` replaced.
+pub fn diff<T: Serialize>(before: Option<&T>, after: Option<&T>) -> BTreeMap<String, FieldChange> {
+    let fields = |value: Option<&T>| match value.map(serde_json::to_value) {
+        Some(Ok(serde_json::Value::Object(map))) => map,
+        _ => serde_json::Map::new(),
+    };
+    let before = fields(before);
+    let after = fields(after);
+
+    let mut changes = BTreeMap::new();
+    for key in before.keys().chain(after.keys()) {
+        let old = before.get(key).cloned().unwrap_or_default();
+        let new = after.get(key).cloned().unwrap_or_default();
+        if old == new || changes.contains_key(key) {
+            continue;
+        }
+        let redact = |value: serde_json::Value| {
+            if REDACTED_FIELDS.contains(&key.as_str()) && !value.is_null() {
+                serde_json::Value::from(REDACTED)
+            } else {
+                value
+            }
+        };
+        changes.insert(
+            key.clone(),
+            FieldChange {
+                before: redact(old),
+                after: redact(new),
+            },
+        );
+    }
+    changes
+}
+
+/// Append-only log of the changes made through the API.
+///
+/// Entries are appended as JSON lines to `audit.jsonl` in the data directory
+/// and kept in memory for `GET /api/audit`. Nothing is ever removed from the
+/// file; a log without file only lives in memory.
+#[derive(Clone)]
+pub struct AuditLog {
+    path: Option<PathBuf>,
+    entries: Arc<Mutex<Vec<AuditEntry>>>,
+}
+
+impl Default for AuditLog {
+    fn default() -> Self {
+        Self::in_memory()
+    }
+}
+
+impl AuditLog {
+    /// Creates an empty log that isn't persisted.
+    pub fn in_memory() -> Self {
+        AuditLog {
+            path: None,
+            entries: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Loads the log of a data directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::IoError` if the data directory or the file cannot be
+    /// read. Malformed lines are skipped.
+    pub fn load<P: AsRef<Path>>(data_dir: P) -> Result<Self, AppError> {
+        fs::create_dir_all(data_dir.as_ref())?;
+        let path = data_dir.as_ref().join("audit.jsonl");
+
+        let mut entries = Vec::new();
+        if path.exists() {
+            for line in BufReader::new(File::open(&path)?).lines() {
+                if let Ok(entry) = serde_json::from_str::<AuditEntry>(&line?) {
+                    entries.push(entry);
+                }
+            }
+        }
+
+        Ok(AuditLog {
+            path: Some(path),
+            entries: Arc::new(Mutex::new(entries)),
+        })
+    }
+
+    /// Appends an entry; failures to write the file are logged.
+    pub fn record(&self, entry: AuditEntry) {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(path) = &self.path {
+            let result = serde_json::to_string(&entry)
+                .map_err(std::io::Error::from)
+                .and_then(|line| {
+                    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+                    writeln!(file, "{}", line)
+                });
+            if let Err(e) = result {
+                error!(path = %path.display(), error = %e, "Failed to write audit log");
+            }
+        }
+        entries.push(entry);
+    }
+
+    /// Lists the recorded entries, newest first.
+    pub fn entries(&self) -> Vec<AuditEntry> {
+        let entries = self.entries.lock().unwrap();
+        entries.iter().rev().cloned().collect()
+    }
+}
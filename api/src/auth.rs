@@ -1,33 +1,231 @@
-use crate::config::User;
+use crate::config::{ApiToken, PreviousJwtSecret, Role, SessionConfig, TokenScope, User};
 use crate::error::AppError;
+use crate::sessions::{Session, SessionStore};
+use actix_web::http::Method;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use chrono::{DateTime, Utc};
+use jsonwebtoken::errors::ErrorKind;
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use subtle::ConstantTimeEq;
+
+/// Username of the user created with the default configuration.
+pub const DEFAULT_ADMIN_USERNAME: &str = "admin";
+
+/// Password of the user created with the default configuration.
+pub const DEFAULT_ADMIN_PASSWORD: &str = "admin";
+
+/// Minimum length of passwords set through the API.
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// Path of the endpoint changing the password of the authenticated user.
+pub const CHANGE_PASSWORD_PATH: &str = "/api/account/password";
+
+/// Prefix of the endpoints managing the account of the authenticated user.
+pub const ACCOUNT_PATH_PREFIX: &str = "/api/account/";
+
+/// Path of the endpoint ending sessions of the authenticated user.
+pub const LOGOUT_PATH: &str = "/api/logout";
+
+/// Prefix of personal API tokens, distinguishing them from JWTs.
+pub const API_TOKEN_PREFIX: &str = "gs_";
+
+/// Generates a new personal API token: the prefix followed by 32 random bytes in hex.
+pub fn generate_api_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    format!("{}{}", API_TOKEN_PREFIX, hex::encode(bytes))
+}
+
+/// Generates a new refresh token: 32 random bytes in hex.
+fn generate_refresh_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Hashes a personal API token or refresh token for storage.
+///
+/// Tokens are long random strings, so a fast hash is sufficient (unlike passwords).
+pub fn hash_api_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Returns the scope an API token needs for a request.
+///
+/// `GET` and `HEAD` requests only read, `POST /api/sync` triggers a sync and
+/// everything else changes the configuration.
+pub fn required_scope(method: &Method, path: &str) -> TokenScope {
+    if method == Method::GET || method == Method::HEAD {
+        TokenScope::Read
+    } else if method == Method::POST && path == "/api/sync" {
+        TokenScope::Sync
+    } else {
+        TokenScope::Write
+    }
+}
+
+/// Returns whether a request creates or revokes API tokens.
+///
+/// Only sessions may do that, so a leaked API token can't create another one
+/// that outlives revoking it.
+pub fn is_token_management(method: &Method, path: &str) -> bool {
+    method != Method::GET
+        && method != Method::HEAD
+        && (path == "/api/tokens" || path.starts_with("/api/tokens/"))
+}
+
+/// Checks a new password against the password rules.
+///
+/// # Errors
+///
+/// Returns `AppError::BadRequest` if the password is shorter than
+/// `MIN_PASSWORD_LENGTH` characters.
+pub fn validate_password(password: &str) -> Result<(), AppError> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(AppError::BadRequest(format!(
+            "Password must be at least {} characters long",
+            MIN_PASSWORD_LENGTH
+        )));
+    }
+    Ok(())
+}
+
+/// Returns the role of a user of an external identity provider: the highest
+/// role of their groups in `group_roles`, or the `default_role`. `None` means
+/// the user isn't allowed in.
+pub fn role_for_groups(
+    group_roles: &HashMap<String, Role>,
+    default_role: Option<Role>,
+    groups: &[String],
+) -> Option<Role> {
+    groups
+        .iter()
+        .filter_map(|group| group_roles.get(group))
+        .max()
+        .copied()
+        .or(default_role)
+}
+
+/// Returns the minimum role a user needs for a request.
+///
+/// Viewers may read everything except credentials, operators may additionally
+/// read credentials, sync and add or update repositories, and everything else
+/// (deleting repositories, managing credentials, webhooks and users, reading
+/// webhook deliveries and the audit log) needs an admin. Every user manages their own API tokens,
+/// password, two-factor authentication and sessions.
+pub fn required_role(method: &Method, path: &str) -> Role {
+    let is_read = method == Method::GET || method == Method::HEAD;
+
+    if path == "/api/tokens"
+        || path.starts_with("/api/tokens/")
+        || path.starts_with(ACCOUNT_PATH_PREFIX)
+        || path == LOGOUT_PATH
+    {
+        Role::Viewer
+    } else if path == "/api/credentials" || path.starts_with("/api/credentials/") {
+        if is_read {
+            Role::Operator
+        } else {
+            Role::Admin
+        }
+    } else if path == "/api/users"
+        || path.starts_with("/api/users/")
+        || path == "/api/audit"
+        // Receiver URLs of deliveries contain the tokens of chat webhooks
+        || path.starts_with("/api/webhooks/")
+    {
+        Role::Admin
+    } else if is_read {
+        Role::Viewer
+    } else if (method == Method::POST && (path == "/api/sync" || path == "/api/repositories"))
+        || (method == Method::PATCH && path.starts_with("/api/repositories/"))
+    {
+        Role::Operator
+    } else {
+        Role::Admin
+    }
+}
 
 /// JWT claims structure containing user information and expiration.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     /// Subject (username)
     pub sub: String,
+    /// Role of the user when the token was issued
+    pub role: Role,
+    /// Whether the token only allows changing the password (see `User::must_change_password`)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub password_change_required: bool,
+    /// Session the token belongs to (see `AuthService::start_session`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
     /// Expiration timestamp
     pub exp: usize,
 }
 
+/// Tokens issued at login or refresh.
+#[derive(Debug, Serialize, Clone)]
+pub struct TokenPair {
+    /// Short-lived JWT for authenticated requests
+    pub token: String,
+    /// Opaque token for `POST /api/token/refresh`, valid for a single use
+    pub refresh_token: String,
+    /// Lifetime of the access token in seconds
+    pub expires_in: i64,
+}
+
 /// Service for handling authentication and authorization operations.
 ///
-/// Provides functionality for password hashing/verification and JWT token
-/// generation/verification.
+/// Provides functionality for password hashing/verification, JWT token
+/// generation/verification and login sessions with rotating refresh tokens.
+/// Tokens of revoked sessions are rejected by `verify_token`.
 pub struct AuthService {
     jwt_secret: String,
+    previous_jwt_secret: Option<PreviousJwtSecret>,
+    settings: SessionConfig,
+    sessions: SessionStore,
 }
 
 impl AuthService {
-    /// Creates a new AuthService instance.
+    /// Creates a new AuthService instance with default token lifetimes and
+    /// sessions kept in memory.
     ///
     /// # Arguments
     ///
     /// * `jwt_secret` - Secret key used for signing and verifying JWT tokens
     pub fn new(jwt_secret: String) -> Self {
-        AuthService { jwt_secret }
+        Self::with_sessions(
+            jwt_secret,
+            None,
+            SessionConfig::default(),
+            SessionStore::in_memory(),
+        )
+    }
+
+    /// Creates a new AuthService instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `jwt_secret` - Secret key used for signing and verifying JWT tokens
+    /// * `previous_jwt_secret` - Replaced secret, still accepted for verifying tokens
+    /// * `settings` - Lifetimes of access and refresh tokens
+    /// * `sessions` - Store of login sessions and revocations
+    pub fn with_sessions(
+        jwt_secret: String,
+        previous_jwt_secret: Option<PreviousJwtSecret>,
+        settings: SessionConfig,
+        sessions: SessionStore,
+    ) -> Self {
+        AuthService {
+            jwt_secret,
+            previous_jwt_secret,
+            settings,
+            sessions,
+        }
     }
 
     /// Verifies a password against a bcrypt hash.
@@ -60,10 +258,14 @@ impl AuthService {
             .map_err(|e| AppError::AuthError(format!("Password hashing failed: {}", e)))
     }
 
-    /// Authenticates a user and returns a JWT token.
+    /// Authenticates a user and starts a session.
     ///
     /// Verifies the username and password against the provided user list,
-    /// and if successful, generates a JWT token valid for 24 hours.
+    /// and if successful, starts a session with the user's role.
+    /// If the user must change the password, or still uses the default `admin`/`admin`
+    /// credentials, the access tokens of the session only allow changing the password.
+    /// Users with two-factor authentication are rejected; their logins are
+    /// completed with `start_password_session` after checking the one-time password.
     ///
     /// # Arguments
     ///
@@ -73,45 +275,170 @@ impl AuthService {
     ///
     /// # Returns
     ///
-    /// Returns a JWT token string on successful authentication, or an error
+    /// Returns the tokens of the session on successful authentication, or an error
     /// if credentials are invalid or authentication fails.
     pub fn authenticate(
         &self,
         username: &str,
         password: &str,
         users: &[User],
-    ) -> Result<String, AppError> {
+    ) -> Result<TokenPair, AppError> {
+        let user = self.verify_credentials(username, password, users)?;
+        if user.has_totp() {
+            return Err(AppError::AuthError("Two-factor code required".to_string()));
+        }
+        self.start_password_session(user, password)
+    }
+
+    /// Verifies the username and password against the provided user list.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::AuthError` if the user doesn't exist, has no password
+    /// (single sign-on) or the password is wrong.
+    pub fn verify_credentials<'a>(
+        &self,
+        username: &str,
+        password: &str,
+        users: &'a [User],
+    ) -> Result<&'a User, AppError> {
         let user = users
             .iter()
             .find(|u| u.username == username)
             .ok_or_else(|| AppError::AuthError("Invalid credentials".to_string()))?;
 
-        if !self.verify_password(password, &user.password_hash)? {
+        // Users added by single sign-on have no password
+        if user.password_hash.is_empty() || !self.verify_password(password, &user.password_hash)? {
             return Err(AppError::AuthError("Invalid credentials".to_string()));
         }
+        Ok(user)
+    }
+
+    /// Starts a session for a user who logged in with a verified password.
+    pub fn start_password_session(
+        &self,
+        user: &User,
+        password: &str,
+    ) -> Result<TokenPair, AppError> {
+        let password_change_required = user.must_change_password
+            || (user.username == DEFAULT_ADMIN_USERNAME && password == DEFAULT_ADMIN_PASSWORD);
+        self.open_session(&user.username, user.role, password_change_required)
+    }
+
+    /// Starts a session for a user authenticated by other means (single sign-on,
+    /// a trusted proxy, `skip_auth` or a password change).
+    pub fn start_session(&self, username: &str, role: Role) -> Result<TokenPair, AppError> {
+        self.open_session(username, role, false)
+    }
 
-        self.generate_token(username)
+    fn open_session(
+        &self,
+        username: &str,
+        role: Role,
+        password_change_required: bool,
+    ) -> Result<TokenPair, AppError> {
+        let now = Utc::now();
+        let id = uuid::Uuid::new_v4().to_string();
+        let refresh_token = generate_refresh_token();
+        let token = self.issue_token(username, role, password_change_required, Some(&id))?;
+        self.sessions.create(Session {
+            id,
+            username: username.to_string(),
+            role,
+            password_change_required,
+            refresh_token_hash: hash_api_token(&refresh_token),
+            previous_refresh_token_hash: None,
+            created_at: now,
+            expires_at: now + self.refresh_token_lifetime(),
+        });
+
+        Ok(TokenPair {
+            token,
+            refresh_token,
+            expires_in: self.access_token_lifetime().num_seconds(),
+        })
     }
 
-    /// Generates a JWT token for a user.
+    /// Exchanges a refresh token for a new access token and refresh token.
     ///
-    /// The token is valid for 24 hours from the time of generation.
+    /// The access token gets the current role of the user. Each refresh token can
+    /// only be used once; using it again revokes the session.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::AuthError` if the refresh token is unknown, expired or
+    /// was already used.
+    pub fn refresh(&self, refresh_token: &str, users: &[User]) -> Result<TokenPair, AppError> {
+        let now = Utc::now();
+        let new_refresh_token = generate_refresh_token();
+        let session = self.sessions.rotate(
+            &hash_api_token(refresh_token),
+            hash_api_token(&new_refresh_token),
+            now + self.refresh_token_lifetime(),
+            self.revocation_end(now),
+        )?;
+
+        let role = users
+            .iter()
+            .find(|u| u.username == session.username)
+            .map_or(session.role, |user| user.role);
+        let token = self.issue_token(
+            &session.username,
+            role,
+            session.password_change_required,
+            Some(&session.id),
+        )?;
+        Ok(TokenPair {
+            token,
+            refresh_token: new_refresh_token,
+            expires_in: self.access_token_lifetime().num_seconds(),
+        })
+    }
+
+    /// Revokes a session: its refresh token and access tokens are rejected from now on.
+    ///
+    /// Returns whether the session existed.
+    pub fn revoke_session(&self, session_id: &str) -> bool {
+        self.sessions
+            .revoke(session_id, self.revocation_end(Utc::now()))
+    }
+
+    /// Revokes all sessions of a user and returns their number.
+    pub fn revoke_user_sessions(&self, username: &str) -> usize {
+        self.sessions
+            .revoke_user(username, self.revocation_end(Utc::now()))
+    }
+
+    /// Generates a JWT token for a user, outside of a session.
+    ///
+    /// The token is valid for the access token lifetime and can't be revoked.
     ///
     /// # Arguments
     ///
     /// * `username` - Username to include in the token
+    /// * `role` - Role of the user
     ///
     /// # Returns
     ///
     /// Returns a JWT token string, or an error if token generation fails.
-    pub fn generate_token(&self, username: &str) -> Result<String, AppError> {
-        let expiration = chrono::Utc::now()
-            .checked_add_signed(chrono::Duration::hours(24))
-            .expect("valid timestamp")
-            .timestamp() as usize;
+    pub fn generate_token(&self, username: &str, role: Role) -> Result<String, AppError> {
+        self.issue_token(username, role, false, None)
+    }
+
+    fn issue_token(
+        &self,
+        username: &str,
+        role: Role,
+        password_change_required: bool,
+        sid: Option<&str>,
+    ) -> Result<String, AppError> {
+        let expiration = (Utc::now() + self.access_token_lifetime()).timestamp() as usize;
 
         let claims = Claims {
             sub: username.to_owned(),
+            role,
+            password_change_required,
+            sid: sid.map(str::to_string),
             exp: expiration,
         };
 
@@ -125,6 +452,9 @@ impl AuthService {
 
     /// Verifies a JWT token and extracts the claims.
     ///
+    /// Tokens signed with the previous JWT secret are accepted until the end of
+    /// its grace period. Tokens of revoked sessions are rejected.
+    ///
     /// # Arguments
     ///
     /// * `token` - JWT token string to verify
@@ -132,14 +462,81 @@ impl AuthService {
     /// # Returns
     ///
     /// Returns the token claims if valid, or an error if verification fails
-    /// (e.g., expired, invalid signature, malformed token).
+    /// (e.g., expired, invalid signature, malformed token, revoked session).
     pub fn verify_token(&self, token: &str) -> Result<Claims, AppError> {
-        decode::<Claims>(
-            token,
-            &DecodingKey::from_secret(self.jwt_secret.as_bytes()),
-            &Validation::default(),
-        )
-        .map(|data| data.claims)
-        .map_err(|e| AppError::AuthError(format!("Token verification failed: {}", e)))
+        let decode_with = |secret: &str| {
+            decode::<Claims>(
+                token,
+                &DecodingKey::from_secret(secret.as_bytes()),
+                &Validation::default(),
+            )
+            .map(|data| data.claims)
+        };
+
+        let claims = decode_with(&self.jwt_secret)
+            .or_else(|e| match &self.previous_jwt_secret {
+                Some(previous)
+                    if *e.kind() == ErrorKind::InvalidSignature
+                        && Utc::now() < previous.valid_until =>
+                {
+                    decode_with(&previous.secret)
+                }
+                _ => Err(e),
+            })
+            .map_err(|e| AppError::AuthError(format!("Token verification failed: {}", e)))?;
+
+        if claims
+            .sid
+            .as_deref()
+            .is_some_and(|sid| self.sessions.is_revoked(sid))
+        {
+            return Err(AppError::AuthError("Token has been revoked".to_string()));
+        }
+        Ok(claims)
+    }
+
+    fn access_token_lifetime(&self) -> chrono::Duration {
+        chrono::Duration::minutes(self.settings.access_token_minutes.into())
+    }
+
+    fn refresh_token_lifetime(&self) -> chrono::Duration {
+        chrono::Duration::days(self.settings.refresh_token_days.into())
+    }
+
+    /// Time until which a session revoked at `now` must stay on the revocation
+    /// list: the expiry of its last access token, plus the validation leeway.
+    fn revocation_end(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        now + self.access_token_lifetime()
+            + chrono::Duration::seconds(Validation::default().leeway as i64)
+    }
+
+    /// Finds the stored API token matching a presented token.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - Personal API token from the Authorization header
+    /// * `tokens` - Stored API tokens
+    /// * `now` - Current time, for checking the expiry
+    ///
+    /// # Returns
+    ///
+    /// Returns the matching token, or an error if no token matches or the token
+    /// expired.
+    pub fn verify_api_token<'a>(
+        &self,
+        token: &str,
+        tokens: &'a [ApiToken],
+        now: DateTime<Utc>,
+    ) -> Result<&'a ApiToken, AppError> {
+        let hash = hash_api_token(token);
+        let api_token = tokens
+            .iter()
+            .find(|t| bool::from(t.token_hash.as_bytes().ct_eq(hash.as_bytes())))
+            .ok_or_else(|| AppError::AuthError("Invalid API token".to_string()))?;
+
+        if api_token.is_expired(now) {
+            return Err(AppError::AuthError("API token expired".to_string()));
+        }
+        Ok(api_token)
     }
 }
@@ -1,7 +1,10 @@
+use crate::encryption::EncryptionKeys;
+use crate::forge::ForgeProvider;
 use chrono::{DateTime, Utc};
 use config::{Config as ConfigBuilder, ConfigError, Environment, File, FileFormat};
+use ipnet::IpNet;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::path::Path;
 
@@ -23,6 +26,12 @@ pub struct Config {
     pub credentials: HashMap<String, Credential>,
     /// List of application users
     pub users: Vec<User>,
+    /// Forge organizations/groups/users scanned periodically for new repositories
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub watch_sources: Vec<WatchSource>,
+    /// Personal API tokens of the users
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub api_tokens: Vec<ApiToken>,
 }
 
 /// Server configuration settings.
@@ -31,10 +40,20 @@ pub struct ServerConfig {
     pub host: String,
     pub port: u16,
     pub jwt_secret: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Secret used before `jwt_secret`; its tokens are accepted until `valid_until`
+    pub previous_jwt_secret: Option<PreviousJwtSecret>,
+    #[serde(default)]
+    /// Lifetimes of access and refresh tokens
+    pub sessions: SessionConfig,
     #[serde(default = "default_encryption_key")]
     pub encryption_key: String,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
-    pub error_webhooks: Vec<String>,
+    /// Keys used before `encryption_key`, still accepted for decrypting stored
+    /// secrets until they are re-encrypted
+    pub retired_encryption_keys: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub error_webhooks: Vec<Webhook>,
     #[serde(default = "default_skip_auth")]
     /// If true, authentication is bypassed and all login attempts succeed
     pub skip_auth: bool,
@@ -44,6 +63,794 @@ pub struct ServerConfig {
     #[serde(default = "default_static_dir", skip_serializing)]
     /// Directory path for serving static web files
     pub static_dir: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Inbound push webhooks (`POST /api/hooks/{provider}`); disabled if not set
+    pub push_hooks: Option<PushHookConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Prometheus metrics (`GET /metrics`); disabled if not set
+    pub metrics: Option<MetricsConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// OpenTelemetry tracing of syncs and API requests; disabled if not set
+    pub tracing: Option<TracingConfig>,
+    #[serde(default)]
+    /// Log output format and the logs kept per sync run
+    pub logging: LoggingConfig,
+    #[serde(default)]
+    /// Retry behaviour and delivery log of outgoing webhooks
+    pub webhook_delivery: WebhookDeliveryConfig,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Email notifications and backup digest; disabled if not set
+    pub email: Option<EmailConfig>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    /// Webhooks notified about selected events and repositories
+    pub notifications: Vec<Subscription>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Single sign-on with an OpenID Connect provider; disabled if not set
+    pub oidc: Option<OidcConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Authentication by a trusted reverse proxy; disabled if not set
+    pub proxy_auth: Option<ProxyAuthConfig>,
+    #[serde(default)]
+    /// Limits of API requests and failed logins per client
+    pub rate_limit: RateLimitConfig,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// HTTPS on `host:port`; plain HTTP if not set
+    pub tls: Option<TlsConfig>,
+}
+
+impl ServerConfig {
+    /// The current and retired encryption keys of stored secrets.
+    pub fn encryption_keys(&self) -> EncryptionKeys {
+        EncryptionKeys::new(&self.encryption_key, &self.retired_encryption_keys)
+    }
+
+    /// All notification subscriptions, including `error_webhooks`, which are
+    /// subscribed to sync errors and disabled repositories of all repositories.
+    pub fn subscriptions(&self) -> Vec<Subscription> {
+        self.error_webhooks
+            .iter()
+            .map(|webhook| Subscription {
+                webhook: webhook.clone(),
+                events: vec![NotificationEvent::Error, NotificationEvent::OutOfAttempts],
+                repositories: Vec::new(),
+                tags: Vec::new(),
+                exclude_repositories: Vec::new(),
+                exclude_tags: Vec::new(),
+            })
+            .chain(self.notifications.iter().cloned())
+            .collect()
+    }
+}
+
+/// Events that can be sent to notification subscriptions.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationEvent {
+    /// A sync or metadata backup failed
+    Error,
+    /// A repository ran out of sync attempts and was disabled
+    #[serde(alias = "repository_disabled")]
+    OutOfAttempts,
+    /// A sync fetched new commits
+    SyncSucceeded,
+    /// A sync found the repository already up-to-date
+    SyncSkipped,
+    /// A sync succeeded after previous failures
+    Recovered,
+    /// A repository was added through the API or by a watch source
+    RepositoryAdded,
+    /// A repository was removed
+    RepositoryDeleted,
+    /// A credential was added, updated or deleted
+    CredentialChanged,
+    /// A backup failed its integrity check after a sync (see `storage.verify_backups`)
+    VerificationFailed,
+    /// Free space on the archive volume fell below `storage.min_free_space_mb`
+    StorageLow,
+    /// A repository hasn't synced successfully within its maximum backup age
+    BackupStale,
+    /// A username or address was locked out after repeated failed logins
+    LoginLockedOut,
+}
+
+impl NotificationEvent {
+    /// Name of the event in payloads and the delivery log.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationEvent::Error => "error",
+            NotificationEvent::OutOfAttempts => "out_of_attempts",
+            NotificationEvent::SyncSucceeded => "sync_succeeded",
+            NotificationEvent::SyncSkipped => "sync_skipped",
+            NotificationEvent::Recovered => "recovered",
+            NotificationEvent::RepositoryAdded => "repository_added",
+            NotificationEvent::RepositoryDeleted => "repository_deleted",
+            NotificationEvent::CredentialChanged => "credential_changed",
+            NotificationEvent::VerificationFailed => "verification_failed",
+            NotificationEvent::StorageLow => "storage_low",
+            NotificationEvent::BackupStale => "backup_stale",
+            NotificationEvent::LoginLockedOut => "login_locked_out",
+        }
+    }
+}
+
+/// A webhook notified about selected events of selected repositories.
+///
+/// ```yaml
+/// notifications:
+///   - webhook: "https://hooks.slack.com/services/T000/B000/XXXX"
+///     events: [sync_succeeded, recovered]
+///     repositories: ["github_com-acme-*"]
+///     exclude_tags: [noisy]
+/// ```
+///
+/// A repository matches if it matches any of `repositories` (ID globs) or
+/// `tags`, or if both are empty, and matches none of the exclusions. Events
+/// without a repository (`credential_changed`, `storage_low`, `login_locked_out`)
+/// ignore the repository filters.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Subscription {
+    pub webhook: Webhook,
+    /// Events to send
+    pub events: Vec<NotificationEvent>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    /// Glob patterns of repository IDs
+    pub repositories: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    /// Glob patterns of repository IDs that are never sent
+    pub exclude_repositories: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude_tags: Vec<String>,
+}
+
+impl Subscription {
+    /// Returns true if the subscription receives `event` for `repo`.
+    pub fn matches(&self, event: NotificationEvent, repo: Option<&Repository>) -> bool {
+        if !self.events.contains(&event) {
+            return false;
+        }
+        let Some(repo) = repo else {
+            return true;
+        };
+
+        let matches_glob = |patterns: &[String]| {
+            patterns.iter().any(|pattern| {
+                glob::Pattern::new(pattern)
+                    .map(|p| p.matches(&repo.id))
+                    .unwrap_or(false)
+            })
+        };
+        let has_tag = |tags: &[String]| tags.iter().any(|tag| repo.tags.contains(tag));
+
+        let included = (self.repositories.is_empty() && self.tags.is_empty())
+            || matches_glob(&self.repositories)
+            || has_tag(&self.tags);
+        included && !matches_glob(&self.exclude_repositories) && !has_tag(&self.exclude_tags)
+    }
+}
+
+/// Retry and logging settings for outgoing webhooks.
+///
+/// Failed deliveries (network errors, timeouts, `408`, `429` and `5xx` responses)
+/// are retried with exponential backoff: the n-th retry waits a random duration
+/// between half and all of `initial_backoff_seconds * 2^(n-1)`, capped at
+/// `max_backoff_seconds`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WebhookDeliveryConfig {
+    #[serde(default = "default_webhook_max_attempts")]
+    /// Total number of attempts per delivery (including the first one)
+    pub max_attempts: u32,
+    #[serde(default = "default_webhook_initial_backoff_seconds")]
+    /// Delay before the first retry
+    pub initial_backoff_seconds: u64,
+    #[serde(default = "default_webhook_max_backoff_seconds")]
+    /// Upper bound for the delay between two attempts
+    pub max_backoff_seconds: u64,
+    #[serde(default = "default_webhook_timeout_seconds")]
+    /// Timeout of a single attempt
+    pub timeout_seconds: u64,
+    #[serde(default = "default_webhook_log_retention")]
+    /// Number of deliveries kept in the delivery log
+    pub log_retention: usize,
+}
+
+impl Default for WebhookDeliveryConfig {
+    fn default() -> Self {
+        WebhookDeliveryConfig {
+            max_attempts: default_webhook_max_attempts(),
+            initial_backoff_seconds: default_webhook_initial_backoff_seconds(),
+            max_backoff_seconds: default_webhook_max_backoff_seconds(),
+            timeout_seconds: default_webhook_timeout_seconds(),
+            log_retention: default_webhook_log_retention(),
+        }
+    }
+}
+
+fn default_webhook_max_attempts() -> u32 {
+    5
+}
+
+fn default_webhook_initial_backoff_seconds() -> u64 {
+    2
+}
+
+fn default_webhook_max_backoff_seconds() -> u64 {
+    300
+}
+
+fn default_webhook_timeout_seconds() -> u64 {
+    10
+}
+
+fn default_webhook_log_retention() -> usize {
+    1000
+}
+
+/// An outgoing webhook receiver.
+///
+/// In the configuration this is either a bare URL string or an object:
+///
+/// ```yaml
+/// error_webhooks:
+///   - "https://example.com/webhook"
+///   - url: "https://alerts.example.com/gitsafe"
+///     secret: "shared-secret"
+///     headers:
+///       Authorization: "Bearer token"
+///     timeout_seconds: 5
+///   - url: "https://hooks.slack.com/services/T000/B000/XXXX"
+///     format: slack
+///   - url: "https://ntfy.sh/gitsafe-alerts"
+///     format: ntfy
+///     priority: 5
+///   - url: "https://example.com/custom"
+///     content_type: "application/json"
+///     template: |
+///       {"summary": {{ title | tojson }}, "repository": {{ repo.id | tojson }}}
+/// ```
+///
+/// Webhooks without options are written back as bare strings.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(from = "WebhookEntry", into = "WebhookEntry")]
+pub struct Webhook {
+    pub url: String,
+    /// HMAC-SHA256 key for the `X-Gitsafe-Signature` header; requests are unsigned if not set
+    pub secret: Option<String>,
+    /// Additional request headers (e.g. `Authorization`)
+    pub headers: BTreeMap<String, String>,
+    /// Timeout per attempt, overriding `webhook_delivery.timeout_seconds`
+    pub timeout_seconds: Option<u64>,
+    /// Message schema of the receiver
+    pub format: WebhookFormat,
+    /// Message priority for ntfy (1-5) and Gotify (0-10); derived from the event if not set
+    pub priority: Option<u8>,
+    /// minijinja template of the request body, replacing the body of `format`
+    pub template: Option<String>,
+    /// Content type of a templated body (default: `application/json`)
+    pub content_type: Option<String>,
+}
+
+impl Webhook {
+    /// Creates a webhook without options.
+    pub fn new(url: impl Into<String>) -> Self {
+        Webhook {
+            url: url.into(),
+            secret: None,
+            headers: BTreeMap::new(),
+            timeout_seconds: None,
+            format: WebhookFormat::Generic,
+            priority: None,
+            template: None,
+            content_type: None,
+        }
+    }
+}
+
+/// Message schema a webhook receiver expects.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    /// GitSafe's own JSON payload
+    #[default]
+    Generic,
+    /// Slack incoming webhook with Block Kit blocks
+    Slack,
+    /// Discord webhook with an embed
+    Discord,
+    /// Microsoft Teams workflow webhook with an Adaptive Card
+    Teams,
+    /// ntfy topic URL; the message is sent as text with title, priority and tag headers
+    Ntfy,
+    /// Gotify message endpoint (`https://gotify.example.com/message?token=...`)
+    Gotify,
+}
+
+impl WebhookFormat {
+    fn is_generic(&self) -> bool {
+        *self == WebhookFormat::Generic
+    }
+}
+
+/// Serialized form of `Webhook`, accepting both a bare URL and an object.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum WebhookEntry {
+    Url(String),
+    Detailed {
+        url: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        secret: Option<String>,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        headers: BTreeMap<String, String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timeout_seconds: Option<u64>,
+        #[serde(default, skip_serializing_if = "WebhookFormat::is_generic")]
+        format: WebhookFormat,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        priority: Option<u8>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        template: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        content_type: Option<String>,
+    },
+}
+
+impl From<WebhookEntry> for Webhook {
+    fn from(entry: WebhookEntry) -> Self {
+        match entry {
+            WebhookEntry::Url(url) => Webhook::new(url),
+            WebhookEntry::Detailed {
+                url,
+                secret,
+                headers,
+                timeout_seconds,
+                format,
+                priority,
+                template,
+                content_type,
+            } => Webhook {
+                url,
+                secret,
+                headers,
+                timeout_seconds,
+                format,
+                priority,
+                template,
+                content_type,
+            },
+        }
+    }
+}
+
+impl From<Webhook> for WebhookEntry {
+    fn from(webhook: Webhook) -> Self {
+        if webhook.secret.is_none()
+            && webhook.headers.is_empty()
+            && webhook.timeout_seconds.is_none()
+            && webhook.format.is_generic()
+            && webhook.priority.is_none()
+            && webhook.template.is_none()
+            && webhook.content_type.is_none()
+        {
+            WebhookEntry::Url(webhook.url)
+        } else {
+            WebhookEntry::Detailed {
+                url: webhook.url,
+                secret: webhook.secret,
+                headers: webhook.headers,
+                timeout_seconds: webhook.timeout_seconds,
+                format: webhook.format,
+                priority: webhook.priority,
+                template: webhook.template,
+                content_type: webhook.content_type,
+            }
+        }
+    }
+}
+
+/// Settings of the Prometheus metrics endpoint.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct MetricsConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Token scrapers must send as `Authorization: Bearer <token>`; no authentication if not set
+    pub bearer_token: Option<String>,
+}
+
+/// Settings of the OpenTelemetry trace export.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TracingConfig {
+    #[serde(default = "default_tracing_endpoint")]
+    /// OTLP/HTTP traces endpoint of the collector
+    pub endpoint: String,
+    #[serde(default = "default_tracing_service_name")]
+    /// Service name reported with every span
+    pub service_name: String,
+    #[serde(default)]
+    /// Also log every finished span with its duration, useful without a collector
+    pub log_spans: bool,
+}
+
+impl Default for TracingConfig {
+    fn default() -> Self {
+        TracingConfig {
+            endpoint: default_tracing_endpoint(),
+            service_name: default_tracing_service_name(),
+            log_spans: false,
+        }
+    }
+}
+
+fn default_tracing_endpoint() -> String {
+    "http://localhost:4318/v1/traces".to_string()
+}
+
+fn default_tracing_service_name() -> String {
+    "gitsafe".to_string()
+}
+
+/// A replaced JWT secret, accepted for verifying tokens during a grace period.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PreviousJwtSecret {
+    pub secret: String,
+    /// End of the grace period
+    pub valid_until: DateTime<Utc>,
+}
+
+/// Lifetimes of the tokens issued at login.
+///
+/// Access tokens are short-lived JWTs; refresh tokens are exchanged for a new
+/// pair at `POST /api/token/refresh` and rotated on every use.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SessionConfig {
+    #[serde(default = "default_access_token_minutes")]
+    pub access_token_minutes: u32,
+    #[serde(default = "default_refresh_token_days")]
+    /// Days a session stays valid without being refreshed
+    pub refresh_token_days: u32,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        SessionConfig {
+            access_token_minutes: default_access_token_minutes(),
+            refresh_token_days: default_refresh_token_days(),
+        }
+    }
+}
+
+fn default_access_token_minutes() -> u32 {
+    15
+}
+
+fn default_refresh_token_days() -> u32 {
+    30
+}
+
+/// Settings of the log output.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LoggingConfig {
+    #[serde(default)]
+    /// Format of the log lines written to stderr
+    pub format: LogFormat,
+    #[serde(default = "default_sync_log_runs")]
+    /// Number of sync runs whose log lines are kept for `GET /api/sync-logs`
+    pub sync_log_runs: usize,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            format: LogFormat::default(),
+            sync_log_runs: default_sync_log_runs(),
+        }
+    }
+}
+
+fn default_sync_log_runs() -> usize {
+    200
+}
+
+/// Format of the log output.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable text
+    #[default]
+    Text,
+    /// One JSON object per line, with the fields of the enclosing spans
+    /// (e.g. `repo_id`, `url`, `trigger` and `job_id` during a sync)
+    Json,
+}
+
+/// Settings for inbound push webhooks from GitHub, GitLab and Gitea.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PushHookConfig {
+    /// Shared secret: HMAC key for GitHub/Gitea signatures, secret token for GitLab.
+    /// Push hooks are disabled while it is empty.
+    pub secret: String,
+    #[serde(default = "default_push_hook_debounce_seconds")]
+    /// Quiet period per repository before a push-triggered sync starts
+    pub debounce_seconds: u64,
+}
+
+fn default_push_hook_debounce_seconds() -> u64 {
+    30
+}
+
+/// Settings for single sign-on with an OpenID Connect provider.
+///
+/// Users log in with the authorization code flow (with PKCE) and get a regular
+/// gitsafe token. Their role is taken from the groups in the ID token on every login.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OidcConfig {
+    /// Issuer URL; the provider metadata is read from `<issuer_url>/.well-known/openid-configuration`
+    pub issuer_url: String,
+    pub client_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Client secret for confidential clients (sent with HTTP basic authentication)
+    pub client_secret: Option<String>,
+    /// Callback URL registered at the provider, ending in `/api/oidc/callback`
+    pub redirect_url: String,
+    #[serde(default = "default_oidc_scopes")]
+    pub scopes: Vec<String>,
+    #[serde(default = "default_oidc_username_claim")]
+    /// ID token claim used as the gitsafe username
+    pub username_claim: String,
+    #[serde(default = "default_oidc_groups_claim")]
+    /// ID token claim with the groups of the user (a list or a single string)
+    pub groups_claim: String,
+    #[serde(default)]
+    /// Role of the members of a group; users in several groups get the highest role
+    pub group_roles: HashMap<String, Role>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Role of users in none of `group_roles`; such users are rejected if not set
+    pub default_role: Option<Role>,
+    #[serde(default = "default_oidc_post_login_url")]
+    /// Page the browser is sent to after login, with the token in the URL fragment (`#token=...`)
+    pub post_login_url: String,
+    #[serde(default)]
+    /// Rejects logins with a username and password (`POST /api/login`)
+    pub disable_password_login: bool,
+}
+
+fn default_oidc_scopes() -> Vec<String> {
+    vec![
+        "openid".to_string(),
+        "profile".to_string(),
+        "email".to_string(),
+    ]
+}
+
+fn default_oidc_username_claim() -> String {
+    "preferred_username".to_string()
+}
+
+fn default_oidc_groups_claim() -> String {
+    "groups".to_string()
+}
+
+fn default_oidc_post_login_url() -> String {
+    "/".to_string()
+}
+
+/// Settings for serving HTTPS (see `tls`).
+///
+/// ```yaml
+/// tls:
+///   cert_path: /etc/gitsafe/tls/fullchain.pem
+///   key_path: /etc/gitsafe/tls/privkey.pem
+///   redirect_http_port: 80
+///   client_auth:
+///     ca_path: /etc/gitsafe/tls/clients-ca.pem
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TlsConfig {
+    /// PEM file with the certificate, followed by the intermediate certificates
+    pub cert_path: String,
+    /// PEM file with the private key of the certificate
+    pub key_path: String,
+    #[serde(default = "default_tls_reload_interval_seconds")]
+    /// How often the files are checked for changes (e.g. a renewed certificate); 0 disables reloading
+    pub reload_interval_seconds: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Port of an HTTP listener redirecting every request to HTTPS; none if not set
+    pub redirect_http_port: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Client certificates, authenticating their subject as user; not requested if not set
+    pub client_auth: Option<ClientAuthConfig>,
+}
+
+fn default_tls_reload_interval_seconds() -> u64 {
+    60
+}
+
+/// Settings for mutual TLS (see `tls`).
+///
+/// Clients presenting a certificate issued by one of the CAs are authenticated
+/// as the configured user named by the common name of the certificate subject.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ClientAuthConfig {
+    /// PEM file with the certificates of the CAs issuing client certificates
+    pub ca_path: String,
+    #[serde(default)]
+    /// Whether connections without a valid client certificate are rejected
+    pub required: bool,
+}
+
+/// Settings for authentication by a reverse proxy (Authelia, Authentik, oauth2-proxy, ...).
+///
+/// Requests from a trusted proxy with the user header are authenticated as that
+/// user; the header is ignored on requests from other addresses.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProxyAuthConfig {
+    /// Addresses of the proxies (CIDRs like `10.0.0.0/8` or `::1/128`)
+    pub trusted_proxies: Vec<IpNet>,
+    #[serde(default = "default_proxy_user_header")]
+    /// Header with the username
+    pub user_header: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Header with the comma-separated groups of the user
+    pub groups_header: Option<String>,
+    #[serde(default)]
+    /// Role of the members of a group; users in several groups get the highest role
+    pub group_roles: HashMap<String, Role>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Role of users in none of `group_roles`; such users are rejected if not set
+    pub default_role: Option<Role>,
+}
+
+fn default_proxy_user_header() -> String {
+    "Remote-User".to_string()
+}
+
+/// Limits of API requests and failed logins per client.
+///
+/// ```yaml
+/// rate_limit:
+///   requests_per_minute: 600
+///   trusted_proxies: ["10.0.0.0/8"]
+///   login:
+///     max_failures_per_user: 5
+///     max_failures_per_ip: 20
+/// ```
+///
+/// Clients are identified by their IP address. Behind a reverse proxy, list it
+/// in `trusted_proxies` so that the client address is taken from its
+/// `X-Forwarded-For` header.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RateLimitConfig {
+    #[serde(default = "default_requests_per_minute")]
+    /// Requests to `/api` per client and minute; `0` disables the limit
+    pub requests_per_minute: u32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    /// Proxies whose `X-Forwarded-For` header is trusted (CIDRs)
+    pub trusted_proxies: Vec<IpNet>,
+    #[serde(default)]
+    pub login: LoginThrottleConfig,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            requests_per_minute: default_requests_per_minute(),
+            trusted_proxies: Vec::new(),
+            login: LoginThrottleConfig::default(),
+        }
+    }
+}
+
+fn default_requests_per_minute() -> u32 {
+    600
+}
+
+/// Lockout of usernames and addresses after repeated failed logins.
+///
+/// A username or address is locked for `lockout_minutes` once it reached its
+/// maximum of failures within `window_minutes`; `0` disables a maximum.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LoginThrottleConfig {
+    #[serde(default = "default_login_max_failures_per_user")]
+    pub max_failures_per_user: u32,
+    #[serde(default = "default_login_max_failures_per_ip")]
+    pub max_failures_per_ip: u32,
+    #[serde(default = "default_login_window_minutes")]
+    pub window_minutes: u32,
+    #[serde(default = "default_login_lockout_minutes")]
+    pub lockout_minutes: u32,
+}
+
+impl Default for LoginThrottleConfig {
+    fn default() -> Self {
+        LoginThrottleConfig {
+            max_failures_per_user: default_login_max_failures_per_user(),
+            max_failures_per_ip: default_login_max_failures_per_ip(),
+            window_minutes: default_login_window_minutes(),
+            lockout_minutes: default_login_lockout_minutes(),
+        }
+    }
+}
+
+fn default_login_max_failures_per_user() -> u32 {
+    5
+}
+
+fn default_login_max_failures_per_ip() -> u32 {
+    20
+}
+
+fn default_login_window_minutes() -> u32 {
+    15
+}
+
+fn default_login_lockout_minutes() -> u32 {
+    15
+}
+
+/// SMTP settings for email notifications and the periodic backup digest.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EmailConfig {
+    pub smtp_host: String,
+    #[serde(default = "default_smtp_port")]
+    pub smtp_port: u16,
+    #[serde(default)]
+    /// Transport security of the SMTP connection
+    pub tls: SmtpTls,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// SMTP user; no authentication if not set
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// Sender address, e.g. `GitSafe <gitsafe@example.com>`
+    pub from: String,
+    /// Addresses receiving notifications and digests
+    pub recipients: Vec<String>,
+    #[serde(default = "default_email_notify_errors")]
+    /// Send an email for every sync error and disabled repository
+    pub notify_errors: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Periodic summary of all repositories; disabled if not set
+    pub digest: Option<DigestConfig>,
+}
+
+/// Transport security of an SMTP connection.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Upgrade a plain connection with STARTTLS (usually port 587)
+    #[default]
+    Starttls,
+    /// TLS from the start (usually port 465)
+    Tls,
+    /// Unencrypted; only for local relays
+    None,
+}
+
+/// Schedule and thresholds of the backup digest email.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DigestConfig {
+    #[serde(default = "default_digest_cron_expression")]
+    /// Cron expression, e.g. `"0 0 8 * * *"` (daily) or `"0 0 8 * * Mon"` (weekly)
+    pub cron_expression: String,
+    #[serde(default = "default_digest_stale_after_hours")]
+    /// Enabled repositories not synced successfully for longer are reported as stale
+    pub stale_after_hours: u64,
+}
+
+fn default_smtp_port() -> u16 {
+    587
+}
+
+fn default_email_notify_errors() -> bool {
+    true
+}
+
+fn default_digest_cron_expression() -> String {
+    "0 0 8 * * *".to_string()
+}
+
+fn default_digest_stale_after_hours() -> u64 {
+    48
 }
 
 fn default_sync_attempts() -> u32 {
@@ -67,17 +874,40 @@ pub struct StorageConfig {
     pub archive_dir: String,
     #[serde(default = "default_compact")]
     pub compact: bool,
+    #[serde(default = "default_data_dir")]
+    /// Directory for GitSafe's own state (e.g. the webhook delivery log)
+    pub data_dir: String,
+    #[serde(default)]
+    /// Check the integrity of every backup after a sync that fetched new commits
+    pub verify_backups: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Send `storage_low` notifications when less space is available on the archive volume
+    pub min_free_space_mb: Option<u64>,
 }
 
 fn default_compact() -> bool {
     true
 }
 
+fn default_data_dir() -> String {
+    "./data".to_string()
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SchedulerConfig {
     /// Cron expression for scheduled repository syncing
     /// Format: "sec min hour day_of_month month day_of_week"
     pub cron_expression: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Hours after which a backup without a successful sync is stale; no limit if not set
+    pub max_backup_age_hours: Option<u64>,
+    #[serde(default = "default_watchdog_interval_minutes")]
+    /// How often the watchdog checks for stale backups
+    pub watchdog_interval_minutes: u64,
+}
+
+fn default_watchdog_interval_minutes() -> u64 {
+    15
 }
 
 /// Repository configuration.
@@ -103,6 +933,124 @@ pub struct Repository {
     /// Number of sync attempts remaining before the repository is disabled
     /// None means no error has occurred or the repository has recovered
     pub attempts_left: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// ID of the watch source that discovered this repository (None if added manually)
+    pub source_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Upstream state reported by the forge during discovery (None if the repository is active)
+    pub upstream_status: Option<UpstreamStatus>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Backup of forge metadata (issues, pull requests, releases, wiki); disabled if not set
+    pub metadata: Option<MetadataBackup>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    /// Free-form labels, e.g. for routing notifications
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Overrides `scheduler.max_backup_age_hours` for this repository
+    pub max_backup_age_hours: Option<u64>,
+}
+
+/// Upstream state of a discovered repository that is no longer active on the forge.
+///
+/// Flagged repositories keep their backups; they are never removed automatically.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum UpstreamStatus {
+    /// The repository was archived (made read-only) upstream
+    Archived,
+    /// The repository no longer exists upstream
+    Deleted,
+}
+
+/// Settings for backing up the forge metadata of a repository.
+///
+/// Issues, comments, pull/merge requests, labels, milestones and releases (including
+/// their assets) are exported as JSON, and the wiki is cloned if it exists. The export
+/// is stored next to the code backup and refreshed on every successful sync.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MetadataBackup {
+    pub provider: ForgeProvider,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// API base URL. Defaults to the public GitHub/GitLab API; required for Gitea
+    pub api_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Project path on the forge (e.g. `org/repo`). Derived from the repository URL if not set
+    pub project: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Credential whose password is the API token. Defaults to the repository credential
+    pub credential_id: Option<String>,
+    #[serde(default = "default_metadata_release_assets")]
+    /// If true, release assets are downloaded, otherwise only listed in `releases.json`
+    pub release_assets: bool,
+    #[serde(default = "default_metadata_wiki")]
+    /// If true, the wiki repository is cloned when it exists
+    pub wiki: bool,
+}
+
+fn default_metadata_release_assets() -> bool {
+    true
+}
+
+fn default_metadata_wiki() -> bool {
+    true
+}
+
+/// A forge organization, group or user that is scanned for repositories.
+///
+/// On every scan, repositories matching the filters that are not yet configured
+/// are added automatically. Previously discovered repositories that disappeared
+/// or were archived upstream are flagged via `Repository::upstream_status`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WatchSource {
+    pub id: String,
+    pub provider: ForgeProvider,
+    /// Organization, group (GitLab subgroups as `group/subgroup`) or user name
+    pub owner: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// API base URL (e.g. `https://gitea.example.com/api/v1`). Defaults to the public
+    /// GitHub/GitLab API; required for Gitea
+    pub api_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Credential used for the forge API (password is the access token) and for added repositories
+    pub credential_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    /// Glob patterns a repository name must match (empty means all repositories)
+    pub include: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    /// Glob patterns excluding repositories by name
+    pub exclude: Vec<String>,
+    #[serde(default)]
+    /// If true, repositories that are already archived upstream are added too
+    pub include_archived: bool,
+    #[serde(default)]
+    /// If true, added repositories use the SSH clone URL instead of HTTPS
+    pub use_ssh: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Cron expression for re-scanning this source (defaults to `scheduler.cron_expression`)
+    pub cron_expression: Option<String>,
+    #[serde(default = "default_watch_source_enabled")]
+    pub enabled: bool,
+}
+
+fn default_watch_source_enabled() -> bool {
+    true
+}
+
+impl WatchSource {
+    /// Returns true if a repository name passes the include/exclude filters.
+    ///
+    /// Invalid glob patterns never match.
+    pub fn matches(&self, name: &str) -> bool {
+        let matches_any = |patterns: &[String]| {
+            patterns.iter().any(|p| {
+                glob::Pattern::new(p)
+                    .map(|pattern| pattern.matches(name))
+                    .unwrap_or(false)
+            })
+        };
+
+        (self.include.is_empty() || matches_any(&self.include)) && !matches_any(&self.exclude)
+    }
 }
 
 /// Git credential configuration for authenticated repository access.
@@ -197,6 +1145,124 @@ impl Credential {
 pub struct User {
     pub username: String,
     pub password_hash: String,
+    #[serde(default)]
+    /// What the user may do; users without a role are admins, as before roles existed
+    pub role: Role,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    /// Whether the user has to change the password before using the API
+    pub must_change_password: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Two-factor authentication with time-based one-time passwords, if enrolled
+    pub totp: Option<UserTotp>,
+}
+
+impl User {
+    /// Whether logins of the user require a one-time password.
+    pub fn has_totp(&self) -> bool {
+        self.totp.as_ref().is_some_and(|totp| totp.confirmed)
+    }
+}
+
+/// TOTP enrollment of a user (see `totp`).
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct UserTotp {
+    /// Base32 secret shared with the authenticator app, encrypted with `server.encryption_key`
+    pub secret: String,
+    #[serde(default)]
+    /// Whether the user confirmed the enrollment with a code; until then logins don't require one
+    pub confirmed: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    /// SHA-256 hashes of the unused recovery codes
+    pub recovery_code_hashes: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Time step of the last accepted code, so that a code can't be used twice
+    pub last_used_step: Option<u64>,
+}
+
+/// Permission level of a user; each role includes the permissions of the roles before it.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Lists repositories, sync logs and webhook deliveries
+    Viewer,
+    /// Additionally syncs, adds and updates repositories
+    Operator,
+    /// Additionally deletes repositories and manages credentials, webhooks and users
+    #[default]
+    Admin,
+}
+
+impl Role {
+    /// Name of the role in the configuration and API.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Operator => "operator",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+/// A long-lived personal API token of a user, for automation.
+///
+/// Only the SHA-256 hash of the token is stored; the token itself is shown once
+/// when it is created.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ApiToken {
+    pub id: String,
+    /// User the token acts as
+    pub username: String,
+    /// Description of what the token is used for
+    pub name: String,
+    /// Hex-encoded SHA-256 hash of the token
+    pub token_hash: String,
+    /// Operations the token may perform
+    pub scopes: Vec<TokenScope>,
+    pub created_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Time after which the token is rejected; never expires if not set
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Time the token was last used (updated at most once a minute)
+    pub last_used: Option<DateTime<Utc>>,
+}
+
+impl ApiToken {
+    /// Whether the token may perform operations requiring `scope`; `write`
+    /// includes all other scopes.
+    pub fn has_scope(&self, scope: TokenScope) -> bool {
+        self.scopes
+            .iter()
+            .any(|s| *s == scope || *s == TokenScope::Write)
+    }
+
+    /// Whether the token expired at `now`.
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+/// Operations an API token may perform.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenScope {
+    /// Read-only requests (`GET`)
+    Read,
+    /// Triggering syncs (`POST /api/sync`)
+    Sync,
+    /// All requests, including changes to repositories, credentials and tokens
+    Write,
+}
+
+impl TokenScope {
+    /// Name of the scope in the configuration and API.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenScope::Read => "read",
+            TokenScope::Sync => "sync",
+            TokenScope::Write => "write",
+        }
+    }
 }
 
 impl Config {
@@ -236,6 +1302,99 @@ impl Config {
     pub fn get_credential(&self, id: &str) -> Option<&Credential> {
         self.credentials.get(id)
     }
+
+    /// Number of stored secrets (credential passwords and SSH keys, two-factor
+    /// secrets) that aren't encrypted with the current encryption key.
+    pub fn secrets_to_reencrypt(&self) -> usize {
+        let keys = self.server.encryption_keys();
+        let credential_secrets = self
+            .credentials
+            .values()
+            .flat_map(|c| [c.password.as_deref(), c.ssh_key.as_deref()])
+            .flatten()
+            .filter(|secret| !secret.is_empty());
+        let totp_secrets = self
+            .users
+            .iter()
+            .filter_map(|u| u.totp.as_ref())
+            .map(|t| t.secret.as_str());
+        credential_secrets
+            .chain(totp_secrets)
+            .filter(|secret| !keys.is_current(secret))
+            .count()
+    }
+
+    /// Whether `username` is the only admin, so removing the role would lock everyone out.
+    pub fn is_last_admin(&self, username: &str) -> bool {
+        let admins: Vec<&User> = self
+            .users
+            .iter()
+            .filter(|u| u.role == Role::Admin)
+            .collect();
+        admins.len() == 1 && admins[0].username == username
+    }
+
+    /// Checks that a user authenticated by an external identity provider may
+    /// log in with `role`.
+    ///
+    /// Returns whether `sync_external_user` would change the users.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Forbidden` if the username belongs to a local user with
+    /// a password, which external identities can't take over, or if the role
+    /// would demote the last admin.
+    pub fn check_external_user(
+        &self,
+        username: &str,
+        role: Role,
+    ) -> Result<bool, crate::error::AppError> {
+        let Some(user) = self.users.iter().find(|u| u.username == username) else {
+            return Ok(true);
+        };
+        if !user.password_hash.is_empty() {
+            return Err(crate::error::AppError::Forbidden(format!(
+                "User {} is a local user and can't log in through an external identity provider",
+                username
+            )));
+        }
+        if role != Role::Admin && self.is_last_admin(username) {
+            return Err(crate::error::AppError::Forbidden(format!(
+                "User {} is the last admin and can't be demoted by the identity provider",
+                username
+            )));
+        }
+        Ok(user.role != role)
+    }
+
+    /// Records a user authenticated by an external identity provider: adds the
+    /// user without a password or updates the role of an existing external user.
+    ///
+    /// Returns whether the users changed.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Forbidden` if `check_external_user` refuses the login.
+    pub fn sync_external_user(
+        &mut self,
+        username: &str,
+        role: Role,
+    ) -> Result<bool, crate::error::AppError> {
+        if !self.check_external_user(username, role)? {
+            return Ok(false);
+        }
+        match self.users.iter_mut().find(|u| u.username == username) {
+            Some(user) => user.role = role,
+            None => self.users.push(User {
+                username: username.to_string(),
+                password_hash: String::new(),
+                role,
+                must_change_password: false,
+                totp: None,
+            }),
+        }
+        Ok(true)
+    }
 }
 
 impl Default for Config {
@@ -246,25 +1405,49 @@ impl Default for Config {
                 host: "127.0.0.1".to_string(),
                 port: 8080,
                 jwt_secret: "change-me-in-production".to_string(),
+                previous_jwt_secret: None,
+                sessions: SessionConfig::default(),
                 encryption_key: default_encryption_key(),
+                retired_encryption_keys: Vec::new(),
                 error_webhooks: Vec::new(),
                 skip_auth: false,
                 sync_attempts: 5,
                 static_dir: default_static_dir(),
+                push_hooks: None,
+                metrics: None,
+                tracing: None,
+                logging: LoggingConfig::default(),
+                webhook_delivery: WebhookDeliveryConfig::default(),
+                email: None,
+                notifications: Vec::new(),
+                oidc: None,
+                proxy_auth: None,
+                rate_limit: RateLimitConfig::default(),
+                tls: None,
             },
             storage: StorageConfig {
                 archive_dir: "./archives".to_string(),
                 compact: true,
+                data_dir: default_data_dir(),
+                verify_backups: false,
+                min_free_space_mb: None,
             },
             scheduler: SchedulerConfig {
                 cron_expression: "0 0 * * * *".to_string(), // Every hour
+                max_backup_age_hours: None,
+                watchdog_interval_minutes: default_watchdog_interval_minutes(),
             },
             repositories: Vec::new(),
             credentials: HashMap::new(),
             users: vec![User {
                 username: "admin".to_string(),
                 password_hash: bcrypt::hash("admin", bcrypt::DEFAULT_COST).unwrap(),
+                role: Role::Admin,
+                must_change_password: true,
+                totp: None,
             }],
+            watch_sources: Vec::new(),
+            api_tokens: Vec::new(),
         };
 
         // Serialize defaults to YAML and add as a config source
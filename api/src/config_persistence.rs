@@ -1,7 +1,10 @@
 use crate::config::Config;
-use log::{debug, error, info};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc;
 use tokio::time::{sleep, Duration};
+use tracing::{debug, error, info, instrument};
 
 /// Manages config persistence with debouncing to prevent excessive file I/O.
 ///
@@ -14,6 +17,18 @@ use tokio::time::{sleep, Duration};
 #[derive(Clone)]
 pub struct ConfigPersistence {
     sender: mpsc::UnboundedSender<Config>,
+    status: Arc<Mutex<SaveStatus>>,
+}
+
+/// Outcome of the saves performed so far, for health checks.
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct SaveStatus {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Time of the last successful save
+    pub last_success: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Error of the last save if it failed
+    pub last_error: Option<String>,
 }
 
 impl ConfigPersistence {
@@ -28,11 +43,26 @@ impl ConfigPersistence {
     /// Returns a `ConfigPersistence` handle that can be used to request saves.
     pub fn new(config_path: String) -> Self {
         let (sender, receiver) = mpsc::unbounded_channel();
+        let status = Arc::new(Mutex::new(SaveStatus::default()));
 
         // Spawn background task to handle saves
-        tokio::spawn(Self::persistence_task(receiver, config_path));
+        tokio::spawn(Self::persistence_task(
+            receiver,
+            config_path,
+            Arc::clone(&status),
+        ));
+
+        Self { sender, status }
+    }
 
-        Self { sender }
+    /// Whether the background task is still running and accepting save requests.
+    pub fn is_running(&self) -> bool {
+        !self.sender.is_closed()
+    }
+
+    /// Outcome of the saves performed so far.
+    pub fn status(&self) -> SaveStatus {
+        self.status.lock().unwrap().clone()
     }
 
     /// Requests a config save. The save will be debounced and executed by the background task.
@@ -44,7 +74,7 @@ impl ConfigPersistence {
     /// * `config` - The config to save
     pub fn request_save(&self, config: Config) {
         if let Err(e) = self.sender.send(config) {
-            error!("Failed to queue config save request: {}", e);
+            error!(error = %e, "Failed to queue config save request");
         }
     }
 
@@ -52,7 +82,11 @@ impl ConfigPersistence {
     ///
     /// Waits for a quiet period (100ms) after the last save request before
     /// actually writing to disk. This batches rapid changes efficiently.
-    async fn persistence_task(mut receiver: mpsc::UnboundedReceiver<Config>, config_path: String) {
+    async fn persistence_task(
+        mut receiver: mpsc::UnboundedReceiver<Config>,
+        config_path: String,
+        status: Arc<Mutex<SaveStatus>>,
+    ) {
         const DEBOUNCE_DELAY: Duration = Duration::from_millis(500);
         let mut pending_config: Option<Config> = None;
         let mut debounce_timer: Option<tokio::time::Sleep> = None;
@@ -71,7 +105,7 @@ impl ConfigPersistence {
                         None => {
                             // Channel closed, flush any pending save and exit
                             if let Some(config) = pending_config.take() {
-                                Self::save_config(&config, &config_path).await;
+                                Self::save_config(&config, &config_path, &status).await;
                             }
                             info!("Config persistence task shutting down");
                             break;
@@ -90,7 +124,7 @@ impl ConfigPersistence {
                     }
                 }, if debounce_timer.is_some() => {
                     if let Some(config) = pending_config.take() {
-                        Self::save_config(&config, &config_path).await;
+                        Self::save_config(&config, &config_path, &status).await;
                     }
                     debounce_timer = None;
                 }
@@ -100,13 +134,15 @@ impl ConfigPersistence {
 
     /// Saves the config to disk atomically.
     ///
-    /// Uses a temporary file and rename to ensure atomic writes.
-    async fn save_config(config: &Config, config_path: &str) {
+    /// Uses a temporary file and rename to ensure atomic writes. The outcome is
+    /// recorded in `status`.
+    #[instrument(skip_all, fields(path = config_path))]
+    async fn save_config(config: &Config, config_path: &str, status: &Mutex<SaveStatus>) {
         // Run blocking file I/O in a blocking thread pool
         let config_clone = config.clone();
         let path_clone = config_path.to_string();
 
-        match tokio::task::spawn_blocking(move || {
+        let result = match tokio::task::spawn_blocking(move || {
             // Create temporary file path
             let temp_path = format!("{}.tmp", path_clone);
 
@@ -133,14 +169,26 @@ impl ConfigPersistence {
         .await
         {
             Ok(Ok(())) => {
-                debug!("Config saved successfully to {}", config_path);
+                debug!("Config saved successfully");
+                Ok(())
             }
             Ok(Err(e)) => {
-                error!("Failed to save config: {}", e);
+                error!(error = %e, "Failed to save config");
+                Err(e)
             }
             Err(e) => {
-                error!("Task join error while saving config: {}", e);
+                error!(error = %e, "Task join error while saving config");
+                Err(format!("Task join error: {}", e))
+            }
+        };
+
+        let mut status = status.lock().unwrap();
+        match result {
+            Ok(()) => {
+                status.last_success = Some(Utc::now());
+                status.last_error = None;
             }
+            Err(e) => status.last_error = Some(e),
         }
     }
 }
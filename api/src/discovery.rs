@@ -0,0 +1,324 @@
+use crate::config::{Config, NotificationEvent, Repository, UpstreamStatus, WatchSource};
+use crate::config_persistence::ConfigPersistence;
+use crate::error::AppError;
+use crate::forge::{ForgeClient, ForgeProvider};
+use crate::git::GitService;
+use crate::webhooks::{self, WebhookService};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+/// A repository as listed by a forge API.
+#[derive(Debug, Clone)]
+pub struct RemoteRepository {
+    /// Full path including the owner (e.g. `org/repo` or `group/subgroup/repo`)
+    pub full_name: String,
+    /// HTTPS clone URL
+    pub clone_url: String,
+    /// SSH clone URL
+    pub ssh_url: Option<String>,
+    /// Whether the repository is archived (read-only) upstream
+    pub archived: bool,
+}
+
+/// Outcome of reconciling a watch source with the forge listing.
+#[derive(Debug, Default, Clone)]
+pub struct DiscoveryReport {
+    /// IDs of repositories that were added
+    pub added: Vec<String>,
+    /// IDs of repositories newly flagged as archived or deleted upstream
+    pub flagged: Vec<String>,
+    /// IDs of previously flagged repositories that are active again
+    pub restored: Vec<String>,
+}
+
+impl DiscoveryReport {
+    pub fn has_changes(&self) -> bool {
+        !self.added.is_empty() || !self.flagged.is_empty() || !self.restored.is_empty()
+    }
+}
+
+/// Repository listing shape shared by GitHub and Gitea.
+#[derive(Debug, Deserialize)]
+struct GithubRepository {
+    full_name: String,
+    clone_url: String,
+    ssh_url: Option<String>,
+    #[serde(default)]
+    archived: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitlabProject {
+    path_with_namespace: String,
+    http_url_to_repo: String,
+    ssh_url_to_repo: Option<String>,
+    #[serde(default)]
+    archived: bool,
+}
+
+/// Lists all repositories of an organization, group or user on a forge.
+///
+/// The owner is first looked up as an organization (GitLab: group, including
+/// subgroups) and then as a user.
+///
+/// # Errors
+///
+/// Returns `AppError::ForgeError` if the API calls fail, or `AppError::NotFound`
+/// if the owner exists neither as an organization nor as a user.
+pub async fn list_remote_repositories(
+    client: &ForgeClient,
+    owner: &str,
+) -> Result<Vec<RemoteRepository>, AppError> {
+    let repositories = match client.provider() {
+        ForgeProvider::Github | ForgeProvider::Gitea => {
+            let listed = match client
+                .get_paginated::<GithubRepository>(&format!("/orgs/{}/repos", owner))
+                .await?
+            {
+                Some(repos) => Some(repos),
+                None => {
+                    client
+                        .get_paginated::<GithubRepository>(&format!("/users/{}/repos", owner))
+                        .await?
+                }
+            };
+            listed.map(|repos| {
+                repos
+                    .into_iter()
+                    .map(|r| RemoteRepository {
+                        full_name: r.full_name,
+                        clone_url: r.clone_url,
+                        ssh_url: r.ssh_url,
+                        archived: r.archived,
+                    })
+                    .collect::<Vec<_>>()
+            })
+        }
+        ForgeProvider::Gitlab => {
+            let encoded_owner =
+                url::form_urlencoded::byte_serialize(owner.as_bytes()).collect::<String>();
+            let listed = match client
+                .get_paginated::<GitlabProject>(&format!(
+                    "/groups/{}/projects?include_subgroups=true",
+                    encoded_owner
+                ))
+                .await?
+            {
+                Some(projects) => Some(projects),
+                None => {
+                    client
+                        .get_paginated::<GitlabProject>(&format!(
+                            "/users/{}/projects",
+                            encoded_owner
+                        ))
+                        .await?
+                }
+            };
+            listed.map(|projects| {
+                projects
+                    .into_iter()
+                    .map(|p| RemoteRepository {
+                        full_name: p.path_with_namespace,
+                        clone_url: p.http_url_to_repo,
+                        ssh_url: p.ssh_url_to_repo,
+                        archived: p.archived,
+                    })
+                    .collect::<Vec<_>>()
+            })
+        }
+    };
+
+    repositories.ok_or_else(|| AppError::NotFound(format!("Forge owner {} not found", owner)))
+}
+
+/// Applies a forge listing for a watch source to the configuration.
+///
+/// - Repositories that pass the source filters and are not configured yet are added
+///   (archived ones only if `include_archived` is set).
+/// - Configured repositories archived upstream are flagged `archived`.
+/// - Repositories previously discovered by this source that are missing from the
+///   listing are flagged `deleted`. Their backups are kept.
+/// - Flagged repositories that are active again upstream get their flag cleared.
+///
+/// Repositories are matched by their normalized ID (`GitService::repo_id_from_url`),
+/// so HTTPS and SSH URLs of the same repository are considered equal.
+pub fn reconcile_source(
+    config: &mut Config,
+    source: &WatchSource,
+    remote: &[RemoteRepository],
+) -> DiscoveryReport {
+    let mut report = DiscoveryReport::default();
+    let mut seen = HashSet::new();
+
+    for remote_repo in remote {
+        let keys: Vec<String> = std::iter::once(&remote_repo.clone_url)
+            .chain(remote_repo.ssh_url.as_ref())
+            .map(|url| GitService::repo_id_from_url(url))
+            .collect();
+        seen.extend(keys.iter().cloned());
+
+        let existing = config
+            .repositories
+            .iter_mut()
+            .find(|r| keys.contains(&GitService::repo_id_from_url(&r.url)));
+
+        match existing {
+            Some(repo) => {
+                let status = remote_repo.archived.then_some(UpstreamStatus::Archived);
+                if repo.upstream_status != status {
+                    if status.is_some() {
+                        report.flagged.push(repo.id.clone());
+                    } else {
+                        report.restored.push(repo.id.clone());
+                    }
+                    repo.upstream_status = status;
+                }
+            }
+            None => {
+                let name = remote_repo
+                    .full_name
+                    .strip_prefix(&format!("{}/", source.owner))
+                    .unwrap_or(&remote_repo.full_name);
+                if !source.matches(name) || (remote_repo.archived && !source.include_archived) {
+                    continue;
+                }
+
+                let url = if source.use_ssh {
+                    remote_repo
+                        .ssh_url
+                        .clone()
+                        .unwrap_or_else(|| remote_repo.clone_url.clone())
+                } else {
+                    remote_repo.clone_url.clone()
+                };
+                let id = unique_repository_id(config, GitService::repo_id_from_url(&url));
+
+                config.repositories.push(Repository {
+                    id: id.clone(),
+                    url,
+                    credential_id: source.credential_id.clone(),
+                    enabled: true,
+                    last_sync: None,
+                    last_sync_commit_hash: None,
+                    last_sync_message: None,
+                    error: None,
+                    size: None,
+                    attempts_left: None,
+                    source_id: Some(source.id.clone()),
+                    upstream_status: remote_repo.archived.then_some(UpstreamStatus::Archived),
+                    metadata: None,
+                    tags: Vec::new(),
+                    max_backup_age_hours: None,
+                });
+                report.added.push(id);
+            }
+        }
+    }
+
+    for repo in config
+        .repositories
+        .iter_mut()
+        .filter(|r| r.source_id.as_deref() == Some(source.id.as_str()))
+    {
+        if !seen.contains(&GitService::repo_id_from_url(&repo.url))
+            && repo.upstream_status != Some(UpstreamStatus::Deleted)
+        {
+            repo.upstream_status = Some(UpstreamStatus::Deleted);
+            report.flagged.push(repo.id.clone());
+        }
+    }
+
+    report
+}
+
+/// Appends a numeric suffix to `id` until it doesn't collide with a configured repository.
+fn unique_repository_id(config: &Config, id: String) -> String {
+    if !config.repositories.iter().any(|r| r.id == id) {
+        return id;
+    }
+    (2..)
+        .map(|n| format!("{}-{}", id, n))
+        .find(|candidate| !config.repositories.iter().any(|r| &r.id == candidate))
+        .expect("unbounded iterator always yields a free ID")
+}
+
+/// Scans a watch source and applies the result to the shared configuration.
+///
+/// The forge API token is taken from the password of the source credential.
+/// The configuration is saved if anything changed, and `repository_added`
+/// notifications are sent for new repositories.
+///
+/// # Errors
+///
+/// Returns `AppError::NotFound` if the source doesn't exist, or the error of the
+/// forge listing.
+pub async fn scan_source(
+    config: &Arc<RwLock<Config>>,
+    config_persistence: &ConfigPersistence,
+    webhook_service: &WebhookService,
+    source_id: &str,
+) -> Result<DiscoveryReport, AppError> {
+    let (source, token) = {
+        let cfg = config.read().await;
+        let source = cfg
+            .watch_sources
+            .iter()
+            .find(|s| s.id == source_id)
+            .cloned()
+            .ok_or_else(|| AppError::NotFound(format!("Watch source {} not found", source_id)))?;
+        let token = source
+            .credential_id
+            .as_ref()
+            .and_then(|id| cfg.get_credential(id))
+            .and_then(|c| c.password.as_ref())
+            .map(|p| cfg.server.encryption_keys().decrypt_password(p))
+            .transpose()?;
+        (source, token)
+    };
+
+    let client = ForgeClient::new(source.provider, source.api_url.as_deref(), token)?;
+    let remote = list_remote_repositories(&client, &source.owner).await?;
+
+    let mut cfg = config.write().await;
+    let report = reconcile_source(&mut cfg, &source, &remote);
+    let config_to_save = report.has_changes().then(|| cfg.clone());
+    let subscriptions = cfg.server.subscriptions();
+    let added: Vec<Repository> = cfg
+        .repositories
+        .iter()
+        .filter(|r| report.added.contains(&r.id))
+        .cloned()
+        .collect();
+    drop(cfg); // Release lock before async operation
+
+    for repo in &added {
+        info!(
+            source = %source.id,
+            repository = %repo.id,
+            "Watch source discovered new repository"
+        );
+        webhooks::notify_event(
+            webhook_service,
+            &subscriptions,
+            NotificationEvent::RepositoryAdded,
+            Some(repo),
+            &format!("Discovered by watch source {}", source.id),
+            serde_json::json!({ "source_id": source.id }),
+        );
+    }
+    for id in &report.flagged {
+        warn!(
+            repository = %id,
+            source = %source.id,
+            "Repository was archived or deleted upstream"
+        );
+    }
+    if let Some(config_data) = config_to_save {
+        config_persistence.request_save(config_data);
+    }
+
+    Ok(report)
+}
@@ -0,0 +1,437 @@
+use crate::config::{Config, EmailConfig, Repository, SmtpTls, UpstreamStatus};
+use crate::error::AppError;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tracing::{error, info};
+
+/// Name of the file in the data directory holding the state of the last digest.
+const DIGEST_STATE_FILE: &str = "digest_state.json";
+
+/// Sends a plain text email to all configured recipients.
+///
+/// # Errors
+///
+/// Returns `AppError::EmailError` if an address is invalid, the SMTP server
+/// cannot be reached or rejects the message.
+pub async fn send_email(
+    settings: &EmailConfig,
+    subject: &str,
+    body: String,
+) -> Result<(), AppError> {
+    let parse = |address: &str| {
+        address
+            .parse::<Mailbox>()
+            .map_err(|e| AppError::EmailError(format!("Invalid address {}: {}", address, e)))
+    };
+
+    let mut builder = Message::builder()
+        .from(parse(&settings.from)?)
+        .subject(subject);
+    for recipient in &settings.recipients {
+        builder = builder.to(parse(recipient)?);
+    }
+    let message = builder
+        .body(body)
+        .map_err(|e| AppError::EmailError(e.to_string()))?;
+
+    transport(settings)?
+        .send(message)
+        .await
+        .map_err(|e| AppError::EmailError(e.to_string()))?;
+    Ok(())
+}
+
+fn transport(settings: &EmailConfig) -> Result<AsyncSmtpTransport<Tokio1Executor>, AppError> {
+    let builder = match settings.tls {
+        SmtpTls::Starttls => {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&settings.smtp_host)
+                .map_err(|e| AppError::EmailError(e.to_string()))?
+        }
+        SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&settings.smtp_host)
+            .map_err(|e| AppError::EmailError(e.to_string()))?,
+        SmtpTls::None => {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&settings.smtp_host)
+        }
+    };
+
+    let mut builder = builder
+        .port(settings.smtp_port)
+        .timeout(Some(Duration::from_secs(30)));
+    if let Some(ref username) = settings.username {
+        builder = builder.credentials(Credentials::new(
+            username.clone(),
+            settings.password.clone().unwrap_or_default(),
+        ));
+    }
+    Ok(builder.build())
+}
+
+/// Sends an email in the background. Failures are logged only.
+fn send_in_background(settings: &EmailConfig, subject: String, body: String) {
+    let settings = settings.clone();
+    tokio::spawn(async move {
+        if let Err(e) = send_email(&settings, &subject, body).await {
+            error!(subject = %subject, error = %e, "Failed to send email notification");
+        }
+    });
+}
+
+/// Emails a sync error if email notifications are enabled. Non-blocking.
+///
+/// # Arguments
+///
+/// * `settings` - Email settings, `None` if email is not configured
+/// * `repo` - The repository that encountered the error
+/// * `operation` - The operation that failed (e.g., "sync", "metadata")
+/// * `credential_id` - Optional credential ID used for the operation
+/// * `error_message` - The error message
+pub fn notify_error(
+    settings: Option<&EmailConfig>,
+    repo: &Repository,
+    operation: &str,
+    credential_id: Option<&String>,
+    error_message: &str,
+) {
+    let Some(settings) = settings.filter(|s| s.notify_errors) else {
+        return;
+    };
+
+    let subject = format!("[GitSafe] {} failed for {}", operation, repo.id);
+    let mut body = format!(
+        "The {} operation of repository {} failed at {}.\n\nRepository: {}\n",
+        operation,
+        repo.id,
+        Utc::now().format("%Y-%m-%d %H:%M UTC"),
+        repo.url
+    );
+    if let Some(credential_id) = credential_id {
+        let _ = writeln!(body, "Credential: {}", credential_id);
+    }
+    let _ = write!(body, "\nError:\n{}\n", error_message);
+
+    send_in_background(settings, subject, body);
+}
+
+/// Emails that a repository ran out of sync attempts and was disabled. Non-blocking.
+///
+/// # Arguments
+///
+/// * `settings` - Email settings, `None` if email is not configured
+/// * `repo` - The repository that ran out of attempts
+/// * `error_message` - The last error message before running out of attempts
+/// * `sync_attempts` - The number of attempts that were configured
+pub fn notify_out_of_attempts(
+    settings: Option<&EmailConfig>,
+    repo: &Repository,
+    error_message: &str,
+    sync_attempts: u32,
+) {
+    let Some(settings) = settings.filter(|s| s.notify_errors) else {
+        return;
+    };
+
+    let subject = format!("[GitSafe] {} was disabled", repo.id);
+    let body = format!(
+        "Repository {} failed {} sync attempts in a row and has been disabled. \
+         It is not backed up until it is enabled again.\n\n\
+         Repository: {}\n\nLast error:\n{}\n",
+        repo.id, sync_attempts, repo.url, error_message
+    );
+
+    send_in_background(settings, subject, body);
+}
+
+/// Repository sizes at the time of the last digest, used to report size changes.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DigestState {
+    pub generated_at: Option<DateTime<Utc>>,
+    pub sizes: HashMap<String, u64>,
+}
+
+impl DigestState {
+    /// Loads the state from the data directory; a missing or unreadable file yields an empty state.
+    pub fn load<P: AsRef<Path>>(data_dir: P) -> Self {
+        fs::read(data_dir.as_ref().join(DIGEST_STATE_FILE))
+            .ok()
+            .and_then(|content| serde_json::from_slice(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, data_dir: P) -> Result<(), AppError> {
+        fs::create_dir_all(data_dir.as_ref())?;
+        let content =
+            serde_json::to_vec_pretty(self).map_err(|e| AppError::InternalError(e.to_string()))?;
+        fs::write(data_dir.as_ref().join(DIGEST_STATE_FILE), content)?;
+        Ok(())
+    }
+}
+
+/// State of a single repository in the digest.
+#[derive(Debug, Clone)]
+pub struct DigestEntry {
+    pub id: String,
+    pub url: String,
+    pub enabled: bool,
+    pub last_sync: Option<DateTime<Utc>>,
+    pub size: Option<u64>,
+    /// Size difference since the last digest; `None` for repositories new since then
+    pub size_change: Option<i64>,
+    pub error: Option<String>,
+    pub attempts_left: Option<u32>,
+    /// Enabled but not synced successfully within `stale_after_hours`
+    pub stale: bool,
+}
+
+/// Summary of all repositories sent as the periodic digest.
+#[derive(Debug, Clone)]
+pub struct Digest {
+    pub generated_at: DateTime<Utc>,
+    /// Time of the previous digest
+    pub since: Option<DateTime<Utc>>,
+    pub stale_after_hours: u64,
+    pub repositories: Vec<DigestEntry>,
+}
+
+impl Digest {
+    /// Builds the digest from the current repository state.
+    ///
+    /// # Arguments
+    ///
+    /// * `repositories` - All configured repositories
+    /// * `previous` - State of the previous digest
+    /// * `stale_after_hours` - Age of the last successful sync after which a backup is stale
+    /// * `now` - Time of the digest
+    pub fn build(
+        repositories: &[Repository],
+        previous: &DigestState,
+        stale_after_hours: u64,
+        now: DateTime<Utc>,
+    ) -> Self {
+        let stale_before = now - ChronoDuration::hours(stale_after_hours as i64);
+        let repositories = repositories
+            .iter()
+            .map(|repo| DigestEntry {
+                id: repo.id.clone(),
+                url: repo.url.clone(),
+                enabled: repo.enabled,
+                last_sync: repo.last_sync,
+                size: repo.size,
+                size_change: repo
+                    .size
+                    .zip(previous.sizes.get(&repo.id))
+                    .map(|(size, previous)| size as i64 - *previous as i64),
+                error: repo.error.clone(),
+                attempts_left: repo.attempts_left,
+                // Repositories deleted upstream are not expected to change
+                stale: repo.enabled
+                    && repo.upstream_status != Some(UpstreamStatus::Deleted)
+                    && repo.last_sync.is_none_or(|t| t < stale_before),
+            })
+            .collect();
+
+        Digest {
+            generated_at: now,
+            since: previous.generated_at,
+            stale_after_hours,
+            repositories,
+        }
+    }
+
+    /// Repositories with a sync error, including disabled ones.
+    pub fn failures(&self) -> impl Iterator<Item = &DigestEntry> {
+        self.repositories.iter().filter(|r| r.error.is_some())
+    }
+
+    pub fn stale(&self) -> impl Iterator<Item = &DigestEntry> {
+        self.repositories.iter().filter(|r| r.stale)
+    }
+
+    /// State to compare the next digest against.
+    pub fn state(&self) -> DigestState {
+        DigestState {
+            generated_at: Some(self.generated_at),
+            sizes: self
+                .repositories
+                .iter()
+                .filter_map(|r| r.size.map(|size| (r.id.clone(), size)))
+                .collect(),
+        }
+    }
+
+    pub fn subject(&self) -> String {
+        let failures = self.failures().count();
+        let stale = self.stale().count();
+        let status = if failures == 0 && stale == 0 {
+            "all backups healthy".to_string()
+        } else {
+            format!("{} failing, {} stale", failures, stale)
+        };
+        format!(
+            "[GitSafe] Backup digest {}: {}",
+            self.generated_at.format("%Y-%m-%d"),
+            status
+        )
+    }
+
+    /// Renders the digest as a plain text email body.
+    pub fn render(&self) -> String {
+        let mut body = String::new();
+        let total_size: u64 = self.repositories.iter().filter_map(|r| r.size).sum();
+        let total_change: i64 = self.repositories.iter().filter_map(|r| r.size_change).sum();
+
+        let _ = writeln!(
+            body,
+            "GitSafe backup digest of {}",
+            self.generated_at.format("%Y-%m-%d %H:%M UTC")
+        );
+        if let Some(since) = self.since {
+            let _ = writeln!(body, "Changes since {}", since.format("%Y-%m-%d %H:%M UTC"));
+        }
+        let _ = writeln!(
+            body,
+            "\n{} repositories ({} enabled), {} total ({})",
+            self.repositories.len(),
+            self.repositories.iter().filter(|r| r.enabled).count(),
+            format_size(total_size),
+            format_size_change(total_change)
+        );
+
+        let failures: Vec<_> = self.failures().collect();
+        if !failures.is_empty() {
+            let _ = writeln!(body, "\nFailures ({}):", failures.len());
+            for repo in failures {
+                let state = if !repo.enabled {
+                    "disabled".to_string()
+                } else {
+                    match repo.attempts_left {
+                        Some(attempts) => format!("{} attempts left", attempts),
+                        None => "failing".to_string(),
+                    }
+                };
+                let _ = writeln!(
+                    body,
+                    "  - {} ({}): {}",
+                    repo.id,
+                    state,
+                    repo.error.as_deref().unwrap_or_default()
+                );
+            }
+        }
+
+        let stale: Vec<_> = self.stale().collect();
+        if !stale.is_empty() {
+            let _ = writeln!(
+                body,
+                "\nStale backups, not synced in {} hours ({}):",
+                self.stale_after_hours,
+                stale.len()
+            );
+            for repo in stale {
+                let _ = writeln!(
+                    body,
+                    "  - {} (last sync: {})",
+                    repo.id,
+                    format_time(repo.last_sync)
+                );
+            }
+        }
+
+        let _ = writeln!(body, "\nRepositories:");
+        for repo in &self.repositories {
+            let size = match (repo.size, repo.size_change) {
+                (Some(size), Some(change)) => {
+                    format!("{} ({})", format_size(size), format_size_change(change))
+                }
+                (Some(size), None) => format!("{} (new)", format_size(size)),
+                (None, _) => "not backed up".to_string(),
+            };
+            let _ = writeln!(
+                body,
+                "  - {}{}: last sync {}, {}",
+                repo.id,
+                if repo.enabled { "" } else { " [disabled]" },
+                format_time(repo.last_sync),
+                size
+            );
+        }
+
+        body
+    }
+}
+
+/// Builds the digest of all repositories, emails it and stores the state for the next one.
+///
+/// # Errors
+///
+/// Returns `AppError::ConfigError` if email or the digest is not configured, or
+/// `AppError::EmailError` if sending fails. The state is only updated once the
+/// email was sent, so size changes of a failed digest are reported in the next one.
+pub async fn send_digest(config: &Arc<RwLock<Config>>) -> Result<Digest, AppError> {
+    let (settings, repositories, data_dir) = {
+        let cfg = config.read().await;
+        (
+            cfg.server.email.clone(),
+            cfg.repositories.clone(),
+            cfg.storage.data_dir.clone(),
+        )
+    };
+    let settings =
+        settings.ok_or_else(|| AppError::ConfigError("Email is not configured".to_string()))?;
+    let digest_settings = settings
+        .digest
+        .as_ref()
+        .ok_or_else(|| AppError::ConfigError("The email digest is not configured".to_string()))?;
+
+    let previous = DigestState::load(&data_dir);
+    let digest = Digest::build(
+        &repositories,
+        &previous,
+        digest_settings.stale_after_hours,
+        Utc::now(),
+    );
+
+    send_email(&settings, &digest.subject(), digest.render()).await?;
+    digest.state().save(&data_dir)?;
+    info!(
+        repositories = digest.repositories.len(),
+        recipients = settings.recipients.len(),
+        "Sent backup digest"
+    );
+    Ok(digest)
+}
+
+fn format_time(time: Option<DateTime<Utc>>) -> String {
+    time.map(|t| t.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_else(|| "never".to_string())
+}
+
+/// Formats a size in bytes with a binary unit, e.g. `1.5 MiB`.
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+fn format_size_change(change: i64) -> String {
+    let sign = if change < 0 { "-" } else { "+" };
+    format!("{}{}", sign, format_size(change.unsigned_abs()))
+}
@@ -1,12 +1,240 @@
 use crate::error::AppError;
 use aes_gcm::{
-    aead::{Aead, AeadCore, KeyInit, OsRng},
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng},
     Aes256Gcm, Nonce,
 };
+use argon2::Argon2;
 use base64::{engine::general_purpose, Engine as _};
+use sha2::{Digest, Sha256};
+
+/// Prefix of ciphertexts in the current format:
+/// `v2:<key id>:base64(salt || nonce || ciphertext)`.
+///
+/// Ciphertexts without it are in the legacy format `base64(nonce || ciphertext)`,
+/// encrypted with a SHA-256 hash of the key.
+const VERSION_PREFIX: &str = "v2";
+
+/// Length of the random Argon2 salt of each ciphertext.
+const SALT_LEN: usize = 16;
+
+/// Length of the AES-GCM nonce.
+const NONCE_LEN: usize = 12;
+
+/// Length of the AES-GCM authentication tag.
+const TAG_LEN: usize = 16;
+
+/// The configured encryption keys: the current key, used for encrypting, and
+/// retired keys, still accepted for decrypting until everything is re-encrypted.
+#[derive(Clone)]
+pub struct EncryptionKeys {
+    current: String,
+    retired: Vec<String>,
+}
+
+impl std::fmt::Debug for EncryptionKeys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EncryptionKeys")
+            .field("current", &key_id(&self.current))
+            .field(
+                "retired",
+                &self.retired.iter().map(|k| key_id(k)).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl EncryptionKeys {
+    /// Creates the key ring of the current key and the retired keys.
+    pub fn new(current: &str, retired: &[String]) -> Self {
+        EncryptionKeys {
+            current: current.to_string(),
+            retired: retired.to_vec(),
+        }
+    }
+
+    /// ID of the current key, stored in the ciphertexts it encrypts.
+    pub fn current_key_id(&self) -> String {
+        key_id(&self.current)
+    }
+
+    /// Encrypts sensitive data (SSH key, password or TOTP secret) with the
+    /// current key using AES-256-GCM.
+    ///
+    /// The AES key is derived from the encryption key with Argon2id and a
+    /// random salt.
+    ///
+    /// # Returns
+    ///
+    /// Returns `v2:<key id>:` followed by the base64-encoded salt, nonce and ciphertext.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::InternalError` if encryption fails.
+    pub fn encrypt(&self, data: &str) -> Result<String, AppError> {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let cipher = Aes256Gcm::new(&derive_key(&self.current, &salt)?.into());
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, data.as_bytes())
+            .map_err(|e| AppError::InternalError(format!("Encryption failed: {}", e)))?;
+
+        let mut combined = salt.to_vec();
+        combined.extend_from_slice(&nonce);
+        combined.extend_from_slice(&ciphertext);
+        Ok(format!(
+            "{}:{}:{}",
+            VERSION_PREFIX,
+            self.current_key_id(),
+            general_purpose::STANDARD.encode(&combined)
+        ))
+    }
+
+    /// Decrypts data encrypted with the current or a retired key, in the
+    /// current or the legacy format.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::InternalError` if:
+    /// - The encrypted data format is invalid
+    /// - The key it was encrypted with isn't configured
+    /// - Decryption fails
+    /// - The decrypted data is not valid UTF-8
+    pub fn decrypt(&self, encrypted_data: &str) -> Result<String, AppError> {
+        let plaintext = match encrypted_data.strip_prefix(VERSION_PREFIX) {
+            Some(rest) => self.decrypt_versioned(rest)?,
+            None => self.decrypt_legacy(encrypted_data)?,
+        };
+
+        String::from_utf8(plaintext)
+            .map_err(|e| AppError::InternalError(format!("Invalid UTF-8 in decrypted data: {}", e)))
+    }
+
+    /// Decrypts a stored password.
+    ///
+    /// Passwords stored before encryption was introduced are returned as-is.
+    /// Legacy ciphertexts can't be told apart from plaintext passwords that
+    /// happen to be valid base64 (e.g. hex tokens), so a value that doesn't
+    /// decrypt in the legacy format is taken as plaintext.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::InternalError` if the password is in the current
+    /// format and can't be decrypted, e.g. because its key was removed.
+    pub fn decrypt_password(&self, stored: &str) -> Result<String, AppError> {
+        if stored.starts_with(&format!("{}:", VERSION_PREFIX)) {
+            return self.decrypt(stored);
+        }
+        Ok(self
+            .decrypt_legacy(stored)
+            .ok()
+            .and_then(|plaintext| String::from_utf8(plaintext).ok())
+            .unwrap_or_else(|| stored.to_string()))
+    }
+
+    /// Returns whether a stored secret is encrypted with the current key in the
+    /// current format, i.e. doesn't need to be re-encrypted.
+    pub fn is_current(&self, stored: &str) -> bool {
+        stored.starts_with(&format!("{}:{}:", VERSION_PREFIX, self.current_key_id()))
+    }
+
+    /// Re-encrypts a stored secret with the current key. Plaintext passwords
+    /// and SSH keys are encrypted.
+    ///
+    /// Unlike `decrypt_password`, a value that could be a legacy ciphertext
+    /// but doesn't decrypt with any configured key is an error rather than
+    /// being taken as plaintext: encrypting it would make it impossible to
+    /// recover once the missing key is configured again.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::InternalError` if the secret can't be decrypted.
+    pub fn reencrypt(&self, stored: &str) -> Result<String, AppError> {
+        let plaintext = if !stored.starts_with(&format!("{}:", VERSION_PREFIX))
+            && is_legacy_ciphertext(stored)
+        {
+            self.decrypt(stored).map_err(|_| {
+                AppError::InternalError(
+                    "Value looks like a ciphertext of an encryption key that isn't configured; \
+                     add the key to retired_encryption_keys, or set the password again if it is \
+                     stored as plaintext"
+                        .to_string(),
+                )
+            })?
+        } else {
+            self.decrypt_password(stored)?
+        };
+        self.encrypt(&plaintext)
+    }
+
+    fn keys(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.current).chain(&self.retired)
+    }
+
+    fn decrypt_versioned(&self, rest: &str) -> Result<Vec<u8>, AppError> {
+        let invalid = || AppError::InternalError("Invalid encrypted data format".to_string());
+        let (id, payload) = rest
+            .strip_prefix(':')
+            .and_then(|rest| rest.split_once(':'))
+            .ok_or_else(invalid)?;
+        let key = self.keys().find(|k| key_id(k) == id).ok_or_else(|| {
+            AppError::InternalError(format!(
+                "Data is encrypted with unknown encryption key {}",
+                id
+            ))
+        })?;
+
+        let combined = general_purpose::STANDARD
+            .decode(payload)
+            .map_err(|e| AppError::InternalError(format!("Base64 decode failed: {}", e)))?;
+        if combined.len() < SALT_LEN + NONCE_LEN {
+            return Err(invalid());
+        }
+        let (salt, rest) = combined.split_at(SALT_LEN);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+        Aes256Gcm::new(&derive_key(key, salt)?.into())
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|e| AppError::InternalError(format!("Decryption failed: {}", e)))
+    }
+
+    fn decrypt_legacy(&self, encrypted_data: &str) -> Result<Vec<u8>, AppError> {
+        let combined = general_purpose::STANDARD
+            .decode(encrypted_data)
+            .map_err(|e| AppError::InternalError(format!("Base64 decode failed: {}", e)))?;
+        if combined.len() < NONCE_LEN {
+            return Err(AppError::InternalError(
+                "Invalid encrypted data format".to_string(),
+            ));
+        }
+        let (nonce, ciphertext) = combined.split_at(NONCE_LEN);
+
+        // Legacy ciphertexts don't record their key, so all are tried
+        self.keys()
+            .find_map(|key| {
+                Aes256Gcm::new(&derive_legacy_key(key).into())
+                    .decrypt(Nonce::from_slice(nonce), ciphertext)
+                    .ok()
+            })
+            .ok_or_else(|| {
+                AppError::InternalError(
+                    "Decryption failed with all configured encryption keys".to_string(),
+                )
+            })
+    }
+}
+
+/// Returns whether a value is long enough valid base64 to be a legacy
+/// ciphertext, i.e. a nonce and an authentication tag at least.
+fn is_legacy_ciphertext(value: &str) -> bool {
+    general_purpose::STANDARD
+        .decode(value)
+        .is_ok_and(|combined| combined.len() >= NONCE_LEN + TAG_LEN)
+}
 
 /// Encrypts sensitive data (SSH key or password) using AES-256-GCM.
-/// Returns base64-encoded encrypted data with nonce prepended.
+///
+/// This is a convenience wrapper around `EncryptionKeys::encrypt` for a single key.
 ///
 /// # Arguments
 ///
@@ -15,36 +243,23 @@ use base64::{engine::general_purpose, Engine as _};
 ///
 /// # Returns
 ///
-/// Returns a base64-encoded string containing the nonce and ciphertext.
+/// Returns the versioned ciphertext.
 ///
 /// # Errors
 ///
 /// Returns `AppError::InternalError` if encryption fails.
 pub fn encrypt_data(data: &str, encryption_key: &str) -> Result<String, AppError> {
-    // Derive a 32-byte key from the encryption_key string
-    let key_bytes = derive_key(encryption_key);
-    let cipher = Aes256Gcm::new(&key_bytes.into());
-
-    // Generate a random nonce
-    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
-
-    // Encrypt the data
-    let ciphertext = cipher
-        .encrypt(&nonce, data.as_bytes())
-        .map_err(|e| AppError::InternalError(format!("Encryption failed: {}", e)))?;
-
-    // Combine nonce and ciphertext, then base64 encode
-    let mut combined = nonce.to_vec();
-    combined.extend_from_slice(&ciphertext);
-    Ok(general_purpose::STANDARD.encode(&combined))
+    EncryptionKeys::new(encryption_key, &[]).encrypt(data)
 }
 
-/// Decrypts base64-encoded encrypted data (SSH key or password).
-/// Expects the format: base64(nonce || ciphertext)
+/// Decrypts encrypted data (SSH key or password), in the current or the
+/// legacy format.
+///
+/// This is a convenience wrapper around `EncryptionKeys::decrypt` for a single key.
 ///
 /// # Arguments
 ///
-/// * `encrypted_data` - Base64-encoded encrypted data
+/// * `encrypted_data` - The encrypted data
 /// * `encryption_key` - The encryption key used for decryption
 ///
 /// # Returns
@@ -54,41 +269,14 @@ pub fn encrypt_data(data: &str, encryption_key: &str) -> Result<String, AppError
 /// # Errors
 ///
 /// Returns `AppError::InternalError` if:
-/// - Base64 decoding fails
 /// - The encrypted data format is invalid
 /// - Decryption fails
 /// - The decrypted data is not valid UTF-8
 pub fn decrypt_data(encrypted_data: &str, encryption_key: &str) -> Result<String, AppError> {
-    // Decode base64
-    let combined = general_purpose::STANDARD
-        .decode(encrypted_data)
-        .map_err(|e| AppError::InternalError(format!("Base64 decode failed: {}", e)))?;
-
-    // Extract nonce (first 12 bytes for AES-GCM)
-    if combined.len() < 12 {
-        return Err(AppError::InternalError(
-            "Invalid encrypted data format".to_string(),
-        ));
-    }
-
-    let nonce = Nonce::from_slice(&combined[..12]);
-    let ciphertext = &combined[12..];
-
-    // Derive the same key
-    let key_bytes = derive_key(encryption_key);
-    let cipher = Aes256Gcm::new(&key_bytes.into());
-
-    // Decrypt
-    let plaintext = cipher
-        .decrypt(nonce, ciphertext)
-        .map_err(|e| AppError::InternalError(format!("Decryption failed: {}", e)))?;
-
-    String::from_utf8(plaintext)
-        .map_err(|e| AppError::InternalError(format!("Invalid UTF-8 in decrypted data: {}", e)))
+    EncryptionKeys::new(encryption_key, &[]).decrypt(encrypted_data)
 }
 
 /// Encrypts SSH key content using AES-256-GCM.
-/// Returns base64-encoded encrypted data with nonce prepended.
 ///
 /// This is a convenience wrapper around `encrypt_data` for SSH keys.
 ///
@@ -99,19 +287,18 @@ pub fn decrypt_data(encrypted_data: &str, encryption_key: &str) -> Result<String
 ///
 /// # Returns
 ///
-/// Returns a base64-encoded string containing the nonce and ciphertext.
+/// Returns the versioned ciphertext.
 pub fn encrypt_ssh_key(key_content: &str, encryption_key: &str) -> Result<String, AppError> {
     encrypt_data(key_content, encryption_key)
 }
 
-/// Decrypts base64-encoded encrypted SSH key.
-/// Expects the format: base64(nonce || ciphertext)
+/// Decrypts an encrypted SSH key.
 ///
 /// This is a convenience wrapper around `decrypt_data` for SSH keys.
 ///
 /// # Arguments
 ///
-/// * `encrypted_data` - Base64-encoded encrypted SSH key
+/// * `encrypted_data` - Encrypted SSH key
 /// * `encryption_key` - The encryption key used for decryption
 ///
 /// # Returns
@@ -122,7 +309,6 @@ pub fn decrypt_ssh_key(encrypted_data: &str, encryption_key: &str) -> Result<Str
 }
 
 /// Encrypts password using AES-256-GCM.
-/// Returns base64-encoded encrypted data with nonce prepended.
 ///
 /// This is a convenience wrapper around `encrypt_data` for passwords.
 ///
@@ -133,34 +319,53 @@ pub fn decrypt_ssh_key(encrypted_data: &str, encryption_key: &str) -> Result<Str
 ///
 /// # Returns
 ///
-/// Returns a base64-encoded string containing the nonce and ciphertext.
+/// Returns the versioned ciphertext.
 pub fn encrypt_password(password: &str, encryption_key: &str) -> Result<String, AppError> {
     encrypt_data(password, encryption_key)
 }
 
-/// Decrypts base64-encoded encrypted password.
-/// Expects the format: base64(nonce || ciphertext)
+/// Decrypts an encrypted password.
 ///
-/// This is a convenience wrapper around `decrypt_data` for passwords.
-/// Also handles backward compatibility: if decryption fails, assumes the
-/// password is stored in plaintext and returns it as-is.
+/// This is a convenience wrapper around `EncryptionKeys::decrypt_password` for
+/// a single key: plaintext passwords are returned as-is for backward
+/// compatibility, but versioned ciphertexts that fail to decrypt are an error.
 ///
 /// # Arguments
 ///
-/// * `encrypted_data` - Base64-encoded encrypted password (or plaintext for backward compatibility)
+/// * `encrypted_data` - Encrypted password (or plaintext for backward compatibility)
 /// * `encryption_key` - The encryption key used for decryption
 ///
 /// # Returns
 ///
-/// Returns the decrypted password, or the original string if decryption fails
-/// (for backward compatibility with plaintext passwords).
-pub fn decrypt_password(encrypted_data: &str, encryption_key: &str) -> String {
-    decrypt_data(encrypted_data, encryption_key).unwrap_or_else(|_| encrypted_data.to_string())
+/// Returns the decrypted password.
+///
+/// # Errors
+///
+/// Returns `AppError::InternalError` if the password is a versioned ciphertext
+/// and can't be decrypted.
+pub fn decrypt_password(encrypted_data: &str, encryption_key: &str) -> Result<String, AppError> {
+    EncryptionKeys::new(encryption_key, &[]).decrypt_password(encrypted_data)
+}
+
+/// Short, non-secret ID of a key: the start of its domain-separated SHA-256 hash.
+fn key_id(key: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(b"gitsafe-encryption-key-id:");
+    hasher.update(key.as_bytes());
+    hex::encode(&hasher.finalize()[..4])
+}
+
+/// Derives a 32-byte key from a string and salt using Argon2id
+fn derive_key(key: &str, salt: &[u8]) -> Result<[u8; 32], AppError> {
+    let mut key_bytes = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(key.as_bytes(), salt, &mut key_bytes)
+        .map_err(|e| AppError::InternalError(format!("Key derivation failed: {}", e)))?;
+    Ok(key_bytes)
 }
 
-/// Derives a 32-byte key from a string using SHA-256
-fn derive_key(key: &str) -> [u8; 32] {
-    use sha2::{Digest, Sha256};
+/// Derives a 32-byte key from a string using SHA-256, as legacy ciphertexts were
+fn derive_legacy_key(key: &str) -> [u8; 32] {
     let mut hasher = Sha256::new();
     hasher.update(key.as_bytes());
     let hash = hasher.finalize();
@@ -1,4 +1,8 @@
-use actix_web::{error::ResponseError, http::StatusCode, HttpResponse};
+use actix_web::{
+    error::ResponseError,
+    http::{header, StatusCode},
+    HttpResponse,
+};
 use thiserror::Error;
 
 /// Application error types used throughout the GitSafe application.
@@ -20,12 +24,28 @@ pub enum AppError {
     #[error("Authentication error: {0}")]
     AuthError(String),
 
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
     #[error("Not found: {0}")]
     NotFound(String),
 
     #[error("Bad request: {0}")]
     BadRequest(String),
 
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    #[error("Forge API error: {0}")]
+    ForgeError(String),
+
+    /// Rate limit or login lockout, with the seconds until the client may retry
+    #[error("Too many requests: {0}")]
+    TooManyRequests(String, u64),
+
+    #[error("Email error: {0}")]
+    EmailError(String),
+
     #[error("Internal error: {0}")]
     InternalError(String),
 }
@@ -36,12 +56,23 @@ impl ResponseError for AppError {
             AppError::AuthError(_) => HttpResponse::Unauthorized().json(serde_json::json!({
                 "error": self.to_string()
             })),
+            AppError::Forbidden(_) => HttpResponse::Forbidden().json(serde_json::json!({
+                "error": self.to_string()
+            })),
             AppError::NotFound(_) => HttpResponse::NotFound().json(serde_json::json!({
                 "error": self.to_string()
             })),
             AppError::BadRequest(_) => HttpResponse::BadRequest().json(serde_json::json!({
                 "error": self.to_string()
             })),
+            AppError::Conflict(_) => HttpResponse::Conflict().json(serde_json::json!({
+                "error": self.to_string()
+            })),
+            AppError::TooManyRequests(_, retry_after) => HttpResponse::TooManyRequests()
+                .insert_header((header::RETRY_AFTER, retry_after.to_string()))
+                .json(serde_json::json!({
+                    "error": self.to_string()
+                })),
             _ => HttpResponse::InternalServerError().json(serde_json::json!({
                 "error": self.to_string()
             })),
@@ -51,8 +82,11 @@ impl ResponseError for AppError {
     fn status_code(&self) -> StatusCode {
         match self {
             AppError::AuthError(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
             AppError::NotFound(_) => StatusCode::NOT_FOUND,
             AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::TooManyRequests(..) => StatusCode::TOO_MANY_REQUESTS,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -0,0 +1,219 @@
+use crate::error::AppError;
+use reqwest::{RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// Upper bound on the number of pages fetched from a paginated endpoint.
+const MAX_PAGES: u32 = 1000;
+
+/// Supported Git forges.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ForgeProvider {
+    Github,
+    Gitlab,
+    Gitea,
+}
+
+impl ForgeProvider {
+    /// Returns the public API base URL of the provider, if there is one.
+    ///
+    /// Gitea is always self-hosted, so an explicit API URL is required.
+    pub fn default_api_url(&self) -> Option<&'static str> {
+        match self {
+            ForgeProvider::Github => Some("https://api.github.com"),
+            ForgeProvider::Gitlab => Some("https://gitlab.com/api/v4"),
+            ForgeProvider::Gitea => None,
+        }
+    }
+
+    /// Query parameters used to request a page of `page` from a paginated endpoint.
+    fn page_query(&self, page: u32) -> [(&'static str, String); 2] {
+        match self {
+            // Gitea caps `limit` at 50 by default
+            ForgeProvider::Gitea => [("page", page.to_string()), ("limit", "50".to_string())],
+            _ => [("page", page.to_string()), ("per_page", "100".to_string())],
+        }
+    }
+}
+
+/// Thin authenticated REST client for a forge API.
+///
+/// Handles the provider specific authentication header, pagination and
+/// treats `404 Not Found` and `410 Gone` as a missing resource rather than an error.
+#[derive(Clone)]
+pub struct ForgeClient {
+    provider: ForgeProvider,
+    api_url: String,
+    token: Option<String>,
+    client: reqwest::Client,
+}
+
+impl ForgeClient {
+    /// Creates a new ForgeClient.
+    ///
+    /// # Arguments
+    ///
+    /// * `provider` - The forge type
+    /// * `api_url` - API base URL, or `None` to use the provider default
+    /// * `token` - Optional access token
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::ConfigError` if no API URL is given for a provider
+    /// without a public default (Gitea).
+    pub fn new(
+        provider: ForgeProvider,
+        api_url: Option<&str>,
+        token: Option<String>,
+    ) -> Result<Self, AppError> {
+        let api_url = api_url
+            .or(provider.default_api_url())
+            .ok_or_else(|| {
+                AppError::ConfigError(format!("api_url is required for provider {:?}", provider))
+            })?
+            .trim_end_matches('/')
+            .to_string();
+
+        Ok(ForgeClient {
+            provider,
+            api_url,
+            token: token.filter(|t| !t.is_empty()),
+            client: reqwest::Client::new(),
+        })
+    }
+
+    pub fn provider(&self) -> ForgeProvider {
+        self.provider
+    }
+
+    /// Builds a GET request with authentication for an absolute URL.
+    pub fn get(&self, url: &str) -> RequestBuilder {
+        let mut request = self
+            .client
+            .get(url)
+            .header("User-Agent", "gitsafe")
+            .timeout(std::time::Duration::from_secs(30));
+
+        if let Some(ref token) = self.token {
+            request = match self.provider {
+                ForgeProvider::Github => request.bearer_auth(token),
+                ForgeProvider::Gitlab => request.header("PRIVATE-TOKEN", token),
+                ForgeProvider::Gitea => request.header("Authorization", format!("token {}", token)),
+            };
+        }
+
+        request
+    }
+
+    /// Fetches a JSON document from an API path (relative to the API base URL).
+    ///
+    /// Returns `Ok(None)` if the resource does not exist.
+    pub async fn get_json<T: DeserializeOwned>(&self, path: &str) -> Result<Option<T>, AppError> {
+        let url = format!("{}{}", self.api_url, path);
+        let response = self
+            .get(&url)
+            .send()
+            .await
+            .map_err(|e| AppError::ForgeError(format!("Request to {} failed: {}", url, e)))?;
+
+        if is_missing(response.status()) {
+            return Ok(None);
+        }
+        let response = Self::check_status(&url, response).await?;
+
+        response
+            .json()
+            .await
+            .map(Some)
+            .map_err(|e| AppError::ForgeError(format!("Invalid response from {}: {}", url, e)))
+    }
+
+    /// Fetches all pages of a paginated JSON list from an API path.
+    ///
+    /// Returns `Ok(None)` if the resource does not exist.
+    pub async fn get_paginated<T: DeserializeOwned>(
+        &self,
+        path: &str,
+    ) -> Result<Option<Vec<T>>, AppError> {
+        let url = format!("{}{}", self.api_url, path);
+        let mut items = Vec::new();
+
+        for page in 1..=MAX_PAGES {
+            let response = self
+                .get(&url)
+                .query(&self.provider.page_query(page))
+                .send()
+                .await
+                .map_err(|e| AppError::ForgeError(format!("Request to {} failed: {}", url, e)))?;
+
+            if is_missing(response.status()) && page == 1 {
+                return Ok(None);
+            }
+            let response = Self::check_status(&url, response).await?;
+
+            let page_items: Vec<T> = response.json().await.map_err(|e| {
+                AppError::ForgeError(format!("Invalid response from {}: {}", url, e))
+            })?;
+            if page_items.is_empty() {
+                break;
+            }
+            items.extend(page_items);
+        }
+
+        Ok(Some(items))
+    }
+
+    /// Downloads a file, e.g. a release asset.
+    ///
+    /// The access token is only sent if the URL is on the same host as the API,
+    /// so external asset links don't receive it.
+    pub async fn download(&self, url: &str) -> Result<Vec<u8>, AppError> {
+        let same_host = match (url::Url::parse(url), url::Url::parse(&self.api_url)) {
+            (Ok(target), Ok(api)) => target.origin() == api.origin(),
+            _ => false,
+        };
+        let request = if same_host {
+            self.get(url)
+        } else {
+            self.client.get(url).header("User-Agent", "gitsafe")
+        };
+
+        let response = request
+            // GitHub serves the asset itself instead of its JSON description
+            .header("Accept", "application/octet-stream")
+            .timeout(std::time::Duration::from_secs(600))
+            .send()
+            .await
+            .map_err(|e| AppError::ForgeError(format!("Request to {} failed: {}", url, e)))?;
+        let response = Self::check_status(url, response).await?;
+
+        response
+            .bytes()
+            .await
+            .map(|bytes| bytes.to_vec())
+            .map_err(|e| AppError::ForgeError(format!("Download of {} failed: {}", url, e)))
+    }
+
+    async fn check_status(
+        url: &str,
+        response: reqwest::Response,
+    ) -> Result<reqwest::Response, AppError> {
+        if response.status().is_success() {
+            Ok(response)
+        } else {
+            Err(AppError::ForgeError(format!(
+                "{} returned status {}: {}",
+                url,
+                response.status(),
+                response.text().await.unwrap_or_default()
+            )))
+        }
+    }
+}
+
+/// Forges answer `404 Not Found` for missing resources and `410 Gone` for disabled
+/// features (e.g. GitHub issues).
+fn is_missing(status: StatusCode) -> bool {
+    status == StatusCode::NOT_FOUND || status == StatusCode::GONE
+}
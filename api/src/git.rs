@@ -1,5 +1,5 @@
 use crate::config::{Credential, Repository};
-use crate::encryption;
+use crate::encryption::EncryptionKeys;
 use crate::error::AppError;
 use flate2::read::GzDecoder;
 use flate2::write::GzEncoder;
@@ -8,10 +8,13 @@ use git2::{
     build::RepoBuilder, Cred, CredentialType, FetchOptions, RemoteCallbacks,
     Repository as GitRepository,
 };
-use log::info;
+use std::collections::HashSet;
 use std::fs::{self, File};
+use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use tar::{Archive, Builder};
+use tracing::{debug, info, info_span, instrument};
 use uuid::Uuid;
 
 /// Result of a repository sync operation.
@@ -45,6 +48,25 @@ pub struct SyncResult {
 pub struct GitService {
     archive_dir: PathBuf,
     compact: bool,
+    /// IDs of the repositories being synced, shared by all clones of the service
+    syncing: Arc<Mutex<HashSet<String>>>,
+}
+
+/// Marks a repository as syncing until dropped, also when the sync panics.
+///
+/// Returned by `GitService::begin_sync`.
+pub struct SyncGuard {
+    syncing: Arc<Mutex<HashSet<String>>>,
+    repository_id: String,
+}
+
+impl Drop for SyncGuard {
+    fn drop(&mut self) {
+        self.syncing
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&self.repository_id);
+    }
 }
 
 impl GitService {
@@ -75,9 +97,33 @@ impl GitService {
         Ok(GitService {
             archive_dir,
             compact,
+            syncing: Arc::new(Mutex::new(HashSet::new())),
         })
     }
 
+    /// Marks a repository as syncing until the returned guard is dropped.
+    ///
+    /// Returns `None` if it is already syncing: concurrent syncs of a repository
+    /// would clash in its working directory.
+    pub fn begin_sync(&self, repository_id: &str) -> Option<SyncGuard> {
+        self.syncing
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(repository_id.to_string())
+            .then(|| SyncGuard {
+                syncing: Arc::clone(&self.syncing),
+                repository_id: repository_id.to_string(),
+            })
+    }
+
+    /// Returns whether a repository is syncing.
+    pub fn is_syncing(&self, repository_id: &str) -> bool {
+        self.syncing
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains(repository_id)
+    }
+
     /// Generates a repository ID from a Git URL (for use as identifier).
     ///
     /// The ID is constructed by:
@@ -313,7 +359,7 @@ impl GitService {
     ///
     /// * `repo` - The repository configuration to sync
     /// * `credential` - Optional credential for authenticated access
-    /// * `encryption_key` - Key used to decrypt SSH keys if they are encrypted
+    /// * `encryption_keys` - Keys used to decrypt SSH keys if they are encrypted
     ///
     /// # Returns
     ///
@@ -338,6 +384,7 @@ impl GitService {
     /// ```no_run
     /// use gitsafe::git::GitService;
     /// use gitsafe::config::Repository;
+    /// use gitsafe::encryption::EncryptionKeys;
     ///
     /// let service = GitService::new("./archives", true)?;
     /// let repo = Repository {
@@ -351,28 +398,34 @@ impl GitService {
     ///     error: None,
     ///     size: None,
     ///     attempts_left: None,
+    ///     source_id: None,
+    ///     upstream_status: None,
+    ///     metadata: None,
+    ///     tags: Vec::new(),
+    ///     max_backup_age_hours: None,
     /// };
-    /// let result = service.sync_repository(&repo, None, "encryption-key")?;
+    /// let result = service.sync_repository(&repo, None, &EncryptionKeys::new("encryption-key", &[]))?;
     /// # Ok::<(), Box<dyn std::error::Error>>(())
     /// ```
+    #[instrument(skip_all, fields(repository = %repo.id, url = %repo.url, compact = self.compact))]
     pub fn sync_repository(
         &self,
         repo: &Repository,
         credential: Option<&Credential>,
-        encryption_key: &str,
+        encryption_keys: &EncryptionKeys,
     ) -> Result<SyncResult, AppError> {
-        info!("Syncing repository: {} ({})", repo.id, repo.url);
+        info!("Syncing repository");
 
         // Check if repository is already up-to-date by comparing commit hashes
         if repo.last_sync_commit_hash.is_some() {
-            match self.get_latest_commit_hash(&repo.url, credential, encryption_key) {
+            match self.get_latest_commit_hash(&repo.url, credential, encryption_keys) {
                 Ok((remote_hash, remote_message)) => {
                     // If we have a stored hash and it matches, skip the sync
                     if let Some(ref stored_hash) = repo.last_sync_commit_hash {
                         if stored_hash == &remote_hash {
                             info!(
-                                "Repository {} is already up-to-date (commit: {}), skipping sync",
-                                repo.id, remote_hash
+                                commit = %remote_hash,
+                                "Repository is already up-to-date, skipping sync"
                             );
 
                             // Use repo_path_from_url for storage paths (with slashes)
@@ -401,8 +454,8 @@ impl GitService {
                     // If we can't fetch the remote hash, log a warning but continue with sync
                     // This might happen if the repository is new or network issues occur
                     info!(
-                        "Could not fetch remote commit hash for {}: {}. Proceeding with sync.",
-                        repo.id, e
+                        error = %e,
+                        "Could not fetch remote commit hash, proceeding with sync"
                     );
                 }
             }
@@ -413,9 +466,78 @@ impl GitService {
         let repo_path = Self::repo_path_from_url(&repo.url, self.compact);
 
         if self.compact {
-            self.sync_repository_compact(repo, &repo_path, credential, encryption_key)
+            self.sync_repository_compact(repo, &repo_path, credential, encryption_keys)
         } else {
-            self.sync_repository_non_compact(repo, &repo_path, credential, encryption_key)
+            self.sync_repository_non_compact(repo, &repo_path, credential, encryption_keys)
+        }
+    }
+
+    /// Returns true if repositories are stored as compressed tarballs.
+    pub fn is_compact(&self) -> bool {
+        self.compact
+    }
+
+    /// Returns the storage path of the forge metadata backup of a repository.
+    ///
+    /// The metadata is stored next to the code backup: `<repo>.metadata.tar.gz`
+    /// in compact mode, `<repo>.metadata/` in non-compact mode.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use gitsafe::git::GitService;
+    ///
+    /// let service = GitService::new("./archives", true)?;
+    /// let path = service.metadata_path_from_url("https://github.com/example/repo1.git");
+    /// assert!(path.ends_with("github_com/example/repo1.metadata.tar.gz"));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn metadata_path_from_url(&self, url: &str) -> PathBuf {
+        let base_path = Self::repo_path_from_url(url, false);
+        let metadata_path = if self.compact {
+            format!("{}.metadata.tar.gz", base_path)
+        } else {
+            format!("{}.metadata", base_path)
+        };
+        self.archive_dir.join(metadata_path)
+    }
+
+    /// Clones a repository into `repo_path`, or pulls the latest changes if it
+    /// was cloned there before.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the repository used in log messages
+    /// * `url` - The Git repository URL
+    /// * `repo_path` - Working copy location
+    /// * `credential` - Optional credential for authenticated access
+    /// * `encryption_keys` - Keys used to decrypt SSH keys and passwords if encrypted
+    ///
+    /// # Returns
+    ///
+    /// Returns the opened repository, or `AppError::GitError` if cloning or pulling fails.
+    pub fn clone_or_pull(
+        &self,
+        name: &str,
+        url: &str,
+        repo_path: &Path,
+        credential: Option<&Credential>,
+        encryption_keys: &EncryptionKeys,
+    ) -> Result<GitRepository, AppError> {
+        if repo_path.exists() && repo_path.join(".git").exists() {
+            // Repository exists, pull updates
+            info!(repository = name, "Pulling updates");
+            let git_repo = GitRepository::open(repo_path)
+                .map_err(|e| AppError::GitError(format!("Failed to open repository: {}", e)))?;
+            self.pull_repository(&git_repo, credential, encryption_keys)?;
+            Ok(git_repo)
+        } else {
+            // Clone new repository
+            info!(repository = name, "Cloning repository");
+            self.clone_repository(url, repo_path, credential, encryption_keys)?;
+            GitRepository::open(repo_path).map_err(|e| {
+                AppError::GitError(format!("Failed to open repository after clone: {}", e))
+            })
         }
     }
 
@@ -427,7 +549,7 @@ impl GitService {
     /// # Arguments
     ///
     /// * `credential` - Optional credential for authenticated access
-    /// * `encryption_key` - Key used to decrypt SSH keys and passwords if encrypted
+    /// * `encryption_keys` - Keys used to decrypt SSH keys and passwords if encrypted
     ///
     /// # Returns
     ///
@@ -437,7 +559,7 @@ impl GitService {
     fn create_remote_callbacks(
         &self,
         credential: Option<&Credential>,
-        encryption_key: &str,
+        encryption_keys: &EncryptionKeys,
     ) -> Result<RemoteCallbacks, AppError> {
         let mut callbacks = RemoteCallbacks::new();
 
@@ -447,7 +569,8 @@ impl GitService {
             let password = cred
                 .password
                 .as_ref()
-                .map(|p| encryption::decrypt_password(p, encryption_key))
+                .map(|p| encryption_keys.decrypt_password(p))
+                .transpose()?
                 .unwrap_or_default();
 
             // Decrypt SSH key if encrypted
@@ -457,7 +580,7 @@ impl GitService {
                     Some(key_data.clone())
                 } else {
                     // Likely encrypted - try to decrypt
-                    match encryption::decrypt_ssh_key(key_data, encryption_key) {
+                    match encryption_keys.decrypt(key_data) {
                         Ok(decrypted) => Some(decrypted),
                         Err(_) => {
                             // If decryption fails, return error - SSH keys must be valid content
@@ -529,14 +652,13 @@ impl GitService {
             .name()
             .ok_or_else(|| AppError::GitError("Failed to get branch name".to_string()))?;
 
-        info!("Current branch: {}", branch_name);
+        debug!(branch = branch_name, "Reading local commit");
 
         // Strategy 1: Use FETCH_HEAD first (same as pull_repository uses)
         // This is the most reliable since pull_repository uses FETCH_HEAD to get the fetched commit
         // FETCH_HEAD now contains only the current branch's commit since we fetch only that branch
         let commit = match git_repo.find_reference("FETCH_HEAD") {
             Ok(fetch_head) => {
-                info!("Using FETCH_HEAD (same as pull_repository)");
                 // Use the exact same approach as pull_repository:
                 // reference_to_annotated_commit then find_commit
                 let fetch_commit = git_repo
@@ -550,15 +672,11 @@ impl GitService {
                 let commit_obj = git_repo.find_commit(fetch_commit.id()).map_err(|e| {
                     AppError::GitError(format!("Failed to find commit from FETCH_HEAD: {}", e))
                 })?;
-                info!("FETCH_HEAD points to commit: {}", commit_obj.id());
+                debug!(commit = %commit_obj.id(), "Using FETCH_HEAD");
                 commit_obj
             }
             Err(_) => {
                 // Strategy 2: Use the branch reference (updated by fetch)
-                info!(
-                    "FETCH_HEAD not available, using branch reference: {}",
-                    branch_name
-                );
                 match git_repo.find_reference(branch_name) {
                     Ok(branch_ref) => {
                         let branch_commit = branch_ref.peel_to_commit().map_err(|e| {
@@ -567,20 +685,22 @@ impl GitService {
                                 branch_name, e
                             ))
                         })?;
-                        info!(
-                            "Branch {} points to commit: {}",
-                            branch_name,
-                            branch_commit.id()
+                        debug!(
+                            branch = branch_name,
+                            commit = %branch_commit.id(),
+                            "FETCH_HEAD not available, using branch reference"
                         );
                         branch_commit
                     }
                     Err(_) => {
                         // Strategy 3: Fall back to HEAD
-                        info!("Branch reference not found, using HEAD");
                         let head_commit = head.peel_to_commit().map_err(|e| {
                             AppError::GitError(format!("Failed to peel HEAD to commit: {}", e))
                         })?;
-                        info!("HEAD points to commit: {}", head_commit.id());
+                        debug!(
+                            commit = %head_commit.id(),
+                            "Branch reference not found, using HEAD"
+                        );
                         head_commit
                     }
                 }
@@ -596,7 +716,7 @@ impl GitService {
             .unwrap_or("(no message)")
             .to_string();
 
-        log::debug!("Retrieved commit: {} - {}", commit_hash, commit_message);
+        debug!(commit = %commit_hash, message = %commit_message, "Retrieved commit");
         Ok((commit_hash, commit_message))
     }
 
@@ -615,7 +735,7 @@ impl GitService {
     /// * `repo` - The repository configuration
     /// * `repo_path_str` - The generated repository path (from URL, includes .tar.gz extension)
     /// * `credential` - Optional credential for authentication
-    /// * `encryption_key` - Key for decrypting SSH keys
+    /// * `encryption_keys` - Keys for decrypting SSH keys
     ///
     /// # Returns
     ///
@@ -625,7 +745,7 @@ impl GitService {
         repo: &Repository,
         repo_path_str: &str,
         credential: Option<&Credential>,
-        encryption_key: &str,
+        encryption_keys: &EncryptionKeys,
     ) -> Result<SyncResult, AppError> {
         // repo_path_str already includes .tar.gz extension from repo_path_from_url
         let archive_path = self.archive_dir.join(repo_path_str);
@@ -649,48 +769,18 @@ impl GitService {
 
         // If archive exists, unpack it first
         if archive_path.exists() {
-            info!("Unpacking existing archive for repository: {}", repo.id);
             self.unpack_archive(&archive_path, work_dir)?;
         }
 
         // Clone or pull the repository
-        let git_repo = if repo_path.exists() && repo_path.join(".git").exists() {
-            // Repository exists, pull updates
-            info!("Pulling updates for repository: {}", repo.id);
-            let git_repo = GitRepository::open(&repo_path)
-                .map_err(|e| AppError::GitError(format!("Failed to open repository: {}", e)))?;
-            self.pull_repository(&git_repo, credential, encryption_key)?;
-            git_repo
-        } else {
-            // Clone new repository
-            info!("Cloning repository: {}", repo.id);
-            self.clone_repository(&repo.url, &repo_path, credential, encryption_key)?;
-            GitRepository::open(&repo_path).map_err(|e| {
-                AppError::GitError(format!("Failed to open repository after clone: {}", e))
-            })?
-        };
+        let git_repo =
+            self.clone_or_pull(&repo.id, &repo.url, &repo_path, credential, encryption_keys)?;
 
         // Get commit hash and message from the synced repository
         let (commit_hash, commit_message) = self.get_local_commit_info(&git_repo)?;
 
         // Create new archive (use repo_name_only for archive contents, but store at repo_name path)
-        // Pass the final archive path so temp archive is created in the same directory
-        let new_archive_path = self.create_archive(repo_name_only, &repo_path, &archive_path)?;
-
-        // Calculate archive size
-        let archive_size = fs::metadata(&new_archive_path)
-            .map(|m| m.len())
-            .unwrap_or(0);
-
-        // Replace old archive with new one
-        // Ensure parent directory exists before moving
-        if let Some(parent) = archive_path.parent() {
-            fs::create_dir_all(parent)?;
-        }
-        if archive_path.exists() {
-            fs::remove_file(&archive_path)?;
-        }
-        fs::rename(&new_archive_path, &archive_path)?;
+        let archive_size = self.replace_archive(repo_name_only, &repo_path, &archive_path)?;
 
         // Clean up repo folder
         if repo_path.exists() {
@@ -698,8 +788,10 @@ impl GitService {
         }
 
         info!(
-            "Created archive: {:?} ({} bytes), commit: {}",
-            archive_path, archive_size, commit_hash
+            path = %archive_path.display(),
+            size = archive_size,
+            commit = %commit_hash,
+            "Created archive"
         );
         Ok(SyncResult {
             path: archive_path,
@@ -724,7 +816,7 @@ impl GitService {
     /// * `repo` - The repository configuration
     /// * `repo_path_str` - The generated repository path (from URL, directory path without .tar.gz)
     /// * `credential` - Optional credential for authentication
-    /// * `encryption_key` - Key for decrypting SSH keys
+    /// * `encryption_keys` - Keys for decrypting SSH keys
     ///
     /// # Returns
     ///
@@ -734,7 +826,7 @@ impl GitService {
         repo: &Repository,
         repo_path_str: &str,
         credential: Option<&Credential>,
-        encryption_key: &str,
+        encryption_keys: &EncryptionKeys,
     ) -> Result<SyncResult, AppError> {
         // Create nested directory structure for repository
         let repo_path = self.archive_dir.join(repo_path_str);
@@ -745,31 +837,21 @@ impl GitService {
         }
 
         // Clone or pull the repository
-        let git_repo = if repo_path.exists() && repo_path.join(".git").exists() {
-            // Repository exists, pull updates
-            info!("Pulling updates for repository: {}", repo.id);
-            let git_repo = GitRepository::open(&repo_path)
-                .map_err(|e| AppError::GitError(format!("Failed to open repository: {}", e)))?;
-            self.pull_repository(&git_repo, credential, encryption_key)?;
-            git_repo
-        } else {
-            // Clone new repository
-            info!("Cloning repository: {}", repo.id);
-            self.clone_repository(&repo.url, &repo_path, credential, encryption_key)?;
-            GitRepository::open(&repo_path).map_err(|e| {
-                AppError::GitError(format!("Failed to open repository after clone: {}", e))
-            })?
-        };
+        let git_repo =
+            self.clone_or_pull(&repo.id, &repo.url, &repo_path, credential, encryption_keys)?;
 
         // Get commit hash and message from the synced repository
         let (commit_hash, commit_message) = self.get_local_commit_info(&git_repo)?;
 
         // Calculate cumulative folder size
-        let folder_size = self.calculate_folder_size(&repo_path)?;
+        let folder_size =
+            info_span!("folder_size").in_scope(|| self.calculate_folder_size(&repo_path))?;
 
         info!(
-            "Synced repository to folder: {:?} ({} bytes), commit: {}",
-            repo_path, folder_size, commit_hash
+            path = %repo_path.display(),
+            size = folder_size,
+            commit = %commit_hash,
+            "Synced repository to folder"
         );
         Ok(SyncResult {
             path: repo_path,
@@ -790,7 +872,7 @@ impl GitService {
     ///
     /// * `url` - The Git repository URL
     /// * `credential` - Optional credential for authenticated access
-    /// * `encryption_key` - Key used to decrypt SSH keys if encrypted
+    /// * `encryption_keys` - Keys used to decrypt SSH keys if encrypted
     ///
     /// # Returns
     ///
@@ -803,13 +885,14 @@ impl GitService {
     /// - Authentication fails
     /// - Network connection fails
     /// - Remote HEAD reference cannot be found
+    #[instrument(name = "probe_remote", skip_all)]
     fn get_latest_commit_hash(
         &self,
         url: &str,
         credential: Option<&Credential>,
-        encryption_key: &str,
+        encryption_keys: &EncryptionKeys,
     ) -> Result<(String, String), AppError> {
-        log::debug!("Getting latest commit hash for repository: {}", url);
+        debug!("Getting latest commit hash");
 
         // Create a temporary bare repository
         let temp_dir = tempfile::tempdir().map_err(AppError::IoError)?;
@@ -822,7 +905,7 @@ impl GitService {
             .map_err(|e| AppError::GitError(format!("Failed to create remote: {}", e)))?;
 
         // Set up callbacks for authentication
-        let callbacks = self.create_remote_callbacks(credential, encryption_key)?;
+        let callbacks = self.create_remote_callbacks(credential, encryption_keys)?;
 
         // Set up fetch options with callbacks
         let mut fetch_options = FetchOptions::new();
@@ -864,7 +947,7 @@ impl GitService {
                 }
             }
         } else {
-            info!("No HEAD symref found, trying to find default branch");
+            debug!("No HEAD symref found, trying to find default branch");
         }
 
         // If we still don't have a commit, try common default branch names
@@ -898,7 +981,7 @@ impl GitService {
         // Now fetch the commit to get its message
         // We need to fetch the commit object to get the message
         // Recreate callbacks and fetch options for the second fetch
-        let callbacks2 = self.create_remote_callbacks(credential, encryption_key)?;
+        let callbacks2 = self.create_remote_callbacks(credential, encryption_keys)?;
         let mut fetch_options2 = FetchOptions::new();
         fetch_options2.remote_callbacks(callbacks2);
 
@@ -921,11 +1004,7 @@ impl GitService {
             .unwrap_or("(no message)")
             .to_string();
 
-        log::debug!(
-            "Latest commit hash: {} and message: {}",
-            commit_oid,
-            commit_message
-        );
+        debug!(commit = %commit_oid, message = %commit_message, "Latest remote commit");
         Ok((commit_oid.to_string(), commit_message))
     }
 
@@ -941,7 +1020,7 @@ impl GitService {
     /// * `url` - The Git repository URL to clone
     /// * `repo_path` - Local path where the repository should be cloned
     /// * `credential` - Optional credential for authenticated access
-    /// * `encryption_key` - Key used to decrypt SSH keys if encrypted
+    /// * `encryption_keys` - Keys used to decrypt SSH keys if encrypted
     ///
     /// # Returns
     ///
@@ -954,15 +1033,16 @@ impl GitService {
     /// - Authentication fails
     /// - Network connection fails
     /// - File system operations fail
+    #[instrument(name = "clone", skip_all)]
     fn clone_repository(
         &self,
         url: &str,
         repo_path: &Path,
         credential: Option<&Credential>,
-        encryption_key: &str,
+        encryption_keys: &EncryptionKeys,
     ) -> Result<(), AppError> {
         // Set up callbacks for authentication
-        let callbacks = self.create_remote_callbacks(credential, encryption_key)?;
+        let callbacks = self.create_remote_callbacks(credential, encryption_keys)?;
 
         let mut fetch_options = FetchOptions::new();
         fetch_options.remote_callbacks(callbacks);
@@ -991,7 +1071,7 @@ impl GitService {
     ///
     /// * `git_repo` - An open Git repository instance
     /// * `credential` - Optional credential for authenticated access
-    /// * `encryption_key` - Key used to decrypt SSH keys if encrypted
+    /// * `encryption_keys` - Keys used to decrypt SSH keys if encrypted
     ///
     /// # Returns
     ///
@@ -1007,11 +1087,12 @@ impl GitService {
     /// - Authentication fails
     /// - Merge conflicts are detected
     /// - Git operations fail
+    #[instrument(name = "pull", skip_all)]
     fn pull_repository(
         &self,
         git_repo: &GitRepository,
         credential: Option<&Credential>,
-        encryption_key: &str,
+        encryption_keys: &EncryptionKeys,
     ) -> Result<(), AppError> {
         // Find the remote
         let mut remote = git_repo
@@ -1019,7 +1100,7 @@ impl GitService {
             .map_err(|e| AppError::GitError(format!("Failed to find remote: {}", e)))?;
 
         // Set up callbacks for authentication
-        let callbacks = self.create_remote_callbacks(credential, encryption_key)?;
+        let callbacks = self.create_remote_callbacks(credential, encryption_keys)?;
 
         let mut fetch_options = FetchOptions::new();
         fetch_options.remote_callbacks(callbacks);
@@ -1042,7 +1123,7 @@ impl GitService {
             "refs/heads/{}:refs/heads/{}",
             branch_short_name, branch_short_name
         );
-        info!("Fetching branch: {}", refspec);
+        debug!(refspec = %refspec, "Fetching branch");
         remote
             .fetch(&[&refspec], Some(&mut fetch_options), None)
             .map_err(|e| AppError::GitError(format!("Failed to fetch updates: {}", e)))?;
@@ -1060,7 +1141,7 @@ impl GitService {
             .map_err(|e| AppError::GitError(format!("Failed to analyze merge: {}", e)))?;
 
         if analysis.0.is_up_to_date() {
-            info!("Repository is already up to date");
+            debug!("Working copy is already up to date");
         } else {
             // Always reset to remote state, discarding any local changes
             // This ensures we always match the remote repository state and never create merge commits
@@ -1118,7 +1199,8 @@ impl GitService {
     /// - The archive file cannot be opened
     /// - The archive is corrupted
     /// - File system operations fail
-    fn unpack_archive(&self, archive_path: &Path, dest_dir: &Path) -> Result<(), AppError> {
+    #[instrument(name = "unpack", skip_all, fields(archive = %archive_path.display()))]
+    pub fn unpack_archive(&self, archive_path: &Path, dest_dir: &Path) -> Result<(), AppError> {
         let file = File::open(archive_path)?;
         let decoder = GzDecoder::new(file);
         let mut archive = Archive::new(decoder);
@@ -1146,7 +1228,7 @@ impl GitService {
     /// - Directory reading fails
     /// - File metadata cannot be accessed
     #[allow(clippy::only_used_in_recursion)]
-    fn calculate_folder_size(&self, folder_path: &Path) -> Result<u64, AppError> {
+    pub fn calculate_folder_size(&self, folder_path: &Path) -> Result<u64, AppError> {
         let mut total_size = 0u64;
 
         if folder_path.is_dir() {
@@ -1216,6 +1298,49 @@ impl GitService {
         Ok(temp_archive_path)
     }
 
+    /// Archives a directory and atomically replaces the archive at `archive_path`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the top-level entry inside the archive
+    /// * `dir` - Directory to archive
+    /// * `archive_path` - Path of the archive to create or replace
+    ///
+    /// # Returns
+    ///
+    /// Returns the size of the new archive in bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError` if creating the archive or replacing the old one fails.
+    #[instrument(name = "archive", skip_all, fields(archive = %archive_path.display()))]
+    pub fn replace_archive(
+        &self,
+        name: &str,
+        dir: &Path,
+        archive_path: &Path,
+    ) -> Result<u64, AppError> {
+        // Pass the final archive path so temp archive is created in the same directory
+        let new_archive_path = self.create_archive(name, dir, archive_path)?;
+
+        // Calculate archive size
+        let archive_size = fs::metadata(&new_archive_path)
+            .map(|m| m.len())
+            .unwrap_or(0);
+
+        // Replace old archive with new one
+        // Ensure parent directory exists before moving
+        if let Some(parent) = archive_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if archive_path.exists() {
+            fs::remove_file(archive_path)?;
+        }
+        fs::rename(&new_archive_path, archive_path)?;
+
+        Ok(archive_size)
+    }
+
     /// Lists all archive files (.tar.gz) in the archive directory.
     ///
     /// Only files ending with `.tar.gz` are included. The list is sorted
@@ -1259,4 +1384,75 @@ impl GitService {
         archives.reverse(); // Most recent first
         Ok(archives)
     }
+
+    /// Checks the integrity of a backup created by a sync.
+    ///
+    /// In compact mode the archive is unpacked completely, which detects truncated
+    /// or corrupted archives. The repository is then opened and every object in its
+    /// object database is read (libgit2 verifies object hashes on read), and the
+    /// synced commit must be present.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Archive (compact mode) or folder (non-compact mode) of the backup
+    /// * `commit_hash` - Commit the backup is expected to contain
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::GitError` describing the first problem found, or
+    /// `AppError::IoError` if unpacking the archive fails.
+    pub fn verify_backup(&self, path: &Path, commit_hash: &str) -> Result<(), AppError> {
+        let temp_dir = tempfile::tempdir()?;
+        let repo_path = if self.compact {
+            self.unpack_archive(path, temp_dir.path())?;
+            // The archive contains a single top-level folder with the repository
+            fs::read_dir(temp_dir.path())?
+                .next()
+                .ok_or_else(|| AppError::GitError("Archive is empty".to_string()))??
+                .path()
+        } else {
+            path.to_path_buf()
+        };
+
+        let git_repo = GitRepository::open(&repo_path)
+            .map_err(|e| AppError::GitError(format!("Failed to open backup: {}", e)))?;
+        let odb = git_repo
+            .odb()
+            .map_err(|e| AppError::GitError(format!("Failed to open object database: {}", e)))?;
+
+        let mut oids = Vec::new();
+        odb.foreach(|oid| {
+            oids.push(*oid);
+            true
+        })
+        .map_err(|e| AppError::GitError(format!("Failed to list objects: {}", e)))?;
+        for oid in oids {
+            odb.read(oid)
+                .map_err(|e| AppError::GitError(format!("Corrupted object {}: {}", oid, e)))?;
+        }
+
+        let oid = git2::Oid::from_str(commit_hash).map_err(|e| {
+            AppError::GitError(format!("Invalid commit hash {}: {}", commit_hash, e))
+        })?;
+        git_repo
+            .find_commit(oid)
+            .map_err(|e| AppError::GitError(format!("Commit {} is missing: {}", commit_hash, e)))?;
+        Ok(())
+    }
+
+    /// Checks that files can be created in the archive directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::IoError` if a temporary file cannot be created or written.
+    pub fn check_writable(&self) -> Result<(), AppError> {
+        let mut file = tempfile::NamedTempFile::new_in(&self.archive_dir)?;
+        file.write_all(b"gitsafe")?;
+        Ok(())
+    }
+
+    /// Returns the space available to GitSafe on the volume of the archive directory, in bytes.
+    pub fn available_space(&self) -> Result<u64, AppError> {
+        Ok(fs4::available_space(&self.archive_dir)?)
+    }
 }
@@ -1,82 +1,35 @@
-use crate::auth::AuthService;
-use crate::config::{Config, Credential, Repository};
+use crate::audit::{AuditEntry, AuditLog};
+use crate::auth::{self, AuthService, TokenPair};
+use crate::config::{
+    ApiToken, Config, Credential, MetadataBackup, NotificationEvent, OidcConfig, Repository, Role,
+    TokenScope, UpstreamStatus, User, Webhook,
+};
 use crate::config_persistence::ConfigPersistence;
-use crate::encryption;
+use crate::encryption::EncryptionKeys;
 use crate::error::AppError;
+use crate::forge::ForgeProvider;
 use crate::git::GitService;
-use crate::middleware::AuthenticatedUser;
-use crate::webhooks;
+use crate::health::{self, SchedulerStatus};
+use crate::logging::SyncLogs;
+use crate::middleware::{self, AuthenticatedUser};
+use crate::oidc::{self, OidcService};
+use crate::push_hooks;
+use crate::rate_limit::{self, Lockout, LockoutTarget, RateLimiter};
+use crate::sync::{self, SyncQueue, SyncTrigger};
+use crate::totp;
+use crate::watchdog::Watchdog;
+use crate::webhooks::{self, DeliveryStatus, WebhookDelivery, WebhookService};
+use actix_web::cookie::{time::Duration as CookieDuration, Cookie, SameSite};
+use actix_web::http::header;
 use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
-use log::{info, warn};
 use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
 use std::sync::Arc;
+use subtle::ConstantTimeEq;
 use tokio::sync::RwLock;
+use tracing::{info, warn};
 use uuid::Uuid;
 
-/// Handles sync failure by updating attempts_left and potentially disabling the repository.
-///
-/// Returns true if the repository was disabled (ran out of attempts), false otherwise.
-async fn handle_sync_failure(
-    repo: &mut Repository,
-    error_message: &str,
-    sync_attempts: u32,
-    webhook_urls: &[String],
-) -> bool {
-    // Initialize or decrement attempts_left
-    let attempts_left = if let Some(attempts) = repo.attempts_left {
-        if attempts > 0 {
-            attempts - 1
-        } else {
-            0 // Already at 0, shouldn't happen but handle gracefully
-        }
-    } else {
-        // First failure: set to sync_attempts - 1
-        sync_attempts - 1
-    };
-
-    repo.attempts_left = Some(attempts_left);
-    repo.error = Some(error_message.to_string());
-
-    // Check if we've run out of attempts
-    if attempts_left == 0 {
-        // Reset attempts_left to None and disable the repository
-        repo.attempts_left = None;
-        repo.enabled = false;
-
-        warn!(
-            "Repository {} ran out of sync attempts and has been disabled",
-            repo.id
-        );
-
-        // Notify webhooks about running out of attempts
-        webhooks::notify_out_of_attempts_webhooks(
-            webhook_urls,
-            repo,
-            repo.credential_id.as_ref(),
-            error_message,
-            sync_attempts,
-        )
-        .await;
-
-        return true;
-    }
-
-    false
-}
-
-/// Handles successful sync by resetting attempts_left and clearing error.
-fn handle_sync_success(repo: &mut Repository) {
-    // Reset attempts_left to None on successful sync (recovered from errors)
-    if repo.attempts_left.is_some() {
-        info!(
-            "Repository {} recovered from error spree, resetting attempts",
-            repo.id
-        );
-        repo.attempts_left = None;
-    }
-    repo.error = None;
-}
-
 /// Application state shared across all request handlers.
 ///
 /// Contains the configuration, authentication service, and Git service
@@ -92,6 +45,14 @@ pub struct AppState {
     pub git_service: GitService,
     /// Config persistence manager for debounced saves
     pub config_persistence: ConfigPersistence,
+    /// Debounced queue for syncs triggered by push webhooks
+    pub sync_queue: SyncQueue,
+    /// Outgoing webhook deliveries with retries and delivery log
+    pub webhook_service: WebhookService,
+    /// Counters of API requests and failed logins per client
+    pub rate_limiter: RateLimiter,
+    /// Log of the changes made through the API
+    pub audit_log: AuditLog,
 }
 
 // Request/Response types
@@ -103,13 +64,139 @@ pub struct LoginRequest {
     pub username: String,
     /// Password for authentication
     pub password: String,
+    /// One-time password or recovery code, required for users with two-factor authentication
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub totp_code: Option<String>,
 }
 
 /// Login response containing JWT token.
 #[derive(Debug, Serialize)]
 pub struct LoginResponse {
-    /// JWT token for authenticated requests
-    pub token: String,
+    /// Access token, refresh token and lifetime of the access token
+    #[serde(flatten)]
+    pub tokens: TokenPair,
+    /// Whether the password must be changed before the token allows other requests
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub password_change_required: bool,
+}
+
+/// Request payload for refreshing the tokens of a session.
+#[derive(Debug, Deserialize)]
+pub struct RefreshTokenRequest {
+    pub refresh_token: String,
+}
+
+/// Query parameters for logging out.
+#[derive(Debug, Deserialize)]
+pub struct LogoutQuery {
+    /// End all sessions of the user instead of only the current one
+    #[serde(default)]
+    pub all: bool,
+}
+
+/// Query parameters of the redirect from the OIDC provider back to gitsafe.
+#[derive(Debug, Deserialize)]
+pub struct OidcCallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    /// Set by the provider if the login failed or was denied
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+/// Request payload for adding a new user.
+#[derive(Debug, Deserialize)]
+pub struct AddUserRequest {
+    pub username: String,
+    pub password: String,
+    /// Role of the user (default: viewer)
+    #[serde(default = "default_user_role")]
+    pub role: Role,
+    /// Whether the user has to change the password on first login
+    #[serde(default)]
+    pub must_change_password: bool,
+}
+
+fn default_user_role() -> Role {
+    Role::Viewer
+}
+
+/// Request payload for updating a user. Only provided fields are changed.
+#[derive(Debug, Deserialize)]
+pub struct UpdateUserRequest {
+    pub role: Option<Role>,
+    /// New password, set without knowing the current one
+    pub password: Option<String>,
+    pub must_change_password: Option<bool>,
+    /// Removes the two-factor authentication, e.g. after the user lost the device
+    #[serde(default)]
+    pub disable_totp: bool,
+}
+
+/// User information response (without the password hash).
+#[derive(Debug, Serialize)]
+pub struct UserResponse {
+    pub username: String,
+    pub role: Role,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub must_change_password: bool,
+    /// Whether logins require a one-time password
+    pub totp_enabled: bool,
+}
+
+impl From<&User> for UserResponse {
+    fn from(user: &User) -> Self {
+        UserResponse {
+            username: user.username.clone(),
+            role: user.role,
+            must_change_password: user.must_change_password,
+            totp_enabled: user.has_totp(),
+        }
+    }
+}
+
+/// Request payload for changing the password of the authenticated user.
+#[derive(Debug, Deserialize)]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+/// Request payload for starting the two-factor enrollment of the authenticated user.
+#[derive(Debug, Deserialize)]
+pub struct TotpPasswordRequest {
+    pub current_password: String,
+}
+
+/// Request payload for removing the two-factor authentication of the
+/// authenticated user.
+#[derive(Debug, Deserialize)]
+pub struct DisableTotpRequest {
+    pub current_password: String,
+    /// One-time password or recovery code, required once the enrollment is confirmed
+    #[serde(default)]
+    pub code: Option<String>,
+}
+
+/// Response to starting a TOTP enrollment.
+#[derive(Debug, Serialize)]
+pub struct TotpEnrollmentResponse {
+    /// Base32 secret, for entering it into an authenticator app manually
+    pub secret: String,
+    /// `otpauth://` URI to show as QR code
+    pub provisioning_uri: String,
+}
+
+/// Request payload with a one-time password.
+#[derive(Debug, Deserialize)]
+pub struct TotpCodeRequest {
+    pub code: String,
+}
+
+/// Response containing new recovery codes, each valid for one login.
+#[derive(Debug, Serialize)]
+pub struct RecoveryCodesResponse {
+    pub recovery_codes: Vec<String>,
 }
 
 /// Request payload for adding a new repository.
@@ -121,6 +208,13 @@ pub struct AddRepositoryRequest {
     pub credential_id: Option<String>,
     /// Optional repository ID. If not provided, will be generated from URL using repo_id_from_url logic
     pub id: Option<String>,
+    /// Optional forge metadata backup settings
+    pub metadata: Option<MetadataBackup>,
+    /// Optional labels, e.g. for routing notifications
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Optional maximum backup age in hours, overriding `scheduler.max_backup_age_hours`
+    pub max_backup_age_hours: Option<u64>,
 }
 
 /// Request payload for updating repository settings.
@@ -130,6 +224,12 @@ pub struct UpdateRepositoryRequest {
     pub enabled: Option<bool>,
     /// Optional credential ID for authenticated access
     pub credential_id: Option<String>,
+    /// Forge metadata backup settings (replaces the current settings if provided)
+    pub metadata: Option<MetadataBackup>,
+    /// Labels (replace the current tags if provided)
+    pub tags: Option<Vec<String>>,
+    /// Maximum backup age in hours (0 removes the repository limit)
+    pub max_backup_age_hours: Option<u64>,
 }
 
 /// Repository information response.
@@ -147,6 +247,36 @@ pub struct RepositoryResponse {
     pub size: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub attempts_left: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream_status: Option<UpstreamStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<MetadataBackup>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_backup_age_hours: Option<u64>,
+}
+
+impl From<&Repository> for RepositoryResponse {
+    fn from(repository: &Repository) -> Self {
+        RepositoryResponse {
+            id: repository.id.clone(),
+            url: repository.url.clone(),
+            credential_id: repository.credential_id.clone(),
+            enabled: repository.enabled,
+            last_sync: repository.last_sync,
+            error: repository.error.clone(),
+            size: repository.size,
+            attempts_left: repository.attempts_left,
+            source_id: repository.source_id.clone(),
+            upstream_status: repository.upstream_status,
+            metadata: repository.metadata.clone(),
+            tags: repository.tags.clone(),
+            max_backup_age_hours: repository.max_backup_age_hours,
+        }
+    }
 }
 
 /// Request payload for adding a new credential.
@@ -173,6 +303,67 @@ pub struct CredentialResponse {
     pub is_ssh_key: bool,
 }
 
+/// Result of re-encrypting the stored secrets with the current encryption key.
+#[derive(Debug, Serialize)]
+pub struct ReencryptionResponse {
+    /// ID of the current encryption key
+    pub key_id: String,
+    /// Credentials whose password or SSH key was re-encrypted
+    pub credentials: Vec<String>,
+    /// Users whose two-factor secret was re-encrypted
+    pub users: Vec<String>,
+    /// Credentials whose secrets can't be decrypted with any configured key
+    pub failed_credentials: Vec<String>,
+    /// Users whose two-factor secret can't be decrypted with any configured key
+    pub failed_users: Vec<String>,
+}
+
+/// Request payload for creating a personal API token.
+#[derive(Debug, Deserialize)]
+pub struct CreateApiTokenRequest {
+    /// Description of what the token is used for
+    pub name: String,
+    /// Operations the token may perform
+    pub scopes: Vec<TokenScope>,
+    /// Days until the token expires; never expires if not set
+    pub expires_in_days: Option<u32>,
+}
+
+/// API token information response (without the token hash).
+#[derive(Debug, Serialize)]
+pub struct ApiTokenResponse {
+    pub id: String,
+    pub name: String,
+    pub scopes: Vec<TokenScope>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_used: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl From<&ApiToken> for ApiTokenResponse {
+    fn from(api_token: &ApiToken) -> Self {
+        ApiTokenResponse {
+            id: api_token.id.clone(),
+            name: api_token.name.clone(),
+            scopes: api_token.scopes.clone(),
+            created_at: api_token.created_at,
+            expires_at: api_token.expires_at,
+            last_used: api_token.last_used,
+        }
+    }
+}
+
+/// Response to creating an API token, the only time the token itself is returned.
+#[derive(Debug, Serialize)]
+pub struct CreateApiTokenResponse {
+    /// The token, to be sent as `Authorization: Bearer <token>`
+    pub token: String,
+    #[serde(flatten)]
+    pub api_token: ApiTokenResponse,
+}
+
 /// Request payload for manually syncing a repository.
 #[derive(Debug, Deserialize)]
 pub struct SyncRequest {
@@ -195,10 +386,75 @@ pub struct SyncRequest {
 pub fn get_authenticated_user(req: &HttpRequest) -> Result<String, AppError> {
     req.extensions()
         .get::<AuthenticatedUser>()
-        .map(|user| user.0.clone())
+        .map(|user| user.username.clone())
         .ok_or_else(|| AppError::AuthError("User not authenticated".to_string()))
 }
 
+/// Starts an audit log entry for an action of the authenticated user on `target`.
+fn audit_entry(req: &HttpRequest, config: &Config, action: &str, target: &str) -> AuditEntry {
+    let actor = get_authenticated_user(req).unwrap_or_else(|_| "anonymous".to_string());
+    let source_ip = rate_limit::client_ip(
+        req.peer_addr().map(|addr| addr.ip()),
+        req.headers(),
+        &config.server.rate_limit.trusted_proxies,
+    );
+    AuditEntry::new(
+        &actor,
+        action,
+        Some(target),
+        source_ip.map(|ip| ip.to_string()),
+    )
+}
+
+/// Adds or updates a user of single sign-on or the authentication proxy (see
+/// `Config::sync_external_user`). A change is recorded in the audit log, with
+/// the user as actor, and saved.
+///
+/// # Errors
+///
+/// Returns `AppError::Forbidden` if `Config::check_external_user` refuses the login.
+pub(crate) async fn sync_external_user(
+    state: &AppState,
+    username: &str,
+    role: Role,
+    peer: Option<IpAddr>,
+    headers: &header::HeaderMap,
+) -> Result<(), AppError> {
+    let mut config = state.config.write().await;
+    let before = config
+        .users
+        .iter()
+        .find(|u| u.username == username)
+        .cloned();
+    if !config.sync_external_user(username, role)? {
+        return Ok(());
+    }
+    let after = config
+        .users
+        .iter()
+        .find(|u| u.username == username)
+        .cloned();
+    let action = if before.is_some() {
+        "user.update"
+    } else {
+        "user.create"
+    };
+    let source_ip = rate_limit::client_ip(peer, headers, &config.server.rate_limit.trusted_proxies);
+    state.audit_log.record(
+        AuditEntry::new(
+            username,
+            action,
+            Some(username),
+            source_ip.map(|ip| ip.to_string()),
+        )
+        .with_changes(before.as_ref(), after.as_ref()),
+    );
+    let config_to_save = config.clone();
+    drop(config); // Release lock before async operation
+    state.config_persistence.request_save(config_to_save);
+    Ok(())
+}
+
 /// Determines if a Git URL is an SSH URL (git@host:path) or HTTP/HTTPS URL.
 ///
 /// SSH URLs have the format: git@host:path
@@ -230,25 +486,671 @@ fn is_ssh_url(url: &str) -> bool {
 
 // Handlers
 pub async fn login(
+    req: HttpRequest,
     data: web::Json<LoginRequest>,
     state: web::Data<AppState>,
 ) -> Result<HttpResponse, AppError> {
-    let config = state.config.read().await;
+    // Behind a trusted authenticating proxy, the user of the proxy is logged in
+    let peer = req.peer_addr().map(|addr| addr.ip());
+    if let Some(user) = middleware::authenticate_proxy_user(&state, req.headers(), peer).await? {
+        let tokens = state
+            .auth_service
+            .start_session(&user.username, user.role)?;
+        return Ok(HttpResponse::Ok().json(LoginResponse {
+            tokens,
+            password_change_required: false,
+        }));
+    }
+
+    let config = state.config.read().await;
+
+    // If skip_auth is enabled, bypass authentication and generate an admin token with provided username
+    if config.server.skip_auth {
+        let tokens = state
+            .auth_service
+            .start_session(&data.username, Role::Admin)
+            .map_err(|e| AppError::InternalError(format!("Failed to generate token: {}", e)))?;
+        return Ok(HttpResponse::Ok().json(LoginResponse {
+            tokens,
+            password_change_required: false,
+        }));
+    }
+    if config
+        .server
+        .oidc
+        .as_ref()
+        .is_some_and(|oidc| oidc.disable_password_login)
+    {
+        return Err(AppError::Forbidden(
+            "Password login is disabled, log in with single sign-on".to_string(),
+        ));
+    }
+
+    // Normal authentication flow, throttled per username and address
+    let settings = config.server.rate_limit.clone();
+    drop(config); // Release lock, password_login may need to write
+    let client = rate_limit::client_ip(peer, req.headers(), &settings.trusted_proxies);
+    state
+        .rate_limiter
+        .check_login(client, &data.username, chrono::Utc::now())?;
+    let tokens = match password_login(&state, &data).await {
+        Ok(tokens) => {
+            state.rate_limiter.record_login_success(&data.username);
+            tokens
+        }
+        Err(e) => {
+            warn!(user = %data.username, client = ?client, "Failed login");
+            let config = state.config.read().await;
+            for lockout in state.rate_limiter.record_login_failure(
+                &settings.login,
+                client,
+                &data.username,
+                chrono::Utc::now(),
+            ) {
+                state.audit_log.record(AuditEntry::new(
+                    &data.username,
+                    "login.lockout",
+                    Some(&lockout.target.to_string()),
+                    client.map(|ip| ip.to_string()),
+                ));
+                notify_login_locked_out(&state, &config, &lockout);
+            }
+            return Err(e);
+        }
+    };
+    let password_change_required = state
+        .auth_service
+        .verify_token(&tokens.token)?
+        .password_change_required;
+
+    Ok(HttpResponse::Ok().json(LoginResponse {
+        tokens,
+        password_change_required,
+    }))
+}
+
+/// Verifies the password and, for users with two-factor authentication, the
+/// one-time password or recovery code of a login, and starts a session.
+async fn password_login(state: &AppState, data: &LoginRequest) -> Result<TokenPair, AppError> {
+    let config = state.config.read().await;
+    let user =
+        state
+            .auth_service
+            .verify_credentials(&data.username, &data.password, &config.users)?;
+    if !user.has_totp() {
+        return state
+            .auth_service
+            .start_password_session(user, &data.password);
+    }
+    let code = data
+        .totp_code
+        .as_deref()
+        .filter(|code| !code.trim().is_empty())
+        .ok_or_else(|| AppError::AuthError("Two-factor code required".to_string()))?;
+    drop(config);
+
+    // Codes are single-use, so they are checked and consumed under the write lock
+    let mut config = state.config.write().await;
+    let encryption_keys = config.server.encryption_keys();
+    let user = config
+        .users
+        .iter_mut()
+        .find(|u| u.username == data.username)
+        .ok_or_else(|| AppError::AuthError("Invalid credentials".to_string()))?;
+    let Some(user_totp) = user.totp.as_mut().filter(|totp| totp.confirmed) else {
+        return Err(AppError::AuthError("Invalid credentials".to_string()));
+    };
+    let used_recovery_code =
+        totp::verify_login(user_totp, code, &encryption_keys, chrono::Utc::now())?;
+    if used_recovery_code {
+        warn!(
+            user = %user.username,
+            recovery_codes_left = user_totp.recovery_code_hashes.len(),
+            "User logged in with a recovery code"
+        );
+    }
+    let tokens = state
+        .auth_service
+        .start_password_session(user, &data.password)?;
+
+    let config_to_save = config.clone();
+    drop(config); // Release lock before async operation
+    state.config_persistence.request_save(config_to_save);
+    Ok(tokens)
+}
+
+/// Exchanges a refresh token for a new access token and refresh token.
+pub async fn refresh_token(
+    data: web::Json<RefreshTokenRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, AppError> {
+    let config = state.config.read().await;
+    let tokens = state
+        .auth_service
+        .refresh(&data.refresh_token, &config.users)?;
+    let password_change_required = state
+        .auth_service
+        .verify_token(&tokens.token)?
+        .password_change_required;
+
+    Ok(HttpResponse::Ok().json(LoginResponse {
+        tokens,
+        password_change_required,
+    }))
+}
+
+/// Ends the session of the access token, or with `?all=true` all sessions of the user.
+pub async fn logout(
+    req: HttpRequest,
+    query: web::Query<LogoutQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, AppError> {
+    let (username, session_id) = req
+        .extensions()
+        .get::<AuthenticatedUser>()
+        .map(|user| (user.username.clone(), user.session_id.clone()))
+        .ok_or_else(|| AppError::AuthError("User not authenticated".to_string()))?;
+
+    if query.all {
+        let revoked = state.auth_service.revoke_user_sessions(&username);
+        info!(user = %username, sessions = revoked, "User logged out of all sessions");
+    } else if let Some(session_id) = session_id {
+        state.auth_service.revoke_session(&session_id);
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Starts a single sign-on login by redirecting to the OIDC provider.
+pub async fn oidc_login(
+    state: web::Data<AppState>,
+    oidc: web::Data<OidcService>,
+) -> Result<HttpResponse, AppError> {
+    let config = oidc_config(&state).await?;
+    let (url, login_state) = oidc.authorization_url(&config).await?;
+    Ok(HttpResponse::Found()
+        .insert_header((header::LOCATION, url))
+        .cookie(oidc_state_cookie(&config, login_state))
+        .finish())
+}
+
+/// Cookie binding a single sign-on login to the browser that started it, so a
+/// callback link of someone else's login can't log the victim in as them.
+const OIDC_STATE_COOKIE: &str = "gitsafe_oidc_state";
+
+fn oidc_state_cookie(config: &OidcConfig, value: String) -> Cookie<'static> {
+    Cookie::build(OIDC_STATE_COOKIE, value)
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .secure(config.redirect_url.starts_with("https://"))
+        .max_age(CookieDuration::seconds(
+            oidc::PENDING_LOGIN_TTL.as_secs() as i64
+        ))
+        .finish()
+}
+
+/// Completes a single sign-on login after the redirect back from the OIDC provider.
+///
+/// The user is added to the configuration (without a password) or their role
+/// updated from the groups in the ID token; local users with a password can't
+/// log in this way (see `Config::check_external_user`). Then the browser is redirected to
+/// `post_login_url` with the token in the URL fragment.
+pub async fn oidc_callback(
+    req: HttpRequest,
+    query: web::Query<OidcCallbackQuery>,
+    state: web::Data<AppState>,
+    oidc: web::Data<OidcService>,
+) -> Result<HttpResponse, AppError> {
+    let oidc_config = oidc_config(&state).await?;
+    if let Some(error) = &query.error {
+        return Err(AppError::AuthError(format!(
+            "OIDC login failed: {} {}",
+            error,
+            query.error_description.as_deref().unwrap_or_default()
+        )));
+    }
+    let (Some(code), Some(login_state)) = (&query.code, &query.state) else {
+        return Err(AppError::BadRequest(
+            "Missing code or state parameter".to_string(),
+        ));
+    };
+
+    let started_here = req
+        .cookie(OIDC_STATE_COOKIE)
+        .is_some_and(|cookie| bool::from(cookie.value().as_bytes().ct_eq(login_state.as_bytes())));
+    if !started_here {
+        return Err(AppError::AuthError(
+            "Login was not started in this browser".to_string(),
+        ));
+    }
+
+    let identity = oidc.complete_login(&oidc_config, code, login_state).await?;
+    let role = oidc::role_for(&oidc_config, &identity.groups).ok_or_else(|| {
+        AppError::Forbidden(format!(
+            "User {} is not in a group allowed to log in",
+            identity.username
+        ))
+    })?;
+
+    sync_external_user(
+        &state,
+        &identity.username,
+        role,
+        req.peer_addr().map(|addr| addr.ip()),
+        req.headers(),
+    )
+    .await?;
+
+    let tokens = state.auth_service.start_session(&identity.username, role)?;
+    info!(
+        user = %identity.username,
+        role = role.as_str(),
+        "User logged in with single sign-on"
+    );
+    let mut state_cookie = oidc_state_cookie(&oidc_config, String::new());
+    state_cookie.make_removal();
+    Ok(HttpResponse::Found()
+        .insert_header((
+            header::LOCATION,
+            format!(
+                "{}#token={}&refresh_token={}",
+                oidc_config.post_login_url, tokens.token, tokens.refresh_token
+            ),
+        ))
+        .cookie(state_cookie)
+        .finish())
+}
+
+async fn oidc_config(state: &AppState) -> Result<OidcConfig, AppError> {
+    state
+        .config
+        .read()
+        .await
+        .server
+        .oidc
+        .clone()
+        .ok_or_else(|| AppError::NotFound("Single sign-on is not enabled".to_string()))
+}
+
+/// Changes the password of the authenticated user after verifying the current one.
+///
+/// Also accepted with the restricted token issued while a password change is
+/// required. All sessions of the user are revoked; the response contains the
+/// tokens of a new, unrestricted session.
+pub async fn change_password(
+    req: HttpRequest,
+    data: web::Json<ChangePasswordRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, AppError> {
+    let username = get_authenticated_user(&req)?;
+    auth::validate_password(&data.new_password)?;
+    if data.new_password == data.current_password {
+        return Err(AppError::BadRequest(
+            "The new password must differ from the current one".to_string(),
+        ));
+    }
+
+    let mut config = state.config.write().await;
+    let user = find_local_user(&state, &mut config.users, &username, &data.current_password)?;
+
+    let before = user.clone();
+    user.password_hash = state.auth_service.hash_password(&data.new_password)?;
+    user.must_change_password = false;
+    let after = user.clone();
+    let role = user.role;
+    state.audit_log.record(
+        audit_entry(&req, &config, "user.change_password", &username)
+            .with_changes(Some(&before), Some(&after)),
+    );
+
+    let config_to_save = config.clone();
+    drop(config); // Release lock before async operation
+    state.config_persistence.request_save(config_to_save);
+    info!(user = %username, "User changed the password");
+
+    state.auth_service.revoke_user_sessions(&username);
+    let tokens = state.auth_service.start_session(&username, role)?;
+    Ok(HttpResponse::Ok().json(LoginResponse {
+        tokens,
+        password_change_required: false,
+    }))
+}
+
+/// Starts the two-factor enrollment of the authenticated user.
+///
+/// Returns the new secret and its provisioning URI for the authenticator app.
+/// Logins don't require a code until the enrollment is confirmed with
+/// `confirm_totp`; starting again replaces an unconfirmed enrollment.
+pub async fn start_totp_enrollment(
+    req: HttpRequest,
+    data: web::Json<TotpPasswordRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, AppError> {
+    let username = get_authenticated_user(&req)?;
+    let mut config = state.config.write().await;
+    let encryption_keys = config.server.encryption_keys();
+    let user = find_local_user(&state, &mut config.users, &username, &data.current_password)?;
+    if user.has_totp() {
+        return Err(AppError::BadRequest(
+            "Two-factor authentication is already enabled".to_string(),
+        ));
+    }
+
+    let before = user.clone();
+    let (user_totp, secret) = totp::enroll(&encryption_keys)?;
+    user.totp = Some(user_totp);
+    let after = user.clone();
+    state.audit_log.record(
+        audit_entry(&req, &config, "user.start_totp", &username)
+            .with_changes(Some(&before), Some(&after)),
+    );
+    let config_to_save = config.clone();
+    drop(config); // Release lock before async operation
+    state.config_persistence.request_save(config_to_save);
+
+    Ok(HttpResponse::Ok().json(TotpEnrollmentResponse {
+        provisioning_uri: totp::provisioning_uri(&username, &secret),
+        secret,
+    }))
+}
+
+/// Completes the two-factor enrollment of the authenticated user with a code of
+/// the authenticator app.
+///
+/// Returns the recovery codes; they are shown only once.
+pub async fn confirm_totp(
+    req: HttpRequest,
+    data: web::Json<TotpCodeRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, AppError> {
+    let username = get_authenticated_user(&req)?;
+    let mut config = state.config.write().await;
+    let encryption_keys = config.server.encryption_keys();
+    let user = config
+        .users
+        .iter_mut()
+        .find(|u| u.username == username)
+        .ok_or_else(|| AppError::NotFound(format!("User {} not found", username)))?;
+    let before = user.clone();
+    let Some(user_totp) = user.totp.as_mut().filter(|totp| !totp.confirmed) else {
+        return Err(AppError::BadRequest(
+            "No two-factor enrollment to confirm".to_string(),
+        ));
+    };
+
+    let recovery_codes =
+        totp::confirm(user_totp, &data.code, &encryption_keys, chrono::Utc::now())?;
+    let after = user.clone();
+    state.audit_log.record(
+        audit_entry(&req, &config, "user.enable_totp", &username)
+            .with_changes(Some(&before), Some(&after)),
+    );
+    let config_to_save = config.clone();
+    drop(config); // Release lock before async operation
+    state.config_persistence.request_save(config_to_save);
+    info!(user = %username, "User enabled two-factor authentication");
+
+    Ok(HttpResponse::Ok().json(RecoveryCodesResponse { recovery_codes }))
+}
+
+/// Replaces the recovery codes of the authenticated user, after verifying a
+/// code of the authenticator app.
+pub async fn regenerate_recovery_codes(
+    req: HttpRequest,
+    data: web::Json<TotpCodeRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, AppError> {
+    let username = get_authenticated_user(&req)?;
+    let mut config = state.config.write().await;
+    let encryption_keys = config.server.encryption_keys();
+    let user = config
+        .users
+        .iter_mut()
+        .find(|u| u.username == username)
+        .ok_or_else(|| AppError::NotFound(format!("User {} not found", username)))?;
+    let Some(user_totp) = user.totp.as_mut().filter(|totp| totp.confirmed) else {
+        return Err(AppError::BadRequest(
+            "Two-factor authentication is not enabled".to_string(),
+        ));
+    };
+
+    let secret = encryption_keys.decrypt(&user_totp.secret)?;
+    let step = totp::verify_code(
+        &secret,
+        &data.code,
+        chrono::Utc::now(),
+        user_totp.last_used_step,
+    )?
+    .ok_or_else(|| AppError::BadRequest("Invalid two-factor code".to_string()))?;
+    let recovery_codes = totp::generate_recovery_codes();
+    user_totp.last_used_step = Some(step);
+    user_totp.recovery_code_hashes = recovery_codes
+        .iter()
+        .map(|code| totp::hash_recovery_code(code))
+        .collect();
+    state.audit_log.record(audit_entry(
+        &req,
+        &config,
+        "user.regenerate_recovery_codes",
+        &username,
+    ));
+    let config_to_save = config.clone();
+    drop(config); // Release lock before async operation
+    state.config_persistence.request_save(config_to_save);
+
+    Ok(HttpResponse::Ok().json(RecoveryCodesResponse { recovery_codes }))
+}
+
+/// Removes the two-factor authentication of the authenticated user after
+/// verifying the password and, once the enrollment is confirmed, a current
+/// one-time password or recovery code.
+pub async fn disable_totp(
+    req: HttpRequest,
+    data: web::Json<DisableTotpRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, AppError> {
+    let username = get_authenticated_user(&req)?;
+    let mut config = state.config.write().await;
+    let encryption_keys = config.server.encryption_keys();
+    let user = find_local_user(&state, &mut config.users, &username, &data.current_password)?;
+    if user.totp.is_none() {
+        return Err(AppError::BadRequest(
+            "Two-factor authentication is not enabled".to_string(),
+        ));
+    }
+
+    let before = user.clone();
+    if let Some(user_totp) = user.totp.as_mut().filter(|totp| totp.confirmed) {
+        // A stolen password and session alone must not remove the second factor
+        let code = data
+            .code
+            .as_deref()
+            .filter(|code| !code.trim().is_empty())
+            .ok_or_else(|| AppError::BadRequest("Two-factor code required".to_string()))?;
+        totp::verify_login(user_totp, code, &encryption_keys, chrono::Utc::now()).map_err(|e| {
+            match e {
+                AppError::AuthError(message) => AppError::BadRequest(message),
+                e => e,
+            }
+        })?;
+    }
+    user.totp = None;
+    let after = user.clone();
+    state.audit_log.record(
+        audit_entry(&req, &config, "user.disable_totp", &username)
+            .with_changes(Some(&before), Some(&after)),
+    );
+    let config_to_save = config.clone();
+    drop(config); // Release lock before async operation
+    state.config_persistence.request_save(config_to_save);
+    info!(user = %username, "User disabled two-factor authentication");
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Returns the authenticated local user after verifying the current password.
+fn find_local_user<'a>(
+    state: &AppState,
+    users: &'a mut [User],
+    username: &str,
+    current_password: &str,
+) -> Result<&'a mut User, AppError> {
+    let user = users
+        .iter_mut()
+        .find(|u| u.username == username)
+        .ok_or_else(|| AppError::NotFound(format!("User {} not found", username)))?;
+    if user.password_hash.is_empty() {
+        return Err(AppError::BadRequest(
+            "Users of single sign-on have no password".to_string(),
+        ));
+    }
+    if !state
+        .auth_service
+        .verify_password(current_password, &user.password_hash)?
+    {
+        return Err(AppError::BadRequest(
+            "Current password is incorrect".to_string(),
+        ));
+    }
+    Ok(user)
+}
+
+pub async fn list_users(state: web::Data<AppState>) -> Result<HttpResponse, AppError> {
+    let config = state.config.read().await;
+    let users: Vec<UserResponse> = config.users.iter().map(UserResponse::from).collect();
+    Ok(HttpResponse::Ok().json(users))
+}
+
+pub async fn add_user(
+    req: HttpRequest,
+    data: web::Json<AddUserRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, AppError> {
+    let username = data.username.trim();
+    if username.is_empty() {
+        return Err(AppError::BadRequest("Username cannot be empty".to_string()));
+    }
+    auth::validate_password(&data.password)?;
+
+    let mut config = state.config.write().await;
+    if config.users.iter().any(|u| u.username == username) {
+        return Err(AppError::BadRequest(format!(
+            "User '{}' already exists",
+            username
+        )));
+    }
+
+    let user = User {
+        username: username.to_string(),
+        password_hash: state.auth_service.hash_password(&data.password)?,
+        role: data.role,
+        must_change_password: data.must_change_password,
+        totp: None,
+    };
+    let response = UserResponse::from(&user);
+    state.audit_log.record(
+        audit_entry(&req, &config, "user.add", &user.username).with_changes(None, Some(&user)),
+    );
+    config.users.push(user);
+
+    let config_to_save = config.clone();
+    drop(config); // Release lock before async operation
+    state.config_persistence.request_save(config_to_save);
+
+    Ok(HttpResponse::Created().json(response))
+}
+
+pub async fn update_user(
+    req: HttpRequest,
+    path: web::Path<String>,
+    data: web::Json<UpdateUserRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, AppError> {
+    let username = path.into_inner();
+    if let Some(ref password) = data.password {
+        auth::validate_password(password)?;
+    }
+
+    let mut config = state.config.write().await;
+    let index = config
+        .users
+        .iter()
+        .position(|u| u.username == username)
+        .ok_or_else(|| AppError::NotFound(format!("User {} not found", username)))?;
+    let before = config.users[index].clone();
+    let role_changed = data.role.is_some_and(|role| role != before.role);
+
+    if let Some(role) = data.role {
+        if role != Role::Admin && config.is_last_admin(&username) {
+            return Err(AppError::BadRequest(
+                "Cannot demote the last admin".to_string(),
+            ));
+        }
+        config.users[index].role = role;
+    }
+    if let Some(ref password) = data.password {
+        config.users[index].password_hash = state.auth_service.hash_password(password)?;
+    }
+    if let Some(must_change_password) = data.must_change_password {
+        config.users[index].must_change_password = must_change_password;
+    }
+    if data.disable_totp {
+        config.users[index].totp = None;
+    }
+    let response = UserResponse::from(&config.users[index]);
+    state.audit_log.record(
+        audit_entry(&req, &config, "user.update", &username)
+            .with_changes(Some(&before), Some(&config.users[index])),
+    );
+
+    let config_to_save = config.clone();
+    drop(config); // Release lock before async operation
+    state.config_persistence.request_save(config_to_save);
+
+    // A reset password ends the sessions started with the old one, and tokens
+    // of the sessions carry the role, so a changed role ends them as well
+    if data.password.is_some() || role_changed {
+        state.auth_service.revoke_user_sessions(&username);
+    }
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Deletes a user together with the user's API tokens and sessions.
+pub async fn delete_user(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, AppError> {
+    let username = path.into_inner();
+    let mut config = state.config.write().await;
 
-    // If skip_auth is enabled, bypass authentication and generate token with provided username
-    let token = if config.server.skip_auth {
-        state
-            .auth_service
-            .generate_token(&data.username)
-            .map_err(|e| AppError::InternalError(format!("Failed to generate token: {}", e)))?
-    } else {
-        // Normal authentication flow
-        state
-            .auth_service
-            .authenticate(&data.username, &data.password, &config.users)?
+    let Some(user) = config
+        .users
+        .iter()
+        .find(|u| u.username == username)
+        .cloned()
+    else {
+        return Err(AppError::NotFound(format!("User {} not found", username)));
     };
+    if config.is_last_admin(&username) {
+        return Err(AppError::BadRequest(
+            "Cannot delete the last admin".to_string(),
+        ));
+    }
+
+    config.users.retain(|u| u.username != username);
+    config.api_tokens.retain(|t| t.username != username);
+    state.audit_log.record(
+        audit_entry(&req, &config, "user.delete", &username).with_changes(Some(&user), None),
+    );
+
+    let config_to_save = config.clone();
+    drop(config); // Release lock before async operation
+    state.config_persistence.request_save(config_to_save);
+    state.auth_service.revoke_user_sessions(&username);
 
-    Ok(HttpResponse::Ok().json(LoginResponse { token }))
+    Ok(HttpResponse::NoContent().finish())
 }
 
 /// Query parameters for searching/filtering repositories.
@@ -260,6 +1162,8 @@ pub struct SearchRepositoriesQuery {
     pub url: Option<String>,
     #[serde(default)]
     pub has_error: Option<bool>,
+    #[serde(default)]
+    pub tag: Option<String>,
 }
 
 pub async fn list_repositories(
@@ -270,16 +1174,7 @@ pub async fn list_repositories(
     let mut repositories: Vec<RepositoryResponse> = config
         .repositories
         .iter()
-        .map(|r| RepositoryResponse {
-            id: r.id.clone(),
-            url: r.url.clone(),
-            credential_id: r.credential_id.clone(),
-            enabled: r.enabled,
-            last_sync: r.last_sync,
-            error: r.error.clone(),
-            size: r.size,
-            attempts_left: r.attempts_left,
-        })
+        .map(RepositoryResponse::from)
         .collect();
 
     // Apply filters
@@ -305,10 +1200,17 @@ pub async fn list_repositories(
         });
     }
 
+    if let Some(tag_filter) = &query.tag {
+        if !tag_filter.is_empty() {
+            repositories.retain(|r| r.tags.contains(tag_filter));
+        }
+    }
+
     Ok(HttpResponse::Ok().json(repositories))
 }
 
 pub async fn add_repository(
+    req: HttpRequest,
     data: web::Json<AddRepositoryRequest>,
     state: web::Data<AppState>,
 ) -> Result<HttpResponse, AppError> {
@@ -369,19 +1271,28 @@ pub async fn add_repository(
         error: None,
         size: None,
         attempts_left: None,
+        source_id: None,
+        upstream_status: None,
+        metadata: data.metadata.clone(),
+        tags: data.tags.clone(),
+        max_backup_age_hours: data.max_backup_age_hours,
     };
 
-    let response = RepositoryResponse {
-        id: repository.id.clone(),
-        url: repository.url.clone(),
-        credential_id: repository.credential_id.clone(),
-        enabled: repository.enabled,
-        last_sync: repository.last_sync,
-        error: repository.error.clone(),
-        size: repository.size,
-        attempts_left: repository.attempts_left,
-    };
-
+    let response = RepositoryResponse::from(&repository);
+
+    webhooks::notify_event(
+        &state.webhook_service,
+        &config.server.subscriptions(),
+        NotificationEvent::RepositoryAdded,
+        Some(&repository),
+        "Repository added",
+        serde_json::Value::Null,
+    );
+
+    state.audit_log.record(
+        audit_entry(&req, &config, "repository.add", &repository.id)
+            .with_changes(None, Some(&repository)),
+    );
     config.repositories.push(repository);
     let config_to_save = config.clone();
     drop(config); // Release lock before async operation
@@ -391,6 +1302,7 @@ pub async fn add_repository(
 }
 
 pub async fn update_repository(
+    req: HttpRequest,
     path: web::Path<String>,
     data: web::Json<UpdateRepositoryRequest>,
     state: web::Data<AppState>,
@@ -443,6 +1355,7 @@ pub async fn update_repository(
         .iter_mut()
         .find(|r| r.id == repo_id)
         .ok_or_else(|| AppError::NotFound(format!("Repository {} not found", repo_id)))?;
+    let before = repository.clone();
 
     // Update enabled status if provided
     if let Some(enabled) = data.enabled {
@@ -461,16 +1374,25 @@ pub async fn update_repository(
         }
     }
 
-    let response = RepositoryResponse {
-        id: repository.id.clone(),
-        url: repository.url.clone(),
-        credential_id: repository.credential_id.clone(),
-        enabled: repository.enabled,
-        last_sync: repository.last_sync,
-        error: repository.error.clone(),
-        size: repository.size,
-        attempts_left: repository.attempts_left,
-    };
+    // Update metadata backup settings if provided
+    if let Some(ref metadata) = data.metadata {
+        repository.metadata = Some(metadata.clone());
+    }
+
+    if let Some(ref tags) = data.tags {
+        repository.tags = tags.clone();
+    }
+
+    if let Some(max_backup_age_hours) = data.max_backup_age_hours {
+        repository.max_backup_age_hours = Some(max_backup_age_hours).filter(|&hours| hours > 0);
+    }
+
+    let response = RepositoryResponse::from(&*repository);
+    let after = repository.clone();
+    state.audit_log.record(
+        audit_entry(&req, &config, "repository.update", &repo_id)
+            .with_changes(Some(&before), Some(&after)),
+    );
 
     let config_to_save = config.clone();
     drop(config); // Release lock before async operation
@@ -480,21 +1402,32 @@ pub async fn update_repository(
 }
 
 pub async fn delete_repository(
+    req: HttpRequest,
     path: web::Path<String>,
     state: web::Data<AppState>,
 ) -> Result<HttpResponse, AppError> {
     let repo_id = path.into_inner();
     let mut config = state.config.write().await;
 
-    let initial_len = config.repositories.len();
-    config.repositories.retain(|r| r.id != repo_id);
-
-    if config.repositories.len() == initial_len {
-        return Err(AppError::NotFound(format!(
-            "Repository {} not found",
-            repo_id
-        )));
-    }
+    let index = config
+        .repositories
+        .iter()
+        .position(|r| r.id == repo_id)
+        .ok_or_else(|| AppError::NotFound(format!("Repository {} not found", repo_id)))?;
+    let repository = config.repositories.remove(index);
+    state.audit_log.record(
+        audit_entry(&req, &config, "repository.delete", &repo_id)
+            .with_changes(Some(&repository), None),
+    );
+
+    webhooks::notify_event(
+        &state.webhook_service,
+        &config.server.subscriptions(),
+        NotificationEvent::RepositoryDeleted,
+        Some(&repository),
+        "Repository deleted; existing backups are kept",
+        serde_json::Value::Null,
+    );
 
     let config_to_save = config.clone();
     drop(config); // Release lock before async operation
@@ -504,121 +1437,28 @@ pub async fn delete_repository(
 }
 
 pub async fn sync_repository(
+    req: HttpRequest,
     data: web::Json<SyncRequest>,
     state: web::Data<AppState>,
 ) -> Result<HttpResponse, AppError> {
-    // Clone necessary data before spawning blocking task
-    let repository_id = data.repository_id.clone();
-    let git_service = state.git_service.clone();
-    let config_arc = Arc::clone(&state.config);
-    let config_persistence = state.config_persistence.clone();
-
-    // Read config to get repository and credential info
-    let (repository, repository_for_sync, credential, encryption_key, webhooks, sync_attempts) = {
-        let config = state.config.read().await;
-        let repository = config
-            .repositories
-            .iter()
-            .find(|r| r.id == repository_id)
-            .ok_or_else(|| AppError::NotFound("Repository not found".to_string()))?
-            .clone();
-
-        if !repository.enabled {
-            return Err(AppError::BadRequest("Repository is disabled".to_string()));
-        }
-
-        let repository_for_sync = repository.clone(); // Clone for blocking task
-        let credential = repository
-            .credential_id
-            .as_ref()
-            .and_then(|id| config.credentials.get(id).cloned());
-        let encryption_key = config.server.encryption_key.clone();
-        let webhooks = config.server.error_webhooks.clone();
-        let sync_attempts = config.server.sync_attempts;
-        (
-            repository,
-            repository_for_sync,
-            credential,
-            encryption_key,
-            webhooks,
-            sync_attempts,
-        )
-    }; // Release lock before blocking operation
-
-    // Run the blocking sync operation in a blocking thread pool
-    let sync_result = tokio::task::spawn_blocking(move || {
-        git_service.sync_repository(&repository_for_sync, credential.as_ref(), &encryption_key)
-    })
-    .await
-    .map_err(|e| AppError::InternalError(format!("Task join error: {}", e)))?;
-
-    // Handle sync errors and notify webhooks
-    let sync_result_data = match sync_result {
-        Ok(result) => result,
-        Err(e) => {
-            let error_message = e.to_string();
-
-            // Notify webhooks about the error
-            webhooks::notify_error_webhooks(
-                &webhooks,
-                &repository,
-                "sync",
-                repository.credential_id.as_ref(),
-                &error_message,
-            )
-            .await;
-
-            // Handle sync failure (update attempts_left, potentially disable repo)
-            let mut config = config_arc.write().await;
-            let (was_disabled, config_to_save) = if let Some(repo) = config
-                .repositories
-                .iter_mut()
-                .find(|r| r.id == repository_id)
-            {
-                let disabled =
-                    handle_sync_failure(repo, &error_message, sync_attempts, &webhooks).await;
-                (disabled, Some(config.clone()))
-            } else {
-                (false, None)
-            };
-            drop(config); // Release lock before async operation
-
-            if let Some(config_data) = config_to_save {
-                config_persistence.request_save(config_data);
-            }
-
-            if was_disabled {
-                return Err(AppError::BadRequest(format!(
-                    "Repository {} ran out of sync attempts and has been disabled",
-                    repository_id
-                )));
-            }
-
-            return Err(AppError::InternalError(error_message));
-        }
-    };
-
-    // Update repository size, last_sync, commit hash, and commit message on success
-    let mut config = config_arc.write().await;
-    let config_to_save = if let Some(repo) = config
-        .repositories
-        .iter_mut()
-        .find(|r| r.id == repository_id)
     {
-        repo.size = Some(sync_result_data.size);
-        repo.last_sync = Some(chrono::Utc::now());
-        repo.last_sync_commit_hash = Some(sync_result_data.commit_hash.clone());
-        repo.last_sync_message = Some(sync_result_data.status_message.clone());
-        handle_sync_success(repo);
-        Some(config.clone())
-    } else {
-        None
-    };
-    drop(config); // Release lock before async operation
-
-    if let Some(config_data) = config_to_save {
-        config_persistence.request_save(config_data);
+        let config = state.config.read().await;
+        state.audit_log.record(audit_entry(
+            &req,
+            &config,
+            "repository.sync",
+            &data.repository_id,
+        ));
     }
+    let sync_result_data = sync::sync_repository(
+        &state.config,
+        &state.git_service,
+        &state.config_persistence,
+        &state.webhook_service,
+        &data.repository_id,
+        SyncTrigger::Manual,
+    )
+    .await?;
 
     Ok(HttpResponse::Ok().json(serde_json::json!({
         "message": sync_result_data.status_message,
@@ -630,6 +1470,254 @@ pub async fn sync_repository(
     })))
 }
 
+/// Receives push events from GitHub, GitLab or Gitea and queues a sync of the
+/// matching repositories.
+///
+/// The request must be signed with the configured `server.push_hooks.secret`.
+/// Events other than pushes are acknowledged and ignored.
+pub async fn push_hook(
+    path: web::Path<ForgeProvider>,
+    req: HttpRequest,
+    body: web::Bytes,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, AppError> {
+    let provider = path.into_inner();
+    let secret = {
+        let config = state.config.read().await;
+        config
+            .server
+            .push_hooks
+            .as_ref()
+            .map(|hooks| hooks.secret.clone())
+            // Anyone could sign requests with an empty secret
+            .filter(|secret| !secret.is_empty())
+            .ok_or_else(|| AppError::NotFound("Push hooks are not enabled".to_string()))?
+    };
+
+    push_hooks::verify_request(provider, req.headers(), &body, &secret)?;
+
+    if !push_hooks::is_push_event(provider, req.headers()) {
+        return Ok(HttpResponse::Ok().json(serde_json::json!({ "status": "ignored" })));
+    }
+
+    let payload: serde_json::Value = serde_json::from_slice(&body)
+        .map_err(|e| AppError::BadRequest(format!("Invalid webhook payload: {}", e)))?;
+    let payload_ids = push_hooks::repository_ids(provider, &payload);
+
+    let config = state.config.read().await;
+    let matching: Vec<&Repository> = config
+        .repositories
+        .iter()
+        .filter(|r| payload_ids.contains(&GitService::repo_id_from_url(&r.url)))
+        .collect();
+    if matching.is_empty() {
+        return Err(AppError::NotFound(
+            "No configured repository matches the webhook payload".to_string(),
+        ));
+    }
+
+    let queued: Vec<String> = matching
+        .iter()
+        .filter(|r| r.enabled)
+        .map(|r| r.id.clone())
+        .collect();
+    drop(config);
+
+    for repository_id in &queued {
+        info!(repository = %repository_id, "Push event received, queueing sync");
+        state.sync_queue.enqueue(repository_id);
+    }
+
+    Ok(HttpResponse::Accepted().json(serde_json::json!({ "queued": queued })))
+}
+
+/// Query parameters for listing sync runs.
+#[derive(Debug, Deserialize)]
+pub struct SyncLogsQuery {
+    /// Only return runs of this repository
+    pub repository_id: Option<String>,
+    /// Maximum number of runs to return (default: 20)
+    pub limit: Option<usize>,
+}
+
+/// Lists the latest sync runs with their captured log lines, newest first.
+pub async fn list_sync_logs(
+    query: web::Query<SyncLogsQuery>,
+    sync_logs: web::Data<SyncLogs>,
+) -> HttpResponse {
+    let mut runs = sync_logs.runs(query.repository_id.as_deref());
+    runs.truncate(query.limit.unwrap_or(20));
+    HttpResponse::Ok().json(runs)
+}
+
+/// Returns the log lines captured for a single sync run.
+pub async fn get_sync_log(
+    path: web::Path<String>,
+    sync_logs: web::Data<SyncLogs>,
+) -> Result<HttpResponse, AppError> {
+    let run = sync_logs
+        .get(&path.into_inner())
+        .ok_or_else(|| AppError::NotFound("Sync run not found".to_string()))?;
+    Ok(HttpResponse::Ok().json(run))
+}
+
+/// Query parameters for listing webhook deliveries.
+#[derive(Debug, Deserialize)]
+pub struct DeliveriesQuery {
+    /// Only return deliveries with this status
+    pub status: Option<DeliveryStatus>,
+    /// Maximum number of deliveries to return (default: 100)
+    pub limit: Option<usize>,
+}
+
+pub async fn list_webhook_deliveries(
+    query: web::Query<DeliveriesQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, AppError> {
+    let deliveries: Vec<WebhookDelivery> = state
+        .webhook_service
+        .deliveries()
+        .into_iter()
+        .filter(|d| query.status.is_none_or(|status| d.status == status))
+        .take(query.limit.unwrap_or(100))
+        .collect();
+
+    Ok(HttpResponse::Ok().json(deliveries))
+}
+
+pub async fn redeliver_webhook(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, AppError> {
+    let delivery_id = path.into_inner();
+    let config = state.config.read().await;
+    let webhooks: Vec<_> = config
+        .server
+        .subscriptions()
+        .into_iter()
+        .map(|subscription| subscription.webhook)
+        .collect();
+    let delivery = state.webhook_service.redeliver(&delivery_id, &webhooks)?;
+    state.audit_log.record(audit_entry(
+        &req,
+        &config,
+        "webhook.redeliver",
+        &delivery_id,
+    ));
+    Ok(HttpResponse::Accepted().json(delivery))
+}
+
+/// Query parameters for listing the audit log.
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    /// Only return entries of this user
+    pub actor: Option<String>,
+    /// Only return entries of this action, or of its group (e.g. `repository`)
+    pub action: Option<String>,
+    /// Only return entries about this repository, credential, user, ...
+    pub target: Option<String>,
+    /// Only return entries recorded at or after this time
+    pub since: Option<chrono::DateTime<chrono::Utc>>,
+    /// Only return entries recorded before this time
+    pub until: Option<chrono::DateTime<chrono::Utc>>,
+    /// Maximum number of entries to return (default: 100)
+    pub limit: Option<usize>,
+}
+
+/// Lists the audit log, newest first.
+pub async fn list_audit_log(
+    query: web::Query<AuditQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, AppError> {
+    let matches_action = |action: &str| {
+        query.action.as_deref().is_none_or(|filter| {
+            action == filter
+                || action
+                    .strip_prefix(filter)
+                    .is_some_and(|rest| rest.starts_with('.'))
+        })
+    };
+    let entries: Vec<AuditEntry> = state
+        .audit_log
+        .entries()
+        .into_iter()
+        .filter(|e| query.actor.as_ref().is_none_or(|actor| &e.actor == actor))
+        .filter(|e| matches_action(&e.action))
+        .filter(|e| {
+            query
+                .target
+                .as_ref()
+                .is_none_or(|target| e.target.as_ref() == Some(target))
+        })
+        .filter(|e| query.since.is_none_or(|since| e.time >= since))
+        .filter(|e| query.until.is_none_or(|until| e.time < until))
+        .take(query.limit.unwrap_or(100))
+        .collect();
+
+    Ok(HttpResponse::Ok().json(entries))
+}
+
+/// Request body for sending a sample event to a webhook.
+///
+/// Exactly one of `url` and `webhook` must be set.
+#[derive(Debug, Deserialize)]
+pub struct TestWebhookRequest {
+    /// URL of a configured webhook, tested with its configured options
+    pub url: Option<String>,
+    /// Webhook with other options than configured, e.g. to try out a template.
+    /// Its URL must be one of a configured webhook.
+    pub webhook: Option<Webhook>,
+    /// Event to simulate (default: error)
+    #[serde(default)]
+    pub event: Option<NotificationEvent>,
+}
+
+pub async fn test_webhook(
+    req: HttpRequest,
+    data: web::Json<TestWebhookRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, AppError> {
+    let data = data.into_inner();
+    let config = state.config.read().await;
+    let configured = |url: &str| {
+        config
+            .server
+            .subscriptions()
+            .into_iter()
+            .map(|subscription| subscription.webhook)
+            .find(|webhook| webhook.url == url)
+            .ok_or_else(|| AppError::NotFound(format!("Webhook {} is not configured", url)))
+    };
+    // Only configured receivers can be tested, so the server can't be made to
+    // send requests to arbitrary hosts.
+    let webhook = match (data.url, data.webhook) {
+        (Some(url), None) => configured(&url)?,
+        (None, Some(webhook)) => {
+            configured(&webhook.url)?;
+            webhook
+        }
+        _ => {
+            return Err(AppError::BadRequest(
+                "Either url or webhook must be provided".to_string(),
+            ))
+        }
+    };
+    drop(config);
+
+    let result = state
+        .webhook_service
+        .send_test(&webhook, data.event.unwrap_or(NotificationEvent::Error))
+        .await?;
+    state.audit_log.record(audit_entry(
+        &req,
+        &*state.config.read().await,
+        "webhook.test",
+        &result.delivery.id,
+    ));
+    Ok(HttpResponse::Ok().json(result))
+}
+
 pub async fn list_credentials(state: web::Data<AppState>) -> Result<HttpResponse, AppError> {
     let config = state.config.read().await;
     let credentials: Vec<CredentialResponse> = config
@@ -646,6 +1734,7 @@ pub async fn list_credentials(state: web::Data<AppState>) -> Result<HttpResponse
 }
 
 pub async fn add_credential(
+    req: HttpRequest,
     data: web::Json<AddCredentialRequest>,
     state: web::Data<AppState>,
 ) -> Result<HttpResponse, AppError> {
@@ -667,10 +1756,7 @@ pub async fn add_credential(
             None
         } else if key.starts_with("-----BEGIN") || key.contains('\n') {
             // It's plaintext SSH key content - encrypt it
-            Some(encryption::encrypt_ssh_key(
-                key,
-                &config.server.encryption_key,
-            )?)
+            Some(config.server.encryption_keys().encrypt(key)?)
         } else {
             // Reject keys that don't look like SSH key content (could be file paths)
             return Err(AppError::BadRequest(
@@ -683,10 +1769,7 @@ pub async fn add_credential(
 
     // Encrypt password if provided
     let password = if !data.password.is_empty() {
-        Some(encryption::encrypt_password(
-            &data.password,
-            &config.server.encryption_key,
-        )?)
+        Some(config.server.encryption_keys().encrypt(&data.password)?)
     } else {
         None
     };
@@ -720,6 +1803,11 @@ pub async fn add_credential(
         is_ssh_key: ssh_key.is_some() && !ssh_key.as_ref().unwrap().is_empty(),
     };
 
+    notify_credential_changed(&state, &config, &credential.id, "added");
+    state.audit_log.record(
+        audit_entry(&req, &config, "credential.add", &credential.id)
+            .with_changes(None, Some(&credential)),
+    );
     config.credentials.insert(credential.id.clone(), credential);
     let config_to_save = config.clone();
     drop(config); // Release lock before async operation
@@ -729,6 +1817,7 @@ pub async fn add_credential(
 }
 
 pub async fn update_credential(
+    req: HttpRequest,
     path: web::Path<String>,
     data: web::Json<AddCredentialRequest>,
     state: web::Data<AppState>,
@@ -736,8 +1825,8 @@ pub async fn update_credential(
     let cred_id = path.into_inner();
     let mut config = state.config.write().await;
 
-    // Get encryption key before mutable borrow
-    let encryption_key = config.server.encryption_key.clone();
+    // Get encryption keys before mutable borrow
+    let encryption_keys = config.server.encryption_keys();
 
     // Get existing credential data before mutable borrow
     let existing_credential = config
@@ -758,10 +1847,7 @@ pub async fn update_credential(
     // Determine what will exist after update
     let final_password = if has_password {
         // Encrypt the new password
-        Some(encryption::encrypt_password(
-            &data.password,
-            &encryption_key,
-        )?)
+        Some(encryption_keys.encrypt(&data.password)?)
     } else {
         // Keep existing password (already encrypted or plaintext for backward compatibility)
         existing_credential.password.clone()
@@ -772,7 +1858,7 @@ pub async fn update_credential(
         let key = data.ssh_key.as_ref().unwrap();
         if key.starts_with("-----BEGIN") || key.contains('\n') {
             // It's plaintext SSH key content - encrypt it
-            Some(encryption::encrypt_ssh_key(key, &encryption_key)?)
+            Some(encryption_keys.encrypt(key)?)
         } else {
             // Reject keys that don't look like SSH key content (could be file paths)
             return Err(AppError::BadRequest(
@@ -806,7 +1892,13 @@ pub async fn update_credential(
         username: credential.username.clone(),
         is_ssh_key: final_ssh_key.is_some() && !final_ssh_key.as_ref().unwrap().is_empty(),
     };
+    let after = credential.clone();
+    state.audit_log.record(
+        audit_entry(&req, &config, "credential.update", &cred_id)
+            .with_changes(Some(&existing_credential), Some(&after)),
+    );
 
+    notify_credential_changed(&state, &config, &cred_id, "updated");
     let config_to_save = config.clone();
     drop(config); // Release lock before async operation
     state.config_persistence.request_save(config_to_save);
@@ -815,6 +1907,7 @@ pub async fn update_credential(
 }
 
 pub async fn delete_credential(
+    req: HttpRequest,
     path: web::Path<String>,
     state: web::Data<AppState>,
 ) -> Result<HttpResponse, AppError> {
@@ -833,12 +1926,233 @@ pub async fn delete_credential(
         )));
     }
 
-    if config.credentials.remove(&cred_id).is_none() {
+    let Some(credential) = config.credentials.remove(&cred_id) else {
         return Err(AppError::NotFound(format!(
             "Credential {} not found",
             cred_id
         )));
+    };
+    state.audit_log.record(
+        audit_entry(&req, &config, "credential.delete", &cred_id)
+            .with_changes(Some(&credential), None),
+    );
+    notify_credential_changed(&state, &config, &cred_id, "deleted");
+
+    let config_to_save = config.clone();
+    drop(config); // Release lock before async operation
+    state.config_persistence.request_save(config_to_save);
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Re-encrypts the passwords and SSH keys of all credentials, and the
+/// two-factor secrets of all users, with the current encryption key.
+///
+/// Run after rotating `encryption_key`; once nothing failed, the retired keys
+/// can be removed. Secrets that can't be decrypted are left unchanged.
+pub async fn reencrypt_credentials(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, AppError> {
+    let mut config = state.config.write().await;
+    let encryption_keys = config.server.encryption_keys();
+    let mut response = ReencryptionResponse {
+        key_id: encryption_keys.current_key_id(),
+        credentials: Vec::new(),
+        users: Vec::new(),
+        failed_credentials: Vec::new(),
+        failed_users: Vec::new(),
+    };
+
+    for credential in config.credentials.values_mut() {
+        match reencrypt_credential(&encryption_keys, credential) {
+            Ok(true) => response.credentials.push(credential.id.clone()),
+            Ok(false) => {}
+            Err(e) => {
+                warn!(credential_id = %credential.id, error = %e, "Failed to re-encrypt credential");
+                response.failed_credentials.push(credential.id.clone());
+            }
+        }
+    }
+    for user in config.users.iter_mut() {
+        let Some(user_totp) = user.totp.as_mut() else {
+            continue;
+        };
+        if encryption_keys.is_current(&user_totp.secret) {
+            continue;
+        }
+        match encryption_keys
+            .decrypt(&user_totp.secret)
+            .and_then(|secret| encryption_keys.encrypt(&secret))
+        {
+            Ok(secret) => {
+                user_totp.secret = secret;
+                response.users.push(user.username.clone());
+            }
+            Err(e) => {
+                warn!(
+                    user = %user.username,
+                    error = %e,
+                    "Failed to re-encrypt two-factor secret"
+                );
+                response.failed_users.push(user.username.clone());
+            }
+        }
+    }
+    response.credentials.sort();
+    response.failed_credentials.sort();
+
+    for id in &response.credentials {
+        state
+            .audit_log
+            .record(audit_entry(&req, &config, "credential.reencrypt", id));
+    }
+    for username in &response.users {
+        state
+            .audit_log
+            .record(audit_entry(&req, &config, "user.reencrypt_totp", username));
+    }
+    info!(
+        credentials = response.credentials.len(),
+        two_factor_secrets = response.users.len(),
+        key_id = %response.key_id,
+        "Re-encrypted stored secrets"
+    );
+
+    if !response.credentials.is_empty() || !response.users.is_empty() {
+        let config_to_save = config.clone();
+        drop(config); // Release lock before async operation
+        state.config_persistence.request_save(config_to_save);
+    }
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Re-encrypts the password and SSH key of a credential if they aren't
+/// encrypted with the current key. Returns whether either was; on errors the
+/// credential is unchanged.
+fn reencrypt_credential(
+    encryption_keys: &EncryptionKeys,
+    credential: &mut Credential,
+) -> Result<bool, AppError> {
+    let reencrypt = |stored: &Option<String>| match stored {
+        Some(stored) if !stored.is_empty() && !encryption_keys.is_current(stored) => {
+            encryption_keys.reencrypt(stored).map(Some)
+        }
+        _ => Ok(None),
+    };
+    let password = reencrypt(&credential.password)?;
+    let ssh_key = reencrypt(&credential.ssh_key)?;
+
+    let changed = password.is_some() || ssh_key.is_some();
+    if password.is_some() {
+        credential.password = password;
+    }
+    if ssh_key.is_some() {
+        credential.ssh_key = ssh_key;
+    }
+    Ok(changed)
+}
+
+/// Lists the personal API tokens of the authenticated user.
+pub async fn list_api_tokens(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, AppError> {
+    let username = get_authenticated_user(&req)?;
+    let config = state.config.read().await;
+    let api_tokens: Vec<ApiTokenResponse> = config
+        .api_tokens
+        .iter()
+        .filter(|t| t.username == username)
+        .map(ApiTokenResponse::from)
+        .collect();
+
+    Ok(HttpResponse::Ok().json(api_tokens))
+}
+
+/// Creates a personal API token for the authenticated user.
+///
+/// The token is only returned in this response; GitSafe keeps just its hash.
+pub async fn create_api_token(
+    req: HttpRequest,
+    data: web::Json<CreateApiTokenRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, AppError> {
+    let username = get_authenticated_user(&req)?;
+    let name = data.name.trim();
+    if name.is_empty() {
+        return Err(AppError::BadRequest(
+            "API token name cannot be empty".to_string(),
+        ));
+    }
+    if data.scopes.is_empty() {
+        return Err(AppError::BadRequest(
+            "At least one scope is required".to_string(),
+        ));
     }
+    if data.expires_in_days == Some(0) {
+        return Err(AppError::BadRequest(
+            "expires_in_days must be at least 1".to_string(),
+        ));
+    }
+
+    let token = auth::generate_api_token();
+    let now = chrono::Utc::now();
+    let api_token = ApiToken {
+        id: Uuid::new_v4().to_string(),
+        username,
+        name: name.to_string(),
+        token_hash: auth::hash_api_token(&token),
+        scopes: data.scopes.clone(),
+        created_at: now,
+        expires_at: data
+            .expires_in_days
+            .map(|days| now + chrono::Duration::days(days.into())),
+        last_used: None,
+    };
+    let response = CreateApiTokenResponse {
+        token,
+        api_token: ApiTokenResponse::from(&api_token),
+    };
+
+    let mut config = state.config.write().await;
+    state.audit_log.record(
+        audit_entry(&req, &config, "token.create", &api_token.id)
+            .with_changes(None, Some(&api_token)),
+    );
+    config.api_tokens.push(api_token);
+    let config_to_save = config.clone();
+    drop(config); // Release lock before async operation
+    state.config_persistence.request_save(config_to_save);
+
+    Ok(HttpResponse::Created().json(response))
+}
+
+/// Revokes a personal API token of the authenticated user.
+pub async fn delete_api_token(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, AppError> {
+    let username = get_authenticated_user(&req)?;
+    let token_id = path.into_inner();
+    let mut config = state.config.write().await;
+
+    let Some(index) = config
+        .api_tokens
+        .iter()
+        .position(|t| t.id == token_id && t.username == username)
+    else {
+        return Err(AppError::NotFound(format!(
+            "API token {} not found",
+            token_id
+        )));
+    };
+    let api_token = config.api_tokens.remove(index);
+    state.audit_log.record(
+        audit_entry(&req, &config, "token.delete", &token_id).with_changes(Some(&api_token), None),
+    );
 
     let config_to_save = config.clone();
     drop(config); // Release lock before async operation
@@ -847,8 +2161,129 @@ pub async fn delete_credential(
     Ok(HttpResponse::NoContent().finish())
 }
 
-pub async fn health_check() -> HttpResponse {
+/// Logs a login lockout and sends a `login_locked_out` notification.
+fn notify_login_locked_out(state: &AppState, config: &Config, lockout: &Lockout) {
+    let message = format!(
+        "Login locked for {} until {} after {} failed attempts",
+        lockout.target,
+        lockout.locked_until.format("%Y-%m-%d %H:%M UTC"),
+        lockout.failures
+    );
+    warn!(
+        lockout = %lockout.target,
+        failures = lockout.failures,
+        locked_until = %lockout.locked_until,
+        "Login locked out"
+    );
+
+    let mut details = serde_json::json!({
+        "failures": lockout.failures,
+        "locked_until": lockout.locked_until.to_rfc3339(),
+    });
+    match &lockout.target {
+        LockoutTarget::User(username) => details["username"] = username.as_str().into(),
+        LockoutTarget::Address(ip) => details["address"] = ip.to_string().into(),
+    }
+    webhooks::notify_event(
+        &state.webhook_service,
+        &config.server.subscriptions(),
+        NotificationEvent::LoginLockedOut,
+        None,
+        &message,
+        details,
+    );
+}
+
+/// Sends a `credential_changed` notification. Secrets are never included.
+fn notify_credential_changed(state: &AppState, config: &Config, credential_id: &str, action: &str) {
+    webhooks::notify_event(
+        &state.webhook_service,
+        &config.server.subscriptions(),
+        NotificationEvent::CredentialChanged,
+        None,
+        &format!("Credential {} was {}", credential_id, action),
+        serde_json::json!({ "credential_id": credential_id, "action": action }),
+    );
+}
+
+/// Liveness probe: the process is up and serving requests.
+pub async fn health_live() -> HttpResponse {
     HttpResponse::Ok().json(serde_json::json!({
         "status": "ok"
     }))
 }
+
+/// Readiness probe: `200 OK` if all checks pass, `503 Service Unavailable` otherwise.
+///
+/// See `health::check_readiness` for the checks.
+pub async fn health_ready(
+    state: web::Data<AppState>,
+    scheduler: web::Data<SchedulerStatus>,
+) -> HttpResponse {
+    let readiness = health::check_readiness(
+        &state.config,
+        &state.git_service,
+        &state.config_persistence,
+        &scheduler,
+        chrono::Utc::now(),
+    )
+    .await;
+
+    if readiness.is_ready() {
+        HttpResponse::Ok().json(readiness)
+    } else {
+        HttpResponse::ServiceUnavailable().json(readiness)
+    }
+}
+
+/// Serves Prometheus metrics if `server.metrics` is configured.
+///
+/// # Errors
+///
+/// Returns `AppError::NotFound` if metrics are disabled, or `AppError::AuthError`
+/// if a bearer token is configured and the request doesn't carry it.
+pub async fn metrics(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, AppError> {
+    let config = state.config.read().await;
+    let settings = config
+        .server
+        .metrics
+        .as_ref()
+        .ok_or_else(|| AppError::NotFound("Metrics are disabled".to_string()))?;
+
+    if let Some(ref token) = settings.bearer_token {
+        let provided = req
+            .headers()
+            .get("Authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        if !bool::from(provided.as_bytes().ct_eq(token.as_bytes())) {
+            return Err(AppError::AuthError("Invalid metrics token".to_string()));
+        }
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(crate::metrics::CONTENT_TYPE)
+        .body(crate::metrics::render(&config)))
+}
+
+/// Reports `ok`, or `degraded` with the stale backups while any backup is stale.
+///
+/// Stale backups are usually caused by remotes, not by the server, so the
+/// status code stays `200 OK` and health checks of orchestrators don't restart it.
+pub async fn health_check(watchdog: web::Data<Watchdog>) -> HttpResponse {
+    let stale_backups = watchdog.stale();
+    if stale_backups.is_empty() {
+        return HttpResponse::Ok().json(serde_json::json!({
+            "status": "ok"
+        }));
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "status": "degraded",
+        "stale_backups": stale_backups,
+    }))
+}
@@ -0,0 +1,195 @@
+use crate::config::Config;
+use crate::config_persistence::ConfigPersistence;
+use crate::error::AppError;
+use crate::git::GitService;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::RwLock;
+
+/// Seconds without a scheduler heartbeat after which the scheduler is considered dead.
+/// The heartbeat job runs every minute.
+const HEARTBEAT_TIMEOUT_SECONDS: i64 = 180;
+
+/// Registered jobs and liveness of the cron scheduler.
+///
+/// The scheduler records every job it registers, and a heartbeat job running
+/// every minute shows that jobs are still being executed.
+#[derive(Clone, Default)]
+pub struct SchedulerStatus {
+    state: Arc<Mutex<SchedulerState>>,
+}
+
+#[derive(Default)]
+struct SchedulerState {
+    jobs: Vec<String>,
+    last_heartbeat: Option<DateTime<Utc>>,
+}
+
+impl SchedulerStatus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a job added to the scheduler (e.g. `sync`, `digest`, `watch_source:<id>`).
+    pub fn job_registered(&self, name: impl Into<String>) {
+        self.state.lock().unwrap().jobs.push(name.into());
+    }
+
+    /// Records that the scheduler is executing jobs.
+    pub fn heartbeat(&self) {
+        self.state.lock().unwrap().last_heartbeat = Some(Utc::now());
+    }
+}
+
+/// Result of a single readiness check.
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Why the check failed
+    pub error: Option<String>,
+    /// Check specific details, e.g. free space or the last save
+    #[serde(flatten)]
+    pub details: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Check {
+    fn new(error: Option<String>, details: serde_json::Value) -> Self {
+        Check {
+            ok: error.is_none(),
+            error,
+            details: details.as_object().cloned().unwrap_or_default(),
+        }
+    }
+}
+
+/// Number of repositories by state. Failing repositories don't affect readiness.
+#[derive(Debug, Serialize)]
+pub struct RepositoryCounts {
+    pub total: usize,
+    /// Enabled repositories whose last sync failed
+    pub failing: usize,
+    pub disabled: usize,
+}
+
+/// Response of `GET /health/ready`.
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    /// `ready`, or `not_ready` if any check failed
+    pub status: &'static str,
+    pub checks: BTreeMap<&'static str, Check>,
+    pub repositories: RepositoryCounts,
+}
+
+impl Readiness {
+    pub fn is_ready(&self) -> bool {
+        self.checks.values().all(|check| check.ok)
+    }
+}
+
+/// Checks whether GitSafe can do its job.
+///
+/// * `archive_dir` - the archive directory is writable and has at least
+///   `storage.min_free_space_mb` of free space
+/// * `config_persistence` - the save task is running and the last save succeeded
+/// * `scheduler` - the sync job is registered and the scheduler executed a job recently
+pub async fn check_readiness(
+    config: &Arc<RwLock<Config>>,
+    git_service: &GitService,
+    config_persistence: &ConfigPersistence,
+    scheduler: &SchedulerStatus,
+    now: DateTime<Utc>,
+) -> Readiness {
+    let (min_free_space_mb, repositories) = {
+        let cfg = config.read().await;
+        let repositories = RepositoryCounts {
+            total: cfg.repositories.len(),
+            failing: cfg
+                .repositories
+                .iter()
+                .filter(|r| r.enabled && r.error.is_some())
+                .count(),
+            disabled: cfg.repositories.iter().filter(|r| !r.enabled).count(),
+        };
+        (cfg.storage.min_free_space_mb, repositories)
+    };
+
+    let mut checks = BTreeMap::new();
+
+    let git_service_for_check = git_service.clone();
+    let available = tokio::task::spawn_blocking(move || {
+        git_service_for_check.check_writable()?;
+        git_service_for_check.available_space()
+    })
+    .await
+    .map_err(|e| AppError::InternalError(format!("Task join error: {}", e)))
+    .and_then(|result| result);
+    let error = match (&available, min_free_space_mb) {
+        (Err(e), _) => Some(format!("Archive directory is not usable: {}", e)),
+        (Ok(bytes), Some(min_free_space_mb)) if bytes / 1024 / 1024 < min_free_space_mb => {
+            Some(format!(
+                "Only {} MB free, below the minimum of {} MB",
+                bytes / 1024 / 1024,
+                min_free_space_mb
+            ))
+        }
+        _ => None,
+    };
+    checks.insert(
+        "archive_dir",
+        Check::new(
+            error,
+            serde_json::json!({ "available_bytes": available.ok() }),
+        ),
+    );
+
+    let save_status = config_persistence.status();
+    let error = if !config_persistence.is_running() {
+        Some("Config persistence task is not running".to_string())
+    } else {
+        save_status
+            .last_error
+            .as_ref()
+            .map(|e| format!("Last config save failed: {}", e))
+    };
+    checks.insert(
+        "config_persistence",
+        Check::new(
+            error,
+            serde_json::json!({ "last_success": save_status.last_success }),
+        ),
+    );
+
+    let (jobs, last_heartbeat) = {
+        let state = scheduler.state.lock().unwrap();
+        (state.jobs.clone(), state.last_heartbeat)
+    };
+    let error = if !jobs.iter().any(|job| job == "sync") {
+        Some("Sync job is not registered".to_string())
+    } else if last_heartbeat
+        .is_none_or(|heartbeat| (now - heartbeat).num_seconds() > HEARTBEAT_TIMEOUT_SECONDS)
+    {
+        Some("Scheduler is not executing jobs".to_string())
+    } else {
+        None
+    };
+    checks.insert(
+        "scheduler",
+        Check::new(
+            error,
+            serde_json::json!({ "jobs": jobs, "last_heartbeat": last_heartbeat }),
+        ),
+    );
+
+    let mut readiness = Readiness {
+        status: "ready",
+        checks,
+        repositories,
+    };
+    if !readiness.is_ready() {
+        readiness.status = "not_ready";
+    }
+    readiness
+}
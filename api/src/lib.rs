@@ -2,19 +2,42 @@
 //!
 //! This library provides functionality for:
 //! - Scheduled Git repository synchronization
+//! - Automatic discovery of repositories in forge organizations
 //! - Repository archiving (compact tarball or folder storage)
-//! - REST API for repository and credential management
-//! - JWT-based authentication
-//! - Error webhook notifications
+//! - REST API for repository and credential management, served over HTTP or HTTPS
+//! - JWT-based authentication, TOTP two-factor authentication and OpenID Connect single sign-on
+//! - Audit log of the changes made through the API
+//! - Error webhook and email notifications, periodic backup digest
+//! - Watchdog alerting about stale backups
+//! - Push webhooks triggering immediate syncs
+//! - Backup of forge metadata (issues, pull requests, releases, wikis)
+//! - Prometheus metrics, OpenTelemetry tracing and structured JSON logging
 
+pub mod audit;
 pub mod auth;
 pub mod config;
 pub mod config_persistence;
+pub mod discovery;
+pub mod email;
 pub mod encryption;
 pub mod error;
+pub mod forge;
 pub mod git;
 pub mod handlers;
+pub mod health;
+pub mod logging;
+pub mod metadata;
+pub mod metrics;
 pub mod middleware;
+pub mod oidc;
+pub mod push_hooks;
+pub mod rate_limit;
+pub mod sessions;
+pub mod sync;
+pub mod telemetry;
+pub mod tls;
+pub mod totp;
+pub mod watchdog;
 pub mod webhooks;
 
 pub use git::SyncResult;
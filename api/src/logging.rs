@@ -0,0 +1,350 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// Maximum number of log lines kept per sync run; later lines are dropped.
+pub const MAX_LINES_PER_RUN: usize = 1000;
+
+/// Field identifying a sync run, set on the span of every sync.
+const JOB_ID_FIELD: &str = "job_id";
+
+/// Fields recorded on a span, shared by the layers of this module.
+struct SpanFields(Map<String, Value>);
+
+/// Collects fields of spans and events as JSON values.
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for JsonVisitor<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
+        self.0
+            .insert(field.name().to_string(), Value::from(value.to_string()));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(
+            field.name().to_string(),
+            Value::from(format!("{:?}", value)),
+        );
+    }
+}
+
+/// Stores the fields of a new span, unless another layer already did.
+fn store_span_fields<S>(attrs: &Attributes<'_>, id: &Id, ctx: &Context<'_, S>)
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let Some(span) = ctx.span(id) else {
+        return;
+    };
+    let mut extensions = span.extensions_mut();
+    if extensions.get_mut::<SpanFields>().is_none() {
+        let mut fields = Map::new();
+        attrs.record(&mut JsonVisitor(&mut fields));
+        extensions.insert(SpanFields(fields));
+    }
+}
+
+/// Adds fields recorded after the creation of a span.
+fn update_span_fields<S>(id: &Id, values: &Record<'_>, ctx: &Context<'_, S>)
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    if let Some(span) = ctx.span(id) {
+        if let Some(fields) = span.extensions_mut().get_mut::<SpanFields>() {
+            values.record(&mut JsonVisitor(&mut fields.0));
+        }
+    }
+}
+
+/// Splits the fields of an event into its message and the other fields.
+fn event_fields(event: &Event<'_>) -> (String, Map<String, Value>) {
+    let mut fields = Map::new();
+    event.record(&mut JsonVisitor(&mut fields));
+    let message = match fields.remove("message") {
+        Some(Value::String(message)) => message,
+        Some(other) => other.to_string(),
+        None => String::new(),
+    };
+    (message, fields)
+}
+
+/// Layer writing every event as a single-line JSON object.
+///
+/// Besides `timestamp`, `level`, `target` and `message`, each line carries the
+/// fields of the event and of all enclosing spans, so log lines of a sync can be
+/// filtered by `repo_id`, `url`, `trigger` and `job_id`. Fields of inner spans
+/// and of the event override fields of outer spans with the same name.
+pub struct JsonLayer<W> {
+    make_writer: W,
+}
+
+impl<W> JsonLayer<W>
+where
+    W: for<'writer> MakeWriter<'writer> + 'static,
+{
+    pub fn new(make_writer: W) -> Self {
+        JsonLayer { make_writer }
+    }
+}
+
+impl<S, W> Layer<S> for JsonLayer<W>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+    W: for<'writer> MakeWriter<'writer> + 'static,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        store_span_fields(attrs, id, &ctx);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        update_span_fields(id, values, &ctx);
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let mut line = Map::new();
+        line.insert(
+            "timestamp".to_string(),
+            Value::from(Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)),
+        );
+        line.insert("level".to_string(), Value::from(metadata.level().as_str()));
+        line.insert("target".to_string(), Value::from(metadata.target()));
+
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope.from_root() {
+                if let Some(fields) = span.extensions().get::<SpanFields>() {
+                    line.extend(fields.0.clone());
+                }
+            }
+        }
+
+        let (message, fields) = event_fields(event);
+        line.extend(fields);
+        line.insert("message".to_string(), Value::from(message));
+
+        let Ok(mut bytes) = serde_json::to_vec(&line) else {
+            return;
+        };
+        bytes.push(b'\n');
+        let _ = self.make_writer.make_writer_for(metadata).write_all(&bytes);
+    }
+}
+
+/// A log line captured during a sync run.
+#[derive(Debug, Serialize, Clone)]
+pub struct LogLine {
+    pub timestamp: DateTime<Utc>,
+    pub level: String,
+    pub target: String,
+    pub message: String,
+    /// Fields of the event (without the fields of the sync span)
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub fields: Map<String, Value>,
+}
+
+/// The log lines of a single sync run.
+#[derive(Debug, Serialize, Clone)]
+pub struct SyncRun {
+    pub job_id: String,
+    pub repo_id: String,
+    pub url: Option<String>,
+    /// What initiated the sync (`schedule`, `manual` or `push`)
+    pub trigger: Option<String>,
+    pub started_at: DateTime<Utc>,
+    /// Time the sync finished (None while it's running)
+    pub finished_at: Option<DateTime<Utc>>,
+    pub lines: Vec<LogLine>,
+    /// Whether lines were dropped after `MAX_LINES_PER_RUN`
+    pub truncated: bool,
+}
+
+/// Log lines of the latest sync runs, kept in memory.
+///
+/// Lines are captured by the layer returned by [`SyncLogs::layer`] from every
+/// event inside a span with a `job_id` field. Only the latest `max_runs` runs are
+/// kept; older runs are dropped first.
+#[derive(Clone)]
+pub struct SyncLogs {
+    state: Arc<Mutex<SyncLogsState>>,
+}
+
+struct SyncLogsState {
+    max_runs: usize,
+    /// Runs in start order, oldest first
+    runs: VecDeque<SyncRun>,
+}
+
+impl SyncLogs {
+    pub fn new(max_runs: usize) -> Self {
+        SyncLogs {
+            state: Arc::new(Mutex::new(SyncLogsState {
+                max_runs,
+                runs: VecDeque::new(),
+            })),
+        }
+    }
+
+    /// Returns the layer capturing the log lines of sync runs into this store.
+    pub fn layer(&self) -> SyncLogLayer {
+        SyncLogLayer { logs: self.clone() }
+    }
+
+    /// Returns a sync run with its log lines.
+    pub fn get(&self, job_id: &str) -> Option<SyncRun> {
+        let state = self.state.lock().unwrap();
+        state.runs.iter().find(|run| run.job_id == job_id).cloned()
+    }
+
+    /// Returns the kept sync runs, newest first, optionally only of a single repository.
+    pub fn runs(&self, repo_id: Option<&str>) -> Vec<SyncRun> {
+        let state = self.state.lock().unwrap();
+        state
+            .runs
+            .iter()
+            .rev()
+            .filter(|run| repo_id.is_none_or(|id| run.repo_id == id))
+            .cloned()
+            .collect()
+    }
+
+    fn append(&self, span_fields: &Map<String, Value>, line: LogLine) {
+        let Some(job_id) = span_fields.get(JOB_ID_FIELD).and_then(Value::as_str) else {
+            return;
+        };
+        let text = |name: &str| {
+            span_fields
+                .get(name)
+                .and_then(Value::as_str)
+                .map(str::to_string)
+        };
+
+        let mut state = self.state.lock().unwrap();
+        if let Some(run) = state.runs.iter_mut().rev().find(|run| run.job_id == job_id) {
+            if run.url.is_none() {
+                run.url = text("url");
+            }
+            if run.lines.len() < MAX_LINES_PER_RUN {
+                run.lines.push(line);
+            } else {
+                run.truncated = true;
+            }
+            return;
+        }
+
+        if state.max_runs == 0 {
+            return;
+        }
+        while state.runs.len() >= state.max_runs {
+            state.runs.pop_front();
+        }
+        state.runs.push_back(SyncRun {
+            job_id: job_id.to_string(),
+            repo_id: text("repo_id").unwrap_or_default(),
+            url: text("url"),
+            trigger: text("trigger"),
+            started_at: line.timestamp,
+            finished_at: None,
+            lines: vec![line],
+            truncated: false,
+        });
+    }
+
+    fn finish(&self, job_id: &str) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(run) = state.runs.iter_mut().rev().find(|run| run.job_id == job_id) {
+            run.finished_at = Some(Utc::now());
+        }
+    }
+}
+
+/// Layer capturing the events of sync runs into [`SyncLogs`].
+pub struct SyncLogLayer {
+    logs: SyncLogs,
+}
+
+impl<S> Layer<S> for SyncLogLayer
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        store_span_fields(attrs, id, &ctx);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        update_span_fields(id, values, &ctx);
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let Some(scope) = ctx.event_scope(event) else {
+            return;
+        };
+        for span in scope {
+            let extensions = span.extensions();
+            let Some(fields) = extensions.get::<SpanFields>() else {
+                continue;
+            };
+            if !fields.0.contains_key(JOB_ID_FIELD) {
+                continue;
+            }
+
+            let metadata = event.metadata();
+            let (message, event_fields) = event_fields(event);
+            self.logs.append(
+                &fields.0,
+                LogLine {
+                    timestamp: Utc::now(),
+                    level: metadata.level().to_string(),
+                    target: metadata.target().to_string(),
+                    message,
+                    fields: event_fields,
+                },
+            );
+            return;
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let extensions = span.extensions();
+        if let Some(job_id) = extensions
+            .get::<SpanFields>()
+            .and_then(|fields| fields.0.get(JOB_ID_FIELD))
+            .and_then(Value::as_str)
+        {
+            self.logs.finish(job_id);
+        }
+    }
+}
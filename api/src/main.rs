@@ -1,25 +1,44 @@
+pub mod audit;
 pub mod auth;
 pub mod config;
 pub mod config_persistence;
+pub mod discovery;
+pub mod email;
 pub mod encryption;
 pub mod error;
+pub mod forge;
 pub mod git;
 pub mod handlers;
+pub mod health;
+pub mod logging;
+pub mod metadata;
+pub mod metrics;
 pub mod middleware;
+pub mod oidc;
+pub mod push_hooks;
+pub mod rate_limit;
 mod scheduler;
-mod webhooks;
+pub mod sessions;
+pub mod sync;
+pub mod telemetry;
+pub mod tls;
+pub mod totp;
+pub mod watchdog;
+pub mod webhooks;
 
 use crate::config::Config;
 use crate::handlers::AppState;
 use crate::middleware::AuthMiddleware;
 use actix_files as fs;
-use actix_web::{web, App, HttpServer, Result};
-use log::info;
+use actix_web::middleware::from_fn;
+use actix_web::{web, App, HttpRequest, HttpServer, Result};
 use std::env;
 use std::fs as std_fs;
 use std::path::Path;
 use std::sync::Arc;
 use tokio::sync::RwLock;
+use tracing::{info, warn};
+use tracing_actix_web::TracingLogger;
 
 const DEFAULT_CONFIG_PATH: &str = "config.yaml";
 
@@ -35,20 +54,25 @@ async fn spa_index(static_dir: web::Data<String>) -> Result<fs::NamedFile> {
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    env_logger::init();
     let config_path = get_config_path();
     // Load or create config
-    let config = if Path::new(&config_path).exists() {
-        Config::load(config_path.clone()).expect("Failed to load config")
-    } else {
+    let created = !Path::new(&config_path).exists();
+    let config = if created {
         let config = Config::default();
         config
             .save(config_path.clone())
             .expect("Failed to save config");
-        info!("Created default configuration file at {}", config_path);
         config
+    } else {
+        Config::load(config_path.clone()).expect("Failed to load config")
     };
 
+    // Logging and trace export are configured by the loaded config
+    let telemetry = telemetry::init(config.server.tracing.as_ref(), &config.server.logging);
+    if created {
+        info!(path = %config_path, "Created default configuration file");
+    }
+
     // Create archive directory
     std_fs::create_dir_all(&config.storage.archive_dir)
         .expect("Failed to create archive directory");
@@ -57,18 +81,37 @@ async fn main() -> std::io::Result<()> {
     let static_dir = Path::new(&config.server.static_dir);
     if !static_dir.exists() {
         std_fs::create_dir_all(static_dir).expect("Failed to create static directory");
-        info!("Created static directory at {}", static_dir.display());
+        info!(path = %static_dir.display(), "Created static directory");
+    }
+
+    let secrets_to_reencrypt = config.secrets_to_reencrypt();
+    if secrets_to_reencrypt > 0 {
+        warn!(
+            secrets = secrets_to_reencrypt,
+            "Stored secrets aren't encrypted with the current encryption key; re-encrypt them with POST /api/credentials/reencrypt"
+        );
     }
 
     let host = config.server.host.clone();
     let port = config.server.port;
+    let tls_settings = config.server.tls.clone();
     let jwt_secret = config.server.jwt_secret.clone();
     let static_dir_path = config.server.static_dir.clone();
     let archive_dir = config.storage.archive_dir.clone();
     let compact = config.storage.compact;
 
     let config = Arc::new(RwLock::new(config));
-    let auth_service = auth::AuthService::new(jwt_secret);
+    let auth_service = {
+        let cfg = config.read().await;
+        let sessions = sessions::SessionStore::load(&cfg.storage.data_dir)
+            .expect("Failed to load session store");
+        auth::AuthService::with_sessions(
+            jwt_secret,
+            cfg.server.previous_jwt_secret.clone(),
+            cfg.server.sessions.clone(),
+            sessions,
+        )
+    };
     let git_service =
         git::GitService::new(&archive_dir, compact).expect("Failed to create git service");
     let git_service_arc = Arc::new(git_service);
@@ -76,16 +119,66 @@ async fn main() -> std::io::Result<()> {
     // Create config persistence manager
     let config_persistence = config_persistence::ConfigPersistence::new(config_path.clone());
 
+    // Create webhook service (loads the delivery log)
+    let webhook_service = {
+        let cfg = config.read().await;
+        webhooks::WebhookService::new(cfg.server.webhook_delivery.clone(), &cfg.storage.data_dir)
+            .expect("Failed to create webhook service")
+    };
+
+    // Start the watchdog alerting about stale backups
+    let watchdog = watchdog::Watchdog::new();
+    watchdog.start(
+        Arc::clone(&config),
+        webhook_service.clone(),
+        std::time::Duration::from_secs(
+            config
+                .read()
+                .await
+                .scheduler
+                .watchdog_interval_minutes
+                .max(1)
+                * 60,
+        ),
+    );
+
     // Setup scheduler
+    let scheduler_status = health::SchedulerStatus::new();
     let _scheduler = scheduler::setup_scheduler(
         Arc::clone(&config),
         Arc::clone(&git_service_arc),
         config_persistence.clone(),
+        webhook_service.clone(),
+        scheduler_status.clone(),
     )
     .await
     .expect("Failed to setup scheduler");
 
-    info!("Starting server at {}:{}", host, port);
+    let push_hook_debounce = config
+        .read()
+        .await
+        .server
+        .push_hooks
+        .as_ref()
+        .map(|hooks| hooks.debounce_seconds)
+        .unwrap_or_default();
+    let sync_queue = sync::SyncQueue::new(
+        Arc::clone(&config),
+        (*git_service_arc).clone(),
+        config_persistence.clone(),
+        webhook_service.clone(),
+        std::time::Duration::from_secs(push_hook_debounce),
+    );
+
+    let audit_log = audit::AuditLog::load(&config.read().await.storage.data_dir)
+        .expect("Failed to load audit log");
+
+    info!(
+        scheme = if tls_settings.is_some() { "https" } else { "http" },
+        host = %host,
+        port,
+        "Starting server"
+    );
 
     let app_state = web::Data::new(AppState {
         config: Arc::clone(&config),
@@ -93,17 +186,42 @@ async fn main() -> std::io::Result<()> {
         auth_service,
         git_service: (*git_service_arc).clone(),
         config_persistence,
+        sync_queue,
+        webhook_service,
+        rate_limiter: rate_limit::RateLimiter::new(),
+        audit_log,
     });
 
+    let watchdog_data = web::Data::new(watchdog);
+    let sync_logs_data = web::Data::new(telemetry.sync_logs());
+    let oidc_data = web::Data::new(oidc::OidcService::new());
+    let scheduler_status_data = web::Data::new(scheduler_status);
     let static_dir_data = web::Data::new(static_dir_path.clone());
     let static_dir_for_files = static_dir_path.clone();
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         App::new()
             .app_data(app_state.clone())
             .app_data(static_dir_data.clone())
+            .app_data(watchdog_data.clone())
+            .app_data(scheduler_status_data.clone())
+            .app_data(sync_logs_data.clone())
+            .app_data(oidc_data.clone())
+            .wrap(from_fn(rate_limit::limit_requests))
+            .wrap(from_fn(metrics::track_requests))
+            .wrap(TracingLogger::default())
             // Public routes (no authentication required)
             .route("/health", web::get().to(handlers::health_check))
+            .route("/health/live", web::get().to(handlers::health_live))
+            .route("/health/ready", web::get().to(handlers::health_ready))
+            .route("/metrics", web::get().to(handlers::metrics))
             .route("/api/login", web::post().to(handlers::login))
+            .route(
+                "/api/token/refresh",
+                web::post().to(handlers::refresh_token),
+            )
+            .route("/api/oidc/login", web::get().to(handlers::oidc_login))
+            .route("/api/oidc/callback", web::get().to(handlers::oidc_callback))
+            .route("/api/hooks/{provider}", web::post().to(handlers::push_hook))
             // Protected routes (authentication required)
             .service(
                 web::scope("/api")
@@ -119,8 +237,24 @@ async fn main() -> std::io::Result<()> {
                         web::delete().to(handlers::delete_repository),
                     )
                     .route("/sync", web::post().to(handlers::sync_repository))
+                    .route("/sync-logs", web::get().to(handlers::list_sync_logs))
+                    .route("/audit", web::get().to(handlers::list_audit_log))
+                    .route("/sync-logs/{job_id}", web::get().to(handlers::get_sync_log))
+                    .route(
+                        "/webhooks/deliveries",
+                        web::get().to(handlers::list_webhook_deliveries),
+                    )
+                    .route(
+                        "/webhooks/deliveries/{id}/redeliver",
+                        web::post().to(handlers::redeliver_webhook),
+                    )
+                    .route("/webhooks/test", web::post().to(handlers::test_webhook))
                     .route("/credentials", web::get().to(handlers::list_credentials))
                     .route("/credentials", web::post().to(handlers::add_credential))
+                    .route(
+                        "/credentials/reencrypt",
+                        web::post().to(handlers::reencrypt_credentials),
+                    )
                     .route(
                         "/credentials/{id}",
                         web::patch().to(handlers::update_credential),
@@ -128,7 +262,32 @@ async fn main() -> std::io::Result<()> {
                     .route(
                         "/credentials/{id}",
                         web::delete().to(handlers::delete_credential),
-                    ),
+                    )
+                    .route("/logout", web::post().to(handlers::logout))
+                    .route(
+                        "/account/password",
+                        web::post().to(handlers::change_password),
+                    )
+                    .route(
+                        "/account/totp",
+                        web::post().to(handlers::start_totp_enrollment),
+                    )
+                    .route("/account/totp", web::delete().to(handlers::disable_totp))
+                    .route(
+                        "/account/totp/confirm",
+                        web::post().to(handlers::confirm_totp),
+                    )
+                    .route(
+                        "/account/totp/recovery-codes",
+                        web::post().to(handlers::regenerate_recovery_codes),
+                    )
+                    .route("/users", web::get().to(handlers::list_users))
+                    .route("/users", web::post().to(handlers::add_user))
+                    .route("/users/{username}", web::patch().to(handlers::update_user))
+                    .route("/users/{username}", web::delete().to(handlers::delete_user))
+                    .route("/tokens", web::get().to(handlers::list_api_tokens))
+                    .route("/tokens", web::post().to(handlers::create_api_token))
+                    .route("/tokens/{id}", web::delete().to(handlers::delete_api_token)),
             )
             // Serve static files (JS, CSS, images, etc.) from static directory
             .service(
@@ -136,8 +295,42 @@ async fn main() -> std::io::Result<()> {
                     .index_file("index.html")
                     .default_handler(web::route().to(spa_index)),
             )
-    })
-    .bind((host, port))?
-    .run()
-    .await
+    });
+    let server = match &tls_settings {
+        Some(tls_settings) => {
+            let certificate = Arc::new(
+                tls::ReloadableCertificate::load(&tls_settings.cert_path, &tls_settings.key_path)
+                    .expect("Failed to load TLS certificate"),
+            );
+            if tls_settings.reload_interval_seconds > 0 {
+                certificate.watch(std::time::Duration::from_secs(
+                    tls_settings.reload_interval_seconds,
+                ));
+            }
+            let tls_config =
+                tls::server_config(tls_settings, certificate).expect("Failed to configure TLS");
+            server
+                .on_connect(tls::store_client_certificate)
+                .bind_rustls_0_23((host.clone(), port), tls_config)?
+        }
+        None => server.bind((host.clone(), port))?,
+    };
+
+    // Optional plain HTTP listener redirecting to HTTPS
+    match tls_settings.and_then(|tls_settings| tls_settings.redirect_http_port) {
+        Some(http_port) => {
+            info!(host = %host, port = http_port, "Redirecting HTTP to HTTPS");
+            let redirect = HttpServer::new(move || {
+                App::new().default_service(web::to(move |req: HttpRequest| async move {
+                    tls::redirect_to_https(&req, port)
+                }))
+            })
+            .bind((host, http_port))?;
+            futures_util::future::try_join(server.run(), redirect.run()).await?;
+        }
+        None => server.run().await?,
+    }
+
+    telemetry.shutdown();
+    Ok(())
 }
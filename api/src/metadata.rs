@@ -0,0 +1,447 @@
+use crate::config::{Credential, MetadataBackup, Repository};
+use crate::encryption::EncryptionKeys;
+use crate::error::AppError;
+use crate::forge::{ForgeClient, ForgeProvider};
+use crate::git::GitService;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Result of a forge metadata backup.
+#[derive(Debug, Clone)]
+pub struct MetadataResult {
+    /// Path to the metadata archive (compact mode) or folder (non-compact mode)
+    pub path: PathBuf,
+    /// Size in bytes
+    pub size: u64,
+    /// Number of exported issues
+    pub issues: usize,
+    /// Number of exported pull/merge requests
+    pub pull_requests: usize,
+    /// Number of exported releases
+    pub releases: usize,
+    /// Whether the wiki was backed up
+    pub wiki: bool,
+}
+
+/// Contents of `manifest.json`, linking the export to the code backup.
+#[derive(Debug, Serialize)]
+struct Manifest<'a> {
+    repository_id: &'a str,
+    provider: ForgeProvider,
+    project: &'a str,
+    /// Commit of the code backup this export belongs to
+    commit_hash: &'a str,
+    exported_at: DateTime<Utc>,
+    issues: usize,
+    pull_requests: usize,
+    releases: usize,
+    wiki: bool,
+}
+
+/// Extracts the project path (e.g. `org/repo` or `group/subgroup/repo`) from a Git URL.
+///
+/// Supports HTTPS, `ssh://` and SCP-like (`git@host:path`) URLs. Returns `None`
+/// if the URL has no path.
+///
+/// # Examples
+///
+/// ```
+/// use gitsafe::metadata::project_path_from_url;
+///
+/// let path = project_path_from_url("https://github.com/example/repo1.git");
+/// assert_eq!(path.as_deref(), Some("example/repo1"));
+///
+/// let path = project_path_from_url("git@gitlab.com:group/sub/repo.git");
+/// assert_eq!(path.as_deref(), Some("group/sub/repo"));
+/// ```
+pub fn project_path_from_url(url: &str) -> Option<String> {
+    let path = match url::Url::parse(url) {
+        Ok(parsed) => parsed.path().to_string(),
+        // SCP-like SSH URL: git@host:path
+        Err(_) => url.split_once(':')?.1.to_string(),
+    };
+
+    let path = path.trim_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    (!path.is_empty()).then(|| path.to_string())
+}
+
+/// Returns the URL of the wiki repository belonging to a repository URL.
+///
+/// GitHub, GitLab and Gitea all serve the wiki as `<repo>.wiki.git`.
+pub fn wiki_url(url: &str) -> String {
+    let url = url.trim_end_matches('/');
+    format!("{}.wiki.git", url.strip_suffix(".git").unwrap_or(url))
+}
+
+/// Backs up the forge metadata of a repository next to its code backup.
+///
+/// Writes `issues.json`, `pull_requests.json`, `comments.json`, `labels.json`,
+/// `milestones.json`, `releases.json` and `manifest.json` (GitHub additionally
+/// `review_comments.json`), downloads release assets to `releases/<tag>/` and
+/// clones the wiki to `wiki/` if it exists.
+///
+/// In compact mode the previous export is unpacked, updated and re-archived as
+/// `<repo>.metadata.tar.gz`; otherwise `<repo>.metadata/` is updated in place.
+/// Either way, already downloaded release assets are kept and the wiki is pulled
+/// incrementally.
+///
+/// # Arguments
+///
+/// * `git_service` - Git service providing storage paths and Git operations
+/// * `repository` - The repository whose metadata is backed up
+/// * `settings` - Metadata backup settings of the repository
+/// * `token` - Optional forge API token
+/// * `credential` - Optional credential for cloning the wiki
+/// * `encryption_keys` - Keys used to decrypt the credential
+/// * `commit_hash` - Commit of the code backup, recorded in the manifest
+///
+/// # Errors
+///
+/// Returns `AppError::NotFound` if the project doesn't exist on the forge,
+/// `AppError::ForgeError` if an API call fails, or an I/O error if storing fails.
+/// A missing wiki is not an error.
+pub async fn backup_metadata(
+    git_service: &GitService,
+    repository: &Repository,
+    settings: &MetadataBackup,
+    token: Option<String>,
+    credential: Option<&Credential>,
+    encryption_keys: &EncryptionKeys,
+    commit_hash: &str,
+) -> Result<MetadataResult, AppError> {
+    let project = settings
+        .project
+        .clone()
+        .or_else(|| project_path_from_url(&repository.url))
+        .ok_or_else(|| {
+            AppError::ConfigError(format!(
+                "Cannot derive project path from URL {}",
+                repository.url
+            ))
+        })?;
+    let client = ForgeClient::new(settings.provider, settings.api_url.as_deref(), token)?;
+
+    let metadata_path = git_service.metadata_path_from_url(&repository.url);
+    let entry_name = metadata_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .map(|n| n.strip_suffix(".tar.gz").unwrap_or(n).to_string())
+        .unwrap_or_else(|| "metadata".to_string());
+
+    // Compact mode works on an unpacked copy of the previous export
+    let temp_dir = tempfile::tempdir().map_err(AppError::IoError)?;
+    let work_dir = if git_service.is_compact() {
+        if metadata_path.exists() {
+            let git_service = git_service.clone();
+            let archive_path = metadata_path.clone();
+            let dest_dir = temp_dir.path().to_path_buf();
+            tokio::task::spawn_blocking(move || {
+                git_service.unpack_archive(&archive_path, &dest_dir)
+            })
+            .await
+            .map_err(|e| AppError::InternalError(format!("Task join error: {}", e)))??;
+        }
+        temp_dir.path().join(&entry_name)
+    } else {
+        metadata_path.clone()
+    };
+    fs::create_dir_all(&work_dir)?;
+
+    info!(
+        repository = %repository.id,
+        provider = ?settings.provider,
+        project = %project,
+        "Exporting forge metadata"
+    );
+    let mut manifest = Manifest {
+        repository_id: &repository.id,
+        provider: settings.provider,
+        project: &project,
+        commit_hash,
+        exported_at: Utc::now(),
+        issues: 0,
+        pull_requests: 0,
+        releases: 0,
+        wiki: false,
+    };
+    export_metadata(&client, &project, settings, &work_dir, &mut manifest).await?;
+
+    if settings.wiki {
+        manifest.wiki = backup_wiki(
+            git_service,
+            &repository.id,
+            &wiki_url(&repository.url),
+            &work_dir.join("wiki"),
+            credential,
+            encryption_keys,
+        )
+        .await?;
+    }
+
+    write_json(&work_dir, "manifest.json", &manifest)?;
+
+    let size = if git_service.is_compact() {
+        let git_service = git_service.clone();
+        let archive_path = metadata_path.clone();
+        tokio::task::spawn_blocking(move || {
+            git_service.replace_archive(&entry_name, &work_dir, &archive_path)
+        })
+        .await
+        .map_err(|e| AppError::InternalError(format!("Task join error: {}", e)))??
+    } else {
+        git_service.calculate_folder_size(&work_dir)?
+    };
+
+    info!(
+        "Backed up forge metadata of repository {}: {} issues, {} pull requests, {} releases, wiki: {} ({} bytes)",
+        repository.id,
+        manifest.issues,
+        manifest.pull_requests,
+        manifest.releases,
+        manifest.wiki,
+        size
+    );
+    Ok(MetadataResult {
+        path: metadata_path,
+        size,
+        issues: manifest.issues,
+        pull_requests: manifest.pull_requests,
+        releases: manifest.releases,
+        wiki: manifest.wiki,
+    })
+}
+
+/// Exports the JSON documents and release assets into `dir`.
+async fn export_metadata(
+    client: &ForgeClient,
+    project: &str,
+    settings: &MetadataBackup,
+    dir: &Path,
+    manifest: &mut Manifest<'_>,
+) -> Result<(), AppError> {
+    let provider = client.provider();
+    let base = match provider {
+        ForgeProvider::Github | ForgeProvider::Gitea => format!("/repos/{}", project),
+        ForgeProvider::Gitlab => format!(
+            "/projects/{}",
+            url::form_urlencoded::byte_serialize(project.as_bytes()).collect::<String>()
+        ),
+    };
+
+    if client.get_json::<Value>(&base).await?.is_none() {
+        return Err(AppError::NotFound(format!(
+            "Project {} not found on forge",
+            project
+        )));
+    }
+
+    let (issues, pull_requests, comments) = match provider {
+        ForgeProvider::Github => {
+            // The issues endpoint also lists pull requests
+            let issues = list(client, &format!("{}/issues?state=all", base))
+                .await?
+                .into_iter()
+                .filter(|issue| issue.get("pull_request").is_none())
+                .collect::<Vec<_>>();
+            let pull_requests = list(client, &format!("{}/pulls?state=all", base)).await?;
+            let comments = list(client, &format!("{}/issues/comments", base)).await?;
+            let review_comments = list(client, &format!("{}/pulls/comments", base)).await?;
+            write_json(dir, "review_comments.json", &review_comments)?;
+            (issues, pull_requests, comments)
+        }
+        ForgeProvider::Gitea => {
+            let issues = list(client, &format!("{}/issues?state=all&type=issues", base)).await?;
+            let pull_requests = list(client, &format!("{}/pulls?state=all", base)).await?;
+            let comments = list(client, &format!("{}/issues/comments", base)).await?;
+            (issues, pull_requests, comments)
+        }
+        ForgeProvider::Gitlab => {
+            let issues = list(client, &format!("{}/issues", base)).await?;
+            let pull_requests = list(client, &format!("{}/merge_requests", base)).await?;
+
+            // GitLab only lists notes per issue/merge request; each note carries
+            // `noteable_type` and `noteable_iid`
+            let mut comments = Vec::new();
+            for (kind, items) in [("issues", &issues), ("merge_requests", &pull_requests)] {
+                for item in items.iter().filter(|i| has_notes(i)) {
+                    if let Some(iid) = item.get("iid").and_then(Value::as_u64) {
+                        comments.extend(
+                            list(client, &format!("{}/{}/{}/notes", base, kind, iid)).await?,
+                        );
+                    }
+                }
+            }
+            (issues, pull_requests, comments)
+        }
+    };
+
+    let labels = list(client, &format!("{}/labels", base)).await?;
+    let milestones = match provider {
+        ForgeProvider::Gitlab => list(client, &format!("{}/milestones", base)).await?,
+        _ => list(client, &format!("{}/milestones?state=all", base)).await?,
+    };
+    let releases = list(client, &format!("{}/releases", base)).await?;
+
+    write_json(dir, "issues.json", &issues)?;
+    write_json(dir, "pull_requests.json", &pull_requests)?;
+    write_json(dir, "comments.json", &comments)?;
+    write_json(dir, "labels.json", &labels)?;
+    write_json(dir, "milestones.json", &milestones)?;
+    write_json(dir, "releases.json", &releases)?;
+
+    if settings.release_assets {
+        download_release_assets(client, &releases, &dir.join("releases")).await?;
+    }
+
+    manifest.issues = issues.len();
+    manifest.pull_requests = pull_requests.len();
+    manifest.releases = releases.len();
+    Ok(())
+}
+
+/// Lists all items of a paginated endpoint; a missing endpoint (e.g. a disabled
+/// feature) yields an empty list.
+async fn list(client: &ForgeClient, path: &str) -> Result<Vec<Value>, AppError> {
+    Ok(client.get_paginated(path).await?.unwrap_or_default())
+}
+
+fn has_notes(item: &Value) -> bool {
+    item.get("user_notes_count")
+        .and_then(Value::as_u64)
+        .map(|count| count > 0)
+        .unwrap_or(true)
+}
+
+/// Downloads the assets of all releases to `<dir>/<tag>/<asset name>`.
+///
+/// Assets that were downloaded before are skipped.
+async fn download_release_assets(
+    client: &ForgeClient,
+    releases: &[Value],
+    dir: &Path,
+) -> Result<(), AppError> {
+    for release in releases {
+        let Some(tag) = release.get("tag_name").and_then(Value::as_str) else {
+            continue;
+        };
+
+        for (name, url) in release_assets(client.provider(), release) {
+            let path = dir
+                .join(sanitize_file_name(tag))
+                .join(sanitize_file_name(&name));
+            if path.exists() {
+                continue;
+            }
+
+            let content = client.download(&url).await?;
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, content)?;
+            info!(asset = %name, release = %tag, "Downloaded release asset");
+        }
+    }
+    Ok(())
+}
+
+/// Returns the `(name, download URL)` pairs of the assets of a release.
+fn release_assets(provider: ForgeProvider, release: &Value) -> Vec<(String, String)> {
+    let (assets, url_fields): (Option<&Vec<Value>>, &[&str]) = match provider {
+        // The API URL serves the file itself with `Accept: application/octet-stream`,
+        // which also works for private repositories
+        ForgeProvider::Github => (release.get("assets").and_then(Value::as_array), &["url"]),
+        ForgeProvider::Gitea => (
+            release.get("assets").and_then(Value::as_array),
+            &["browser_download_url"],
+        ),
+        // Uploaded files and external links; generated source archives are skipped
+        ForgeProvider::Gitlab => (
+            release.pointer("/assets/links").and_then(Value::as_array),
+            &["direct_asset_url", "url"],
+        ),
+    };
+
+    assets
+        .into_iter()
+        .flatten()
+        .filter_map(|asset| {
+            let name = asset.get("name").and_then(Value::as_str)?;
+            let url = url_fields
+                .iter()
+                .find_map(|field| asset.get(*field).and_then(Value::as_str))?;
+            Some((name.to_string(), url.to_string()))
+        })
+        .collect()
+}
+
+/// Makes a tag or asset name safe to use as a single path component.
+fn sanitize_file_name(name: &str) -> String {
+    let sanitized = name.replace(['/', '\\'], "_");
+    match sanitized.as_str() {
+        "" | "." | ".." => "_".to_string(),
+        _ => sanitized,
+    }
+}
+
+/// Clones or pulls the wiki repository to `path`.
+///
+/// Returns `Ok(false)` if the repository has no wiki.
+async fn backup_wiki(
+    git_service: &GitService,
+    repository_id: &str,
+    url: &str,
+    path: &Path,
+    credential: Option<&Credential>,
+    encryption_keys: &EncryptionKeys,
+) -> Result<bool, AppError> {
+    let existed = path.exists();
+    let git_service = git_service.clone();
+    let name = format!("{} (wiki)", repository_id);
+    let url = url.to_string();
+    let wiki_path = path.to_path_buf();
+    let credential = credential.cloned();
+    let encryption_keys = encryption_keys.clone();
+
+    let result = tokio::task::spawn_blocking(move || {
+        git_service
+            .clone_or_pull(
+                &name,
+                &url,
+                &wiki_path,
+                credential.as_ref(),
+                &encryption_keys,
+            )
+            .map(|_| ())
+    })
+    .await
+    .map_err(|e| AppError::InternalError(format!("Task join error: {}", e)))?;
+
+    match result {
+        Ok(()) => Ok(true),
+        Err(e) if existed => {
+            // Keep the previous wiki backup
+            warn!(repository = %repository_id, error = %e, "Failed to update wiki");
+            Ok(true)
+        }
+        Err(e) => {
+            // Wikis that were never created can't be cloned
+            info!(repository = %repository_id, reason = %e, "Repository has no wiki");
+            if path.exists() {
+                fs::remove_dir_all(path)?;
+            }
+            Ok(false)
+        }
+    }
+}
+
+fn write_json<T: Serialize + ?Sized>(dir: &Path, name: &str, value: &T) -> Result<(), AppError> {
+    let content = serde_json::to_vec_pretty(value)
+        .map_err(|e| AppError::InternalError(format!("Failed to serialize {}: {}", name, e)))?;
+    fs::write(dir.join(name), content)?;
+    Ok(())
+}
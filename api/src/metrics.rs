@@ -0,0 +1,256 @@
+use crate::config::Config;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::Error;
+use prometheus::{
+    Encoder, GaugeVec, Histogram, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+/// Content type of the Prometheus text exposition format.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Prometheus metrics of the process, exposed at `GET /metrics`.
+///
+/// Events (syncs, scheduler runs, webhook deliveries, HTTP requests) are recorded
+/// where they happen. Repository state (last success, size, attempts left,
+/// enabled) is read from the configuration on every scrape, so it is also
+/// available for syncs that happened before a restart.
+struct Metrics {
+    registry: Registry,
+    /// Serializes scrapes, which reset and refill the repository gauges
+    render_lock: Mutex<()>,
+    repository_last_success: GaugeVec,
+    repository_last_attempt: GaugeVec,
+    repository_size: IntGaugeVec,
+    repository_attempts_left: IntGaugeVec,
+    repository_enabled: IntGaugeVec,
+    sync_duration: HistogramVec,
+    sync_failures: IntCounterVec,
+    scheduler_run_duration: Histogram,
+    webhook_deliveries: IntCounterVec,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+}
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("gitsafe".to_string()), None)
+            .expect("metrics prefix is valid");
+        let repository_gauge = |name: &str, help: &str| {
+            GaugeVec::new(Opts::new(name, help), &["repository"]).expect("metric is valid")
+        };
+        let repository_int_gauge = |name: &str, help: &str| {
+            IntGaugeVec::new(Opts::new(name, help), &["repository"]).expect("metric is valid")
+        };
+
+        let metrics = Metrics {
+            repository_last_success: repository_gauge(
+                "repository_last_success_timestamp_seconds",
+                "Unix time of the last successful sync",
+            ),
+            repository_last_attempt: repository_gauge(
+                "repository_last_attempt_timestamp_seconds",
+                "Unix time of the last sync attempt since the start of the process",
+            ),
+            repository_size: repository_int_gauge(
+                "repository_size_bytes",
+                "Size of the backup (archive size or folder size)",
+            ),
+            repository_attempts_left: repository_int_gauge(
+                "repository_attempts_left",
+                "Sync attempts left before the repository is disabled, only set after failures",
+            ),
+            repository_enabled: repository_int_gauge(
+                "repository_enabled",
+                "Whether the repository is enabled for syncing (1) or not (0)",
+            ),
+            sync_duration: HistogramVec::new(
+                HistogramOpts::new("sync_duration_seconds", "Duration of repository syncs")
+                    .buckets(vec![
+                        0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1800.0,
+                    ]),
+                &["repository", "result"],
+            )
+            .expect("metric is valid"),
+            sync_failures: IntCounterVec::new(
+                Opts::new("sync_failures_total", "Failed repository syncs"),
+                &["repository"],
+            )
+            .expect("metric is valid"),
+            scheduler_run_duration: Histogram::with_opts(
+                HistogramOpts::new(
+                    "scheduler_run_duration_seconds",
+                    "Duration of scheduled runs syncing all repositories",
+                )
+                .buckets(vec![
+                    1.0, 10.0, 30.0, 60.0, 300.0, 600.0, 1800.0, 3600.0, 7200.0, 14400.0,
+                ]),
+            )
+            .expect("metric is valid"),
+            webhook_deliveries: IntCounterVec::new(
+                Opts::new(
+                    "webhook_deliveries_total",
+                    "Finished outgoing webhook deliveries",
+                ),
+                &["event", "status"],
+            )
+            .expect("metric is valid"),
+            http_requests: IntCounterVec::new(
+                Opts::new("http_requests_total", "Handled HTTP requests"),
+                &["method", "path", "status"],
+            )
+            .expect("metric is valid"),
+            http_request_duration: HistogramVec::new(
+                HistogramOpts::new("http_request_duration_seconds", "Duration of HTTP requests"),
+                &["method", "path"],
+            )
+            .expect("metric is valid"),
+            registry,
+            render_lock: Mutex::new(()),
+        };
+
+        for collector in [
+            Box::new(metrics.repository_last_success.clone())
+                as Box<dyn prometheus::core::Collector>,
+            Box::new(metrics.repository_last_attempt.clone()),
+            Box::new(metrics.repository_size.clone()),
+            Box::new(metrics.repository_attempts_left.clone()),
+            Box::new(metrics.repository_enabled.clone()),
+            Box::new(metrics.sync_duration.clone()),
+            Box::new(metrics.sync_failures.clone()),
+            Box::new(metrics.scheduler_run_duration.clone()),
+            Box::new(metrics.webhook_deliveries.clone()),
+            Box::new(metrics.http_requests.clone()),
+            Box::new(metrics.http_request_duration.clone()),
+        ] {
+            metrics
+                .registry
+                .register(collector)
+                .expect("metric is registered once");
+        }
+        metrics
+    }
+}
+
+/// Records the start of a sync attempt.
+pub fn record_sync_started(repository_id: &str) {
+    METRICS
+        .repository_last_attempt
+        .with_label_values(&[repository_id])
+        .set(chrono::Utc::now().timestamp() as f64);
+}
+
+/// Records the outcome of a sync.
+///
+/// # Arguments
+///
+/// * `repository_id` - ID of the synced repository
+/// * `duration` - How long the sync took
+/// * `result` - `success`, `up_to_date` or `failure`
+pub fn record_sync_finished(repository_id: &str, duration: Duration, result: &str) {
+    METRICS
+        .sync_duration
+        .with_label_values(&[repository_id, result])
+        .observe(duration.as_secs_f64());
+    if result == "failure" {
+        METRICS
+            .sync_failures
+            .with_label_values(&[repository_id])
+            .inc();
+    }
+}
+
+/// Records the duration of a scheduled run.
+pub fn record_scheduler_run(duration: Duration) {
+    METRICS
+        .scheduler_run_duration
+        .observe(duration.as_secs_f64());
+}
+
+/// Records a webhook delivery that was delivered or failed for good.
+pub fn record_webhook_delivery(event: &str, status: &str) {
+    METRICS
+        .webhook_deliveries
+        .with_label_values(&[event, status])
+        .inc();
+}
+
+/// Middleware recording the count and duration of HTTP requests.
+///
+/// Requests are labeled with the route pattern (e.g. `/api/repositories/{id}`)
+/// rather than the path, so the number of series stays bounded. Requests not
+/// matching any route are labeled `unmatched`.
+pub async fn track_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let method = req.method().to_string();
+    let started = Instant::now();
+    let res = next.call(req).await?;
+
+    let path = res
+        .request()
+        .match_pattern()
+        .unwrap_or_else(|| "unmatched".to_string());
+    let status = res.status().as_u16().to_string();
+    METRICS
+        .http_requests
+        .with_label_values(&[&method, &path, &status])
+        .inc();
+    METRICS
+        .http_request_duration
+        .with_label_values(&[&method, &path])
+        .observe(started.elapsed().as_secs_f64());
+    Ok(res)
+}
+
+/// Renders all metrics in the Prometheus text format.
+///
+/// The repository gauges are rebuilt from `config` first, so removed
+/// repositories disappear from them.
+pub fn render(config: &Config) -> String {
+    let metrics = &*METRICS;
+    let _guard = metrics.render_lock.lock().unwrap();
+    metrics.repository_last_success.reset();
+    metrics.repository_size.reset();
+    metrics.repository_attempts_left.reset();
+    metrics.repository_enabled.reset();
+
+    for repo in &config.repositories {
+        let labels = [repo.id.as_str()];
+        if let Some(last_sync) = repo.last_sync {
+            metrics
+                .repository_last_success
+                .with_label_values(&labels)
+                .set(last_sync.timestamp() as f64);
+        }
+        if let Some(size) = repo.size {
+            metrics
+                .repository_size
+                .with_label_values(&labels)
+                .set(size as i64);
+        }
+        if let Some(attempts_left) = repo.attempts_left {
+            metrics
+                .repository_attempts_left
+                .with_label_values(&labels)
+                .set(attempts_left as i64);
+        }
+        metrics
+            .repository_enabled
+            .with_label_values(&labels)
+            .set(repo.enabled as i64);
+    }
+
+    let mut buffer = Vec::new();
+    if let Err(e) = TextEncoder::new().encode(&metrics.registry.gather(), &mut buffer) {
+        tracing::error!(error = %e, "Failed to encode metrics");
+    }
+    String::from_utf8(buffer).unwrap_or_default()
+}
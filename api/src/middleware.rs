@@ -1,5 +1,9 @@
+use crate::auth::{self, API_TOKEN_PREFIX};
+use crate::config::Role;
 use crate::error::AppError;
-use crate::handlers::AppState;
+use crate::handlers::{self, AppState};
+use crate::tls::ClientCertificate;
+use actix_web::http::header::HeaderMap;
 use actix_web::{
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
     Error, HttpMessage,
@@ -7,19 +11,34 @@ use actix_web::{
 use futures_util::future::LocalBoxFuture;
 use std::{
     future::{ready, Ready},
+    net::IpAddr,
     rc::Rc,
 };
+use tracing::warn;
 
-/// Extension data stored in request extensions containing the authenticated username.
+/// Extension data stored in request extensions containing the authenticated user.
 ///
 /// This is set by the AuthMiddleware after successful token verification.
-pub struct AuthenticatedUser(pub String);
+pub struct AuthenticatedUser {
+    pub username: String,
+    pub role: Role,
+    /// Session of the access token (None for API tokens, proxy and client certificate authentication)
+    pub session_id: Option<String>,
+}
+
+/// Minimum time between two updates of the `last_used` time of an API token,
+/// to avoid a config save for every request.
+const API_TOKEN_LAST_USED_INTERVAL_SECONDS: i64 = 60;
 
-/// Middleware factory for JWT authentication.
+/// Middleware factory for JWT and API token authentication.
 ///
-/// This middleware protects routes by requiring a valid JWT token in the
-/// Authorization header. The token is verified and the username is stored
-/// in request extensions for use by handlers.
+/// This middleware protects routes by requiring a valid JWT token or personal
+/// API token in the Authorization header, the user header of a trusted
+/// reverse proxy (see `authenticate_proxy_user`), or a client certificate (see
+/// `authenticate_client_certificate`). The token is verified and the
+/// user is stored in request extensions for use by handlers. The role of the
+/// user must allow the route (see `auth::required_role`), and API tokens are
+/// additionally checked for the scope the request requires.
 pub struct AuthMiddleware;
 
 impl<S, B> Transform<S, ServiceRequest> for AuthMiddleware
@@ -67,28 +86,32 @@ where
                 .app_data::<actix_web::web::Data<AppState>>()
                 .ok_or_else(|| AppError::InternalError("AppState not found".to_string()))?;
 
-            // Extract Authorization header
-            let auth_header = req
-                .headers()
-                .get("Authorization")
-                .ok_or_else(|| AppError::AuthError("Missing Authorization header".to_string()))?
-                .to_str()
-                .map_err(|_| AppError::AuthError("Invalid Authorization header".to_string()))?;
-
-            if !auth_header.starts_with("Bearer ") {
-                return Err(actix_web::error::ErrorUnauthorized(AppError::AuthError(
-                    "Invalid Authorization format".to_string(),
-                )));
-            }
+            let peer = req.peer_addr().map(|addr| addr.ip());
+            let user = match authenticate_proxy_user(app_state, req.headers(), peer).await? {
+                Some(user) => user,
+                None => match authenticate_client_certificate(
+                    app_state,
+                    req.conn_data::<ClientCertificate>(),
+                    req.headers(),
+                )
+                .await
+                {
+                    Some(user) => user,
+                    None => authenticate_bearer(app_state, &req).await?,
+                },
+            };
 
-            let token = &auth_header[7..];
-            let claims = app_state
-                .auth_service
-                .verify_token(token)
-                .map_err(actix_web::error::ErrorUnauthorized)?;
+            let required_role = auth::required_role(req.method(), req.path());
+            if user.role < required_role {
+                return Err(AppError::Forbidden(format!(
+                    "Requires the {} role",
+                    required_role.as_str()
+                ))
+                .into());
+            }
 
-            // Store authenticated username in request extensions
-            req.extensions_mut().insert(AuthenticatedUser(claims.sub));
+            // Store authenticated user in request extensions
+            req.extensions_mut().insert(user);
 
             // Continue with the request
             let res = service.call(req).await?;
@@ -96,3 +119,240 @@ where
         })
     }
 }
+
+/// Authenticates a request by the JWT or personal API token in the Authorization header.
+async fn authenticate_bearer(
+    app_state: &AppState,
+    req: &ServiceRequest,
+) -> Result<AuthenticatedUser, Error> {
+    // Extract Authorization header
+    let auth_header = req
+        .headers()
+        .get("Authorization")
+        .ok_or_else(|| AppError::AuthError("Missing Authorization header".to_string()))?
+        .to_str()
+        .map_err(|_| AppError::AuthError("Invalid Authorization header".to_string()))?;
+
+    if !auth_header.starts_with("Bearer ") {
+        return Err(actix_web::error::ErrorUnauthorized(AppError::AuthError(
+            "Invalid Authorization format".to_string(),
+        )));
+    }
+
+    let token = &auth_header[7..];
+    let user = if token.starts_with(API_TOKEN_PREFIX) {
+        authenticate_api_token(app_state, token, req).await?
+    } else {
+        let claims = app_state
+            .auth_service
+            .verify_token(token)
+            .map_err(actix_web::error::ErrorUnauthorized)?;
+        if claims.password_change_required
+            && req.path() != auth::CHANGE_PASSWORD_PATH
+            && req.path() != auth::LOGOUT_PATH
+        {
+            return Err(
+                AppError::Forbidden("The password must be changed first".to_string()).into(),
+            );
+        }
+        AuthenticatedUser {
+            username: claims.sub,
+            role: claims.role,
+            session_id: claims.sid,
+        }
+    };
+
+    Ok(user)
+}
+
+/// Authenticates a request by the verified client certificate of its TLS
+/// connection (see `ClientAuthConfig`).
+///
+/// The common name of the certificate must be a configured user, who gets
+/// their configured role. Returns `None` if mutual TLS is disabled, the request
+/// has an Authorization header (which takes precedence), the connection has no
+/// client certificate, or its user doesn't exist.
+pub async fn authenticate_client_certificate(
+    app_state: &AppState,
+    certificate: Option<&ClientCertificate>,
+    headers: &HeaderMap,
+) -> Option<AuthenticatedUser> {
+    let certificate = certificate?;
+    if headers.contains_key("Authorization") {
+        return None;
+    }
+
+    let config = app_state.config.read().await;
+    config
+        .server
+        .tls
+        .as_ref()
+        .and_then(|tls| tls.client_auth.as_ref())?;
+    let Some(user) = config
+        .users
+        .iter()
+        .find(|u| u.username == certificate.common_name)
+    else {
+        warn!(
+            user = %certificate.common_name,
+            "Ignoring client certificate of unknown user"
+        );
+        return None;
+    };
+    Some(AuthenticatedUser {
+        username: user.username.clone(),
+        role: user.role,
+        session_id: None,
+    })
+}
+
+/// Authenticates a request by the user header set by a trusted reverse proxy
+/// (see `ProxyAuthConfig`).
+///
+/// Returns `None` if proxy authentication is disabled, the header is missing, or
+/// the request doesn't come from a trusted proxy. New users are added to the
+/// configuration, and the role of existing users is updated from their groups.
+///
+/// # Errors
+///
+/// Returns `AppError::Forbidden` if the user is in none of the groups allowed in,
+/// or `Config::check_external_user` refuses the login.
+pub async fn authenticate_proxy_user(
+    app_state: &AppState,
+    headers: &HeaderMap,
+    peer: Option<IpAddr>,
+) -> Result<Option<AuthenticatedUser>, AppError> {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    };
+
+    let (user, changed) = {
+        let config = app_state.config.read().await;
+        let Some(proxy) = &config.server.proxy_auth else {
+            return Ok(None);
+        };
+        let Some(username) = header(&proxy.user_header) else {
+            return Ok(None);
+        };
+        let trusted = peer.is_some_and(|ip| {
+            let ip = ip.to_canonical();
+            proxy.trusted_proxies.iter().any(|net| net.contains(&ip))
+        });
+        if !trusted {
+            warn!(
+                header = %proxy.user_header,
+                peer = ?peer,
+                "Ignoring proxy authentication header from untrusted address"
+            );
+            return Ok(None);
+        }
+
+        let groups: Vec<String> = proxy
+            .groups_header
+            .as_deref()
+            .and_then(header)
+            .map(|groups| {
+                groups
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|group| !group.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let role = auth::role_for_groups(&proxy.group_roles, proxy.default_role, &groups)
+            .ok_or_else(|| {
+                AppError::Forbidden(format!(
+                    "User {} is not in a group allowed to log in",
+                    username
+                ))
+            })?;
+        let changed = config.check_external_user(username, role)?;
+        (
+            AuthenticatedUser {
+                username: username.to_string(),
+                role,
+                session_id: None,
+            },
+            changed,
+        )
+    };
+
+    if changed {
+        handlers::sync_external_user(app_state, &user.username, user.role, peer, headers).await?;
+    }
+
+    Ok(Some(user))
+}
+
+/// Verifies a personal API token and its scope, returning the user it acts as.
+///
+/// The token has the current role of its user; with `skip_auth`, tokens of
+/// users that aren't configured act as admins. The `last_used` time of the token is updated (and the config saved) at most
+/// once per `API_TOKEN_LAST_USED_INTERVAL_SECONDS`.
+async fn authenticate_api_token(
+    app_state: &AppState,
+    token: &str,
+    req: &ServiceRequest,
+) -> Result<AuthenticatedUser, AppError> {
+    let now = chrono::Utc::now();
+    let (token_id, user, needs_touch) = {
+        let config = app_state.config.read().await;
+        let api_token = app_state
+            .auth_service
+            .verify_api_token(token, &config.api_tokens, now)?;
+
+        if auth::is_token_management(req.method(), req.path()) {
+            return Err(AppError::Forbidden(
+                "API tokens can only be managed when logged in with a password or single sign-on"
+                    .to_string(),
+            ));
+        }
+        let scope = auth::required_scope(req.method(), req.path());
+        if !api_token.has_scope(scope) {
+            return Err(AppError::Forbidden(format!(
+                "API token lacks the {} scope",
+                scope.as_str()
+            )));
+        }
+
+        let role = match config
+            .users
+            .iter()
+            .find(|u| u.username == api_token.username)
+        {
+            Some(user) => user.role,
+            None if config.server.skip_auth => Role::Admin,
+            None => return Err(AppError::AuthError("Invalid API token".to_string())),
+        };
+
+        let needs_touch = api_token.last_used.is_none_or(|last_used| {
+            (now - last_used).num_seconds() >= API_TOKEN_LAST_USED_INTERVAL_SECONDS
+        });
+        (
+            api_token.id.clone(),
+            AuthenticatedUser {
+                username: api_token.username.clone(),
+                role,
+                session_id: None,
+            },
+            needs_touch,
+        )
+    };
+
+    if needs_touch {
+        let mut config = app_state.config.write().await;
+        if let Some(api_token) = config.api_tokens.iter_mut().find(|t| t.id == token_id) {
+            api_token.last_used = Some(now);
+            let config_to_save = config.clone();
+            drop(config);
+            app_state.config_persistence.request_save(config_to_save);
+        }
+    }
+
+    Ok(user)
+}
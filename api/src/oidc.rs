@@ -0,0 +1,322 @@
+use crate::auth;
+use crate::config::{OidcConfig, Role};
+use crate::error::AppError;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Time a user has to complete the login at the provider.
+pub(crate) const PENDING_LOGIN_TTL: Duration = Duration::from_secs(600);
+
+/// Upper bound on logins started but not completed; the oldest are dropped first.
+const MAX_PENDING_LOGINS: usize = 1000;
+
+/// Signature algorithms accepted for ID tokens.
+///
+/// HMAC algorithms are excluded: they would need the client secret as key.
+const ID_TOKEN_ALGORITHMS: [Algorithm; 9] = [
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+];
+
+/// The parts of the provider metadata (`.well-known/openid-configuration`) used for login.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// A user authenticated by the provider.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OidcIdentity {
+    /// Value of the `username_claim` of the ID token
+    pub username: String,
+    /// Values of the `groups_claim` of the ID token
+    pub groups: Vec<String>,
+}
+
+/// A login started at the provider, waiting for the callback.
+struct PendingLogin {
+    code_verifier: String,
+    nonce: String,
+    started_at: Instant,
+}
+
+/// Client for the OpenID Connect authorization code flow with PKCE.
+///
+/// The provider settings are passed to every call, so configuration changes
+/// apply to the next login. Started logins are kept in memory, keyed by their
+/// `state` parameter, until the callback or `PENDING_LOGIN_TTL`.
+#[derive(Clone)]
+pub struct OidcService {
+    client: reqwest::Client,
+    pending: Arc<Mutex<HashMap<String, PendingLogin>>>,
+}
+
+impl Default for OidcService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OidcService {
+    pub fn new() -> Self {
+        OidcService {
+            client: reqwest::Client::new(),
+            pending: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Fetches the provider metadata from the discovery document of the issuer.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::InternalError` if the document can't be fetched and
+    /// `AppError::ConfigError` if its issuer differs from the configured one.
+    pub async fn discover(&self, config: &OidcConfig) -> Result<ProviderMetadata, AppError> {
+        let issuer_url = config.issuer_url.trim_end_matches('/');
+        let url = format!("{}/.well-known/openid-configuration", issuer_url);
+        let metadata: ProviderMetadata = self.get_json(&url).await?;
+        if metadata.issuer.trim_end_matches('/') != issuer_url {
+            return Err(AppError::ConfigError(format!(
+                "OIDC provider reports issuer {} instead of {}",
+                metadata.issuer, config.issuer_url
+            )));
+        }
+        Ok(metadata)
+    }
+
+    /// Starts a login and returns the URL of the provider's login page and the
+    /// `state` parameter identifying the login.
+    pub async fn authorization_url(
+        &self,
+        config: &OidcConfig,
+    ) -> Result<(String, String), AppError> {
+        let metadata = self.discover(config).await?;
+
+        let state = random_token();
+        let nonce = random_token();
+        let code_verifier = random_token();
+        let code_challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()));
+
+        let url = url::Url::parse_with_params(
+            &metadata.authorization_endpoint,
+            [
+                ("response_type", "code"),
+                ("client_id", config.client_id.as_str()),
+                ("redirect_uri", config.redirect_url.as_str()),
+                ("scope", config.scopes.join(" ").as_str()),
+                ("state", state.as_str()),
+                ("nonce", nonce.as_str()),
+                ("code_challenge", code_challenge.as_str()),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map_err(|e| {
+            AppError::ConfigError(format!("Invalid OIDC authorization endpoint: {}", e))
+        })?;
+
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|_, login| login.started_at.elapsed() < PENDING_LOGIN_TTL);
+        while pending.len() >= MAX_PENDING_LOGINS {
+            let Some(oldest) = pending
+                .iter()
+                .min_by_key(|(_, login)| login.started_at)
+                .map(|(state, _)| state.clone())
+            else {
+                break;
+            };
+            pending.remove(&oldest);
+        }
+        pending.insert(
+            state.clone(),
+            PendingLogin {
+                code_verifier,
+                nonce,
+                started_at: Instant::now(),
+            },
+        );
+
+        Ok((url.to_string(), state))
+    }
+
+    /// Completes a login: exchanges the authorization code for an ID token and
+    /// returns the identity in the verified token.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::AuthError` if the state is unknown or expired, the code
+    /// is rejected, or the ID token is invalid (signature, issuer, audience,
+    /// expiry or nonce).
+    pub async fn complete_login(
+        &self,
+        config: &OidcConfig,
+        code: &str,
+        state: &str,
+    ) -> Result<OidcIdentity, AppError> {
+        let login = self
+            .pending
+            .lock()
+            .unwrap()
+            .remove(state)
+            .filter(|login| login.started_at.elapsed() < PENDING_LOGIN_TTL)
+            .ok_or_else(|| AppError::AuthError("Unknown or expired login".to_string()))?;
+
+        let metadata = self.discover(config).await?;
+
+        let mut request = self.client.post(&metadata.token_endpoint).form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", config.redirect_url.as_str()),
+            ("client_id", config.client_id.as_str()),
+            ("code_verifier", login.code_verifier.as_str()),
+        ]);
+        if let Some(secret) = &config.client_secret {
+            request = request.basic_auth(&config.client_id, Some(secret));
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| AppError::InternalError(format!("OIDC token request failed: {}", e)))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(AppError::AuthError(format!(
+                "OIDC provider rejected the authorization code ({}): {}",
+                status, body
+            )));
+        }
+        let tokens: TokenResponse = response
+            .json()
+            .await
+            .map_err(|e| AppError::InternalError(format!("Invalid OIDC token response: {}", e)))?;
+
+        let jwks: JwkSet = self.get_json(&metadata.jwks_uri).await?;
+        let claims = verify_id_token(
+            &tokens.id_token,
+            &jwks,
+            &metadata.issuer,
+            &config.client_id,
+            &login.nonce,
+        )?;
+        identity(config, &claims)
+    }
+
+    async fn get_json<T: serde::de::DeserializeOwned>(&self, url: &str) -> Result<T, AppError> {
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| {
+                AppError::InternalError(format!("OIDC request to {} failed: {}", url, e))
+            })?;
+        response.json().await.map_err(|e| {
+            AppError::InternalError(format!("Invalid OIDC response from {}: {}", url, e))
+        })
+    }
+}
+
+/// Verifies the signature and the standard claims of an ID token and returns its claims.
+pub fn verify_id_token(
+    id_token: &str,
+    jwks: &JwkSet,
+    issuer: &str,
+    client_id: &str,
+    nonce: &str,
+) -> Result<Map<String, Value>, AppError> {
+    let invalid =
+        |e: jsonwebtoken::errors::Error| AppError::AuthError(format!("Invalid ID token: {}", e));
+
+    let header = decode_header(id_token).map_err(invalid)?;
+    if !ID_TOKEN_ALGORITHMS.contains(&header.alg) {
+        return Err(AppError::AuthError(format!(
+            "Unsupported ID token algorithm {:?}",
+            header.alg
+        )));
+    }
+    let jwk = match &header.kid {
+        Some(kid) => jwks.find(kid),
+        // Without a key ID, the provider must have a single key
+        None if jwks.keys.len() == 1 => jwks.keys.first(),
+        None => None,
+    }
+    .ok_or_else(|| AppError::AuthError("Unknown ID token signing key".to_string()))?;
+    let key = DecodingKey::from_jwk(jwk).map_err(invalid)?;
+
+    let mut validation = Validation::new(header.alg);
+    validation.set_issuer(&[issuer]);
+    validation.set_audience(&[client_id]);
+    validation.set_required_spec_claims(&["exp", "iss", "aud"]);
+    let claims = decode::<Map<String, Value>>(id_token, &key, &validation)
+        .map_err(invalid)?
+        .claims;
+
+    if claims.get("nonce").and_then(Value::as_str) != Some(nonce) {
+        return Err(AppError::AuthError("ID token nonce mismatch".to_string()));
+    }
+    Ok(claims)
+}
+
+/// Reads the username and groups from the claims of an ID token.
+pub fn identity(
+    config: &OidcConfig,
+    claims: &Map<String, Value>,
+) -> Result<OidcIdentity, AppError> {
+    let username = claims
+        .get(&config.username_claim)
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|username| !username.is_empty())
+        .ok_or_else(|| {
+            AppError::AuthError(format!("ID token has no {} claim", config.username_claim))
+        })?
+        .to_string();
+
+    let groups = match claims.get(&config.groups_claim) {
+        Some(Value::Array(groups)) => groups
+            .iter()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect(),
+        Some(Value::String(group)) => vec![group.clone()],
+        _ => Vec::new(),
+    };
+
+    Ok(OidcIdentity { username, groups })
+}
+
+/// Returns the role of a user from their groups (see `auth::role_for_groups`).
+pub fn role_for(config: &OidcConfig, groups: &[String]) -> Option<Role> {
+    auth::role_for_groups(&config.group_roles, config.default_role, groups)
+}
+
+/// A random URL-safe string with 32 bytes of entropy, for `state`, `nonce` and the PKCE verifier.
+fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
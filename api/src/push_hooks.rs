@@ -0,0 +1,108 @@
+use crate::error::AppError;
+use crate::forge::ForgeProvider;
+use crate::git::GitService;
+use actix_web::http::header::HeaderMap;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use subtle::ConstantTimeEq;
+
+/// Verifies that an inbound webhook request was sent by the forge.
+///
+/// - GitHub: `X-Hub-Signature-256: sha256=<hex HMAC-SHA256 of the body>`
+/// - Gitea: `X-Gitea-Signature: <hex HMAC-SHA256 of the body>`
+/// - GitLab: `X-Gitlab-Token: <secret>`
+///
+/// All comparisons are constant-time.
+///
+/// # Errors
+///
+/// Returns `AppError::AuthError` if the signature/token header is missing or invalid.
+pub fn verify_request(
+    provider: ForgeProvider,
+    headers: &HeaderMap,
+    body: &[u8],
+    secret: &str,
+) -> Result<(), AppError> {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .ok_or_else(|| AppError::AuthError(format!("Missing {} header", name)))
+    };
+
+    let valid = match provider {
+        ForgeProvider::Github => {
+            let signature = header("X-Hub-Signature-256")?;
+            let hex_signature = signature.strip_prefix("sha256=").unwrap_or_default();
+            verify_hmac(secret, body, hex_signature)
+        }
+        ForgeProvider::Gitea => verify_hmac(secret, body, header("X-Gitea-Signature")?),
+        ForgeProvider::Gitlab => header("X-Gitlab-Token")?
+            .as_bytes()
+            .ct_eq(secret.as_bytes())
+            .into(),
+    };
+
+    if valid {
+        Ok(())
+    } else {
+        Err(AppError::AuthError("Invalid webhook signature".to_string()))
+    }
+}
+
+fn verify_hmac(secret: &str, body: &[u8], hex_signature: &str) -> bool {
+    let Ok(signature) = hex::decode(hex_signature) else {
+        return false;
+    };
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// Returns true if the request carries a push event.
+///
+/// Requests without an event header are treated as push events.
+pub fn is_push_event(provider: ForgeProvider, headers: &HeaderMap) -> bool {
+    let (name, push_events): (&str, &[&str]) = match provider {
+        ForgeProvider::Github => ("X-GitHub-Event", &["push"]),
+        ForgeProvider::Gitea => ("X-Gitea-Event", &["push"]),
+        ForgeProvider::Gitlab => ("X-Gitlab-Event", &["Push Hook", "Tag Push Hook"]),
+    };
+
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(|event| push_events.contains(&event))
+        .unwrap_or(true)
+}
+
+/// Extracts the normalized repository IDs (see `GitService::repo_id_from_url`) of all
+/// repository URLs (HTTPS clone, SSH clone and web URLs) found in a push event payload.
+pub fn repository_ids(provider: ForgeProvider, payload: &serde_json::Value) -> Vec<String> {
+    let pointers: &[&str] = match provider {
+        ForgeProvider::Github | ForgeProvider::Gitea => &[
+            "/repository/clone_url",
+            "/repository/ssh_url",
+            "/repository/html_url",
+        ],
+        ForgeProvider::Gitlab => &[
+            "/project/git_http_url",
+            "/project/git_ssh_url",
+            "/project/web_url",
+            "/repository/git_http_url",
+            "/repository/git_ssh_url",
+            "/repository/homepage",
+        ],
+    };
+
+    let mut ids: Vec<String> = pointers
+        .iter()
+        .filter_map(|pointer| payload.pointer(pointer).and_then(|v| v.as_str()))
+        .map(GitService::repo_id_from_url)
+        .filter(|id| !id.is_empty())
+        .collect();
+    ids.sort();
+    ids.dedup();
+    ids
+}
@@ -0,0 +1,331 @@
+use crate::config::LoginThrottleConfig;
+use crate::error::AppError;
+use crate::handlers::AppState;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::HeaderMap;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, ResponseError};
+use chrono::{DateTime, Duration, Utc};
+use ipnet::IpNet;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+
+/// Length of the windows in which API requests are counted.
+const REQUEST_WINDOW_SECONDS: i64 = 60;
+
+/// Requests of a client in the current window.
+struct RequestWindow {
+    start: DateTime<Utc>,
+    count: u32,
+}
+
+/// Failed logins of a username or address in the current window.
+struct Failures {
+    window_start: DateTime<Utc>,
+    count: u32,
+    locked_until: Option<DateTime<Utc>>,
+}
+
+#[derive(Default)]
+struct LimiterState {
+    requests: HashMap<IpAddr, RequestWindow>,
+    /// Last time expired request windows were dropped
+    requests_pruned: Option<DateTime<Utc>>,
+    user_failures: HashMap<String, Failures>,
+    ip_failures: HashMap<IpAddr, Failures>,
+}
+
+/// What a lockout applies to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LockoutTarget {
+    User(String),
+    Address(IpAddr),
+}
+
+impl fmt::Display for LockoutTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockoutTarget::User(username) => write!(f, "user {}", username),
+            LockoutTarget::Address(ip) => write!(f, "address {}", ip),
+        }
+    }
+}
+
+/// A lockout started by a failed login.
+#[derive(Debug, Clone)]
+pub struct Lockout {
+    pub target: LockoutTarget,
+    /// Failed logins within the window that led to the lockout
+    pub failures: u32,
+    pub locked_until: DateTime<Utc>,
+}
+
+/// Counters of API requests and failed logins per client.
+///
+/// Limits are passed on every call, so changes to `server.rate_limit` take
+/// effect immediately. The counters are kept in memory only.
+#[derive(Clone, Default)]
+pub struct RateLimiter {
+    state: Arc<Mutex<LimiterState>>,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts an API request of a client.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::TooManyRequests` if the client already sent
+    /// `requests_per_minute` requests in the current window. `0` disables the limit.
+    pub fn check_request(
+        &self,
+        client: IpAddr,
+        requests_per_minute: u32,
+        now: DateTime<Utc>,
+    ) -> Result<(), AppError> {
+        if requests_per_minute == 0 {
+            return Ok(());
+        }
+        let window = Duration::seconds(REQUEST_WINDOW_SECONDS);
+        let mut state = self.state.lock().unwrap();
+        if state
+            .requests_pruned
+            .is_none_or(|pruned| now - pruned >= window)
+        {
+            state.requests.retain(|_, w| now - w.start < window);
+            state.requests_pruned = Some(now);
+        }
+
+        let entry = state.requests.entry(client).or_insert(RequestWindow {
+            start: now,
+            count: 0,
+        });
+        if now - entry.start >= window {
+            entry.start = now;
+            entry.count = 0;
+        }
+        if entry.count >= requests_per_minute {
+            let retry_after = (entry.start + window - now).num_seconds().max(1) as u64;
+            return Err(AppError::TooManyRequests(
+                format!(
+                    "Rate limit of {} requests per minute exceeded",
+                    requests_per_minute
+                ),
+                retry_after,
+            ));
+        }
+        entry.count += 1;
+        Ok(())
+    }
+
+    /// Checks that neither the username nor the address of a login is locked out.
+    ///
+    /// Called before verifying the password, so locked out clients don't cost
+    /// a bcrypt check.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::TooManyRequests` with the remaining lockout.
+    pub fn check_login(
+        &self,
+        client: Option<IpAddr>,
+        username: &str,
+        now: DateTime<Utc>,
+    ) -> Result<(), AppError> {
+        let state = self.state.lock().unwrap();
+        let locked_until = [
+            state.user_failures.get(username),
+            client.and_then(|ip| state.ip_failures.get(&ip)),
+        ]
+        .into_iter()
+        .flatten()
+        .filter_map(|failures| failures.locked_until)
+        .filter(|until| *until > now)
+        .max();
+
+        match locked_until {
+            Some(until) => Err(AppError::TooManyRequests(
+                "Too many failed logins, try again later".to_string(),
+                (until - now).num_seconds().max(1) as u64,
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Records a failed login of a username from an address.
+    ///
+    /// Returns the lockouts the failure started.
+    pub fn record_login_failure(
+        &self,
+        settings: &LoginThrottleConfig,
+        client: Option<IpAddr>,
+        username: &str,
+        now: DateTime<Utc>,
+    ) -> Vec<Lockout> {
+        let window = Duration::minutes(settings.window_minutes.into());
+        let lockout = Duration::minutes(settings.lockout_minutes.into());
+        let mut state = self.state.lock().unwrap();
+        prune_failures(&mut state.user_failures, window, now);
+        prune_failures(&mut state.ip_failures, window, now);
+
+        let mut lockouts = Vec::new();
+        if let Some((failures, locked_until)) = record_failure(
+            &mut state.user_failures,
+            username.to_string(),
+            settings.max_failures_per_user,
+            window,
+            lockout,
+            now,
+        ) {
+            lockouts.push(Lockout {
+                target: LockoutTarget::User(username.to_string()),
+                failures,
+                locked_until,
+            });
+        }
+        if let Some(ip) = client {
+            if let Some((failures, locked_until)) = record_failure(
+                &mut state.ip_failures,
+                ip,
+                settings.max_failures_per_ip,
+                window,
+                lockout,
+                now,
+            ) {
+                lockouts.push(Lockout {
+                    target: LockoutTarget::Address(ip),
+                    failures,
+                    locked_until,
+                });
+            }
+        }
+        lockouts
+    }
+
+    /// Resets the failed logins of a username after a successful login.
+    ///
+    /// Failures of the address are kept, so that an attacker with one valid
+    /// account can't reset the limit of the address.
+    pub fn record_login_success(&self, username: &str) {
+        self.state.lock().unwrap().user_failures.remove(username);
+    }
+}
+
+/// Counts a failure and returns the failures and end of the lockout if it
+/// started one. `0` for `max_failures` disables lockouts.
+fn record_failure<K: Hash + Eq>(
+    failures: &mut HashMap<K, Failures>,
+    key: K,
+    max_failures: u32,
+    window: Duration,
+    lockout: Duration,
+    now: DateTime<Utc>,
+) -> Option<(u32, DateTime<Utc>)> {
+    if max_failures == 0 {
+        return None;
+    }
+    let entry = failures.entry(key).or_insert(Failures {
+        window_start: now,
+        count: 0,
+        locked_until: None,
+    });
+    if now - entry.window_start >= window {
+        entry.window_start = now;
+        entry.count = 0;
+    }
+    entry.count += 1;
+    if entry.count < max_failures {
+        return None;
+    }
+
+    let count = entry.count;
+    let locked_until = now + lockout;
+    entry.locked_until = Some(locked_until);
+    entry.window_start = now;
+    entry.count = 0;
+    Some((count, locked_until))
+}
+
+/// Drops failures whose window and lockout ended.
+fn prune_failures<K>(failures: &mut HashMap<K, Failures>, window: Duration, now: DateTime<Utc>) {
+    failures.retain(|_, f| {
+        now - f.window_start < window || f.locked_until.is_some_and(|until| until > now)
+    });
+}
+
+/// Returns the address of the client of a request.
+///
+/// If the request comes from one of `trusted_proxies`, the client is the last
+/// address in its `X-Forwarded-For` header that isn't a trusted proxy itself.
+/// The header is ignored on requests from other addresses, so clients can't
+/// evade the limits by setting it.
+pub fn client_ip(
+    peer: Option<IpAddr>,
+    headers: &HeaderMap,
+    trusted_proxies: &[IpNet],
+) -> Option<IpAddr> {
+    let peer = peer?.to_canonical();
+    let is_trusted = |ip: &IpAddr| trusted_proxies.iter().any(|net| net.contains(ip));
+    if !is_trusted(&peer) {
+        return Some(peer);
+    }
+
+    let forwarded = headers
+        .get_all("X-Forwarded-For")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|addr| addr.trim().parse::<IpAddr>().ok())
+        .map(|ip| ip.to_canonical())
+        .collect::<Vec<_>>();
+    forwarded
+        .into_iter()
+        .rev()
+        .find(|ip| !is_trusted(ip))
+        .or(Some(peer))
+}
+
+/// Middleware limiting the requests to `/api` per client (see
+/// `RateLimitConfig::requests_per_minute`).
+///
+/// Requests over the limit are answered with `429 Too Many Requests` and a
+/// `Retry-After` header.
+pub async fn limit_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    if req.path().starts_with("/api/") {
+        if let Some(state) = req.app_data::<web::Data<AppState>>() {
+            let result = {
+                let config = state.config.read().await;
+                let settings = &config.server.rate_limit;
+                match client_ip(
+                    req.peer_addr().map(|addr| addr.ip()),
+                    req.headers(),
+                    &settings.trusted_proxies,
+                ) {
+                    Some(ip) => state.rate_limiter.check_request(
+                        ip,
+                        settings.requests_per_minute,
+                        Utc::now(),
+                    ),
+                    None => Ok(()),
+                }
+            };
+            if let Err(e) = result {
+                let response = e.error_response();
+                return Ok(req.into_response(response).map_into_right_body());
+            }
+        }
+    }
+
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
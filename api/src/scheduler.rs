@@ -1,208 +1,176 @@
 use crate::config::Config;
+use crate::config::UpstreamStatus;
 use crate::config_persistence::ConfigPersistence;
+use crate::discovery;
+use crate::email;
+use crate::error::AppError;
 use crate::git::GitService;
-use crate::webhooks;
-use log::{error, info, warn};
+use crate::health::SchedulerStatus;
+use crate::metrics;
+use crate::sync::{self, SyncTrigger};
+use crate::webhooks::WebhookService;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::sync::RwLock;
 use tokio_cron_scheduler::{Job, JobScheduler};
+use tracing::{error, info};
 
 pub async fn setup_scheduler(
     config: Arc<RwLock<Config>>,
     git_service: Arc<GitService>,
     config_persistence: ConfigPersistence,
+    webhook_service: WebhookService,
+    status: SchedulerStatus,
 ) -> Result<JobScheduler, Box<dyn std::error::Error>> {
     let scheduler = JobScheduler::new().await?;
 
-    let cron_expression = {
+    // Shows readiness checks that jobs are still being executed
+    let heartbeat_status = status.clone();
+    let job = Job::new_async("0 * * * * *", move |_uuid, _l| {
+        let status = heartbeat_status.clone();
+        Box::pin(async move { status.heartbeat() })
+    })?;
+    scheduler.add(job).await?;
+    status.job_registered("heartbeat");
+
+    let (cron_expression, watch_sources, digest) = {
         let cfg = config.read().await;
-        cfg.scheduler.cron_expression.clone()
+        (
+            cfg.scheduler.cron_expression.clone(),
+            cfg.watch_sources.clone(),
+            cfg.server
+                .email
+                .as_ref()
+                .and_then(|email| email.digest.clone()),
+        )
     };
 
+    if let Some(digest) = digest {
+        let config = Arc::clone(&config);
+        let job = Job::new_async(digest.cron_expression.as_str(), move |_uuid, _l| {
+            let config = Arc::clone(&config);
+            Box::pin(async move {
+                if let Err(e) = email::send_digest(&config).await {
+                    error!(error = %e, "Failed to send backup digest");
+                }
+            })
+        })?;
+        scheduler.add(job).await?;
+        status.job_registered("digest");
+
+        info!(
+            cron_expression = %digest.cron_expression,
+            "Backup digest scheduled"
+        );
+    }
+
+    for source in watch_sources.iter().filter(|s| s.enabled) {
+        let source_cron = source
+            .cron_expression
+            .clone()
+            .unwrap_or_else(|| cron_expression.clone());
+        let source_id = source.id.clone();
+        let config = Arc::clone(&config);
+        let config_persistence = config_persistence.clone();
+        let webhook_service = webhook_service.clone();
+
+        let job = Job::new_async(source_cron.as_str(), move |_uuid, _l| {
+            let source_id = source_id.clone();
+            let config = Arc::clone(&config);
+            let config_persistence = config_persistence.clone();
+            let webhook_service = webhook_service.clone();
+
+            Box::pin(async move {
+                info!(source = %source_id, "Scanning watch source");
+                match discovery::scan_source(
+                    &config,
+                    &config_persistence,
+                    &webhook_service,
+                    &source_id,
+                )
+                .await
+                {
+                    Ok(report) => info!(
+                        source = %source_id,
+                        added = report.added.len(),
+                        flagged = report.flagged.len(),
+                        restored = report.restored.len(),
+                        "Watch source scanned"
+                    ),
+                    Err(e) => {
+                        error!(source = %source_id, error = %e, "Failed to scan watch source")
+                    }
+                }
+            })
+        })?;
+        scheduler.add(job).await?;
+        status.job_registered(format!("watch_source:{}", source.id));
+
+        info!(
+            source = %source.id,
+            cron_expression = %source_cron,
+            "Watch source scheduled"
+        );
+    }
+
+    // Whether the archive volume was low on space at the end of the last run
+    let storage_low = Arc::new(AtomicBool::new(false));
     let job = Job::new_async(cron_expression.as_str(), move |_uuid, _l| {
         let config = Arc::clone(&config);
         let git_service = Arc::clone(&git_service);
         let config_persistence = config_persistence.clone();
+        let webhook_service = webhook_service.clone();
+        let storage_low = Arc::clone(&storage_low);
 
         Box::pin(async move {
             info!("Starting scheduled sync");
+            let started = Instant::now();
 
-            let cfg = config.read().await;
-            let credentials = cfg.credentials.clone();
-            let repositories = cfg.repositories.clone();
-            let encryption_key = cfg.server.encryption_key.clone();
-            let sync_attempts = cfg.server.sync_attempts;
-            let webhook_urls = cfg.server.error_webhooks.clone();
-            drop(cfg); // Release the lock
+            let repositories = config.read().await.repositories.clone();
 
             for repo in repositories.iter().filter(|r| r.enabled) {
-                let repo_clone = repo.clone();
-                let credential = repo
-                    .credential_id
-                    .as_ref()
-                    .and_then(|id| credentials.get(id).cloned());
-                let encryption_key_clone = encryption_key.clone();
-                let git_service_clone = git_service.clone();
-                let config_clone = Arc::clone(&config);
-
-                // Run the blocking sync operation in a blocking thread pool
-                let sync_result = match tokio::task::spawn_blocking(move || {
-                    git_service_clone.sync_repository(
-                        &repo_clone,
-                        credential.as_ref(),
-                        &encryption_key_clone,
-                    )
-                })
+                // Nothing left to fetch, keep the existing backup as is
+                if repo.upstream_status == Some(UpstreamStatus::Deleted) {
+                    info!(repository = %repo.id, "Repository was deleted upstream, skipping sync");
+                    continue;
+                }
+
+                match sync::sync_repository(
+                    &config,
+                    &git_service,
+                    &config_persistence,
+                    &webhook_service,
+                    &repo.id,
+                    SyncTrigger::Schedule,
+                )
                 .await
                 {
-                    Ok(result) => result,
-                    Err(e) => {
-                        error!("Task join error for repository {}: {}", repo.id, e);
-                        continue; // Skip this repository and continue with the next one
-                    }
-                };
-
-                match sync_result {
-                    Ok(sync_result_data) => {
-                        if sync_result_data.skipped {
-                            info!(
-                                "Repository {} already up-to-date (commit: {}), skipped sync",
-                                repo.id, sync_result_data.commit_hash
-                            );
-                        } else {
-                            info!(
-                                "Successfully synced repository {}: {:?} ({} bytes), commit: {}",
-                                repo.id,
-                                sync_result_data.path,
-                                sync_result_data.size,
-                                sync_result_data.commit_hash
-                            );
-                        }
-
-                        // Update repository size, last_sync, commit hash, and commit message in config
-                        let mut cfg = config_clone.write().await;
-                        let config_to_save = if let Some(repo_mut) =
-                            cfg.repositories.iter_mut().find(|r| r.id == repo.id)
-                        {
-                            repo_mut.size = Some(sync_result_data.size);
-                            repo_mut.last_sync = Some(chrono::Utc::now());
-                            repo_mut.last_sync_commit_hash =
-                                Some(sync_result_data.commit_hash.clone());
-                            repo_mut.last_sync_message =
-                                Some(sync_result_data.status_message.clone());
-
-                            // Reset attempts_left on successful sync (recovered from errors)
-                            if repo_mut.attempts_left.is_some() {
-                                info!(
-                                    "Repository {} recovered from error spree, resetting attempts",
-                                    repo_mut.id
-                                );
-                                repo_mut.attempts_left = None;
-                            }
-                            repo_mut.error = None;
-                            Some(cfg.clone())
-                        } else {
-                            None
-                        };
-                        drop(cfg); // Release lock before async operation
-
-                        // Request config save (non-blocking, debounced)
-                        if let Some(config_data) = config_to_save {
-                            config_persistence.request_save(config_data);
-                        }
+                    Ok(_) => {}
+                    Err(AppError::Conflict(_)) => {
+                        info!(repository = %repo.id, "Repository is already syncing, skipping sync");
                     }
                     Err(e) => {
-                        error!("Failed to sync repository {}: {}", repo.id, e);
-                        let error_message = e.to_string();
-
-                        // Notify webhooks about the error
-                        webhooks::notify_error_webhooks(
-                            &webhook_urls,
-                            repo,
-                            "sync",
-                            repo.credential_id.as_ref(),
-                            &error_message,
-                        )
-                        .await;
-
-                        // Handle sync failure (update attempts_left, potentially disable repo)
-                        let mut cfg = config_clone.write().await;
-                        let (config_to_save, was_disabled, repo_for_webhook) =
-                            if let Some(repo_mut) =
-                                cfg.repositories.iter_mut().find(|r| r.id == repo.id)
-                            {
-                                // Initialize or decrement attempts_left
-                                let attempts_left = if let Some(attempts) = repo_mut.attempts_left {
-                                    if attempts > 0 {
-                                        attempts - 1
-                                    } else {
-                                        0 // Already at 0, shouldn't happen but handle gracefully
-                                    }
-                                } else {
-                                    // First failure: set to sync_attempts - 1
-                                    sync_attempts - 1
-                                };
-
-                                repo_mut.attempts_left = Some(attempts_left);
-                                repo_mut.error = Some(error_message.clone());
-
-                                // Check if we've run out of attempts
-                                let disabled = if attempts_left == 0 {
-                                    // Reset attempts_left to None and disable the repository
-                                    repo_mut.attempts_left = None;
-                                    repo_mut.enabled = false;
-
-                                    warn!(
-                                    "Repository {} ran out of sync attempts and has been disabled",
-                                    repo_mut.id
-                                );
-                                    true
-                                } else {
-                                    false
-                                };
-
-                                // Clone repo for webhook (before dropping lock)
-                                let repo_clone = repo_mut.clone();
-                                // Clone config for async save
-                                let config_clone = cfg.clone();
-                                (Some(config_clone), disabled, Some(repo_clone))
-                            } else {
-                                (None, false, None)
-                            };
-                        drop(cfg); // Release lock before async operations
-
-                        // Notify webhooks about running out of attempts (if disabled)
-                        if was_disabled {
-                            if let Some(repo_for_webhook) = repo_for_webhook {
-                                webhooks::notify_out_of_attempts_webhooks(
-                                    &webhook_urls,
-                                    &repo_for_webhook,
-                                    repo_for_webhook.credential_id.as_ref(),
-                                    &error_message,
-                                    sync_attempts,
-                                )
-                                .await;
-                            }
-                        }
-
-                        // Request config save (non-blocking, debounced)
-                        if let Some(config_data) = config_to_save {
-                            config_persistence.request_save(config_data);
-                        }
+                        error!(repository = %repo.id, error = %e, "Failed to sync repository")
                     }
                 }
             }
 
             info!("Scheduled sync completed");
+            metrics::record_scheduler_run(started.elapsed());
+
+            let was_low = storage_low.load(Ordering::Relaxed);
+            let is_low =
+                sync::check_free_space(&config, &git_service, &webhook_service, was_low).await;
+            storage_low.store(is_low, Ordering::Relaxed);
         })
     })?;
 
     scheduler.add(job).await?;
+    status.job_registered("sync");
     scheduler.start().await?;
+    status.heartbeat();
 
-    info!(
-        "Scheduler started with cron expression: {}",
-        cron_expression
-    );
+    info!(cron_expression = %cron_expression, "Scheduler started");
     Ok(scheduler)
 }
@@ -0,0 +1,224 @@
+use crate::config::Role;
+use crate::error::AppError;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::error;
+
+/// A login session, kept until logout or until its refresh token expires.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Session {
+    pub id: String,
+    pub username: String,
+    /// Role at login, used if the user isn't configured (with `skip_auth`)
+    pub role: Role,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    /// Whether the access tokens only allow changing the password
+    pub password_change_required: bool,
+    /// SHA-256 hash of the current refresh token
+    pub refresh_token_hash: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Hash of the refresh token replaced by the last rotation, to detect its reuse
+    pub previous_refresh_token_hash: Option<String>,
+    pub created_at: DateTime<Utc>,
+    /// Expiry of the current refresh token
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct SessionsState {
+    sessions: Vec<Session>,
+    /// IDs of revoked sessions, until their last access token expired
+    revoked: HashMap<String, DateTime<Utc>>,
+}
+
+impl SessionsState {
+    fn revoke(&mut self, id: &str, until: DateTime<Utc>) -> bool {
+        let before = self.sessions.len();
+        self.sessions.retain(|session| session.id != id);
+        self.revoked.insert(id.to_string(), until);
+        self.sessions.len() != before
+    }
+
+    /// Drops expired sessions and revocations of sessions without valid access tokens.
+    fn prune(&mut self, now: DateTime<Utc>) {
+        self.sessions.retain(|session| session.expires_at > now);
+        self.revoked.retain(|_, until| *until > now);
+    }
+}
+
+/// Login sessions and the list of revoked sessions.
+///
+/// The store is kept in `<data_dir>/sessions.json` so that sessions and
+/// revocations survive restarts; a store without file only lives in memory.
+#[derive(Clone)]
+pub struct SessionStore {
+    path: Option<PathBuf>,
+    state: Arc<Mutex<SessionsState>>,
+}
+
+impl Default for SessionStore {
+    fn default() -> Self {
+        Self::in_memory()
+    }
+}
+
+impl SessionStore {
+    /// Creates an empty store that isn't persisted.
+    pub fn in_memory() -> Self {
+        SessionStore {
+            path: None,
+            state: Arc::new(Mutex::new(SessionsState::default())),
+        }
+    }
+
+    /// Loads the store of a data directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::IoError` if the data directory or the file cannot be
+    /// read. A malformed file is replaced by an empty store.
+    pub fn load<P: AsRef<Path>>(data_dir: P) -> Result<Self, AppError> {
+        fs::create_dir_all(data_dir.as_ref())?;
+        let path = data_dir.as_ref().join("sessions.json");
+        let state = if path.exists() {
+            serde_json::from_str(&fs::read_to_string(&path)?).unwrap_or_else(|e| {
+                error!(path = %path.display(), error = %e, "Ignoring malformed session store");
+                SessionsState::default()
+            })
+        } else {
+            SessionsState::default()
+        };
+
+        Ok(SessionStore {
+            path: Some(path),
+            state: Arc::new(Mutex::new(state)),
+        })
+    }
+
+    /// Adds a new session.
+    pub fn create(&self, session: Session) {
+        let mut state = self.state.lock().unwrap();
+        state.prune(Utc::now());
+        state.sessions.push(session);
+        self.save(&state);
+    }
+
+    /// Replaces the refresh token of the session it belongs to and returns the
+    /// updated session.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::AuthError` if the token is unknown or expired. A token
+    /// that was already replaced revokes its session (`revoke_until` is the
+    /// expiry of its last access token): it was either stolen or used twice.
+    pub fn rotate(
+        &self,
+        refresh_token_hash: &str,
+        new_refresh_token_hash: String,
+        expires_at: DateTime<Utc>,
+        revoke_until: DateTime<Utc>,
+    ) -> Result<Session, AppError> {
+        let now = Utc::now();
+        let mut state = self.state.lock().unwrap();
+        state.prune(now);
+
+        if let Some(id) = state
+            .sessions
+            .iter()
+            .find(|s| s.previous_refresh_token_hash.as_deref() == Some(refresh_token_hash))
+            .map(|s| s.id.clone())
+        {
+            state.revoke(&id, revoke_until);
+            self.save(&state);
+            return Err(AppError::AuthError(
+                "Refresh token was already used, the session has been revoked".to_string(),
+            ));
+        }
+
+        let session = state
+            .sessions
+            .iter_mut()
+            .find(|s| s.refresh_token_hash == refresh_token_hash)
+            .ok_or_else(|| AppError::AuthError("Invalid or expired refresh token".to_string()))?;
+        session.previous_refresh_token_hash = Some(std::mem::replace(
+            &mut session.refresh_token_hash,
+            new_refresh_token_hash,
+        ));
+        session.expires_at = expires_at;
+        let session = session.clone();
+        self.save(&state);
+        Ok(session)
+    }
+
+    /// Revokes a session until `until`, the expiry of its last access token.
+    ///
+    /// Returns whether the session existed.
+    pub fn revoke(&self, id: &str, until: DateTime<Utc>) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.prune(Utc::now());
+        let existed = state.revoke(id, until);
+        self.save(&state);
+        existed
+    }
+
+    /// Revokes all sessions of a user and returns their number.
+    pub fn revoke_user(&self, username: &str, until: DateTime<Utc>) -> usize {
+        let mut state = self.state.lock().unwrap();
+        state.prune(Utc::now());
+        let ids: Vec<String> = state
+            .sessions
+            .iter()
+            .filter(|s| s.username == username)
+            .map(|s| s.id.clone())
+            .collect();
+        for id in &ids {
+            state.revoke(id, until);
+        }
+        if !ids.is_empty() {
+            self.save(&state);
+        }
+        ids.len()
+    }
+
+    /// Whether a session has been revoked.
+    pub fn is_revoked(&self, id: &str) -> bool {
+        let state = self.state.lock().unwrap();
+        state
+            .revoked
+            .get(id)
+            .is_some_and(|until| *until > Utc::now())
+    }
+
+    /// Returns the active sessions of a user.
+    pub fn sessions(&self, username: &str) -> Vec<Session> {
+        let now = Utc::now();
+        let state = self.state.lock().unwrap();
+        state
+            .sessions
+            .iter()
+            .filter(|s| s.username == username && s.expires_at > now)
+            .cloned()
+            .collect()
+    }
+
+    /// Writes the store to its file; failures are logged.
+    fn save(&self, state: &SessionsState) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let result = serde_json::to_vec(state)
+            .map_err(std::io::Error::from)
+            .and_then(|contents| {
+                let temp_path = path.with_extension("json.tmp");
+                fs::write(&temp_path, contents)?;
+                fs::rename(&temp_path, path)
+            });
+        if let Err(e) = result {
+            error!(path = %path.display(), error = %e, "Failed to write session store");
+        }
+    }
+}
@@ -0,0 +1,667 @@
+use crate::config::{Config, EmailConfig, NotificationEvent, Repository, Subscription};
+use crate::config_persistence::ConfigPersistence;
+use crate::email;
+use crate::error::AppError;
+use crate::git::{GitService, SyncResult};
+use crate::metadata;
+use crate::metrics;
+use crate::webhooks::{self, WebhookService};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use tokio::sync::{mpsc, RwLock};
+use tokio::time::{sleep_until, Duration, Instant};
+use tracing::{error, field, info, instrument, warn, Span};
+use uuid::Uuid;
+
+/// What initiated a sync run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncTrigger {
+    /// The cron scheduler
+    Schedule,
+    /// A user through the API
+    Manual,
+    /// An inbound push webhook from a forge
+    Push,
+}
+
+impl fmt::Display for SyncTrigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncTrigger::Schedule => write!(f, "schedule"),
+            SyncTrigger::Manual => write!(f, "manual"),
+            SyncTrigger::Push => write!(f, "push"),
+        }
+    }
+}
+
+/// Handles sync failure by updating attempts_left and potentially disabling the repository.
+///
+/// Returns true if the repository was disabled (ran out of attempts), false otherwise.
+fn handle_sync_failure(
+    repo: &mut Repository,
+    error_message: &str,
+    sync_attempts: u32,
+    webhook_service: &WebhookService,
+    subscriptions: &[Subscription],
+    email: Option<&EmailConfig>,
+) -> bool {
+    // Initialize or decrement attempts_left
+    let attempts_left = if let Some(attempts) = repo.attempts_left {
+        if attempts > 0 {
+            attempts - 1
+        } else {
+            0 // Already at 0, shouldn't happen but handle gracefully
+        }
+    } else {
+        // First failure: set to sync_attempts - 1
+        sync_attempts - 1
+    };
+
+    repo.attempts_left = Some(attempts_left);
+    repo.error = Some(error_message.to_string());
+
+    // Check if we've run out of attempts
+    if attempts_left == 0 {
+        // Reset attempts_left to None and disable the repository
+        repo.attempts_left = None;
+        repo.enabled = false;
+
+        warn!(
+            repository = %repo.id,
+            "Repository ran out of sync attempts and has been disabled"
+        );
+
+        // Notify webhooks about running out of attempts
+        webhooks::notify_out_of_attempts_webhooks(
+            webhook_service,
+            subscriptions,
+            repo,
+            repo.credential_id.as_ref(),
+            error_message,
+            sync_attempts,
+        );
+        email::notify_out_of_attempts(email, repo, error_message, sync_attempts);
+
+        return true;
+    }
+
+    false
+}
+
+/// Handles successful sync by resetting attempts_left and clearing error.
+///
+/// Returns the error of the previous sync if the repository recovered from failures.
+fn handle_sync_success(repo: &mut Repository) -> Option<String> {
+    // Reset attempts_left to None on successful sync (recovered from errors)
+    if repo.attempts_left.is_some() {
+        info!(
+            repository = %repo.id,
+            "Repository recovered from error spree, resetting attempts"
+        );
+        repo.attempts_left = None;
+    }
+    repo.error.take()
+}
+
+/// Syncs a single repository and records the outcome in the configuration.
+///
+/// This is the single entry point for all sync triggers (scheduler, manual API
+/// calls and push webhooks). It:
+/// 1. Runs the blocking Git sync in the blocking thread pool
+/// 2. On success, updates size, last sync time and commit info, resets failed attempts,
+///    verifies the backup if enabled and backs up the forge metadata if enabled
+///    (verification and metadata failures don't fail the sync)
+/// 3. On failure, notifies error webhooks and email recipients, decrements
+///    `attempts_left` and disables the repository once it runs out of attempts
+/// 4. Requests a (debounced) config save
+///
+/// Each run gets a `job_id`; the log lines of the run carry it together with
+/// `repo_id`, `url` and `trigger` and can be retrieved through `GET /api/sync-logs`.
+///
+/// # Arguments
+///
+/// * `config` - Shared configuration
+/// * `git_service` - Git service performing the sync
+/// * `config_persistence` - Config persistence manager for debounced saves
+/// * `webhook_service` - Service delivering notification webhooks
+/// * `repository_id` - ID of the repository to sync
+/// * `trigger` - What initiated the sync
+///
+/// # Errors
+///
+/// Returns `AppError::NotFound` if the repository doesn't exist,
+/// `AppError::BadRequest` if it is disabled or was disabled by this failure,
+/// `AppError::Conflict` if it is already syncing (not counted as a failure),
+/// or `AppError::InternalError` with the sync error message otherwise.
+#[instrument(
+    name = "sync",
+    skip_all,
+    fields(
+        repo_id = repository_id,
+        url = field::Empty,
+        trigger = %trigger,
+        job_id = %Uuid::new_v4(),
+    )
+)]
+pub async fn sync_repository(
+    config: &Arc<RwLock<Config>>,
+    git_service: &GitService,
+    config_persistence: &ConfigPersistence,
+    webhook_service: &WebhookService,
+    repository_id: &str,
+    trigger: SyncTrigger,
+) -> Result<SyncResult, AppError> {
+    // Read config to get repository and credential info
+    let (
+        repository,
+        credential,
+        encryption_keys,
+        subscriptions,
+        email,
+        sync_attempts,
+        verify_backups,
+        metadata_token,
+    ) = {
+        let cfg = config.read().await;
+        let encryption_keys = cfg.server.encryption_keys();
+        let repository = cfg
+            .repositories
+            .iter()
+            .find(|r| r.id == repository_id)
+            .ok_or_else(|| AppError::NotFound("Repository not found".to_string()))?
+            .clone();
+
+        if !repository.enabled {
+            return Err(AppError::BadRequest("Repository is disabled".to_string()));
+        }
+
+        let credential = repository
+            .credential_id
+            .as_ref()
+            .and_then(|id| cfg.credentials.get(id).cloned());
+        // The forge API token is the password of the metadata credential, falling
+        // back to the repository credential
+        let metadata_token = repository
+            .metadata
+            .as_ref()
+            .and_then(|m| {
+                m.credential_id
+                    .as_ref()
+                    .or(repository.credential_id.as_ref())
+            })
+            .and_then(|id| cfg.get_credential(id))
+            .and_then(|c| c.password.as_ref())
+            .map(|p| encryption_keys.decrypt_password(p))
+            .transpose();
+        (
+            repository,
+            credential,
+            encryption_keys,
+            cfg.server.subscriptions(),
+            cfg.server.email.clone(),
+            cfg.server.sync_attempts,
+            cfg.storage.verify_backups,
+            metadata_token,
+        )
+    }; // Release lock before blocking operation
+
+    // Held until verification and the metadata backup are done as well
+    let _guard = git_service.begin_sync(repository_id).ok_or_else(|| {
+        AppError::Conflict(format!("Repository {} is already syncing", repository_id))
+    })?;
+
+    Span::current().record("url", repository.url.as_str());
+    info!("Starting sync");
+    metrics::record_sync_started(&repository.id);
+    let started = Instant::now();
+
+    // Run the blocking sync operation in a blocking thread pool
+    let git_service_for_sync = git_service.clone();
+    let repository_for_sync = repository.clone();
+    let credential_for_sync = credential.clone();
+    let encryption_keys_for_sync = encryption_keys.clone();
+    // Keep the Git phases in the trace of this sync
+    let span = Span::current();
+    let sync_result = tokio::task::spawn_blocking(move || {
+        span.in_scope(|| {
+            git_service_for_sync.sync_repository(
+                &repository_for_sync,
+                credential_for_sync.as_ref(),
+                &encryption_keys_for_sync,
+            )
+        })
+    })
+    .await
+    .map_err(|e| AppError::InternalError(format!("Task join error: {}", e)))?;
+
+    let result = match sync_result {
+        Ok(ref sync_result_data) if sync_result_data.skipped => "up_to_date",
+        Ok(_) => "success",
+        Err(_) => "failure",
+    };
+    metrics::record_sync_finished(&repository.id, started.elapsed(), result);
+
+    match sync_result {
+        Ok(sync_result_data) => {
+            if sync_result_data.skipped {
+                info!(
+                    commit = %sync_result_data.commit_hash,
+                    duration_ms = started.elapsed().as_millis() as u64,
+                    "Repository already up-to-date, skipped sync"
+                );
+            } else {
+                info!(
+                    path = %sync_result_data.path.display(),
+                    size = sync_result_data.size,
+                    commit = %sync_result_data.commit_hash,
+                    duration_ms = started.elapsed().as_millis() as u64,
+                    "Successfully synced repository"
+                );
+            }
+
+            // Update repository size, last_sync, commit hash, and commit message on success
+            let mut cfg = config.write().await;
+            let (previous_error, config_to_save) =
+                if let Some(repo) = cfg.repositories.iter_mut().find(|r| r.id == repository_id) {
+                    repo.size = Some(sync_result_data.size);
+                    repo.last_sync = Some(chrono::Utc::now());
+                    repo.last_sync_commit_hash = Some(sync_result_data.commit_hash.clone());
+                    repo.last_sync_message = Some(sync_result_data.status_message.clone());
+                    (handle_sync_success(repo), Some(cfg.clone()))
+                } else {
+                    (None, None)
+                };
+            drop(cfg); // Release lock before async operation
+
+            if let Some(config_data) = config_to_save {
+                config_persistence.request_save(config_data);
+            }
+
+            let (event, message) = if sync_result_data.skipped {
+                (
+                    NotificationEvent::SyncSkipped,
+                    "Repository already up-to-date".to_string(),
+                )
+            } else {
+                (
+                    NotificationEvent::SyncSucceeded,
+                    format!("Synced new commits: {}", sync_result_data.commit_message),
+                )
+            };
+            webhooks::notify_event(
+                webhook_service,
+                &subscriptions,
+                event,
+                Some(&repository),
+                &message,
+                serde_json::json!({
+                    "trigger": trigger,
+                    "commit_hash": sync_result_data.commit_hash,
+                    "size": sync_result_data.size,
+                }),
+            );
+            if let Some(previous_error) = previous_error {
+                webhooks::notify_event(
+                    webhook_service,
+                    &subscriptions,
+                    NotificationEvent::Recovered,
+                    Some(&repository),
+                    "Sync succeeded after previous failures",
+                    serde_json::json!({ "previous_error": previous_error }),
+                );
+            }
+
+            if verify_backups && !sync_result_data.skipped {
+                verify_backup(
+                    git_service,
+                    webhook_service,
+                    &subscriptions,
+                    email.as_ref(),
+                    &repository,
+                    &sync_result_data,
+                )
+                .await?;
+            }
+
+            // Issues, releases etc. change independently of commits, so the metadata
+            // is refreshed even if the code was already up-to-date
+            if let Some(ref settings) = repository.metadata {
+                let result = match metadata_token {
+                    Ok(token) => {
+                        metadata::backup_metadata(
+                            git_service,
+                            &repository,
+                            settings,
+                            token,
+                            credential.as_ref(),
+                            &encryption_keys,
+                            &sync_result_data.commit_hash,
+                        )
+                        .await
+                    }
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = result {
+                    let error_message = e.to_string();
+                    error!(error = %error_message, "Failed to back up forge metadata");
+                    webhooks::notify_error_webhooks(
+                        webhook_service,
+                        &subscriptions,
+                        &repository,
+                        "metadata",
+                        repository.credential_id.as_ref(),
+                        &error_message,
+                    );
+                    email::notify_error(
+                        email.as_ref(),
+                        &repository,
+                        "metadata",
+                        repository.credential_id.as_ref(),
+                        &error_message,
+                    );
+                }
+            }
+
+            Ok(sync_result_data)
+        }
+        Err(e) => {
+            let error_message = e.to_string();
+            error!(error = %error_message, "Sync failed");
+
+            // Notify webhooks and email recipients about the error
+            webhooks::notify_error_webhooks(
+                webhook_service,
+                &subscriptions,
+                &repository,
+                "sync",
+                repository.credential_id.as_ref(),
+                &error_message,
+            );
+            email::notify_error(
+                email.as_ref(),
+                &repository,
+                "sync",
+                repository.credential_id.as_ref(),
+                &error_message,
+            );
+
+            // Handle sync failure (update attempts_left, potentially disable repo)
+            let mut cfg = config.write().await;
+            let (was_disabled, config_to_save) =
+                if let Some(repo) = cfg.repositories.iter_mut().find(|r| r.id == repository_id) {
+                    let disabled = handle_sync_failure(
+                        repo,
+                        &error_message,
+                        sync_attempts,
+                        webhook_service,
+                        &subscriptions,
+                        email.as_ref(),
+                    );
+                    (disabled, Some(cfg.clone()))
+                } else {
+                    (false, None)
+                };
+            drop(cfg); // Release lock before async operation
+
+            if let Some(config_data) = config_to_save {
+                config_persistence.request_save(config_data);
+            }
+
+            if was_disabled {
+                return Err(AppError::BadRequest(format!(
+                    "Repository {} ran out of sync attempts and has been disabled",
+                    repository_id
+                )));
+            }
+
+            Err(AppError::InternalError(error_message))
+        }
+    }
+}
+
+/// Checks the integrity of a fresh backup and notifies about failures.
+///
+/// Only fails if the verification task cannot be joined; a failed verification
+/// is reported through `verification_failed` notifications and email.
+async fn verify_backup(
+    git_service: &GitService,
+    webhook_service: &WebhookService,
+    subscriptions: &[Subscription],
+    email: Option<&EmailConfig>,
+    repository: &Repository,
+    sync_result: &SyncResult,
+) -> Result<(), AppError> {
+    let git_service = git_service.clone();
+    let path = sync_result.path.clone();
+    let commit_hash = sync_result.commit_hash.clone();
+    let result =
+        tokio::task::spawn_blocking(move || git_service.verify_backup(&path, &commit_hash))
+            .await
+            .map_err(|e| AppError::InternalError(format!("Task join error: {}", e)))?;
+
+    match result {
+        Ok(()) => info!(repository = %repository.id, "Verified backup"),
+        Err(e) => {
+            let error_message = e.to_string();
+            error!(
+                repository = %repository.id,
+                error = %error_message,
+                "Backup verification failed"
+            );
+            webhooks::notify_event(
+                webhook_service,
+                subscriptions,
+                NotificationEvent::VerificationFailed,
+                Some(repository),
+                &error_message,
+                serde_json::json!({
+                    "path": sync_result.path,
+                    "commit_hash": sync_result.commit_hash,
+                }),
+            );
+            email::notify_error(
+                email,
+                repository,
+                "verification",
+                repository.credential_id.as_ref(),
+                &error_message,
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Checks the free space of the archive volume against `storage.min_free_space_mb`.
+///
+/// A `storage_low` notification is sent when the free space falls below the
+/// threshold, but not again until it recovered in between. Returns whether the
+/// space is low, to be passed as `was_low` to the next check.
+pub async fn check_free_space(
+    config: &Arc<RwLock<Config>>,
+    git_service: &GitService,
+    webhook_service: &WebhookService,
+    was_low: bool,
+) -> bool {
+    let (min_free_space_mb, subscriptions) = {
+        let cfg = config.read().await;
+        (cfg.storage.min_free_space_mb, cfg.server.subscriptions())
+    };
+    let Some(min_free_space_mb) = min_free_space_mb else {
+        return false;
+    };
+
+    let available = match git_service.available_space() {
+        Ok(available) => available,
+        Err(e) => {
+            error!(error = %e, "Failed to determine free space of the archive directory");
+            return was_low;
+        }
+    };
+    let available_mb = available / (1024 * 1024);
+    let is_low = available_mb < min_free_space_mb;
+
+    if is_low && !was_low {
+        warn!(
+            available_mb,
+            min_free_space_mb, "Free space on the archive volume is below the minimum"
+        );
+        webhooks::notify_event(
+            webhook_service,
+            &subscriptions,
+            NotificationEvent::StorageLow,
+            None,
+            &format!(
+                "Only {} MB free on the archive volume, below the minimum of {} MB",
+                available_mb, min_free_space_mb
+            ),
+            serde_json::json!({
+                "available_mb": available_mb,
+                "min_free_space_mb": min_free_space_mb,
+            }),
+        );
+    }
+    is_low
+}
+
+/// Minimum delay before retrying a due sync whose repository is still syncing,
+/// so that a debounce period of `0` doesn't busy-loop until the sync finishes.
+const RUNNING_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Queue of repository syncs requested by push webhooks.
+///
+/// Requests are debounced per repository: a sync starts once no further request
+/// for the same repository arrived for the debounce period, so a burst of pushes
+/// results in a single sync. Requests due while the repository syncs, whatever
+/// triggered that sync, are scheduled again after the debounce period (at least
+/// `RUNNING_RETRY_DELAY`).
+#[derive(Clone)]
+pub struct SyncQueue {
+    sender: mpsc::UnboundedSender<String>,
+}
+
+impl SyncQueue {
+    /// Creates a new SyncQueue and starts the background task.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Shared configuration
+    /// * `git_service` - Git service performing the syncs
+    /// * `config_persistence` - Config persistence manager for debounced saves
+    /// * `webhook_service` - Service delivering error webhooks
+    /// * `debounce` - Quiet period per repository before a queued sync starts
+    pub fn new(
+        config: Arc<RwLock<Config>>,
+        git_service: GitService,
+        config_persistence: ConfigPersistence,
+        webhook_service: WebhookService,
+        debounce: Duration,
+    ) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        tokio::spawn(Self::queue_task(
+            receiver,
+            sender.downgrade(),
+            config,
+            git_service,
+            config_persistence,
+            webhook_service,
+            debounce,
+        ));
+
+        Self { sender }
+    }
+
+    /// Requests a sync of a repository. Non-blocking.
+    pub fn enqueue(&self, repository_id: &str) {
+        if let Err(e) = self.sender.send(repository_id.to_string()) {
+            error!(error = %e, "Failed to queue sync request");
+        }
+    }
+
+    async fn queue_task(
+        mut receiver: mpsc::UnboundedReceiver<String>,
+        sender: mpsc::WeakUnboundedSender<String>,
+        config: Arc<RwLock<Config>>,
+        git_service: GitService,
+        config_persistence: ConfigPersistence,
+        webhook_service: WebhookService,
+        debounce: Duration,
+    ) {
+        let mut pending: HashMap<String, Instant> = HashMap::new();
+
+        loop {
+            let next_deadline = pending.values().min().copied();
+
+            tokio::select! {
+                request = receiver.recv() => {
+                    match request {
+                        // (Re)start the debounce period for this repository
+                        Some(repository_id) => {
+                            pending.insert(repository_id, Instant::now() + debounce);
+                        }
+                        None => {
+                            info!("Sync queue shutting down");
+                            break;
+                        }
+                    }
+                }
+                _ = async {
+                    match next_deadline {
+                        Some(deadline) => sleep_until(deadline).await,
+                        None => std::future::pending().await,
+                    }
+                }, if next_deadline.is_some() => {
+                    let now = Instant::now();
+                    let due: Vec<String> = pending
+                        .iter()
+                        .filter(|(_, deadline)| **deadline <= now)
+                        .map(|(id, _)| id.clone())
+                        .collect();
+
+                    for repository_id in due {
+                        pending.remove(&repository_id);
+
+                        if git_service.is_syncing(&repository_id) {
+                            // Still syncing, try again later
+                            let retry = now + debounce.max(RUNNING_RETRY_DELAY);
+                            pending.insert(repository_id, retry);
+                            continue;
+                        }
+
+                        let config = Arc::clone(&config);
+                        let git_service = git_service.clone();
+                        let config_persistence = config_persistence.clone();
+                        let webhook_service = webhook_service.clone();
+                        let sender = sender.clone();
+                        tokio::spawn(async move {
+                            match sync_repository(
+                                &config,
+                                &git_service,
+                                &config_persistence,
+                                &webhook_service,
+                                &repository_id,
+                                SyncTrigger::Push,
+                            )
+                            .await
+                            {
+                                Ok(_) => {}
+                                // Another sync started in the meantime, queue the request again
+                                Err(AppError::Conflict(_)) => {
+                                    if let Some(sender) = sender.upgrade() {
+                                        let _ = sender.send(repository_id);
+                                    }
+                                }
+                                Err(e) => {
+                                    error!(repository = %repository_id, error = %e, "Failed to sync repository");
+                                }
+                            }
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use crate::config::{LogFormat, LoggingConfig, TracingConfig};
+use crate::error::AppError;
+use crate::logging::{JsonLayer, SyncLogs};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+use std::io::IsTerminal;
+use tracing::level_filters::LevelFilter;
+use tracing::Subscriber;
+use tracing_subscriber::filter::{filter_fn, FilterExt, Targets};
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+/// Handle of the installed trace export, flushing pending spans on shutdown.
+pub struct Telemetry {
+    provider: Option<SdkTracerProvider>,
+    sync_logs: SyncLogs,
+}
+
+impl Telemetry {
+    /// The store receiving the log lines of sync runs.
+    pub fn sync_logs(&self) -> SyncLogs {
+        self.sync_logs.clone()
+    }
+
+    /// Exports the spans that are still buffered and stops the exporter.
+    pub fn shutdown(self) {
+        if let Some(provider) = self.provider {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Failed to shut down trace export: {}", e);
+            }
+        }
+    }
+}
+
+/// Installs the global subscriber for logs and traces.
+///
+/// Logs are written to stderr in the configured format, filtered by `RUST_LOG`
+/// like before; records of the `log` crate are forwarded as well. The log lines
+/// of sync runs are additionally captured into the returned `SyncLogs`,
+/// independently of `RUST_LOG`. If `tracing` is set, the spans of syncs and API
+/// requests are exported to the OTLP collector, also independently of `RUST_LOG`.
+///
+/// # Panics
+///
+/// Panics if a global subscriber is already installed.
+pub fn init(tracing: Option<&TracingConfig>, logging: &LoggingConfig) -> Telemetry {
+    let (text_layer, json_layer) = match logging.format {
+        LogFormat::Text => {
+            let span_events = if tracing.is_some_and(|t| t.log_spans) {
+                FmtSpan::CLOSE
+            } else {
+                FmtSpan::NONE
+            };
+            let layer = tracing_subscriber::fmt::layer()
+                .with_writer(std::io::stderr)
+                .with_ansi(std::io::stderr().is_terminal())
+                .with_span_events(span_events)
+                .with_filter(EnvFilter::from_default_env());
+            (Some(layer), None)
+        }
+        LogFormat::Json => {
+            // The spans of GitSafe are always enabled, so their fields are
+            // attached to every line even if `RUST_LOG` only lets errors through
+            let layer = JsonLayer::new(std::io::stderr).with_filter(
+                EnvFilter::from_default_env().or(filter_fn(|metadata| {
+                    metadata.is_span() && metadata.target().starts_with("gitsafe")
+                })),
+            );
+            (None, Some(layer))
+        }
+    };
+
+    let sync_logs = SyncLogs::new(logging.sync_log_runs);
+    let sync_log_layer = sync_logs
+        .layer()
+        .with_filter(Targets::new().with_target("gitsafe", LevelFilter::INFO));
+
+    let provider = tracing.and_then(|config| match tracer_provider(config) {
+        Ok(provider) => Some(provider),
+        Err(e) => {
+            eprintln!("Tracing disabled: {}", e);
+            None
+        }
+    });
+
+    if provider.is_some() {
+        // Continue traces of callers sending a W3C `traceparent` header
+        opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+    }
+
+    tracing_subscriber::registry()
+        .with(text_layer)
+        .with(json_layer)
+        .with(sync_log_layer)
+        .with(provider.as_ref().map(otel_layer))
+        .init();
+
+    Telemetry {
+        provider,
+        sync_logs,
+    }
+}
+
+/// Creates a tracer provider exporting spans in batches to the OTLP/HTTP endpoint.
+///
+/// # Errors
+///
+/// Returns `AppError::ConfigError` if the exporter can't be created, e.g. for an
+/// invalid endpoint.
+pub fn tracer_provider(config: &TracingConfig) -> Result<SdkTracerProvider, AppError> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(&config.endpoint)
+        .build()
+        .map_err(|e| AppError::ConfigError(format!("Invalid tracing settings: {}", e)))?;
+
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(
+            Resource::builder()
+                .with_service_name(config.service_name.clone())
+                .build(),
+        )
+        .build())
+}
+
+/// Layer turning the spans of GitSafe and the request spans of actix into
+/// OpenTelemetry spans.
+///
+/// Only spans of GitSafe itself (`target` = `gitsafe`) and the HTTP request
+/// spans are exported, so the HTTP client of the exporter and other
+/// dependencies don't add noise to the traces.
+pub fn otel_layer<S>(provider: &SdkTracerProvider) -> impl Layer<S>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer()
+        .with_tracer(provider.tracer("gitsafe"))
+        .with_filter(
+            Targets::new()
+                .with_target("gitsafe", LevelFilter::INFO)
+                .with_target("tracing_actix_web", LevelFilter::INFO),
+        )
+}
@@ -0,0 +1,256 @@
+use crate::config::TlsConfig;
+use crate::error::AppError;
+use actix_tls::accept::rustls_0_23::TlsStream;
+use actix_web::dev::Extensions;
+use actix_web::http::header;
+use actix_web::rt::net::TcpStream;
+use actix_web::{HttpRequest, HttpResponse};
+use rustls::crypto::{ring, CryptoProvider};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier};
+use rustls::sign::CertifiedKey;
+use rustls::{RootCertStore, ServerConfig};
+use std::any::Any;
+use std::fs;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::{error, info};
+
+/// Verified certificate of the client of a connection, stored in the
+/// connection data by `store_client_certificate`.
+#[derive(Debug, Clone)]
+pub struct ClientCertificate {
+    /// Common name of the certificate subject, the username it authenticates
+    pub common_name: String,
+}
+
+/// Certificate and key loaded from PEM files, and the file contents they were
+/// loaded from.
+struct LoadedCertificate {
+    key: Arc<CertifiedKey>,
+    cert_pem: Vec<u8>,
+    key_pem: Vec<u8>,
+}
+
+/// Server certificate that is reloaded when its files change, e.g. after a
+/// renewal, without restarting the server.
+///
+/// New connections get the certificate loaded last; a failed reload keeps it.
+pub struct ReloadableCertificate {
+    cert_path: String,
+    key_path: String,
+    current: RwLock<LoadedCertificate>,
+}
+
+impl std::fmt::Debug for ReloadableCertificate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReloadableCertificate")
+            .field("cert_path", &self.cert_path)
+            .field("key_path", &self.key_path)
+            .finish()
+    }
+}
+
+impl ReloadableCertificate {
+    /// Loads the certificate chain and private key.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::ConfigError` if a file can't be read, contains no
+    /// certificate or key, or the key doesn't belong to the certificate.
+    pub fn load(cert_path: &str, key_path: &str) -> Result<Self, AppError> {
+        Ok(ReloadableCertificate {
+            cert_path: cert_path.to_string(),
+            key_path: key_path.to_string(),
+            current: RwLock::new(load_certificate(cert_path, key_path)?),
+        })
+    }
+
+    /// The certificate currently served.
+    pub fn current(&self) -> Arc<CertifiedKey> {
+        Arc::clone(&self.current.read().unwrap().key)
+    }
+
+    /// Reloads the certificate if the content of its files changed.
+    ///
+    /// Returns whether a new certificate was loaded.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::ConfigError` if the changed files are invalid; the
+    /// previous certificate stays in use.
+    pub fn reload_if_changed(&self) -> Result<bool, AppError> {
+        let cert_pem = read_file(&self.cert_path)?;
+        let key_pem = read_file(&self.key_path)?;
+        {
+            let current = self.current.read().unwrap();
+            if current.cert_pem == cert_pem && current.key_pem == key_pem {
+                return Ok(false);
+            }
+        }
+
+        let loaded = parse_certificate(cert_pem, key_pem)?;
+        *self.current.write().unwrap() = loaded;
+        Ok(true)
+    }
+
+    /// Starts the background task checking the files for changes every `interval`.
+    pub fn watch(self: &Arc<Self>, interval: Duration) {
+        let certificate = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                match certificate.reload_if_changed() {
+                    Ok(true) => info!(path = %certificate.cert_path, "Reloaded TLS certificate"),
+                    Ok(false) => {}
+                    Err(e) => error!(error = %e, "Failed to reload TLS certificate"),
+                }
+            }
+        });
+    }
+}
+
+impl ResolvesServerCert for ReloadableCertificate {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current())
+    }
+}
+
+fn read_file(path: &str) -> Result<Vec<u8>, AppError> {
+    fs::read(path).map_err(|e| AppError::ConfigError(format!("Failed to read {}: {}", path, e)))
+}
+
+fn load_certificate(cert_path: &str, key_path: &str) -> Result<LoadedCertificate, AppError> {
+    parse_certificate(read_file(cert_path)?, read_file(key_path)?)
+}
+
+fn parse_certificate(cert_pem: Vec<u8>, key_pem: Vec<u8>) -> Result<LoadedCertificate, AppError> {
+    let certs = parse_certificates(&cert_pem)?;
+    let key = PrivateKeyDer::from_pem_slice(&key_pem)
+        .map_err(|e| AppError::ConfigError(format!("Invalid TLS private key: {}", e)))?;
+    let key = CertifiedKey::from_der(certs, key, &provider())
+        .map_err(|e| AppError::ConfigError(format!("Invalid TLS certificate or key: {}", e)))?;
+
+    Ok(LoadedCertificate {
+        key: Arc::new(key),
+        cert_pem,
+        key_pem,
+    })
+}
+
+fn parse_certificates(pem: &[u8]) -> Result<Vec<CertificateDer<'static>>, AppError> {
+    let certs = CertificateDer::pem_slice_iter(pem)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| AppError::ConfigError(format!("Invalid TLS certificate: {}", e)))?;
+    if certs.is_empty() {
+        return Err(AppError::ConfigError(
+            "No certificate found in PEM file".to_string(),
+        ));
+    }
+    Ok(certs)
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(ring::default_provider())
+}
+
+/// Builds the rustls configuration of the HTTPS listener.
+///
+/// With `client_auth`, clients are asked for a certificate issued by one of
+/// its CAs; unless it's `required`, clients without one may still connect.
+///
+/// # Errors
+///
+/// Returns `AppError::ConfigError` if the CA file is invalid.
+pub fn server_config(
+    settings: &TlsConfig,
+    certificate: Arc<ReloadableCertificate>,
+) -> Result<ServerConfig, AppError> {
+    let builder = ServerConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| AppError::ConfigError(format!("Invalid TLS settings: {}", e)))?;
+
+    let builder = match &settings.client_auth {
+        Some(client_auth) => {
+            let mut roots = RootCertStore::empty();
+            for cert in parse_certificates(&read_file(&client_auth.ca_path)?)? {
+                roots.add(cert).map_err(|e| {
+                    AppError::ConfigError(format!("Invalid client CA certificate: {}", e))
+                })?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider());
+            let verifier = if client_auth.required {
+                verifier
+            } else {
+                verifier.allow_unauthenticated()
+            };
+            let verifier = verifier
+                .build()
+                .map_err(|e| AppError::ConfigError(format!("Invalid client CA: {}", e)))?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    Ok(builder.with_cert_resolver(certificate))
+}
+
+/// Stores the verified client certificate of a new HTTPS connection in its
+/// connection data (see `HttpServer::on_connect`), where `AuthMiddleware`
+/// finds it.
+pub fn store_client_certificate(connection: &dyn Any, data: &mut Extensions) {
+    let Some(stream) = connection.downcast_ref::<TlsStream<TcpStream>>() else {
+        return;
+    };
+    let (_, session) = stream.get_ref();
+    let common_name = session
+        .peer_certificates()
+        .and_then(|certs| certs.first())
+        .and_then(|cert| common_name(cert));
+    if let Some(common_name) = common_name {
+        data.insert(ClientCertificate { common_name });
+    }
+}
+
+/// Returns the common name of the subject of a DER certificate.
+pub fn common_name(der: &[u8]) -> Option<String> {
+    let (_, cert) = x509_parser::parse_x509_certificate(der).ok()?;
+    let common_name = cert
+        .subject()
+        .iter_common_name()
+        .next()?
+        .as_str()
+        .ok()?
+        .to_string();
+    Some(common_name)
+}
+
+/// Redirects a request of the HTTP listener to the same URL on the HTTPS port.
+pub fn redirect_to_https(req: &HttpRequest, https_port: u16) -> HttpResponse {
+    let info = req.connection_info();
+    let host = info.host();
+    // Strip the port of the HTTP listener, keeping IPv6 addresses intact
+    let host = match host.rfind(':') {
+        Some(index) if !host[index..].contains(']') => &host[..index],
+        _ => host,
+    };
+    let port = if https_port == 443 {
+        String::new()
+    } else {
+        format!(":{}", https_port)
+    };
+    let path = req
+        .uri()
+        .path_and_query()
+        .map_or("/", |path_and_query| path_and_query.as_str());
+
+    HttpResponse::PermanentRedirect()
+        .insert_header((
+            header::LOCATION,
+            format!("https://{}{}{}", host, port, path),
+        ))
+        .finish()
+}
@@ -0,0 +1,215 @@
+use crate::auth::hash_api_token;
+use crate::config::UserTotp;
+use crate::encryption::EncryptionKeys;
+use crate::error::AppError;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use chrono::{DateTime, Utc};
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use subtle::ConstantTimeEq;
+
+/// Issuer shown next to the account in authenticator apps.
+const ISSUER: &str = "GitSafe";
+
+/// Seconds each one-time password is valid (RFC 6238 default).
+pub const TIME_STEP_SECONDS: i64 = 30;
+
+/// Digits of a one-time password.
+const DIGITS: u32 = 6;
+
+/// Time steps before and after the current one whose codes are accepted, to
+/// tolerate clock drift between the server and the authenticator app.
+const ALLOWED_DRIFT_STEPS: u64 = 1;
+
+/// Number of recovery codes generated on enrollment.
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Generates a new secret: 20 random bytes in base32, as expected by authenticator apps.
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 20];
+    OsRng.fill_bytes(&mut bytes);
+    BASE32_NOPAD.encode(&bytes)
+}
+
+/// Returns the `otpauth://` URI that authenticator apps scan as QR code.
+pub fn provisioning_uri(username: &str, secret: &str) -> String {
+    let mut uri = url::Url::parse("otpauth://totp/").expect("valid URI");
+    uri.set_path(&format!("{}:{}", ISSUER, username));
+    uri.query_pairs_mut()
+        .append_pair("secret", secret)
+        .append_pair("issuer", ISSUER)
+        .append_pair("algorithm", "SHA1")
+        .append_pair("digits", &DIGITS.to_string())
+        .append_pair("period", &TIME_STEP_SECONDS.to_string());
+    uri.to_string()
+}
+
+/// Returns the one-time password of a base32 secret at a point in time.
+///
+/// # Errors
+///
+/// Returns `AppError::InternalError` if the secret isn't valid base32.
+pub fn code_at(secret: &str, time: DateTime<Utc>) -> Result<String, AppError> {
+    Ok(code_for_step(&decode_secret(secret)?, time_step(time)))
+}
+
+/// Checks a one-time password against the codes of the current time step and
+/// the steps within the allowed drift.
+///
+/// Steps up to `last_used_step` are skipped, so that every code is accepted
+/// only once. Returns the step of the matching code.
+///
+/// # Errors
+///
+/// Returns `AppError::InternalError` if the secret isn't valid base32.
+pub fn verify_code(
+    secret: &str,
+    code: &str,
+    now: DateTime<Utc>,
+    last_used_step: Option<u64>,
+) -> Result<Option<u64>, AppError> {
+    let key = decode_secret(secret)?;
+    let code = code.trim();
+    if code.len() != DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return Ok(None);
+    }
+
+    let current = time_step(now);
+    let first = current
+        .saturating_sub(ALLOWED_DRIFT_STEPS)
+        .max(last_used_step.map_or(0, |step| step + 1));
+    Ok((first..=current + ALLOWED_DRIFT_STEPS).find(|step| {
+        code_for_step(&key, *step)
+            .as_bytes()
+            .ct_eq(code.as_bytes())
+            .into()
+    }))
+}
+
+/// Generates `RECOVERY_CODE_COUNT` recovery codes: 8 random bytes in hex,
+/// grouped in blocks of four characters.
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let mut bytes = [0u8; 8];
+            OsRng.fill_bytes(&mut bytes);
+            let hex = hex::encode(bytes);
+            [&hex[0..4], &hex[4..8], &hex[8..12], &hex[12..16]].join("-")
+        })
+        .collect()
+}
+
+/// Hashes a recovery code for storage, ignoring case, dashes and whitespace.
+pub fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| *c != '-' && !c.is_whitespace())
+        .collect::<String>()
+        .to_lowercase();
+    hash_api_token(&normalized)
+}
+
+/// Starts an enrollment with a new secret, encrypted with the current key.
+///
+/// Returns the enrollment and the plaintext secret for the authenticator app.
+/// The enrollment only takes effect after `confirm`.
+pub fn enroll(encryption_keys: &EncryptionKeys) -> Result<(UserTotp, String), AppError> {
+    let secret = generate_secret();
+    let totp = UserTotp {
+        secret: encryption_keys.encrypt(&secret)?,
+        confirmed: false,
+        recovery_code_hashes: Vec::new(),
+        last_used_step: None,
+    };
+    Ok((totp, secret))
+}
+
+/// Confirms an enrollment with a code of the authenticator app and replaces the
+/// recovery codes.
+///
+/// Returns the new recovery codes; only their hashes are kept.
+///
+/// # Errors
+///
+/// Returns `AppError::BadRequest` if the code is wrong.
+pub fn confirm(
+    totp: &mut UserTotp,
+    code: &str,
+    encryption_keys: &EncryptionKeys,
+    now: DateTime<Utc>,
+) -> Result<Vec<String>, AppError> {
+    let secret = encryption_keys.decrypt(&totp.secret)?;
+    let step = verify_code(&secret, code, now, totp.last_used_step)?
+        .ok_or_else(|| AppError::BadRequest("Invalid two-factor code".to_string()))?;
+
+    totp.confirmed = true;
+    totp.last_used_step = Some(step);
+    let recovery_codes = generate_recovery_codes();
+    totp.recovery_code_hashes = recovery_codes
+        .iter()
+        .map(|code| hash_recovery_code(code))
+        .collect();
+    Ok(recovery_codes)
+}
+
+/// Verifies the second factor of a login: a one-time password or an unused
+/// recovery code, which is then removed.
+///
+/// Returns whether a recovery code was used.
+///
+/// # Errors
+///
+/// Returns `AppError::AuthError` if the code is neither.
+pub fn verify_login(
+    totp: &mut UserTotp,
+    code: &str,
+    encryption_keys: &EncryptionKeys,
+    now: DateTime<Utc>,
+) -> Result<bool, AppError> {
+    let secret = encryption_keys.decrypt(&totp.secret)?;
+    if let Some(step) = verify_code(&secret, code, now, totp.last_used_step)? {
+        totp.last_used_step = Some(step);
+        return Ok(false);
+    }
+
+    let hash = hash_recovery_code(code);
+    match totp.recovery_code_hashes.iter().position(|h| *h == hash) {
+        Some(index) => {
+            totp.recovery_code_hashes.remove(index);
+            Ok(true)
+        }
+        None => Err(AppError::AuthError("Invalid two-factor code".to_string())),
+    }
+}
+
+fn decode_secret(secret: &str) -> Result<Vec<u8>, AppError> {
+    BASE32_NOPAD
+        .decode(secret.trim_end_matches('=').to_uppercase().as_bytes())
+        .map_err(|e| AppError::InternalError(format!("Invalid TOTP secret: {}", e)))
+}
+
+fn time_step(time: DateTime<Utc>) -> u64 {
+    (time.timestamp() / TIME_STEP_SECONDS).max(0) as u64
+}
+
+/// HOTP value (RFC 4226) of a key and counter, as zero-padded decimal.
+fn code_for_step(key: &[u8], step: u64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    format!(
+        "{:0width$}",
+        binary % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    )
+}
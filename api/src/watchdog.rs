@@ -0,0 +1,150 @@
+use crate::config::{Config, NotificationEvent, UpstreamStatus};
+use crate::webhooks::{self, WebhookService};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+/// A repository without a successful sync within its maximum backup age.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct StaleBackup {
+    /// Repository ID
+    pub id: String,
+    /// Time of the last successful sync (None if it never synced)
+    pub last_sync: Option<DateTime<Utc>>,
+    pub max_backup_age_hours: u64,
+}
+
+/// Alerts when backups get older than their maximum age.
+///
+/// The watchdog runs independently of the scheduler, so it also notices syncs
+/// that never happen because a job hangs or is skipped. Each repository that
+/// becomes stale is reported once with a `backup_stale` event; the current
+/// stale backups are reported by `/health`.
+#[derive(Clone, Default)]
+pub struct Watchdog {
+    state: Arc<Mutex<WatchdogState>>,
+}
+
+#[derive(Default)]
+struct WatchdogState {
+    /// When the watchdog first saw each repository, the reference for
+    /// repositories that never synced
+    first_seen: HashMap<String, DateTime<Utc>>,
+    stale: Vec<StaleBackup>,
+}
+
+impl Watchdog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts the background task checking the backups every `interval`.
+    pub fn start(
+        &self,
+        config: Arc<RwLock<Config>>,
+        webhook_service: WebhookService,
+        interval: Duration,
+    ) {
+        let watchdog = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            loop {
+                interval.tick().await;
+                watchdog.check(&config, &webhook_service, Utc::now()).await;
+            }
+        });
+    }
+
+    /// Backups that were stale at the last check.
+    pub fn stale(&self) -> Vec<StaleBackup> {
+        self.state.lock().unwrap().stale.clone()
+    }
+
+    /// Compares the last sync of every enabled repository against its maximum
+    /// backup age and notifies about backups that became stale since the last check.
+    ///
+    /// Repositories without a maximum age, disabled repositories and repositories
+    /// deleted upstream (which are not synced anymore) are never stale.
+    ///
+    /// # Returns
+    ///
+    /// The backups that are stale at `now`.
+    pub async fn check(
+        &self,
+        config: &Arc<RwLock<Config>>,
+        webhook_service: &WebhookService,
+        now: DateTime<Utc>,
+    ) -> Vec<StaleBackup> {
+        let config = config.read().await;
+        let mut state = self.state.lock().unwrap();
+
+        state
+            .first_seen
+            .retain(|id, _| config.repositories.iter().any(|r| &r.id == id));
+        let mut stale = Vec::new();
+        for repo in &config.repositories {
+            let first_seen = *state.first_seen.entry(repo.id.clone()).or_insert(now);
+            let Some(max_backup_age_hours) = repo
+                .max_backup_age_hours
+                .or(config.scheduler.max_backup_age_hours)
+            else {
+                continue;
+            };
+            if !repo.enabled || repo.upstream_status == Some(UpstreamStatus::Deleted) {
+                continue;
+            }
+
+            let age = now - repo.last_sync.unwrap_or(first_seen);
+            if age > chrono::Duration::hours(max_backup_age_hours as i64) {
+                stale.push(StaleBackup {
+                    id: repo.id.clone(),
+                    last_sync: repo.last_sync,
+                    max_backup_age_hours,
+                });
+            }
+        }
+
+        let subscriptions = config.server.subscriptions();
+        for backup in &stale {
+            if state.stale.iter().any(|s| s.id == backup.id) {
+                continue;
+            }
+            let message = match backup.last_sync {
+                Some(last_sync) => format!(
+                    "No successful sync since {}, the maximum backup age is {} hours",
+                    last_sync.format("%Y-%m-%d %H:%M UTC"),
+                    backup.max_backup_age_hours
+                ),
+                None => format!(
+                    "No successful sync yet, the maximum backup age is {} hours",
+                    backup.max_backup_age_hours
+                ),
+            };
+            warn!(repository = %backup.id, reason = %message, "Backup is stale");
+            let repo = config.repositories.iter().find(|r| r.id == backup.id);
+            webhooks::notify_event(
+                webhook_service,
+                &subscriptions,
+                NotificationEvent::BackupStale,
+                repo,
+                &message,
+                serde_json::json!({
+                    "last_sync": backup.last_sync,
+                    "max_backup_age_hours": backup.max_backup_age_hours,
+                }),
+            );
+        }
+        for backup in &state.stale {
+            if !stale.iter().any(|s| s.id == backup.id) {
+                info!(repository = %backup.id, "Backup is no longer stale");
+            }
+        }
+
+        state.stale = stale.clone();
+        stale
+    }
+}
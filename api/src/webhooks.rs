@@ -1,7 +1,27 @@
-use crate::config::Repository;
-use chrono::Utc;
-use log::warn;
-use serde::Serialize;
+use crate::config::{
+    NotificationEvent, Repository, Subscription, Webhook, WebhookDeliveryConfig, WebhookFormat,
+};
+use crate::error::AppError;
+use crate::metrics;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+/// Maximum number of characters of a response body kept in the delivery log.
+const RESPONSE_SNIPPET_LENGTH: usize = 500;
+
+/// Maximum number of characters of an error message in chat notifications.
+/// Slack limits section text to 3000 characters.
+const MESSAGE_LENGTH: usize = 2500;
 
 /// Payload sent to error webhooks when a sync operation fails.
 #[derive(Debug, Serialize, Clone)]
@@ -35,6 +55,22 @@ pub struct OutOfAttemptsWebhookPayload {
     pub sync_attempts: u32,
 }
 
+/// Payload sent to webhooks for events other than errors and disabled repositories.
+#[derive(Debug, Serialize, Clone)]
+pub struct EventWebhookPayload {
+    /// Timestamp of the event (ISO 8601 format)
+    pub time: String,
+    pub event: NotificationEvent,
+    /// Repository the event is about, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repo: Option<RepoInfo>,
+    /// Human readable description of the event
+    pub message: String,
+    /// Event specific details (e.g. commit hash, credential ID, free space)
+    #[serde(skip_serializing_if = "serde_json::Value::is_null")]
+    pub details: serde_json::Value,
+}
+
 /// Repository information included in webhook payloads.
 #[derive(Debug, Serialize, Clone)]
 pub struct RepoInfo {
@@ -46,135 +82,1045 @@ pub struct RepoInfo {
     pub enabled: bool,
 }
 
-/// Sends error notifications to configured webhooks.
+impl From<&Repository> for RepoInfo {
+    fn from(repo: &Repository) -> Self {
+        RepoInfo {
+            id: repo.id.clone(),
+            url: repo.url.clone(),
+            enabled: repo.enabled,
+        }
+    }
+}
+
+/// Outcome of a webhook delivery.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    /// Not delivered yet, further attempts are scheduled
+    Pending,
+    /// The receiver answered with a 2xx status
+    Delivered,
+    /// All attempts failed, or the receiver rejected the payload
+    Failed,
+}
+
+/// A webhook delivery as recorded in the delivery log.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WebhookDelivery {
+    pub id: String,
+    /// Receiver URL
+    pub url: String,
+    /// Kind of notification (e.g. "error", "out_of_attempts")
+    pub event: String,
+    /// JSON payload sent to the receiver
+    pub payload: serde_json::Value,
+    pub status: DeliveryStatus,
+    /// Number of attempts made so far
+    pub attempts: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// HTTP status code of the last attempt
+    pub status_code: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Duration of the last attempt in milliseconds
+    pub latency_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Beginning of the response body of the last attempt
+    pub response: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Error of the last attempt if no response was received
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// ID of the delivery this one re-sends
+    pub redelivery_of: Option<String>,
+}
+
+/// Sends outgoing webhooks with retries and records every delivery.
 ///
-/// This function sends HTTP POST requests to all configured webhook URLs
-/// with error details. Failures to send webhooks are logged but do not
-/// affect the main error handling flow.
+/// Deliveries run in the background. Each state change is appended to a JSON
+/// lines file (`webhook_deliveries.jsonl` in the data directory) and the latest
+/// `log_retention` deliveries are kept in memory for the API. The file is
+/// compacted once it holds twice as many records as retained.
+#[derive(Clone)]
+pub struct WebhookService {
+    settings: WebhookDeliveryConfig,
+    client: reqwest::Client,
+    log: Arc<Mutex<DeliveryLog>>,
+}
+
+struct DeliveryLog {
+    path: PathBuf,
+    entries: VecDeque<WebhookDelivery>,
+    /// Number of records in the file
+    records: usize,
+}
+
+impl WebhookService {
+    /// Creates a new WebhookService and loads the existing delivery log.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - Retry and retention settings
+    /// * `data_dir` - Directory holding the delivery log
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::IoError` if the data directory or log file cannot be read.
+    /// Malformed lines in the log are skipped, and deliveries that were still
+    /// pending when the server stopped are marked as failed.
+    pub fn new<P: AsRef<Path>>(
+        settings: WebhookDeliveryConfig,
+        data_dir: P,
+    ) -> Result<Self, AppError> {
+        fs::create_dir_all(data_dir.as_ref())?;
+        let path = data_dir.as_ref().join("webhook_deliveries.jsonl");
+
+        let mut entries: VecDeque<WebhookDelivery> = VecDeque::new();
+        let mut records = 0;
+        if path.exists() {
+            for line in BufReader::new(File::open(&path)?).lines() {
+                let Ok(delivery) = serde_json::from_str::<WebhookDelivery>(&line?) else {
+                    continue;
+                };
+                records += 1;
+                // Later records of a delivery replace earlier ones
+                entries.retain(|d| d.id != delivery.id);
+                entries.push_back(delivery);
+                if entries.len() > settings.log_retention {
+                    entries.pop_front();
+                }
+            }
+        }
+
+        let mut log = DeliveryLog {
+            path,
+            entries,
+            records,
+        };
+        // Retries of deliveries pending when the server stopped were lost with
+        // it; they can be redelivered through the API
+        let now = Utc::now();
+        let mut interrupted = 0;
+        for delivery in log
+            .entries
+            .iter_mut()
+            .filter(|d| d.status == DeliveryStatus::Pending)
+        {
+            delivery.status = DeliveryStatus::Failed;
+            delivery.error = Some("Interrupted by a restart of the server".to_string());
+            delivery.updated_at = now;
+            interrupted += 1;
+        }
+        if interrupted > 0 {
+            warn!(
+                deliveries = interrupted,
+                "Marked webhook deliveries interrupted by a restart as failed"
+            );
+            log.compact()?;
+        }
+
+        Ok(WebhookService {
+            settings,
+            client: reqwest::Client::new(),
+            log: Arc::new(Mutex::new(log)),
+        })
+    }
+
+    /// Queues a delivery of `payload` to a webhook and returns its ID. Non-blocking.
+    pub fn send<T: Serialize>(&self, webhook: &Webhook, event: &str, payload: &T) -> String {
+        let payload = serde_json::to_value(payload).unwrap_or_default();
+        self.start(
+            webhook.clone(),
+            self.new_delivery(&webhook.url, event, payload, None),
+        )
+    }
+
+    /// Lists recorded deliveries, newest first.
+    pub fn deliveries(&self) -> Vec<WebhookDelivery> {
+        let log = self.log.lock().unwrap();
+        log.entries.iter().rev().cloned().collect()
+    }
+
+    /// Returns a recorded delivery by ID.
+    pub fn delivery(&self, id: &str) -> Option<WebhookDelivery> {
+        let log = self.log.lock().unwrap();
+        log.entries.iter().find(|d| d.id == id).cloned()
+    }
+
+    /// Sends the payload of a recorded delivery again as a new delivery.
+    ///
+    /// Secrets and headers are not stored in the log, so the receiver is looked
+    /// up by URL in the currently configured `webhooks`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::NotFound` if the delivery is not in the log, or
+    /// `AppError::BadRequest` if its receiver is no longer configured.
+    pub fn redeliver(&self, id: &str, webhooks: &[Webhook]) -> Result<WebhookDelivery, AppError> {
+        let original = self
+            .delivery(id)
+            .ok_or_else(|| AppError::NotFound(format!("Webhook delivery {} not found", id)))?;
+        let webhook = webhooks
+            .iter()
+            .find(|w| w.url == original.url)
+            .cloned()
+            .ok_or_else(|| {
+                AppError::BadRequest(format!("Webhook {} is no longer configured", original.url))
+            })?;
+
+        let delivery = self.new_delivery(
+            &original.url,
+            &original.event,
+            original.payload,
+            Some(original.id),
+        );
+        info!(delivery = %id, redelivery = %delivery.id, "Redelivering webhook delivery");
+        self.start(webhook, delivery.clone());
+        Ok(delivery)
+    }
+
+    fn new_delivery(
+        &self,
+        url: &str,
+        event: &str,
+        payload: serde_json::Value,
+        redelivery_of: Option<String>,
+    ) -> WebhookDelivery {
+        let now = Utc::now();
+        WebhookDelivery {
+            id: Uuid::new_v4().to_string(),
+            url: url.to_string(),
+            event: event.to_string(),
+            payload,
+            status: DeliveryStatus::Pending,
+            attempts: 0,
+            status_code: None,
+            latency_ms: None,
+            response: None,
+            error: None,
+            created_at: now,
+            updated_at: now,
+            redelivery_of,
+        }
+    }
+
+    fn start(&self, webhook: Webhook, delivery: WebhookDelivery) -> String {
+        let id = delivery.id.clone();
+        self.record(&delivery);
+        let service = self.clone();
+        tokio::spawn(async move { service.deliver(&webhook, delivery).await });
+        id
+    }
+
+    /// Builds the request of a single delivery attempt.
+    ///
+    /// Custom headers are applied first so they cannot override the format
+    /// headers or the signature.
+    fn request(&self, webhook: &Webhook, rendered: &RenderedPayload) -> reqwest::RequestBuilder {
+        let timeout = webhook
+            .timeout_seconds
+            .unwrap_or(self.settings.timeout_seconds);
+        let mut request = self
+            .client
+            .post(&webhook.url)
+            .timeout(Duration::from_secs(timeout));
+
+        for (name, value) in &webhook.headers {
+            request = request.header(name, value);
+        }
+        for (name, value) in &rendered.headers {
+            request = request.header(*name, value);
+        }
+
+        if let Some(ref secret) = webhook.secret {
+            let timestamp = Utc::now().timestamp();
+            request = request
+                .header("X-Gitsafe-Timestamp", timestamp.to_string())
+                .header(
+                    "X-Gitsafe-Signature",
+                    format!("sha256={}", sign_payload(secret, timestamp, &rendered.body)),
+                );
+        }
+
+        request
+            .header("Content-Type", &rendered.content_type)
+            .body(rendered.body.clone())
+    }
+
+    /// Renders a sample event for a webhook and sends it once, without retries.
+    ///
+    /// The delivery is recorded in the delivery log like any other.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::BadRequest` if the template of the webhook cannot be rendered.
+    pub async fn send_test(
+        &self,
+        webhook: &Webhook,
+        event: NotificationEvent,
+    ) -> Result<WebhookTestResult, AppError> {
+        let payload = sample_payload(event);
+        let rendered = render_payload(webhook, event.as_str(), &payload)
+            .map_err(|e| AppError::BadRequest(format!("Invalid webhook template: {}", e)))?;
+
+        let mut delivery = self.new_delivery(&webhook.url, event.as_str(), payload, None);
+        self.record(&delivery);
+        if !self.attempt(webhook, &rendered, &mut delivery).await {
+            delivery.status = DeliveryStatus::Failed;
+        }
+        self.record(&delivery);
+
+        Ok(WebhookTestResult {
+            content_type: rendered.content_type,
+            body: String::from_utf8_lossy(&rendered.body).into_owned(),
+            delivery,
+        })
+    }
+
+    /// Attempts a delivery until it succeeds, is rejected or runs out of attempts.
+    ///
+    /// The log keeps the generic payload; it is rendered into the format of the
+    /// webhook right before sending, so redeliveries use the current format.
+    async fn deliver(&self, webhook: &Webhook, mut delivery: WebhookDelivery) {
+        let rendered = match render_payload(webhook, &delivery.event, &delivery.payload) {
+            Ok(rendered) => rendered,
+            Err(e) => {
+                warn!(url = %delivery.url, error = %e, "Failed to render webhook template");
+                delivery.status = DeliveryStatus::Failed;
+                delivery.error = Some(format!("Template error: {}", e));
+                delivery.updated_at = Utc::now();
+                self.record(&delivery);
+                return;
+            }
+        };
+
+        loop {
+            if self.attempt(webhook, &rendered, &mut delivery).await {
+                self.record(&delivery);
+                return;
+            }
+            // No response at all, a server error, a timeout or rate limiting
+            let retryable = delivery.error.is_some()
+                || delivery
+                    .status_code
+                    .is_some_and(|code| (500..600).contains(&code) || code == 408 || code == 429);
+
+            if !retryable || delivery.attempts >= self.settings.max_attempts {
+                delivery.status = DeliveryStatus::Failed;
+                warn!(
+                    delivery = %delivery.id,
+                    url = %delivery.url,
+                    attempts = delivery.attempts,
+                    status_code = delivery.status_code,
+                    error = delivery.error.as_deref(),
+                    "Webhook delivery failed"
+                );
+                self.record(&delivery);
+                return;
+            }
+
+            self.record(&delivery);
+            tokio::time::sleep(self.backoff(delivery.attempts)).await;
+        }
+    }
+
+    /// Sends a single attempt of a delivery and stores its outcome in `delivery`.
+    ///
+    /// Returns `true` and marks the delivery as delivered if the receiver
+    /// answered with a 2xx status.
+    async fn attempt(
+        &self,
+        webhook: &Webhook,
+        rendered: &RenderedPayload,
+        delivery: &mut WebhookDelivery,
+    ) -> bool {
+        delivery.attempts += 1;
+        let started = Instant::now();
+        let result = self.request(webhook, rendered).send().await;
+        delivery.latency_ms = Some(started.elapsed().as_millis() as u64);
+        delivery.updated_at = Utc::now();
+
+        match result {
+            Ok(response) => {
+                let status = response.status();
+                let body = response.text().await.unwrap_or_default();
+                delivery.status_code = Some(status.as_u16());
+                delivery.response = Some(body.chars().take(RESPONSE_SNIPPET_LENGTH).collect());
+                delivery.error = None;
+
+                if status.is_success() {
+                    delivery.status = DeliveryStatus::Delivered;
+                }
+                status.is_success()
+            }
+            Err(e) => {
+                delivery.status_code = None;
+                delivery.response = None;
+                delivery.error = Some(e.to_string());
+                false
+            }
+        }
+    }
+
+    /// Delay before the retry following attempt number `attempt`.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self
+            .settings
+            .initial_backoff_seconds
+            .saturating_mul(1u64 << (attempt - 1).min(32));
+        let delay_ms = exponential.min(self.settings.max_backoff_seconds) * 1000;
+        // Jitter: wait between half and all of the delay
+        Duration::from_millis(delay_ms / 2 + fastrand::u64(0..=delay_ms / 2))
+    }
+
+    /// Stores the current state of a delivery in memory and in the log file.
+    fn record(&self, delivery: &WebhookDelivery) {
+        match delivery.status {
+            DeliveryStatus::Delivered => {
+                metrics::record_webhook_delivery(&delivery.event, "delivered")
+            }
+            DeliveryStatus::Failed => metrics::record_webhook_delivery(&delivery.event, "failed"),
+            DeliveryStatus::Pending => {}
+        }
+        let mut log = self.log.lock().unwrap();
+
+        match log.entries.iter_mut().find(|d| d.id == delivery.id) {
+            Some(entry) => *entry = delivery.clone(),
+            None => {
+                log.entries.push_back(delivery.clone());
+                if log.entries.len() > self.settings.log_retention {
+                    log.entries.pop_front();
+                }
+            }
+        }
+
+        let result = if log.records >= self.settings.log_retention.max(1) * 2 {
+            log.compact()
+        } else {
+            log.append(delivery)
+        };
+        if let Err(e) = result {
+            error!(error = %e, "Failed to write webhook delivery log");
+        }
+    }
+}
+
+impl DeliveryLog {
+    fn append(&mut self, delivery: &WebhookDelivery) -> std::io::Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(delivery)?)?;
+        self.records += 1;
+        Ok(())
+    }
+
+    /// Rewrites the file with only the retained deliveries.
+    fn compact(&mut self) -> std::io::Result<()> {
+        let temp_path = self.path.with_extension("jsonl.tmp");
+        let mut file = File::create(&temp_path)?;
+        for delivery in &self.entries {
+            writeln!(file, "{}", serde_json::to_string(delivery)?)?;
+        }
+        fs::rename(&temp_path, &self.path)?;
+        self.records = self.entries.len();
+        Ok(())
+    }
+}
+
+/// Request body and headers of a delivery in the format of its webhook.
+struct RenderedPayload {
+    body: Vec<u8>,
+    content_type: String,
+    headers: Vec<(&'static str, String)>,
+}
+
+/// Outcome of a test delivery sent by `WebhookService::send_test`.
+#[derive(Debug, Serialize, Clone)]
+pub struct WebhookTestResult {
+    /// Content type of the request
+    pub content_type: String,
+    /// Request body as sent to the receiver
+    pub body: String,
+    pub delivery: WebhookDelivery,
+}
+
+/// How urgently a notification needs attention.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+/// Human readable summary of an event, the common ground of all chat and push formats.
+struct Notification {
+    title: String,
+    message: String,
+    /// Label and value pairs shown as a list or table
+    fields: Vec<(String, String)>,
+    time: Option<String>,
+    severity: Severity,
+}
+
+impl Notification {
+    /// Summarizes an event from its generic payload.
+    ///
+    /// Unknown events are summarized from the common `repo`, `message` and
+    /// `error_message` fields, so new events render without changes here.
+    fn from_event(event: &str, payload: &serde_json::Value) -> Self {
+        let text = |key: &str| payload.get(key).and_then(|v| v.as_str());
+        let repo_id = payload["repo"]["id"].as_str().unwrap_or("unknown");
+
+        let (title, severity) = match event {
+            "error" => (
+                format!(
+                    "GitSafe: {} failed for {}",
+                    text("operation").unwrap_or("operation"),
+                    repo_id
+                ),
+                Severity::Warning,
+            ),
+            "out_of_attempts" => (
+                format!("GitSafe: {} was disabled", repo_id),
+                Severity::Critical,
+            ),
+            "sync_succeeded" => (format!("GitSafe: {} synced", repo_id), Severity::Info),
+            "sync_skipped" => (
+                format!("GitSafe: {} already up-to-date", repo_id),
+                Severity::Info,
+            ),
+            "recovered" => (format!("GitSafe: {} recovered", repo_id), Severity::Info),
+            "repository_added" => (format!("GitSafe: {} added", repo_id), Severity::Info),
+            "repository_deleted" => (format!("GitSafe: {} deleted", repo_id), Severity::Info),
+            "credential_changed" => (
+                format!(
+                    "GitSafe: credential {} {}",
+                    payload["details"]["credential_id"]
+                        .as_str()
+                        .unwrap_or("unknown"),
+                    payload["details"]["action"].as_str().unwrap_or("changed")
+                ),
+                Severity::Info,
+            ),
+            "verification_failed" => (
+                format!("GitSafe: backup verification failed for {}", repo_id),
+                Severity::Critical,
+            ),
+            "storage_low" => ("GitSafe: storage low".to_string(), Severity::Critical),
+            "backup_stale" => (
+                format!("GitSafe: backup of {} is stale", repo_id),
+                Severity::Critical,
+            ),
+            _ => (
+                format!("GitSafe: {} for {}", event, repo_id),
+                Severity::Warning,
+            ),
+        };
+
+        let mut message: String = text("error_message")
+            .or(text("message"))
+            .unwrap_or_default()
+            .chars()
+            .take(MESSAGE_LENGTH)
+            .collect();
+        if event == "out_of_attempts" {
+            message = format!(
+                "Ran out of sync attempts and was disabled. Last error: {}",
+                message
+            );
+        }
+
+        let mut fields = Vec::new();
+        if let Some(url) = payload["repo"]["url"].as_str() {
+            fields.push(("Repository".to_string(), url.to_string()));
+        }
+        if let Some(operation) = text("operation") {
+            fields.push(("Operation".to_string(), operation.to_string()));
+        }
+        if let Some(credential_id) = text("credential_id") {
+            fields.push(("Credential".to_string(), credential_id.to_string()));
+        }
+        if let Some(attempts) = payload.get("sync_attempts").and_then(|v| v.as_u64()) {
+            fields.push(("Attempts".to_string(), attempts.to_string()));
+        }
+        if let Some(details) = payload.get("details").and_then(|v| v.as_object()) {
+            for (key, value) in details {
+                let value = match value {
+                    serde_json::Value::String(value) => value.clone(),
+                    value => value.to_string(),
+                };
+                fields.push((key.replace('_', " "), value));
+            }
+        }
+
+        Notification {
+            title,
+            message,
+            fields,
+            time: text("time").map(str::to_string),
+            severity,
+        }
+    }
+
+    /// Message body followed by the fields, one per line, for plain text formats.
+    fn plain_text(&self) -> String {
+        let mut text = self.message.clone();
+        for (label, value) in &self.fields {
+            text.push_str(&format!("\n{}: {}", label, value));
+        }
+        text
+    }
+}
+
+/// Builds the context of webhook templates.
+///
+/// The fields of the generic payload are available at the top level (`repo`,
+/// `operation`, `credential_id`, `error_message`, `sync_attempts`, `details`,
+/// `time`), together with `event`, the `title`, `message` and `severity` of the
+/// chat formats, `timestamp` (Unix seconds) and the whole generic `payload`.
+fn template_context(
+    event: &str,
+    payload: &serde_json::Value,
+    notification: &Notification,
+) -> serde_json::Value {
+    let mut context = payload.as_object().cloned().unwrap_or_default();
+    let timestamp = notification
+        .time
+        .as_deref()
+        .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+        .map(|time| time.timestamp());
+
+    context.insert("event".to_string(), event.into());
+    context.insert("title".to_string(), notification.title.clone().into());
+    context.insert("message".to_string(), notification.message.clone().into());
+    context.insert(
+        "severity".to_string(),
+        notification.severity.as_str().into(),
+    );
+    context.insert("timestamp".to_string(), timestamp.into());
+    context.insert("payload".to_string(), payload.clone());
+    serde_json::Value::Object(context)
+}
+
+/// Renders a webhook template against the context of an event.
+///
+/// Undefined values render as empty and their attributes are undefined too, so
+/// one template can serve events with and without a repository.
+fn render_template(
+    template: &str,
+    context: &serde_json::Value,
+) -> Result<String, minijinja::Error> {
+    let mut environment = minijinja::Environment::new();
+    environment.set_undefined_behavior(minijinja::UndefinedBehavior::Chainable);
+    environment.render_str(template, context)
+}
+
+/// Renders a generic event payload into the message schema of a webhook.
+///
+/// # Errors
+///
+/// Returns the minijinja error if the webhook has a template that fails to render.
+fn render_payload(
+    webhook: &Webhook,
+    event: &str,
+    payload: &serde_json::Value,
+) -> Result<RenderedPayload, minijinja::Error> {
+    let json = |value: serde_json::Value| RenderedPayload {
+        body: serde_json::to_vec(&value).unwrap_or_default(),
+        content_type: "application/json".to_string(),
+        headers: Vec::new(),
+    };
+    let notification = Notification::from_event(event, payload);
+
+    if let Some(ref template) = webhook.template {
+        let context = template_context(event, payload, &notification);
+        return Ok(RenderedPayload {
+            body: render_template(template, &context)?.into_bytes(),
+            content_type: webhook
+                .content_type
+                .clone()
+                .unwrap_or_else(|| "application/json".to_string()),
+            headers: Vec::new(),
+        });
+    }
+
+    Ok(match webhook.format {
+        WebhookFormat::Generic => json(payload.clone()),
+        WebhookFormat::Slack => {
+            let fields: Vec<_> = notification
+                .fields
+                .iter()
+                .map(|(label, value)| {
+                    serde_json::json!({"type": "mrkdwn", "text": format!("*{}*\n{}", label, value)})
+                })
+                .collect();
+            let mut blocks = vec![
+                serde_json::json!({
+                    "type": "header",
+                    "text": {"type": "plain_text", "text": truncate(&notification.title, 150)},
+                }),
+                serde_json::json!({
+                    "type": "section",
+                    "text": {"type": "mrkdwn", "text": format!("```{}```", notification.message)},
+                }),
+            ];
+            if !fields.is_empty() {
+                blocks.push(serde_json::json!({"type": "section", "fields": fields}));
+            }
+            if let Some(ref time) = notification.time {
+                blocks.push(serde_json::json!({
+                    "type": "context",
+                    "elements": [{"type": "mrkdwn", "text": time}],
+                }));
+            }
+            // `text` is the fallback shown in notifications
+            json(serde_json::json!({"text": notification.title, "blocks": blocks}))
+        }
+        WebhookFormat::Discord => {
+            let fields: Vec<_> = notification
+                .fields
+                .iter()
+                .map(|(label, value)| {
+                    serde_json::json!({"name": label, "value": truncate(value, 1024), "inline": true})
+                })
+                .collect();
+            let mut embed = serde_json::json!({
+                "title": truncate(&notification.title, 256),
+                "description": notification.message,
+                "color": match notification.severity {
+                    Severity::Info => 0x2EB67D,
+                    Severity::Warning => 0xF2A33A,
+                    Severity::Critical => 0xE01E5A,
+                },
+                "fields": fields,
+                "footer": {"text": "GitSafe"},
+            });
+            if let Some(time) = notification.time {
+                embed["timestamp"] = time.into();
+            }
+            json(serde_json::json!({"username": "GitSafe", "embeds": [embed]}))
+        }
+        WebhookFormat::Teams => {
+            let facts: Vec<_> = notification
+                .fields
+                .iter()
+                .map(|(label, value)| serde_json::json!({"title": label, "value": value}))
+                .collect();
+            let card = serde_json::json!({
+                "$schema": "http://adaptivecards.io/schemas/adaptive-card.json",
+                "type": "AdaptiveCard",
+                "version": "1.4",
+                "body": [
+                    {
+                        "type": "TextBlock",
+                        "text": notification.title,
+                        "weight": "Bolder",
+                        "size": "Medium",
+                        "color": match notification.severity {
+                            Severity::Info => "Good",
+                            Severity::Warning => "Warning",
+                            Severity::Critical => "Attention",
+                        },
+                        "wrap": true,
+                    },
+                    {"type": "TextBlock", "text": notification.message, "wrap": true},
+                    {"type": "FactSet", "facts": facts},
+                ],
+            });
+            json(serde_json::json!({
+                "type": "message",
+                "attachments": [{
+                    "contentType": "application/vnd.microsoft.card.adaptive",
+                    "content": card,
+                }],
+            }))
+        }
+        WebhookFormat::Ntfy => {
+            let (default_priority, tags) = match notification.severity {
+                Severity::Info => (3, "white_check_mark"),
+                Severity::Warning => (4, "warning"),
+                Severity::Critical => (5, "rotating_light"),
+            };
+            RenderedPayload {
+                body: notification.plain_text().into_bytes(),
+                content_type: "text/plain; charset=utf-8".to_string(),
+                headers: vec![
+                    ("Title", notification.title),
+                    (
+                        "Priority",
+                        webhook.priority.unwrap_or(default_priority).to_string(),
+                    ),
+                    ("Tags", tags.to_string()),
+                ],
+            }
+        }
+        WebhookFormat::Gotify => {
+            let default_priority = match notification.severity {
+                Severity::Info => 2,
+                Severity::Warning => 5,
+                Severity::Critical => 8,
+            };
+            json(serde_json::json!({
+                "title": notification.title,
+                "message": notification.plain_text(),
+                "priority": webhook.priority.unwrap_or(default_priority),
+            }))
+        }
+    })
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    text.chars().take(max_chars).collect()
+}
+
+/// Computes the hex encoded `X-Gitsafe-Signature` of a webhook request.
+///
+/// The signature is the HMAC-SHA256 of `"{timestamp}.{body}"` keyed with the
+/// webhook secret, where `timestamp` is the value of the `X-Gitsafe-Timestamp`
+/// header (Unix seconds). Receivers should recompute it over the raw request body,
+/// compare in constant time and reject old timestamps to prevent replays.
+pub fn sign_payload(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Builds an example payload of an event, as sent by `WebhookService::send_test`.
+pub fn sample_payload(event: NotificationEvent) -> serde_json::Value {
+    let time = Utc::now().to_rfc3339();
+    let repo = RepoInfo {
+        id: "github_com-example-repository".to_string(),
+        url: "https://github.com/example/repository.git".to_string(),
+        enabled: true,
+    };
+    let error_message = "Failed to fetch: authentication required".to_string();
+    let sample = |repo: Option<RepoInfo>, message: &str, details: serde_json::Value| {
+        serde_json::to_value(EventWebhookPayload {
+            time: time.clone(),
+            event,
+            repo,
+            message: message.to_string(),
+            details,
+        })
+    };
+
+    let payload = match event {
+        NotificationEvent::Error => serde_json::to_value(ErrorWebhookPayload {
+            time: time.clone(),
+            repo,
+            operation: "sync".to_string(),
+            credential_id: Some("github".to_string()),
+            error_message,
+        }),
+        NotificationEvent::OutOfAttempts => serde_json::to_value(OutOfAttemptsWebhookPayload {
+            time: time.clone(),
+            repo: RepoInfo {
+                enabled: false,
+                ..repo
+            },
+            credential_id: Some("github".to_string()),
+            error_message,
+            sync_attempts: 3,
+        }),
+        NotificationEvent::SyncSucceeded | NotificationEvent::SyncSkipped => sample(
+            Some(repo),
+            "Synced new commits: Update README",
+            serde_json::json!({
+                "trigger": "schedule",
+                "commit_hash": "4b825dc642cb6eb9a060e54bf8d69288fbee4904",
+                "size": 1048576,
+            }),
+        ),
+        NotificationEvent::Recovered => sample(
+            Some(repo),
+            "Sync succeeded after previous failures",
+            serde_json::json!({ "previous_error": error_message }),
+        ),
+        NotificationEvent::RepositoryAdded => {
+            sample(Some(repo), "Repository added", serde_json::Value::Null)
+        }
+        NotificationEvent::RepositoryDeleted => sample(
+            Some(repo),
+            "Repository deleted; existing backups are kept",
+            serde_json::Value::Null,
+        ),
+        NotificationEvent::CredentialChanged => sample(
+            None,
+            "Credential github was updated",
+            serde_json::json!({ "credential_id": "github", "action": "updated" }),
+        ),
+        NotificationEvent::VerificationFailed => sample(
+            Some(repo),
+            "Backup verification failed: object not found",
+            serde_json::json!({
+                "path": "github_com-example-repository.tar.gz",
+                "commit_hash": "4b825dc642cb6eb9a060e54bf8d69288fbee4904",
+            }),
+        ),
+        NotificationEvent::StorageLow => sample(
+            None,
+            "Only 512 MB free on the archive volume, below the minimum of 1024 MB",
+            serde_json::json!({ "available_mb": 512, "min_free_space_mb": 1024 }),
+        ),
+        NotificationEvent::BackupStale => sample(
+            Some(repo),
+            "No successful sync since 2024-01-01 12:00 UTC, the maximum backup age is 48 hours",
+            serde_json::json!({
+                "last_sync": "2024-01-01T12:00:00Z",
+                "max_backup_age_hours": 48,
+            }),
+        ),
+        NotificationEvent::LoginLockedOut => sample(
+            None,
+            "Login locked for user alice until 2024-01-01 12:15 UTC after 5 failed attempts",
+            serde_json::json!({
+                "username": "alice",
+                "failures": 5,
+                "locked_until": "2024-01-01T12:15:00+00:00",
+            }),
+        ),
+    };
+    payload.unwrap_or_default()
+}
+
+/// Sends an event to every subscription matching it. Non-blocking.
+///
+/// The deliveries run in the background with retries and are recorded in the
+/// delivery log. Failures do not affect the caller.
+///
+/// # Arguments
+///
+/// * `webhook_service` - Service performing and recording the deliveries
+/// * `subscriptions` - Configured subscriptions (see `ServerConfig::subscriptions`)
+/// * `event` - The event to send
+/// * `repo` - The repository the event is about, used for repository filters
+/// * `payload` - The JSON payload
+pub fn notify<T: Serialize>(
+    webhook_service: &WebhookService,
+    subscriptions: &[Subscription],
+    event: NotificationEvent,
+    repo: Option<&Repository>,
+    payload: &T,
+) {
+    for subscription in subscriptions.iter().filter(|s| s.matches(event, repo)) {
+        webhook_service.send(&subscription.webhook, event.as_str(), payload);
+    }
+}
+
+/// Sends an event with an `EventWebhookPayload` to matching subscriptions. Non-blocking.
+///
+/// # Arguments
+///
+/// * `webhook_service` - Service performing and recording the deliveries
+/// * `subscriptions` - Configured subscriptions
+/// * `event` - The event to send
+/// * `repo` - The repository the event is about, if any
+/// * `message` - Human readable description of the event
+/// * `details` - Event specific details, `Value::Null` if there are none
+pub fn notify_event(
+    webhook_service: &WebhookService,
+    subscriptions: &[Subscription],
+    event: NotificationEvent,
+    repo: Option<&Repository>,
+    message: &str,
+    details: serde_json::Value,
+) {
+    let payload = EventWebhookPayload {
+        time: Utc::now().to_rfc3339(),
+        event,
+        repo: repo.map(RepoInfo::from),
+        message: message.to_string(),
+        details,
+    };
+    notify(webhook_service, subscriptions, event, repo, &payload);
+}
+
+/// Sends error notifications to subscribed webhooks.
+///
+/// The deliveries run in the background with retries and are recorded in the
+/// delivery log. Failures do not affect the main error handling flow.
 ///
 /// # Arguments
 ///
-/// * `webhook_urls` - List of webhook URLs to notify
+/// * `webhook_service` - Service performing and recording the deliveries
+/// * `subscriptions` - Configured subscriptions
 /// * `repo` - The repository that encountered the error
 /// * `operation` - The operation that failed (e.g., "sync", "clone", "pull")
 /// * `credential_id` - Optional credential ID used for the operation
 /// * `error_message` - The error message
-pub async fn notify_error_webhooks(
-    webhook_urls: &[String],
+pub fn notify_error_webhooks(
+    webhook_service: &WebhookService,
+    subscriptions: &[Subscription],
     repo: &Repository,
     operation: &str,
     credential_id: Option<&String>,
     error_message: &str,
 ) {
-    if webhook_urls.is_empty() {
-        return;
-    }
-
     let payload = ErrorWebhookPayload {
         time: Utc::now().to_rfc3339(),
-        repo: RepoInfo {
-            id: repo.id.clone(),
-            url: repo.url.clone(),
-            enabled: repo.enabled,
-        },
+        repo: RepoInfo::from(repo),
         operation: operation.to_string(),
         credential_id: credential_id.cloned(),
         error_message: error_message.to_string(),
     };
-
-    for webhook_url in webhook_urls {
-        let url = webhook_url.clone();
-        let payload = payload.clone();
-
-        tokio::spawn(async move {
-            let client = reqwest::Client::new();
-            match client
-                .post(&url)
-                .json(&payload)
-                .timeout(std::time::Duration::from_secs(10))
-                .send()
-                .await
-            {
-                Ok(response) => {
-                    if !response.status().is_success() {
-                        warn!(
-                            "Webhook {} returned status {}: {}",
-                            url,
-                            response.status(),
-                            response.text().await.unwrap_or_default()
-                        );
-                    }
-                }
-                Err(e) => {
-                    warn!("Failed to send webhook to {}: {}", url, e);
-                }
-            }
-        });
-    }
+    notify(
+        webhook_service,
+        subscriptions,
+        NotificationEvent::Error,
+        Some(repo),
+        &payload,
+    );
 }
 
-/// Sends "out of attempts" notifications to configured webhooks.
+/// Sends "out of attempts" notifications to subscribed webhooks.
 ///
-/// This function sends HTTP POST requests to all configured webhook URLs
+/// This function queues HTTP POST requests to all subscribed webhooks
 /// when a repository has exhausted all sync attempts and been disabled.
 ///
 /// # Arguments
 ///
-/// * `webhook_urls` - List of webhook URLs to notify
+/// * `webhook_service` - Service performing and recording the deliveries
+/// * `subscriptions` - Configured subscriptions
 /// * `repo` - The repository that ran out of attempts
 /// * `credential_id` - Optional credential ID used for the operation
 /// * `error_message` - The last error message before running out of attempts
 /// * `sync_attempts` - The number of attempts that were configured
-pub async fn notify_out_of_attempts_webhooks(
-    webhook_urls: &[String],
+pub fn notify_out_of_attempts_webhooks(
+    webhook_service: &WebhookService,
+    subscriptions: &[Subscription],
     repo: &Repository,
     credential_id: Option<&String>,
     error_message: &str,
     sync_attempts: u32,
 ) {
-    if webhook_urls.is_empty() {
-        return;
-    }
-
     let payload = OutOfAttemptsWebhookPayload {
         time: Utc::now().to_rfc3339(),
-        repo: RepoInfo {
-            id: repo.id.clone(),
-            url: repo.url.clone(),
-            enabled: repo.enabled,
-        },
+        repo: RepoInfo::from(repo),
         credential_id: credential_id.cloned(),
         error_message: error_message.to_string(),
         sync_attempts,
     };
-
-    for webhook_url in webhook_urls {
-        let url = webhook_url.clone();
-        let payload = payload.clone();
-
-        tokio::spawn(async move {
-            let client = reqwest::Client::new();
-            match client
-                .post(&url)
-                .json(&payload)
-                .timeout(std::time::Duration::from_secs(10))
-                .send()
-                .await
-            {
-                Ok(response) => {
-                    if !response.status().is_success() {
-                        warn!(
-                            "Webhook {} returned status {}: {}",
-                            url,
-                            response.status(),
-                            response.text().await.unwrap_or_default()
-                        );
-                    }
-                }
-                Err(e) => {
-                    warn!("Failed to send webhook to {}: {}", url, e);
-                }
-            }
-        });
-    }
+    notify(
+        webhook_service,
+        subscriptions,
+        NotificationEvent::OutOfAttempts,
+        Some(repo),
+        &payload,
+    );
 }
@@ -1,16 +1,27 @@
 use actix_web::{test, web, App};
+use gitsafe::audit::AuditLog;
 use gitsafe::auth::AuthService;
-use gitsafe::config::{Config, User};
+use gitsafe::config::{Config, Role, User, WebhookDeliveryConfig};
 use gitsafe::config_persistence::ConfigPersistence;
 use gitsafe::git::GitService;
 use gitsafe::handlers::{health_check, login, AppState, LoginRequest};
+use gitsafe::rate_limit::RateLimiter;
+use gitsafe::sync::SyncQueue;
+use gitsafe::watchdog::Watchdog;
+use gitsafe::webhooks::WebhookService;
 use std::sync::Arc;
+use std::time::Duration;
 use tempfile::TempDir;
 use tokio::sync::RwLock;
 
 #[actix_web::test]
 async fn test_health_check() {
-    let app = test::init_service(App::new().route("/health", web::get().to(health_check))).await;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(Watchdog::new()))
+            .route("/health", web::get().to(health_check)),
+    )
+    .await;
 
     let req = test::TestRequest::get().uri("/health").to_request();
     let resp = test::call_service(&app, req).await;
@@ -30,17 +41,38 @@ async fn test_login_success() {
     config.users.push(User {
         username: "testuser".to_string(),
         password_hash,
+        role: Role::Admin,
+        must_change_password: false,
+        totp: None,
     });
 
     let git_service = GitService::new(temp_dir.path(), true).unwrap();
     let config_persistence = ConfigPersistence::new(config_path.to_string_lossy().to_string());
 
+    let config = Arc::new(RwLock::new(config));
+    let webhook_service = WebhookService::new(
+        WebhookDeliveryConfig::default(),
+        temp_dir.path().join("data"),
+    )
+    .unwrap();
+    let sync_queue = SyncQueue::new(
+        Arc::clone(&config),
+        git_service.clone(),
+        config_persistence.clone(),
+        webhook_service.clone(),
+        Duration::ZERO,
+    );
+
     let app_state = web::Data::new(AppState {
-        config: Arc::new(RwLock::new(config)),
+        config,
         config_path: config_path.to_string_lossy().to_string(),
         auth_service,
         git_service,
         config_persistence,
+        sync_queue,
+        webhook_service,
+        rate_limiter: RateLimiter::new(),
+        audit_log: AuditLog::in_memory(),
     });
 
     let app = test::init_service(
@@ -53,6 +85,7 @@ async fn test_login_success() {
     let login_req = LoginRequest {
         username: "testuser".to_string(),
         password: "testpass".to_string(),
+        totp_code: None,
     };
 
     let req = test::TestRequest::post()
@@ -76,17 +109,38 @@ async fn test_login_failure() {
     config.users.push(User {
         username: "testuser".to_string(),
         password_hash,
+        role: Role::Admin,
+        must_change_password: false,
+        totp: None,
     });
 
     let git_service = GitService::new(temp_dir.path(), true).unwrap();
     let config_persistence = ConfigPersistence::new(config_path.to_string_lossy().to_string());
 
+    let config = Arc::new(RwLock::new(config));
+    let webhook_service = WebhookService::new(
+        WebhookDeliveryConfig::default(),
+        temp_dir.path().join("data"),
+    )
+    .unwrap();
+    let sync_queue = SyncQueue::new(
+        Arc::clone(&config),
+        git_service.clone(),
+        config_persistence.clone(),
+        webhook_service.clone(),
+        Duration::ZERO,
+    );
+
     let app_state = web::Data::new(AppState {
-        config: Arc::new(RwLock::new(config)),
+        config,
         config_path: config_path.to_string_lossy().to_string(),
         auth_service,
         git_service,
         config_persistence,
+        sync_queue,
+        webhook_service,
+        rate_limiter: RateLimiter::new(),
+        audit_log: AuditLog::in_memory(),
     });
 
     let app = test::init_service(
@@ -99,6 +153,7 @@ async fn test_login_failure() {
     let login_req = LoginRequest {
         username: "testuser".to_string(),
         password: "wrongpass".to_string(),
+        totp_code: None,
     };
 
     let req = test::TestRequest::post()
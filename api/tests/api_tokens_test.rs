@@ -0,0 +1,189 @@
+use actix_web::http::{Method, StatusCode};
+use actix_web::{test, web, App};
+use gitsafe::auth::{self};
+use gitsafe::config::{Config, Role, TokenScope};
+use gitsafe::handlers::{
+    create_api_token, delete_api_token, list_api_tokens, list_repositories, sync_repository,
+};
+use gitsafe::middleware::AuthMiddleware;
+use tempfile::TempDir;
+
+#[macro_use]
+mod common;
+
+use common::{api_token, app_state};
+
+macro_rules! init_app {
+    ($state:expr) => {
+        test::init_service(
+            App::new().app_data($state.clone()).service(
+                web::scope("/api")
+                    .wrap(AuthMiddleware)
+                    .route("/repositories", web::get().to(list_repositories))
+                    .route("/sync", web::post().to(sync_repository))
+                    .route("/tokens", web::get().to(list_api_tokens))
+                    .route("/tokens", web::post().to(create_api_token))
+                    .route("/tokens/{id}", web::delete().to(delete_api_token)),
+            ),
+        )
+        .await
+    };
+}
+
+#[actix_web::test]
+async fn test_api_token_scopes_are_enforced() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = Config::default();
+    config
+        .api_tokens
+        .push(api_token("gs_read", vec![TokenScope::Read]));
+    let mut sync_token = api_token("gs_sync", vec![TokenScope::Sync]);
+    sync_token.id = "token-2".to_string();
+    config.api_tokens.push(sync_token);
+    let mut write_token = api_token("gs_write", vec![TokenScope::Write]);
+    write_token.id = "token-3".to_string();
+    config.api_tokens.push(write_token);
+    let state = app_state(config, temp_dir.path());
+    let app = init_app!(state);
+
+    assert_eq!(
+        status!(app, Method::GET, "/api/repositories", "gs_read"),
+        StatusCode::OK
+    );
+    assert_eq!(
+        status!(app, Method::POST, "/api/sync", "gs_read"),
+        StatusCode::FORBIDDEN
+    );
+    // Passes authentication, fails for the unknown repository
+    assert_eq!(
+        status!(app, Method::POST, "/api/sync", "gs_sync"),
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        status!(app, Method::GET, "/api/repositories", "gs_unknown"),
+        StatusCode::UNAUTHORIZED
+    );
+
+    // Not even write tokens can create or revoke tokens, e.g. one without expiry
+    assert_eq!(
+        status!(app, Method::POST, "/api/tokens", "gs_write"),
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        status!(app, Method::DELETE, "/api/tokens/token-1", "gs_write"),
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        status!(app, Method::GET, "/api/tokens", "gs_write"),
+        StatusCode::OK
+    );
+    assert_eq!(state.config.read().await.api_tokens.len(), 3);
+
+    let config = state.config.read().await;
+    assert!(config.api_tokens[0].last_used.is_some());
+}
+
+#[actix_web::test]
+async fn test_api_token_lifecycle() {
+    let temp_dir = TempDir::new().unwrap();
+    let state = app_state(Config::default(), temp_dir.path());
+    let jwt = state
+        .auth_service
+        .generate_token("admin", Role::Admin)
+        .unwrap();
+    let app = init_app!(state);
+
+    let req = test::TestRequest::post()
+        .uri("/api/tokens")
+        .insert_header(("Authorization", format!("Bearer {}", jwt)))
+        .set_json(serde_json::json!({
+            "name": "nightly export",
+            "scopes": ["read"],
+            "expires_in_days": 30
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let created: serde_json::Value = test::read_body_json(resp).await;
+    let token = created["token"].as_str().unwrap().to_string();
+    let id = created["id"].as_str().unwrap().to_string();
+    assert!(token.starts_with(auth::API_TOKEN_PREFIX));
+    assert!(created["expires_at"].is_string());
+    {
+        // Only the hash is stored
+        let config = state.config.read().await;
+        assert_eq!(config.api_tokens.len(), 1);
+        assert_eq!(
+            config.api_tokens[0].token_hash,
+            auth::hash_api_token(&token)
+        );
+        assert_eq!(config.api_tokens[0].username, "admin");
+    }
+
+    // The token authenticates as its user
+    let req = test::TestRequest::get()
+        .uri("/api/tokens")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request();
+    let listed: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0]["name"], "nightly export");
+    assert!(listed[0].get("token").is_none());
+    assert!(listed[0].get("token_hash").is_none());
+
+    // Other users don't see or revoke it
+    let other_jwt = state
+        .auth_service
+        .generate_token("other", Role::Admin)
+        .unwrap();
+    let req = test::TestRequest::get()
+        .uri("/api/tokens")
+        .insert_header(("Authorization", format!("Bearer {}", other_jwt)))
+        .to_request();
+    let listed: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+    assert!(listed.is_empty());
+    assert_eq!(
+        status!(
+            app,
+            Method::DELETE,
+            &format!("/api/tokens/{}", id),
+            &other_jwt
+        ),
+        StatusCode::NOT_FOUND
+    );
+
+    // Revoked tokens are rejected
+    assert_eq!(
+        status!(app, Method::DELETE, &format!("/api/tokens/{}", id), &jwt),
+        StatusCode::NO_CONTENT
+    );
+    assert_eq!(
+        status!(app, Method::GET, "/api/tokens", &token),
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[actix_web::test]
+async fn test_create_api_token_validation() {
+    let temp_dir = TempDir::new().unwrap();
+    let state = app_state(Config::default(), temp_dir.path());
+    let jwt = state
+        .auth_service
+        .generate_token("admin", Role::Admin)
+        .unwrap();
+    let app = init_app!(state);
+
+    for body in [
+        serde_json::json!({ "name": " ", "scopes": ["read"] }),
+        serde_json::json!({ "name": "ci", "scopes": [] }),
+        serde_json::json!({ "name": "ci", "scopes": ["read"], "expires_in_days": 0 }),
+    ] {
+        let req = test::TestRequest::post()
+            .uri("/api/tokens")
+            .insert_header(("Authorization", format!("Bearer {}", jwt)))
+            .set_json(body)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+}
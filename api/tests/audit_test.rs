@@ -0,0 +1,248 @@
+use actix_web::http::{Method, StatusCode};
+use actix_web::{test, web, App};
+use gitsafe::audit::{self, AuditEntry, AuditLog, REDACTED};
+use gitsafe::config::{Config, Credential, Role, Webhook};
+use gitsafe::handlers::{
+    add_credential, add_repository, delete_repository, list_audit_log, test_webhook,
+    update_repository,
+};
+use gitsafe::middleware::AuthMiddleware;
+use tempfile::TempDir;
+
+#[macro_use]
+mod common;
+
+use common::app_state;
+
+#[actix_web::test]
+async fn test_diff_redacts_secrets() {
+    let before = Credential::try_new(
+        "github".to_string(),
+        "bot".to_string(),
+        Some("encrypted-old".to_string()),
+        None,
+    )
+    .unwrap();
+    let after = Credential {
+        password: Some("encrypted-new".to_string()),
+        ..before.clone()
+    };
+
+    let changes = audit::diff(Some(&before), Some(&after));
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes["password"].before, REDACTED);
+    assert_eq!(changes["password"].after, REDACTED);
+
+    // Created and deleted objects list all of their fields
+    let changes = audit::diff(None, Some(&before));
+    assert_eq!(changes["username"].before, serde_json::Value::Null);
+    assert_eq!(changes["username"].after, "bot");
+    assert_eq!(changes["password"].after, REDACTED);
+    assert!(!serde_json::to_string(&changes)
+        .unwrap()
+        .contains("encrypted-old"));
+}
+
+#[actix_web::test]
+async fn test_audit_log_is_appended_to_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let log = AuditLog::load(temp_dir.path()).unwrap();
+    log.record(AuditEntry::new(
+        "alice",
+        "repository.delete",
+        Some("repo"),
+        None,
+    ));
+    log.record(AuditEntry::new("bob", "user.add", Some("carol"), None));
+
+    let reloaded = AuditLog::load(temp_dir.path()).unwrap();
+    let entries = reloaded.entries();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].actor, "bob");
+    assert_eq!(entries[1].action, "repository.delete");
+    assert_eq!(
+        std::fs::read_to_string(temp_dir.path().join("audit.jsonl"))
+            .unwrap()
+            .lines()
+            .count(),
+        2
+    );
+}
+
+#[actix_web::test]
+async fn test_changes_are_recorded_and_queryable() {
+    let temp_dir = TempDir::new().unwrap();
+    let state = app_state(Config::default(), temp_dir.path());
+    let app = test::init_service(
+        App::new().app_data(state.clone()).service(
+            web::scope("/api")
+                .wrap(AuthMiddleware)
+                .route("/repositories", web::post().to(add_repository))
+                .route("/repositories/{id}", web::patch().to(update_repository))
+                .route("/repositories/{id}", web::delete().to(delete_repository))
+                .route("/credentials", web::post().to(add_credential))
+                .route("/audit", web::get().to(list_audit_log)),
+        ),
+    )
+    .await;
+    let admin = state
+        .auth_service
+        .generate_token("alice", Role::Admin)
+        .unwrap();
+    let viewer = state
+        .auth_service
+        .generate_token("bob", Role::Viewer)
+        .unwrap();
+
+    let (status, _) = send!(
+        app,
+        Method::POST,
+        "/api/credentials",
+        admin,
+        serde_json::json!({ "id": "github", "username": "bot", "password": "s3cret" })
+    );
+    assert_eq!(status, StatusCode::CREATED);
+    let (status, _) = send!(
+        app,
+        Method::POST,
+        "/api/repositories",
+        admin,
+        serde_json::json!({ "id": "repo", "url": "https://github.com/acme/repo.git" })
+    );
+    assert_eq!(status, StatusCode::CREATED);
+    let (status, _) = send!(
+        app,
+        Method::PATCH,
+        "/api/repositories/repo",
+        admin,
+        serde_json::json!({ "enabled": false })
+    );
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send!(
+        app,
+        Method::DELETE,
+        "/api/repositories/repo",
+        admin,
+        serde_json::json!({})
+    );
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (status, body) = send!(
+        app,
+        Method::GET,
+        "/api/audit?action=repository",
+        admin,
+        serde_json::json!({})
+    );
+    assert_eq!(status, StatusCode::OK);
+    let entries = body.as_array().unwrap();
+    let actions: Vec<&str> = entries
+        .iter()
+        .map(|e| e["action"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        actions,
+        ["repository.delete", "repository.update", "repository.add"]
+    );
+    assert!(entries
+        .iter()
+        .all(|e| e["actor"] == "alice" && e["target"] == "repo" && e["source_ip"] == "192.0.2.1"));
+    assert_eq!(
+        entries[1]["changes"],
+        serde_json::json!({ "enabled": { "before": true, "after": false } })
+    );
+
+    // Secrets never reach the log
+    let (_, body) = send!(
+        app,
+        Method::GET,
+        "/api/audit?target=github",
+        admin,
+        serde_json::json!({})
+    );
+    assert_eq!(body[0]["action"], "credential.add");
+    assert_eq!(body[0]["changes"]["password"]["after"], REDACTED);
+    assert!(!body.to_string().contains("s3cret"));
+
+    let (status, _) = send!(
+        app,
+        Method::GET,
+        "/api/audit",
+        viewer,
+        serde_json::json!({})
+    );
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+async fn test_webhook_tests_are_audited_and_limited_to_configured_webhooks() {
+    let mut server = mockito::Server::new_async().await;
+    let mock = server
+        .mock("POST", "/hook")
+        .with_status(200)
+        .expect(2)
+        .create_async()
+        .await;
+    let url = format!("{}/hook", server.url());
+
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = Config::default();
+    config.server.error_webhooks.push(Webhook::new(url.clone()));
+    let state = app_state(config, temp_dir.path());
+    let app = test::init_service(
+        App::new().app_data(state.clone()).service(
+            web::scope("/api")
+                .wrap(AuthMiddleware)
+                .route("/webhooks/test", web::post().to(test_webhook))
+                .route("/audit", web::get().to(list_audit_log)),
+        ),
+    )
+    .await;
+    let admin = state
+        .auth_service
+        .generate_token("alice", Role::Admin)
+        .unwrap();
+
+    let (status, body) = send!(
+        app,
+        Method::POST,
+        "/api/webhooks/test",
+        admin,
+        serde_json::json!({ "url": url })
+    );
+    assert_eq!(status, StatusCode::OK);
+    let first = body["delivery"]["id"].clone();
+    let (status, body) = send!(
+        app,
+        Method::POST,
+        "/api/webhooks/test",
+        admin,
+        serde_json::json!({ "webhook": { "url": url, "template": "{{ event }}" } })
+    );
+    assert_eq!(status, StatusCode::OK);
+    let second = body["delivery"]["id"].clone();
+
+    // Requests can't be sent to hosts that aren't configured as webhooks
+    let (status, _) = send!(
+        app,
+        Method::POST,
+        "/api/webhooks/test",
+        admin,
+        serde_json::json!({ "webhook": { "url": "http://169.254.169.254/latest/meta-data" } })
+    );
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    mock.assert_async().await;
+
+    let (_, body) = send!(
+        app,
+        Method::GET,
+        "/api/audit?action=webhook.test",
+        admin,
+        serde_json::json!({})
+    );
+    let entries = body.as_array().unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0]["target"], second);
+    assert_eq!(entries[1]["target"], first);
+    assert!(entries.iter().all(|e| e["actor"] == "alice"));
+}
@@ -1,5 +1,11 @@
-use gitsafe::auth::AuthService;
-use gitsafe::config::User;
+use actix_web::http::Method;
+use chrono::{Duration as ChronoDuration, Utc};
+use gitsafe::auth::{self, AuthService};
+use gitsafe::config::{Role, TokenScope, User};
+
+mod common;
+
+use common::api_token;
 
 // Use this test to generate a password hash for your users
 #[test]
@@ -40,7 +46,7 @@ fn test_token_generation_and_verification() {
     let username = "testuser";
 
     // Generate token
-    let token = auth_service.generate_token(username).unwrap();
+    let token = auth_service.generate_token(username, Role::Admin).unwrap();
 
     // Token should not be empty
     assert!(!token.is_empty());
@@ -68,18 +74,117 @@ fn test_authentication_success() {
     let users = vec![User {
         username: "testuser".to_string(),
         password_hash: hash,
+        role: Role::Admin,
+        must_change_password: false,
+        totp: None,
     }];
 
     // Authenticate with correct credentials
     let result = auth_service.authenticate("testuser", password, &users);
     assert!(result.is_ok());
 
-    let token = result.unwrap();
-    assert!(!token.is_empty());
+    let tokens = result.unwrap();
+    assert!(!tokens.token.is_empty());
+    assert!(!tokens.refresh_token.is_empty());
 
     // Verify the token
-    let claims = auth_service.verify_token(&token).unwrap();
+    let claims = auth_service.verify_token(&tokens.token).unwrap();
     assert_eq!(claims.sub, "testuser");
+    assert_eq!(claims.role, Role::Admin);
+}
+
+#[test]
+fn test_token_carries_user_role() {
+    let auth_service = AuthService::new("test-secret".to_string());
+    let hash = auth_service.hash_password("test-password").unwrap();
+    let users = vec![User {
+        username: "reader".to_string(),
+        password_hash: hash,
+        role: Role::Viewer,
+        must_change_password: false,
+        totp: None,
+    }];
+
+    let token = auth_service
+        .authenticate("reader", "test-password", &users)
+        .unwrap()
+        .token;
+    assert_eq!(
+        auth_service.verify_token(&token).unwrap().role,
+        Role::Viewer
+    );
+}
+
+#[test]
+fn test_must_change_password_restricts_token() {
+    let auth_service = AuthService::new("test-secret".to_string());
+    let hash = auth_service.hash_password("test-password").unwrap();
+    let mut users = vec![User {
+        username: "new".to_string(),
+        password_hash: hash,
+        role: Role::Operator,
+        must_change_password: true,
+        totp: None,
+    }];
+
+    let token = auth_service
+        .authenticate("new", "test-password", &users)
+        .unwrap()
+        .token;
+    assert!(
+        auth_service
+            .verify_token(&token)
+            .unwrap()
+            .password_change_required
+    );
+
+    users[0].must_change_password = false;
+    let token = auth_service
+        .authenticate("new", "test-password", &users)
+        .unwrap()
+        .token;
+    assert!(
+        !auth_service
+            .verify_token(&token)
+            .unwrap()
+            .password_change_required
+    );
+}
+
+#[test]
+fn test_validate_password() {
+    assert!(auth::validate_password("short").is_err());
+    assert!(auth::validate_password("long-enough").is_ok());
+}
+
+#[test]
+fn test_required_role() {
+    for (method, path, role) in [
+        (Method::GET, "/api/repositories", Role::Viewer),
+        (Method::GET, "/api/sync-logs", Role::Viewer),
+        (Method::POST, "/api/tokens", Role::Viewer),
+        (Method::DELETE, "/api/tokens/1", Role::Viewer),
+        (Method::POST, "/api/sync", Role::Operator),
+        (Method::POST, "/api/repositories", Role::Operator),
+        (Method::PATCH, "/api/repositories/repo", Role::Operator),
+        (Method::GET, "/api/credentials", Role::Operator),
+        (Method::DELETE, "/api/repositories/repo", Role::Admin),
+        (Method::POST, "/api/credentials", Role::Admin),
+        (Method::PATCH, "/api/credentials/cred", Role::Admin),
+        (Method::POST, "/api/webhooks/test", Role::Admin),
+        (Method::GET, "/api/webhooks/deliveries", Role::Admin),
+        (Method::POST, "/api/users", Role::Admin),
+        (Method::GET, "/api/users", Role::Admin),
+        (Method::POST, "/api/account/password", Role::Viewer),
+    ] {
+        assert_eq!(
+            auth::required_role(&method, path),
+            role,
+            "{} {}",
+            method,
+            path
+        );
+    }
 }
 
 #[test]
@@ -91,6 +196,9 @@ fn test_authentication_wrong_password() {
     let users = vec![User {
         username: "testuser".to_string(),
         password_hash: hash,
+        role: Role::Admin,
+        must_change_password: false,
+        totp: None,
     }];
 
     // Authenticate with wrong password
@@ -107,3 +215,61 @@ fn test_authentication_nonexistent_user() {
     let result = auth_service.authenticate("nonexistent", "password", &users);
     assert!(result.is_err());
 }
+
+#[test]
+fn test_generated_tokens_are_unique_and_hashed() {
+    let first = auth::generate_api_token();
+    let second = auth::generate_api_token();
+
+    assert!(first.starts_with(auth::API_TOKEN_PREFIX));
+    assert_eq!(first.len(), auth::API_TOKEN_PREFIX.len() + 64);
+    assert_ne!(first, second);
+    assert_eq!(auth::hash_api_token(&first), auth::hash_api_token(&first));
+    assert_ne!(auth::hash_api_token(&first), first);
+}
+
+#[test]
+fn test_required_scope() {
+    assert_eq!(
+        auth::required_scope(&Method::GET, "/api/repositories"),
+        TokenScope::Read
+    );
+    assert_eq!(
+        auth::required_scope(&Method::POST, "/api/sync"),
+        TokenScope::Sync
+    );
+    assert_eq!(
+        auth::required_scope(&Method::POST, "/api/repositories"),
+        TokenScope::Write
+    );
+    assert_eq!(
+        auth::required_scope(&Method::DELETE, "/api/tokens/1"),
+        TokenScope::Write
+    );
+
+    let token = api_token("gs_1", vec![TokenScope::Write]);
+    assert!(token.has_scope(TokenScope::Read));
+    assert!(token.has_scope(TokenScope::Sync));
+    let token = api_token("gs_1", vec![TokenScope::Read]);
+    assert!(!token.has_scope(TokenScope::Sync));
+}
+
+#[test]
+fn test_verify_api_token() {
+    let auth_service = AuthService::new("test-secret".to_string());
+    let now = Utc::now();
+    let mut expired = api_token("gs_expired", vec![TokenScope::Read]);
+    expired.expires_at = Some(now - ChronoDuration::minutes(1));
+    let tokens = vec![api_token("gs_valid", vec![TokenScope::Read]), expired];
+
+    let token = auth_service
+        .verify_api_token("gs_valid", &tokens, now)
+        .unwrap();
+    assert_eq!(token.username, "admin");
+    assert!(auth_service
+        .verify_api_token("gs_expired", &tokens, now)
+        .is_err());
+    assert!(auth_service
+        .verify_api_token("gs_unknown", &tokens, now)
+        .is_err());
+}
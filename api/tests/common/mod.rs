@@ -0,0 +1,143 @@
+//! Helpers shared by the integration tests.
+//!
+//! Every test binary includes this module with `#[macro_use] mod common;` and
+//! uses only some of it.
+#![allow(dead_code, unused_macros)]
+
+use actix_web::web;
+use chrono::Utc;
+use gitsafe::audit::AuditLog;
+use gitsafe::auth::{self, AuthService};
+use gitsafe::config::{ApiToken, Config, Repository, TokenScope, WebhookDeliveryConfig};
+use gitsafe::config_persistence::ConfigPersistence;
+use gitsafe::git::GitService;
+use gitsafe::handlers::AppState;
+use gitsafe::rate_limit::RateLimiter;
+use gitsafe::sync::SyncQueue;
+use gitsafe::webhooks::WebhookService;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+/// Builds the state of the app with its files (config, archives, data) in `dir`.
+///
+/// Push-triggered syncs are debounced for an hour, so tests don't actually sync.
+pub fn app_state(config: Config, dir: &Path) -> web::Data<AppState> {
+    let git_service = GitService::new(dir.join("archives"), true).unwrap();
+    let config_path = dir.join("config.yaml").to_string_lossy().to_string();
+    let config_persistence = ConfigPersistence::new(config_path.clone());
+    let config = Arc::new(RwLock::new(config));
+    let webhook_service =
+        WebhookService::new(WebhookDeliveryConfig::default(), dir.join("data")).unwrap();
+    let sync_queue = SyncQueue::new(
+        Arc::clone(&config),
+        git_service.clone(),
+        config_persistence.clone(),
+        webhook_service.clone(),
+        Duration::from_secs(3600),
+    );
+
+    web::Data::new(AppState {
+        config,
+        config_path,
+        auth_service: AuthService::new("test-secret".to_string()),
+        git_service,
+        config_persistence,
+        sync_queue,
+        webhook_service,
+        rate_limiter: RateLimiter::new(),
+        audit_log: AuditLog::in_memory(),
+    })
+}
+
+/// Creates a repository with a single commit at `path` and returns its `file://` URL.
+pub fn create_remote(path: &Path) -> String {
+    let repo = git2::Repository::init(path).unwrap();
+    fs::write(path.join("README.md"), "# Tool").unwrap();
+    let mut index = repo.index().unwrap();
+    index.add_path(Path::new("README.md")).unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let signature = git2::Signature::now("test", "test@example.com").unwrap();
+    repo.commit(Some("HEAD"), &signature, &signature, "Init", &tree, &[])
+        .unwrap();
+    format!("file://{}", path.display())
+}
+
+/// An enabled repository that was never synced.
+pub fn repository(id: &str, url: &str) -> Repository {
+    Repository {
+        id: id.to_string(),
+        url: url.to_string(),
+        credential_id: None,
+        enabled: true,
+        last_sync: None,
+        last_sync_commit_hash: None,
+        last_sync_message: None,
+        error: None,
+        size: None,
+        attempts_left: None,
+        source_id: None,
+        upstream_status: None,
+        metadata: None,
+        tags: Vec::new(),
+        max_backup_age_hours: None,
+    }
+}
+
+/// An API token of the default admin, without expiry.
+pub fn api_token(token: &str, scopes: Vec<TokenScope>) -> ApiToken {
+    ApiToken {
+        id: "token-1".to_string(),
+        username: "admin".to_string(),
+        name: "backup script".to_string(),
+        token_hash: auth::hash_api_token(token),
+        scopes,
+        created_at: Utc::now(),
+        expires_at: None,
+        last_used: None,
+    }
+}
+
+/// Sends a request with a bearer token through the app and returns its status,
+/// including authentication errors of the middleware.
+macro_rules! status {
+    ($app:expr, $method:expr, $uri:expr, $token:expr) => {{
+        let req = actix_web::test::TestRequest::default()
+            .method($method)
+            .uri($uri)
+            .insert_header(("Authorization", format!("Bearer {}", $token)))
+            .set_json(serde_json::json!({ "repository_id": "missing" }))
+            .to_request();
+        match actix_web::dev::Service::call(&$app, req).await {
+            Ok(resp) => resp.status(),
+            Err(e) => e.as_response_error().status_code(),
+        }
+    }};
+}
+
+/// Sends a JSON request with a bearer token through the app and returns its
+/// status and body, including errors of the middleware.
+macro_rules! send {
+    ($app:expr, $method:expr, $uri:expr, $token:expr, $body:expr) => {{
+        let req = actix_web::test::TestRequest::default()
+            .method($method)
+            .uri($uri)
+            .peer_addr("192.0.2.1:40000".parse().unwrap())
+            .insert_header(("Authorization", format!("Bearer {}", $token)))
+            .set_json($body)
+            .to_request();
+        match actix_web::dev::Service::call(&$app, req).await {
+            Ok(resp) => {
+                let status = resp.status();
+                let body = actix_web::test::read_body(resp).await;
+                (
+                    status,
+                    serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null),
+                )
+            }
+            Err(e) => (e.as_response_error().status_code(), serde_json::Value::Null),
+        }
+    }};
+}
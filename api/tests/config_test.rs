@@ -21,6 +21,9 @@ fn test_config_serialization() {
     config.users.push(User {
         username: "test".to_string(),
         password_hash: "hash".to_string(),
+        role: Role::Admin,
+        must_change_password: false,
+        totp: None,
     });
 
     // Add a repository
@@ -35,6 +38,11 @@ fn test_config_serialization() {
         last_sync_message: None,
         error: None,
         attempts_left: None,
+        source_id: None,
+        upstream_status: None,
+        metadata: None,
+        tags: Vec::new(),
+        max_backup_age_hours: None,
     });
 
     // Add a credential
@@ -61,6 +69,45 @@ fn test_config_serialization() {
     assert_eq!(deserialized.credentials.len(), 1);
 }
 
+#[test]
+fn test_users_without_role_are_admins() {
+    let users: Vec<User> = serde_yaml_ng::from_str(
+        r#"
+- username: "legacy"
+  password_hash: "hash"
+- username: "reader"
+  password_hash: "hash"
+  role: viewer
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(users[0].role, Role::Admin);
+    assert_eq!(users[1].role, Role::Viewer);
+    assert!(Role::Viewer < Role::Operator && Role::Operator < Role::Admin);
+}
+
+#[test]
+fn test_proxy_auth_config() {
+    let proxy: ProxyAuthConfig = serde_yaml_ng::from_str(
+        r#"
+trusted_proxies: ["172.16.0.0/12", "::1/128"]
+groups_header: Remote-Groups
+group_roles:
+  admins: admin
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(proxy.user_header, "Remote-User");
+    assert!(proxy.trusted_proxies[0].contains(&"172.18.0.5".parse::<std::net::IpAddr>().unwrap()));
+    assert_eq!(proxy.group_roles["admins"], Role::Admin);
+    assert!(proxy.default_role.is_none());
+
+    // Trusted proxies must be valid CIDRs
+    assert!(serde_yaml_ng::from_str::<ProxyAuthConfig>("trusted_proxies: [\"proxy\"]").is_err());
+}
+
 #[test]
 fn test_repository_creation() {
     let repo = Repository {
@@ -74,6 +121,11 @@ fn test_repository_creation() {
         last_sync_message: None,
         error: None,
         attempts_left: None,
+        source_id: None,
+        upstream_status: None,
+        metadata: None,
+        tags: Vec::new(),
+        max_backup_age_hours: None,
     };
 
     assert_eq!(repo.id, "test-id");
@@ -161,6 +213,11 @@ fn test_config_save_and_load() {
         last_sync_message: None,
         error: None,
         attempts_left: None,
+        source_id: None,
+        upstream_status: None,
+        metadata: None,
+        tags: Vec::new(),
+        max_backup_age_hours: None,
     });
 
     // Save config
@@ -173,3 +230,101 @@ fn test_config_save_and_load() {
     assert_eq!(loaded_config.repositories.len(), 1);
     assert_eq!(loaded_config.repositories[0].id, "repo1");
 }
+
+#[test]
+fn test_error_webhooks_accept_urls_and_objects() {
+    let yaml = r#"
+- https://hooks.example.com/plain
+- url: https://hooks.example.com/signed
+  secret: shared-secret
+  headers:
+    Authorization: Bearer token
+  timeout_seconds: 5
+- url: https://ntfy.sh/alerts
+  format: ntfy
+  priority: 3
+- url: https://hooks.example.com/templated
+  content_type: text/plain
+  template: |
+    {{ title }}: {{ message }}
+"#;
+    let webhooks: Vec<Webhook> = serde_yaml_ng::from_str(yaml).unwrap();
+
+    assert_eq!(webhooks[0], Webhook::new("https://hooks.example.com/plain"));
+    assert_eq!(webhooks[1].secret.as_deref(), Some("shared-secret"));
+    assert_eq!(webhooks[1].headers["Authorization"], "Bearer token");
+    assert_eq!(webhooks[1].timeout_seconds, Some(5));
+    assert_eq!(webhooks[1].format, WebhookFormat::Generic);
+    assert_eq!(webhooks[2].format, WebhookFormat::Ntfy);
+    assert_eq!(webhooks[2].priority, Some(3));
+    assert_eq!(
+        webhooks[3].template.as_deref(),
+        Some("{{ title }}: {{ message }}\n")
+    );
+    assert_eq!(webhooks[3].content_type.as_deref(), Some("text/plain"));
+
+    // Plain webhooks are written back as bare URLs
+    let serialized = serde_yaml_ng::to_string(&webhooks).unwrap();
+    assert!(serialized.starts_with("- https://hooks.example.com/plain\n"));
+    let reparsed: Vec<Webhook> = serde_yaml_ng::from_str(&serialized).unwrap();
+    assert_eq!(reparsed, webhooks);
+}
+
+#[test]
+fn test_subscription_matching() {
+    let mut repo = Repository {
+        size: None,
+        id: "github_com-acme-tool".to_string(),
+        url: "https://github.com/acme/tool.git".to_string(),
+        credential_id: None,
+        enabled: true,
+        last_sync: None,
+        last_sync_commit_hash: None,
+        last_sync_message: None,
+        error: None,
+        attempts_left: None,
+        source_id: None,
+        upstream_status: None,
+        metadata: None,
+        tags: vec!["noisy".to_string()],
+        max_backup_age_hours: None,
+    };
+    let yaml = r#"
+webhook: https://hooks.example.com/acme
+events: [sync_succeeded, storage_low]
+repositories: ["github_com-acme-*"]
+exclude_tags: [noisy]
+"#;
+    let subscription: Subscription = serde_yaml_ng::from_str(yaml).unwrap();
+
+    assert!(!subscription.matches(NotificationEvent::SyncSucceeded, Some(&repo)));
+    repo.tags.clear();
+    assert!(subscription.matches(NotificationEvent::SyncSucceeded, Some(&repo)));
+    assert!(!subscription.matches(NotificationEvent::Error, Some(&repo)));
+    repo.id = "gitlab_com-other-tool".to_string();
+    assert!(!subscription.matches(NotificationEvent::SyncSucceeded, Some(&repo)));
+    // Events without a repository ignore repository filters
+    assert!(subscription.matches(NotificationEvent::StorageLow, None));
+
+    // Tags include repositories in addition to ID globs
+    let by_tag = Subscription {
+        tags: vec!["critical".to_string()],
+        exclude_tags: Vec::new(),
+        ..subscription
+    };
+    repo.tags = vec!["critical".to_string()];
+    assert!(by_tag.matches(NotificationEvent::SyncSucceeded, Some(&repo)));
+
+    // Error webhooks are subscribed to errors of all repositories
+    let mut config = Config::default();
+    config
+        .server
+        .error_webhooks
+        .push(Webhook::new("https://hooks.example.com/errors"));
+    config.server.notifications.push(by_tag);
+    let subscriptions = config.server.subscriptions();
+    assert_eq!(subscriptions.len(), 2);
+    assert!(subscriptions[0].matches(NotificationEvent::Error, Some(&repo)));
+    assert!(subscriptions[0].matches(NotificationEvent::OutOfAttempts, Some(&repo)));
+    assert!(!subscriptions[0].matches(NotificationEvent::SyncSucceeded, Some(&repo)));
+}
@@ -0,0 +1,209 @@
+use gitsafe::config::{Config, Repository, UpstreamStatus, WatchSource};
+use gitsafe::discovery::{list_remote_repositories, reconcile_source, RemoteRepository};
+use gitsafe::forge::{ForgeClient, ForgeProvider};
+use mockito::Matcher;
+
+fn watch_source() -> WatchSource {
+    WatchSource {
+        id: "acme".to_string(),
+        provider: ForgeProvider::Github,
+        owner: "acme".to_string(),
+        api_url: None,
+        credential_id: Some("acme-token".to_string()),
+        include: Vec::new(),
+        exclude: vec!["*-sandbox".to_string()],
+        include_archived: false,
+        use_ssh: false,
+        cron_expression: None,
+        enabled: true,
+    }
+}
+
+fn remote(name: &str, archived: bool) -> RemoteRepository {
+    RemoteRepository {
+        full_name: format!("acme/{}", name),
+        clone_url: format!("https://github.com/acme/{}.git", name),
+        ssh_url: Some(format!("git@github.com:acme/{}.git", name)),
+        archived,
+    }
+}
+
+#[test]
+fn test_watch_source_filters() {
+    let mut source = watch_source();
+    source.include = vec!["api-*".to_string(), "web".to_string()];
+
+    assert!(source.matches("api-gateway"));
+    assert!(source.matches("web"));
+    assert!(!source.matches("docs"));
+
+    source.exclude = vec!["api-legacy*".to_string()];
+    assert!(!source.matches("api-legacy-v1"));
+}
+
+#[test]
+fn test_reconcile_adds_new_repositories() {
+    let mut config = Config::default();
+    // Already configured via SSH URL, must not be added a second time
+    config.repositories.push(Repository {
+        id: "existing".to_string(),
+        url: "git@github.com:acme/service.git".to_string(),
+        credential_id: None,
+        enabled: true,
+        last_sync: None,
+        last_sync_commit_hash: None,
+        last_sync_message: None,
+        error: None,
+        size: None,
+        attempts_left: None,
+        source_id: None,
+        upstream_status: None,
+        metadata: None,
+        tags: Vec::new(),
+        max_backup_age_hours: None,
+    });
+
+    let listing = vec![
+        remote("service", false),
+        remote("tool", false),
+        remote("old", true),
+        remote("play-sandbox", false),
+    ];
+    let report = reconcile_source(&mut config, &watch_source(), &listing);
+
+    assert_eq!(report.added, vec!["github_com-acme-tool".to_string()]);
+    assert!(report.flagged.is_empty());
+    assert_eq!(config.repositories.len(), 2);
+
+    let added = &config.repositories[1];
+    assert_eq!(added.url, "https://github.com/acme/tool.git");
+    assert_eq!(added.credential_id.as_deref(), Some("acme-token"));
+    assert_eq!(added.source_id.as_deref(), Some("acme"));
+    assert!(added.enabled);
+}
+
+#[test]
+fn test_reconcile_flags_deleted_and_archived_repositories() {
+    let mut config = Config::default();
+    let source = watch_source();
+    reconcile_source(
+        &mut config,
+        &source,
+        &[remote("tool", false), remote("lib", false)],
+    );
+
+    // "tool" was archived, "lib" was deleted upstream
+    let report = reconcile_source(&mut config, &source, &[remote("tool", true)]);
+    assert_eq!(report.flagged.len(), 2);
+    assert_eq!(config.repositories.len(), 2, "backups must not be dropped");
+    assert_eq!(
+        config.repositories[0].upstream_status,
+        Some(UpstreamStatus::Archived)
+    );
+    assert_eq!(
+        config.repositories[1].upstream_status,
+        Some(UpstreamStatus::Deleted)
+    );
+
+    // Flags are only reported once
+    let report = reconcile_source(&mut config, &source, &[remote("tool", true)]);
+    assert!(!report.has_changes());
+
+    // "lib" reappears
+    let report = reconcile_source(
+        &mut config,
+        &source,
+        &[remote("tool", true), remote("lib", false)],
+    );
+    assert_eq!(report.restored, vec!["github_com-acme-lib".to_string()]);
+    assert_eq!(config.repositories[1].upstream_status, None);
+}
+
+#[tokio::test]
+async fn test_list_github_repositories_falls_back_to_user() {
+    let mut server = mockito::Server::new_async().await;
+    let org = server
+        .mock("GET", "/orgs/octo/repos")
+        .match_query(Matcher::Any)
+        .with_status(404)
+        .create_async()
+        .await;
+    let first_page = server
+        .mock("GET", "/users/octo/repos")
+        .match_query(Matcher::UrlEncoded("page".into(), "1".into()))
+        .match_header("authorization", "Bearer secret-token")
+        .with_body(
+            r#"[{"full_name": "octo/one", "clone_url": "https://github.com/octo/one.git",
+                 "ssh_url": "git@github.com:octo/one.git", "archived": true}]"#,
+        )
+        .create_async()
+        .await;
+    let last_page = server
+        .mock("GET", "/users/octo/repos")
+        .match_query(Matcher::UrlEncoded("page".into(), "2".into()))
+        .with_body("[]")
+        .create_async()
+        .await;
+
+    let client = ForgeClient::new(
+        ForgeProvider::Github,
+        Some(&server.url()),
+        Some("secret-token".to_string()),
+    )
+    .unwrap();
+    let repos = list_remote_repositories(&client, "octo").await.unwrap();
+
+    org.assert_async().await;
+    first_page.assert_async().await;
+    last_page.assert_async().await;
+    assert_eq!(repos.len(), 1);
+    assert_eq!(repos[0].full_name, "octo/one");
+    assert!(repos[0].archived);
+}
+
+#[tokio::test]
+async fn test_list_gitlab_group_projects() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("GET", "/groups/team%2Fbackend/projects")
+        .match_query(Matcher::AllOf(vec![
+            Matcher::UrlEncoded("include_subgroups".into(), "true".into()),
+            Matcher::UrlEncoded("page".into(), "1".into()),
+        ]))
+        .match_header("private-token", "glpat")
+        .with_body(
+            r#"[{"path_with_namespace": "team/backend/api",
+                 "http_url_to_repo": "https://gitlab.com/team/backend/api.git",
+                 "ssh_url_to_repo": "git@gitlab.com:team/backend/api.git"}]"#,
+        )
+        .create_async()
+        .await;
+    server
+        .mock("GET", "/groups/team%2Fbackend/projects")
+        .match_query(Matcher::UrlEncoded("page".into(), "2".into()))
+        .with_body("[]")
+        .create_async()
+        .await;
+
+    let client = ForgeClient::new(
+        ForgeProvider::Gitlab,
+        Some(&server.url()),
+        Some("glpat".to_string()),
+    )
+    .unwrap();
+    let repos = list_remote_repositories(&client, "team/backend")
+        .await
+        .unwrap();
+
+    assert_eq!(repos.len(), 1);
+    assert_eq!(
+        repos[0].clone_url,
+        "https://gitlab.com/team/backend/api.git"
+    );
+    assert!(!repos[0].archived);
+}
+
+#[test]
+fn test_gitea_requires_api_url() {
+    assert!(ForgeClient::new(ForgeProvider::Gitea, None, None).is_err());
+}
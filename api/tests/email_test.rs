@@ -0,0 +1,227 @@
+use chrono::{Duration, TimeZone, Utc};
+use gitsafe::config::{Config, DigestConfig, EmailConfig, SmtpTls};
+use gitsafe::email::{self, Digest, DigestState};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tempfile::TempDir;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, RwLock};
+
+mod common;
+
+use common::repository;
+
+/// A message received by the SMTP stand-in.
+#[derive(Debug)]
+struct ReceivedMail {
+    /// Lines of the SMTP dialogue sent by the client before `DATA`
+    commands: Vec<String>,
+    data: String,
+}
+
+/// Starts a minimal SMTP server accepting every message, returning its port
+/// and a receiver of the messages.
+async fn smtp_stand_in() -> (u16, mpsc::UnboundedReceiver<ReceivedMail>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (sender, receiver) = mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        loop {
+            let Ok((stream, _)) = listener.accept().await else {
+                return;
+            };
+            let sender = sender.clone();
+            tokio::spawn(async move {
+                let (reader, mut writer) = stream.into_split();
+                let mut lines = BufReader::new(reader).lines();
+                let mut commands = Vec::new();
+                writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+
+                while let Ok(Some(line)) = lines.next_line().await {
+                    let command = line.to_uppercase();
+                    let reply: &[u8] = if command.starts_with("EHLO") {
+                        b"250-localhost\r\n250 AUTH PLAIN LOGIN\r\n"
+                    } else if command.starts_with("AUTH") {
+                        b"235 Authentication successful\r\n"
+                    } else if command.starts_with("DATA") {
+                        writer.write_all(b"354 End data with .\r\n").await.unwrap();
+                        let mut data = String::new();
+                        while let Ok(Some(line)) = lines.next_line().await {
+                            if line == "." {
+                                break;
+                            }
+                            data.push_str(&line);
+                            data.push('\n');
+                        }
+                        let _ = sender.send(ReceivedMail {
+                            commands: std::mem::take(&mut commands),
+                            data,
+                        });
+                        b"250 OK\r\n"
+                    } else if command.starts_with("QUIT") {
+                        let _ = writer.write_all(b"221 Bye\r\n").await;
+                        return;
+                    } else {
+                        b"250 OK\r\n"
+                    };
+                    commands.push(line);
+                    writer.write_all(reply).await.unwrap();
+                }
+            });
+        }
+    });
+
+    (port, receiver)
+}
+
+fn email_config(port: u16) -> EmailConfig {
+    EmailConfig {
+        smtp_host: "127.0.0.1".to_string(),
+        smtp_port: port,
+        tls: SmtpTls::None,
+        username: None,
+        password: None,
+        from: "GitSafe <gitsafe@example.com>".to_string(),
+        recipients: vec![
+            "ops@example.com".to_string(),
+            "audit@example.com".to_string(),
+        ],
+        notify_errors: true,
+        digest: Some(DigestConfig {
+            cron_expression: "0 0 8 * * *".to_string(),
+            stale_after_hours: 48,
+        }),
+    }
+}
+
+async fn receive(receiver: &mut mpsc::UnboundedReceiver<ReceivedMail>) -> ReceivedMail {
+    tokio::time::timeout(std::time::Duration::from_secs(10), receiver.recv())
+        .await
+        .expect("no email received")
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_send_email_with_auth_to_multiple_recipients() {
+    let (port, mut receiver) = smtp_stand_in().await;
+    let mut settings = email_config(port);
+    settings.username = Some("gitsafe".to_string());
+    settings.password = Some("secret".to_string());
+
+    email::send_email(&settings, "Hello", "Body".to_string())
+        .await
+        .unwrap();
+
+    let mail = receive(&mut receiver).await;
+    assert!(mail.commands.iter().any(|c| c.starts_with("AUTH")));
+    assert!(mail
+        .commands
+        .iter()
+        .any(|c| c.starts_with("MAIL FROM:<gitsafe@example.com>")));
+    let recipients: Vec<_> = mail
+        .commands
+        .iter()
+        .filter(|c| c.starts_with("RCPT TO"))
+        .collect();
+    assert_eq!(recipients.len(), 2);
+    assert!(mail.data.contains("Subject: Hello"));
+    assert!(mail.data.contains("Body"));
+}
+
+#[tokio::test]
+async fn test_error_notification_email() {
+    let (port, mut receiver) = smtp_stand_in().await;
+    let settings = email_config(port);
+    let repo = repository("tool", "https://github.com/acme/tool.git");
+
+    email::notify_error(
+        Some(&settings),
+        &repo,
+        "sync",
+        None,
+        "authentication failed",
+    );
+    let mail = receive(&mut receiver).await;
+    assert!(mail
+        .data
+        .contains("Subject: [GitSafe] sync failed for tool"));
+    assert!(mail.data.contains("authentication failed"));
+
+    email::notify_out_of_attempts(Some(&settings), &repo, "authentication failed", 5);
+    let mail = receive(&mut receiver).await;
+    assert!(mail.data.contains("Subject: [GitSafe] tool was disabled"));
+}
+
+#[test]
+fn test_digest_report() {
+    let now = Utc.with_ymd_and_hms(2024, 1, 8, 8, 0, 0).unwrap();
+
+    let mut healthy = repository("healthy", "https://github.com/acme/healthy.git");
+    healthy.last_sync = Some(now - Duration::hours(1));
+    healthy.size = Some(3 * 1024 * 1024);
+    let mut failing = repository("failing", "https://github.com/acme/failing.git");
+    failing.last_sync = Some(now - Duration::hours(72));
+    failing.size = Some(1024);
+    failing.error = Some("authentication failed".to_string());
+    failing.attempts_left = Some(2);
+    let mut disabled = repository("disabled", "https://github.com/acme/disabled.git");
+    disabled.enabled = false;
+    disabled.error = Some("not found".to_string());
+    let new = repository("new", "https://github.com/acme/new.git");
+
+    let previous = DigestState {
+        generated_at: Some(now - Duration::days(1)),
+        sizes: HashMap::from([
+            ("healthy".to_string(), 2 * 1024 * 1024),
+            ("failing".to_string(), 1024),
+        ]),
+    };
+    let digest = Digest::build(&[healthy, failing, disabled, new], &previous, 48, now);
+
+    assert_eq!(digest.repositories[0].size_change, Some(1024 * 1024));
+    let failures: Vec<_> = digest.failures().map(|r| r.id.as_str()).collect();
+    assert_eq!(failures, ["failing", "disabled"]);
+    // Never synced counts as stale, disabled repositories don't
+    let stale: Vec<_> = digest.stale().map(|r| r.id.as_str()).collect();
+    assert_eq!(stale, ["failing", "new"]);
+
+    assert_eq!(
+        digest.subject(),
+        "[GitSafe] Backup digest 2024-01-08: 2 failing, 2 stale"
+    );
+    let body = digest.render();
+    assert!(body.contains("failing (2 attempts left): authentication failed"));
+    assert!(body.contains("disabled (disabled): not found"));
+    assert!(body.contains("healthy: last sync 2024-01-08 07:00 UTC, 3.0 MiB (+1.0 MiB)"));
+    assert!(body.contains("new: last sync never, not backed up"));
+}
+
+#[tokio::test]
+async fn test_send_digest_stores_state() {
+    let (port, mut receiver) = smtp_stand_in().await;
+    let temp_dir = TempDir::new().unwrap();
+
+    let mut config = Config::default();
+    config.storage.data_dir = temp_dir.path().to_string_lossy().to_string();
+    config.server.email = Some(email_config(port));
+    let mut repo = repository("tool", "https://github.com/acme/tool.git");
+    repo.last_sync = Some(Utc::now());
+    repo.size = Some(2048);
+    config.repositories.push(repo);
+    let config = Arc::new(RwLock::new(config));
+
+    email::send_digest(&config).await.unwrap();
+    let mail = receive(&mut receiver).await;
+    assert!(mail.data.contains("all backups healthy"));
+    assert!(mail.data.contains("2.0 KiB (new)"));
+
+    config.write().await.repositories[0].size = Some(1024);
+    email::send_digest(&config).await.unwrap();
+    let mail = receive(&mut receiver).await;
+    assert!(mail.data.contains("1.0 KiB (-1.0 KiB)"));
+
+    let state = DigestState::load(temp_dir.path());
+    assert_eq!(state.sizes["tool"], 1024);
+}
@@ -1,6 +1,12 @@
+use gitsafe::encryption::EncryptionKeys;
 use gitsafe::git::GitService;
+use std::fs;
 use tempfile::TempDir;
 
+mod common;
+
+use common::{create_remote, repository};
+
 #[test]
 fn test_repo_id_from_url() {
     // Test standard GitHub URL
@@ -117,6 +123,22 @@ fn test_git_service_new() {
     assert!(archive_path.exists());
 }
 
+#[test]
+fn test_repository_syncs_one_at_a_time() {
+    let temp_dir = TempDir::new().unwrap();
+    let service = GitService::new(temp_dir.path(), true).unwrap();
+
+    let guard = service.begin_sync("repo").unwrap();
+    assert!(service.is_syncing("repo"));
+    // Clones share the repositories being synced
+    assert!(service.clone().begin_sync("repo").is_none());
+    assert!(service.begin_sync("other").is_some());
+
+    drop(guard);
+    assert!(!service.is_syncing("repo"));
+    assert!(service.begin_sync("repo").is_some());
+}
+
 #[test]
 fn test_repo_id_special_characters() {
     // Test that special characters are handled
@@ -176,3 +198,47 @@ fn test_repo_path_edge_cases() {
     let path = GitService::repo_path_from_url("https://github.com/user/repo/", false);
     assert_eq!(path, "github_com/user/repo");
 }
+
+#[test]
+fn test_verify_backup() {
+    let temp_dir = TempDir::new().unwrap();
+    let url = create_remote(&temp_dir.path().join("remote/tool"));
+
+    for compact in [true, false] {
+        let service = GitService::new(
+            temp_dir.path().join(format!("archives-{}", compact)),
+            compact,
+        )
+        .unwrap();
+        let result = service
+            .sync_repository(
+                &repository("tool", &url),
+                None,
+                &EncryptionKeys::new("key", &[]),
+            )
+            .unwrap();
+
+        service
+            .verify_backup(&result.path, &result.commit_hash)
+            .unwrap();
+        // A commit the backup doesn't contain
+        assert!(service
+            .verify_backup(&result.path, "0123456789012345678901234567890123456789")
+            .is_err());
+    }
+
+    // A truncated archive fails to unpack
+    let service = GitService::new(temp_dir.path().join("archives-true"), true).unwrap();
+    let result = service
+        .sync_repository(
+            &repository("tool", &url),
+            None,
+            &EncryptionKeys::new("key", &[]),
+        )
+        .unwrap();
+    let content = fs::read(&result.path).unwrap();
+    fs::write(&result.path, &content[..content.len() / 2]).unwrap();
+    assert!(service
+        .verify_backup(&result.path, &result.commit_hash)
+        .is_err());
+}
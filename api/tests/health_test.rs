@@ -0,0 +1,146 @@
+use actix_web::{test, web, App};
+use chrono::{Duration as ChronoDuration, Utc};
+use gitsafe::config::Config;
+use gitsafe::handlers::{health_live, health_ready};
+use gitsafe::health::{self, SchedulerStatus};
+use std::time::Duration;
+use tempfile::TempDir;
+
+mod common;
+
+use common::{app_state, repository};
+
+fn running_scheduler() -> SchedulerStatus {
+    let status = SchedulerStatus::new();
+    status.job_registered("heartbeat");
+    status.job_registered("sync");
+    status.heartbeat();
+    status
+}
+
+#[actix_web::test]
+async fn test_ready_reports_checks_and_repository_counts() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = Config::default();
+    let mut failing = repository("failing", "https://github.com/acme/failing.git");
+    failing.error = Some("authentication failed".to_string());
+    let mut disabled = repository("disabled", "https://github.com/acme/disabled.git");
+    disabled.enabled = false;
+    config.repositories = vec![
+        repository("healthy", "https://github.com/acme/healthy.git"),
+        failing,
+        disabled,
+    ];
+    let state = app_state(config, temp_dir.path());
+
+    let app = test::init_service(
+        App::new()
+            .app_data(state)
+            .app_data(web::Data::new(running_scheduler()))
+            .route("/health/live", web::get().to(health_live))
+            .route("/health/ready", web::get().to(health_ready)),
+    )
+    .await;
+
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get().uri("/health/live").to_request(),
+    )
+    .await;
+    assert!(resp.status().is_success());
+
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get().uri("/health/ready").to_request(),
+    )
+    .await;
+    assert!(resp.status().is_success());
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["status"], "ready");
+    assert_eq!(body["checks"]["archive_dir"]["ok"], true);
+    assert!(body["checks"]["archive_dir"]["available_bytes"].is_u64());
+    assert_eq!(body["checks"]["config_persistence"]["ok"], true);
+    assert_eq!(
+        body["checks"]["scheduler"]["jobs"],
+        serde_json::json!(["heartbeat", "sync"])
+    );
+    // Failing repositories are reported but don't affect readiness
+    assert_eq!(
+        body["repositories"],
+        serde_json::json!({"total": 3, "failing": 1, "disabled": 1})
+    );
+}
+
+#[tokio::test]
+async fn test_not_ready_when_scheduler_is_missing_or_stalled() {
+    let temp_dir = TempDir::new().unwrap();
+    let state = app_state(Config::default(), temp_dir.path());
+
+    let readiness = health::check_readiness(
+        &state.config,
+        &state.git_service,
+        &state.config_persistence,
+        &SchedulerStatus::new(),
+        Utc::now(),
+    )
+    .await;
+    assert!(!readiness.is_ready());
+    assert_eq!(readiness.status, "not_ready");
+    assert_eq!(
+        readiness.checks["scheduler"].error.as_deref(),
+        Some("Sync job is not registered")
+    );
+
+    // No heartbeat for five minutes
+    let readiness = health::check_readiness(
+        &state.config,
+        &state.git_service,
+        &state.config_persistence,
+        &running_scheduler(),
+        Utc::now() + ChronoDuration::minutes(5),
+    )
+    .await;
+    assert_eq!(
+        readiness.checks["scheduler"].error.as_deref(),
+        Some("Scheduler is not executing jobs")
+    );
+    assert!(readiness.checks["archive_dir"].ok);
+}
+
+#[tokio::test]
+async fn test_not_ready_when_storage_or_persistence_fails() {
+    let temp_dir = TempDir::new().unwrap();
+    let archive_dir = temp_dir.path().join("archives");
+    let state = app_state(Config::default(), temp_dir.path());
+    // The config file can't be written where a directory is in the way
+    std::fs::create_dir(temp_dir.path().join("config.yaml")).unwrap();
+    state.config_persistence.request_save(Config::default());
+    for _ in 0..50 {
+        if state.config_persistence.status().last_error.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    std::fs::remove_dir_all(&archive_dir).unwrap();
+
+    let readiness = health::check_readiness(
+        &state.config,
+        &state.git_service,
+        &state.config_persistence,
+        &running_scheduler(),
+        Utc::now(),
+    )
+    .await;
+    assert!(!readiness.is_ready());
+    assert!(readiness.checks["scheduler"].ok);
+    assert!(readiness.checks["archive_dir"]
+        .error
+        .as_deref()
+        .unwrap()
+        .starts_with("Archive directory is not usable"));
+    assert!(readiness.checks["config_persistence"]
+        .error
+        .as_deref()
+        .unwrap()
+        .starts_with("Last config save failed"));
+}
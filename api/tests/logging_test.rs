@@ -0,0 +1,187 @@
+use actix_web::{test, web, App};
+use gitsafe::config::{Config, WebhookDeliveryConfig};
+use gitsafe::config_persistence::ConfigPersistence;
+use gitsafe::git::GitService;
+use gitsafe::handlers::{get_sync_log, list_sync_logs};
+use gitsafe::logging::{JsonLayer, SyncLogs};
+use gitsafe::sync::{self, SyncTrigger};
+use gitsafe::webhooks::WebhookService;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+use tokio::sync::RwLock;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+
+mod common;
+
+use common::{create_remote, repository};
+
+/// Writer collecting the log output in memory.
+#[derive(Clone, Default)]
+struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for Buffer {
+    type Writer = Buffer;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+#[actix_web::test]
+async fn test_json_lines_carry_span_fields() {
+    let buffer = Buffer::default();
+    let subscriber = tracing_subscriber::registry().with(JsonLayer::new(buffer.clone()));
+
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!(
+            "sync",
+            repo_id = "tool",
+            url = tracing::field::Empty,
+            trigger = "manual",
+            job_id = "job-1"
+        );
+        let _entered = span.enter();
+        span.record("url", "https://example.com/tool.git");
+        tracing::warn!(attempts = 3, "Sync failed");
+    });
+
+    let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 1);
+    let line: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+    assert_eq!(line["level"], "WARN");
+    assert_eq!(line["target"], "logging_test");
+    assert_eq!(line["message"], "Sync failed");
+    assert_eq!(line["repo_id"], "tool");
+    assert_eq!(line["url"], "https://example.com/tool.git");
+    assert_eq!(line["trigger"], "manual");
+    assert_eq!(line["job_id"], "job-1");
+    assert_eq!(line["attempts"], 3);
+    assert!(line["timestamp"].is_string());
+}
+
+#[actix_web::test]
+async fn test_sync_logs_keep_latest_runs() {
+    let logs = SyncLogs::new(2);
+    let subscriber = tracing_subscriber::registry().with(logs.layer());
+
+    tracing::subscriber::with_default(subscriber, || {
+        // Events outside of a sync are not captured
+        tracing::info!("Scheduler started");
+        for (repo_id, job_id) in [("a", "job-1"), ("b", "job-2"), ("a", "job-3")] {
+            let span = tracing::info_span!("sync", repo_id, trigger = "schedule", job_id);
+            let _entered = span.enter();
+            tracing::info!("Starting sync");
+            tracing::info_span!("clone").in_scope(|| tracing::info!(phase = "clone", "Cloning"));
+        }
+    });
+
+    assert!(logs.get("job-1").is_none());
+    let runs: Vec<String> = logs.runs(None).into_iter().map(|r| r.job_id).collect();
+    assert_eq!(runs, vec!["job-3", "job-2"]);
+    assert_eq!(logs.runs(Some("a")).len(), 1);
+
+    let run = logs.get("job-3").unwrap();
+    assert_eq!(run.repo_id, "a");
+    assert_eq!(run.trigger.as_deref(), Some("schedule"));
+    assert!(run.finished_at.is_some());
+    let messages: Vec<&str> = run.lines.iter().map(|l| l.message.as_str()).collect();
+    assert_eq!(messages, vec!["Starting sync", "Cloning"]);
+    assert_eq!(run.lines[1].fields["phase"], "clone");
+}
+
+#[tokio::test]
+async fn test_sync_run_logs_are_served() {
+    let logs = SyncLogs::new(10);
+    let subscriber = tracing_subscriber::registry().with(logs.layer());
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let temp_dir = TempDir::new().unwrap();
+    let url = create_remote(&temp_dir.path().join("remote/tool"));
+    let mut config = Config::default();
+    config.repositories.push(repository("tool", &url));
+    let config = Arc::new(RwLock::new(config));
+    let git_service = GitService::new(temp_dir.path().join("archives"), true).unwrap();
+    let config_persistence = ConfigPersistence::new(
+        temp_dir
+            .path()
+            .join("config.yaml")
+            .to_string_lossy()
+            .to_string(),
+    );
+    let webhook_service = WebhookService::new(
+        WebhookDeliveryConfig::default(),
+        temp_dir.path().join("data"),
+    )
+    .unwrap();
+
+    sync::sync_repository(
+        &config,
+        &git_service,
+        &config_persistence,
+        &webhook_service,
+        "tool",
+        SyncTrigger::Manual,
+    )
+    .await
+    .unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(logs.clone()))
+            .route("/api/sync-logs", web::get().to(list_sync_logs))
+            .route("/api/sync-logs/{job_id}", web::get().to(get_sync_log)),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/sync-logs?repository_id=tool")
+        .to_request();
+    let runs: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(runs.len(), 1);
+    assert_eq!(runs[0]["repo_id"], "tool");
+    assert_eq!(runs[0]["url"], url.as_str());
+    assert_eq!(runs[0]["trigger"], "manual");
+    let job_id = runs[0]["job_id"].as_str().unwrap().to_string();
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/sync-logs/{}", job_id))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let run: serde_json::Value = test::read_body_json(resp).await;
+    let messages: Vec<&str> = run["lines"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|l| l["message"].as_str().unwrap())
+        .collect();
+    assert!(messages.contains(&"Starting sync"));
+    assert!(messages.contains(&"Successfully synced repository"));
+    assert!(!run["finished_at"].is_null());
+
+    let req = test::TestRequest::get()
+        .uri("/api/sync-logs/unknown")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+
+    let req = test::TestRequest::get()
+        .uri("/api/sync-logs?repository_id=other")
+        .to_request();
+    let runs: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+    assert!(runs.is_empty());
+}
@@ -0,0 +1,275 @@
+use gitsafe::config::{MetadataBackup, Repository};
+use gitsafe::encryption::EncryptionKeys;
+use gitsafe::error::AppError;
+use gitsafe::forge::ForgeProvider;
+use gitsafe::git::GitService;
+use gitsafe::metadata::{backup_metadata, wiki_url};
+use mockito::{Matcher, ServerGuard};
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+mod common;
+
+use common::repository;
+
+fn settings(provider: ForgeProvider, server: &ServerGuard) -> MetadataBackup {
+    MetadataBackup {
+        provider,
+        api_url: Some(server.url()),
+        project: Some("acme/tool".to_string()),
+        credential_id: None,
+        release_assets: true,
+        wiki: true,
+    }
+}
+
+/// Mocks a paginated list endpoint returning `body` on the first and nothing on the second page.
+async fn mock_list(server: &mut ServerGuard, path: &str, body: &str) {
+    server
+        .mock("GET", path)
+        .match_query(Matcher::UrlEncoded("page".into(), "1".into()))
+        .with_body(body)
+        .create_async()
+        .await;
+    server
+        .mock("GET", path)
+        .match_query(Matcher::UrlEncoded("page".into(), "2".into()))
+        .with_body("[]")
+        .create_async()
+        .await;
+}
+
+fn read_json(path: &Path) -> serde_json::Value {
+    serde_json::from_slice(&fs::read(path).unwrap()).unwrap()
+}
+
+/// Creates a Git repository with a single commit, standing in for a forge wiki.
+fn create_wiki(path: &Path) {
+    let repo = git2::Repository::init(path).unwrap();
+    fs::write(path.join("Home.md"), "# Welcome").unwrap();
+    let mut index = repo.index().unwrap();
+    index.add_path(Path::new("Home.md")).unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let signature = git2::Signature::now("test", "test@example.com").unwrap();
+    repo.commit(Some("HEAD"), &signature, &signature, "Init", &tree, &[])
+        .unwrap();
+}
+
+#[test]
+fn test_wiki_url() {
+    assert_eq!(
+        wiki_url("https://github.com/acme/tool.git"),
+        "https://github.com/acme/tool.wiki.git"
+    );
+    assert_eq!(
+        wiki_url("git@gitlab.com:acme/tool"),
+        "git@gitlab.com:acme/tool.wiki.git"
+    );
+}
+
+#[tokio::test]
+async fn test_github_metadata_backup() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("GET", "/repos/acme/tool")
+        .with_body(r#"{"full_name": "acme/tool"}"#)
+        .create_async()
+        .await;
+    // The GitHub issues endpoint also returns pull requests
+    mock_list(
+        &mut server,
+        "/repos/acme/tool/issues",
+        r#"[{"number": 1, "title": "Bug"}, {"number": 2, "title": "Fix", "pull_request": {}}]"#,
+    )
+    .await;
+    mock_list(
+        &mut server,
+        "/repos/acme/tool/pulls",
+        r#"[{"number": 2, "title": "Fix"}]"#,
+    )
+    .await;
+    mock_list(
+        &mut server,
+        "/repos/acme/tool/issues/comments",
+        r#"[{"id": 10, "body": "Confirmed"}]"#,
+    )
+    .await;
+    mock_list(&mut server, "/repos/acme/tool/pulls/comments", "[]").await;
+    mock_list(
+        &mut server,
+        "/repos/acme/tool/labels",
+        r#"[{"name": "bug"}]"#,
+    )
+    .await;
+    mock_list(&mut server, "/repos/acme/tool/milestones", "[]").await;
+    let releases = format!(
+        r#"[{{"tag_name": "v1.0", "assets": [{{"name": "tool.tar.gz", "url": "{}/repos/acme/tool/releases/assets/7"}}]}}]"#,
+        server.url()
+    );
+    mock_list(&mut server, "/repos/acme/tool/releases", &releases).await;
+    let asset = server
+        .mock("GET", "/repos/acme/tool/releases/assets/7")
+        .match_header("accept", "application/octet-stream")
+        .match_header("authorization", "Bearer gh-token")
+        .with_body("binary")
+        .expect(1)
+        .create_async()
+        .await;
+
+    let temp_dir = TempDir::new().unwrap();
+    let remote_dir = temp_dir.path().join("remote");
+    create_wiki(&remote_dir.join("tool.wiki.git"));
+    let url = format!("file://{}/tool.git", remote_dir.display());
+
+    let git_service = GitService::new(temp_dir.path().join("archives"), false).unwrap();
+    let settings = settings(ForgeProvider::Github, &server);
+    let repo = Repository {
+        metadata: Some(settings.clone()),
+        ..repository("tool", &url)
+    };
+
+    let result = backup_metadata(
+        &git_service,
+        &repo,
+        &settings,
+        Some("gh-token".to_string()),
+        None,
+        &EncryptionKeys::new("key", &[]),
+        "abc123",
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(result.issues, 1);
+    assert_eq!(result.pull_requests, 1);
+    assert_eq!(result.releases, 1);
+    assert!(result.wiki);
+    assert!(result.path.ends_with("tool.metadata"));
+
+    let manifest = read_json(&result.path.join("manifest.json"));
+    assert_eq!(manifest["commit_hash"], "abc123");
+    assert_eq!(
+        read_json(&result.path.join("issues.json"))[0]["title"],
+        "Bug"
+    );
+    assert_eq!(
+        read_json(&result.path.join("comments.json"))[0]["body"],
+        "Confirmed"
+    );
+    assert_eq!(
+        fs::read(result.path.join("releases/v1.0/tool.tar.gz")).unwrap(),
+        b"binary"
+    );
+    assert!(result.path.join("wiki/Home.md").exists());
+
+    // A second run updates in place and doesn't download assets again
+    backup_metadata(
+        &git_service,
+        &repo,
+        &settings,
+        Some("gh-token".to_string()),
+        None,
+        &EncryptionKeys::new("key", &[]),
+        "def456",
+    )
+    .await
+    .unwrap();
+    asset.assert_async().await;
+    let manifest = read_json(&result.path.join("manifest.json"));
+    assert_eq!(manifest["commit_hash"], "def456");
+}
+
+#[tokio::test]
+async fn test_gitlab_metadata_backup_compact() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("GET", "/projects/acme%2Ftool")
+        .with_body(r#"{"id": 5}"#)
+        .create_async()
+        .await;
+    mock_list(
+        &mut server,
+        "/projects/acme%2Ftool/issues",
+        r#"[{"iid": 3, "title": "Bug", "user_notes_count": 1}]"#,
+    )
+    .await;
+    mock_list(
+        &mut server,
+        "/projects/acme%2Ftool/issues/3/notes",
+        r#"[{"id": 1, "body": "Seen", "noteable_iid": 3}]"#,
+    )
+    .await;
+    for path in ["merge_requests", "labels", "milestones", "releases"] {
+        mock_list(
+            &mut server,
+            &format!("/projects/acme%2Ftool/{}", path),
+            "[]",
+        )
+        .await;
+    }
+
+    let temp_dir = TempDir::new().unwrap();
+    let git_service = GitService::new(temp_dir.path(), true).unwrap();
+    let settings = settings(ForgeProvider::Gitlab, &server);
+    // No wiki exists at this location
+    let url = format!("file://{}/missing/tool.git", temp_dir.path().display());
+    let repo = Repository {
+        metadata: Some(settings.clone()),
+        ..repository("tool", &url)
+    };
+
+    let result = backup_metadata(
+        &git_service,
+        &repo,
+        &settings,
+        None,
+        None,
+        &EncryptionKeys::new("key", &[]),
+        "abc123",
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(result.issues, 1);
+    assert!(!result.wiki);
+    assert!(result.path.ends_with("tool.metadata.tar.gz"));
+    assert!(result.size > 0);
+
+    let unpacked = TempDir::new().unwrap();
+    git_service
+        .unpack_archive(&result.path, unpacked.path())
+        .unwrap();
+    let comments = read_json(&unpacked.path().join("tool.metadata/comments.json"));
+    assert_eq!(comments[0]["body"], "Seen");
+}
+
+#[tokio::test]
+async fn test_metadata_backup_missing_project() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("GET", "/repos/acme/tool")
+        .with_status(404)
+        .create_async()
+        .await;
+
+    let temp_dir = TempDir::new().unwrap();
+    let git_service = GitService::new(temp_dir.path(), false).unwrap();
+    let settings = settings(ForgeProvider::Gitea, &server);
+    let repo = Repository {
+        metadata: Some(settings.clone()),
+        ..repository("tool", "https://gitea.example.com/acme/tool.git")
+    };
+
+    let result = backup_metadata(
+        &git_service,
+        &repo,
+        &settings,
+        None,
+        None,
+        &EncryptionKeys::new("key", &[]),
+        "abc",
+    )
+    .await;
+    assert!(matches!(result, Err(AppError::NotFound(_))));
+}
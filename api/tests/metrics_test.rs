@@ -0,0 +1,137 @@
+use actix_web::middleware::from_fn;
+use actix_web::{test, web, App};
+use chrono::{TimeZone, Utc};
+use gitsafe::config::{Config, MetricsConfig};
+use gitsafe::error::AppError;
+use gitsafe::handlers::metrics;
+use gitsafe::sync::{self, SyncTrigger};
+use tempfile::TempDir;
+
+mod common;
+
+use common::{app_state, repository};
+
+#[actix_web::test]
+async fn test_metrics_disabled_by_default() {
+    let temp_dir = TempDir::new().unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(app_state(Config::default(), temp_dir.path()))
+            .route("/metrics", web::get().to(metrics)),
+    )
+    .await;
+
+    let resp =
+        test::call_service(&app, test::TestRequest::get().uri("/metrics").to_request()).await;
+    assert_eq!(resp.status(), 404);
+}
+
+#[actix_web::test]
+async fn test_metrics_expose_repositories_and_requests() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = Config::default();
+    config.server.metrics = Some(MetricsConfig {
+        bearer_token: Some("scrape-token".to_string()),
+    });
+    let mut healthy = repository("healthy", "https://github.com/acme/healthy.git");
+    healthy.last_sync = Some(Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap());
+    healthy.size = Some(2048);
+    let mut failing = repository("failing", "https://github.com/acme/failing.git");
+    failing.attempts_left = Some(2);
+    failing.enabled = false;
+    config.repositories = vec![healthy, failing];
+
+    let app = test::init_service(
+        App::new()
+            .app_data(app_state(config, temp_dir.path()))
+            .wrap(from_fn(gitsafe::metrics::track_requests))
+            .route("/metrics", web::get().to(metrics)),
+    )
+    .await;
+
+    let resp =
+        test::call_service(&app, test::TestRequest::get().uri("/metrics").to_request()).await;
+    assert_eq!(resp.status(), 401);
+
+    let req = test::TestRequest::get()
+        .uri("/metrics")
+        .insert_header(("Authorization", "Bearer scrape-token"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+
+    assert!(body.contains(
+        "gitsafe_repository_last_success_timestamp_seconds{repository=\"healthy\"} 1704110400"
+    ));
+    assert!(body.contains("gitsafe_repository_size_bytes{repository=\"healthy\"} 2048"));
+    assert!(body.contains("gitsafe_repository_enabled{repository=\"healthy\"} 1"));
+    assert!(body.contains("gitsafe_repository_enabled{repository=\"failing\"} 0"));
+    assert!(body.contains("gitsafe_repository_attempts_left{repository=\"failing\"} 2"));
+    assert!(!body.contains("gitsafe_repository_attempts_left{repository=\"healthy\"}"));
+    // The rejected scrape was recorded by the middleware with its route pattern
+    assert!(body
+        .contains("gitsafe_http_requests_total{method=\"GET\",path=\"/metrics\",status=\"401\"}"));
+}
+
+#[tokio::test]
+async fn test_sync_failures_are_counted() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = Config::default();
+    let missing = temp_dir.path().join("missing.git");
+    config
+        .repositories
+        .push(repository("metrics-missing", &missing.to_string_lossy()));
+    let state = app_state(config, temp_dir.path());
+
+    let result = sync::sync_repository(
+        &state.config,
+        &state.git_service,
+        &state.config_persistence,
+        &state.webhook_service,
+        "metrics-missing",
+        SyncTrigger::Manual,
+    )
+    .await;
+    assert!(result.is_err());
+
+    let body = gitsafe::metrics::render(&*state.config.read().await);
+    assert!(body.contains("gitsafe_sync_failures_total{repository=\"metrics-missing\"} 1"));
+    assert!(body.contains(
+        "gitsafe_sync_duration_seconds_count{repository=\"metrics-missing\",result=\"failure\"} 1"
+    ));
+    assert!(body.contains(
+        "gitsafe_repository_last_attempt_timestamp_seconds{repository=\"metrics-missing\"}"
+    ));
+    assert!(body.contains("gitsafe_repository_attempts_left{repository=\"metrics-missing\"} 4"));
+}
+
+#[tokio::test]
+async fn test_concurrent_sync_is_not_a_failure() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = Config::default();
+    let missing = temp_dir.path().join("missing.git");
+    config
+        .repositories
+        .push(repository("metrics-busy", &missing.to_string_lossy()));
+    let state = app_state(config, temp_dir.path());
+
+    // Another trigger is syncing the repository
+    let _guard = state.git_service.begin_sync("metrics-busy").unwrap();
+    let result = sync::sync_repository(
+        &state.config,
+        &state.git_service,
+        &state.config_persistence,
+        &state.webhook_service,
+        "metrics-busy",
+        SyncTrigger::Schedule,
+    )
+    .await;
+    assert!(matches!(result, Err(AppError::Conflict(_))));
+
+    let config = state.config.read().await;
+    assert_eq!(config.repositories[0].attempts_left, None);
+    assert!(config.repositories[0].error.is_none());
+    let body = gitsafe::metrics::render(&config);
+    assert!(!body.contains("gitsafe_sync_failures_total{repository=\"metrics-busy\"}"));
+}
@@ -0,0 +1,257 @@
+use actix_web::dev::Service;
+use actix_web::http::{Method, StatusCode};
+use actix_web::{test, web, App};
+use gitsafe::config::{Config, ProxyAuthConfig, Role, User};
+use gitsafe::handlers::{list_repositories, login, sync_repository};
+use gitsafe::middleware::AuthMiddleware;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use tempfile::TempDir;
+
+mod common;
+
+use common::app_state;
+
+const PROXY: &str = "10.0.0.2:40000";
+
+fn proxy_auth() -> ProxyAuthConfig {
+    ProxyAuthConfig {
+        trusted_proxies: vec!["10.0.0.0/24".parse().unwrap(), "::1/128".parse().unwrap()],
+        user_header: "Remote-User".to_string(),
+        groups_header: Some("Remote-Groups".to_string()),
+        group_roles: HashMap::from([
+            ("admins".to_string(), Role::Admin),
+            ("devs".to_string(), Role::Operator),
+        ]),
+        default_role: None,
+    }
+}
+
+/// Sends a request with proxy headers from a peer address and returns its status,
+/// including authentication errors of the middleware.
+macro_rules! status {
+    ($app:expr, $method:expr, $uri:expr, $peer:expr, $headers:expr) => {{
+        let mut req = test::TestRequest::default()
+            .method($method)
+            .uri($uri)
+            .peer_addr($peer.parse::<SocketAddr>().unwrap())
+            .set_json(serde_json::json!({ "repository_id": "missing" }));
+        for (name, value) in $headers {
+            req = req.insert_header((name, value));
+        }
+        match $app.call(req.to_request()).await {
+            Ok(resp) => resp.status(),
+            Err(e) => e.as_response_error().status_code(),
+        }
+    }};
+}
+
+#[actix_web::test]
+async fn test_proxy_header_authenticates_trusted_proxies_only() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = Config::default();
+    config.server.proxy_auth = Some(proxy_auth());
+    let state = app_state(config, temp_dir.path());
+    let app = test::init_service(
+        App::new().app_data(state.clone()).service(
+            web::scope("/api")
+                .wrap(AuthMiddleware)
+                .route("/repositories", web::get().to(list_repositories))
+                .route("/sync", web::post().to(sync_repository)),
+        ),
+    )
+    .await;
+    let dev = [("Remote-User", "alice"), ("Remote-Groups", "staff, devs")];
+
+    assert_eq!(
+        status!(app, Method::GET, "/api/repositories", PROXY, dev),
+        StatusCode::OK
+    );
+    // Passes authorization as operator, fails for the missing repository
+    assert_eq!(
+        status!(app, Method::POST, "/api/sync", PROXY, dev),
+        StatusCode::NOT_FOUND
+    );
+    {
+        let config = state.config.read().await;
+        let user = config.users.iter().find(|u| u.username == "alice").unwrap();
+        assert_eq!(user.role, Role::Operator);
+        assert!(user.password_hash.is_empty());
+    }
+
+    // Added and changed users are audited with the user as actor
+    assert_eq!(
+        status!(
+            app,
+            Method::GET,
+            "/api/repositories",
+            PROXY,
+            [("Remote-User", "alice"), ("Remote-Groups", "admins")]
+        ),
+        StatusCode::OK
+    );
+    let entries: Vec<_> = state
+        .audit_log
+        .entries()
+        .into_iter()
+        .filter(|e| e.action.starts_with("user."))
+        .collect();
+    let actions: Vec<&str> = entries.iter().map(|e| e.action.as_str()).collect();
+    assert_eq!(actions, ["user.update", "user.create"]);
+    assert!(entries
+        .iter()
+        .all(|e| e.actor == "alice" && e.target.as_deref() == Some("alice")));
+    assert_eq!(entries[0].changes["role"].before, "operator");
+    assert_eq!(entries[0].changes["role"].after, "admin");
+
+    // IPv4-mapped IPv6 addresses of trusted proxies are trusted
+    assert_eq!(
+        status!(
+            app,
+            Method::GET,
+            "/api/repositories",
+            "[::ffff:10.0.0.2]:40000",
+            dev
+        ),
+        StatusCode::OK
+    );
+
+    // The header is ignored from other addresses
+    assert_eq!(
+        status!(
+            app,
+            Method::GET,
+            "/api/repositories",
+            "192.168.1.5:40000",
+            dev
+        ),
+        StatusCode::UNAUTHORIZED
+    );
+
+    // Users in none of the groups are rejected
+    assert_eq!(
+        status!(
+            app,
+            Method::GET,
+            "/api/repositories",
+            PROXY,
+            [("Remote-User", "mallory"), ("Remote-Groups", "staff")]
+        ),
+        StatusCode::FORBIDDEN
+    );
+
+    // Without the header, the Authorization header is used as usual
+    let token = state
+        .auth_service
+        .generate_token("admin", Role::Admin)
+        .unwrap();
+    assert_eq!(
+        status!(
+            app,
+            Method::GET,
+            "/api/repositories",
+            PROXY,
+            [("Authorization", format!("Bearer {}", token).as_str())]
+        ),
+        StatusCode::OK
+    );
+}
+
+#[actix_web::test]
+async fn test_proxy_header_cannot_take_over_local_users_or_demote_last_admin() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = Config::default();
+    config.users.push(User {
+        username: "root".to_string(),
+        password_hash: String::new(),
+        role: Role::Operator,
+        must_change_password: false,
+        totp: None,
+    });
+    config.server.proxy_auth = Some(proxy_auth());
+    let state = app_state(config, temp_dir.path());
+    let app = test::init_service(
+        App::new().app_data(state.clone()).service(
+            web::scope("/api")
+                .wrap(AuthMiddleware)
+                .route("/repositories", web::get().to(list_repositories)),
+        ),
+    )
+    .await;
+
+    // The local admin has a password, so the proxy can't sign in as it
+    assert_eq!(
+        status!(
+            app,
+            Method::GET,
+            "/api/repositories",
+            PROXY,
+            [("Remote-User", "admin"), ("Remote-Groups", "admins")]
+        ),
+        StatusCode::FORBIDDEN
+    );
+
+    // External users get the role of their groups, but the last admin isn't demoted
+    assert_eq!(
+        status!(
+            app,
+            Method::GET,
+            "/api/repositories",
+            PROXY,
+            [("Remote-User", "root"), ("Remote-Groups", "admins")]
+        ),
+        StatusCode::OK
+    );
+    state
+        .config
+        .write()
+        .await
+        .users
+        .retain(|u| u.username != "admin");
+    assert_eq!(
+        status!(
+            app,
+            Method::GET,
+            "/api/repositories",
+            PROXY,
+            [("Remote-User", "root"), ("Remote-Groups", "devs")]
+        ),
+        StatusCode::FORBIDDEN
+    );
+    let config = state.config.read().await;
+    assert_eq!(config.users[0].username, "root");
+    assert_eq!(config.users[0].role, Role::Admin);
+}
+
+#[actix_web::test]
+async fn test_login_behind_proxy_issues_token_of_proxy_user() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = Config::default();
+    let mut proxy = proxy_auth();
+    proxy.groups_header = None;
+    proxy.default_role = Some(Role::Viewer);
+    config.server.proxy_auth = Some(proxy);
+    let state = app_state(config, temp_dir.path());
+    let app = test::init_service(
+        App::new()
+            .app_data(state.clone())
+            .route("/api/login", web::post().to(login)),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/login")
+        .peer_addr("[::1]:40000".parse().unwrap())
+        .insert_header(("Remote-User", "bob"))
+        .set_json(serde_json::json!({ "username": "admin", "password": "" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    let claims = state
+        .auth_service
+        .verify_token(body["token"].as_str().unwrap())
+        .unwrap();
+    assert_eq!(claims.sub, "bob");
+    assert_eq!(claims.role, Role::Viewer);
+}
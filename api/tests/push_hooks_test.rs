@@ -0,0 +1,174 @@
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+use actix_web::{test, web, App};
+use gitsafe::config::{Config, PushHookConfig};
+use gitsafe::forge::ForgeProvider;
+use gitsafe::handlers::push_hook;
+use gitsafe::push_hooks::{is_push_event, repository_ids, verify_request};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tempfile::TempDir;
+
+mod common;
+
+use common::{app_state, repository};
+
+const SECRET: &str = "hook-secret";
+
+fn sign(body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(SECRET.as_bytes()).unwrap();
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+    let mut map = HeaderMap::new();
+    for (name, value) in pairs {
+        map.insert(
+            HeaderName::from_static(name),
+            HeaderValue::from_str(value).unwrap(),
+        );
+    }
+    map
+}
+
+#[tokio::test]
+async fn test_verify_github_signature() {
+    let body = br#"{"ref": "refs/heads/main"}"#;
+    let valid = headers(&[("x-hub-signature-256", &format!("sha256={}", sign(body)))]);
+    assert!(verify_request(ForgeProvider::Github, &valid, body, SECRET).is_ok());
+
+    let tampered = br#"{"ref": "refs/heads/evil"}"#;
+    assert!(verify_request(ForgeProvider::Github, &valid, tampered, SECRET).is_err());
+    assert!(verify_request(ForgeProvider::Github, &HeaderMap::new(), body, SECRET).is_err());
+}
+
+#[tokio::test]
+async fn test_verify_gitea_and_gitlab() {
+    let body = b"{}";
+    let gitea = headers(&[("x-gitea-signature", &sign(body))]);
+    assert!(verify_request(ForgeProvider::Gitea, &gitea, body, SECRET).is_ok());
+
+    let gitlab = headers(&[("x-gitlab-token", SECRET)]);
+    assert!(verify_request(ForgeProvider::Gitlab, &gitlab, body, SECRET).is_ok());
+    let wrong = headers(&[("x-gitlab-token", "guess")]);
+    assert!(verify_request(ForgeProvider::Gitlab, &wrong, body, SECRET).is_err());
+}
+
+#[tokio::test]
+async fn test_push_event_detection() {
+    assert!(is_push_event(
+        ForgeProvider::Github,
+        &headers(&[("x-github-event", "push")])
+    ));
+    assert!(!is_push_event(
+        ForgeProvider::Github,
+        &headers(&[("x-github-event", "ping")])
+    ));
+    assert!(is_push_event(
+        ForgeProvider::Gitlab,
+        &headers(&[("x-gitlab-event", "Tag Push Hook")])
+    ));
+}
+
+#[tokio::test]
+async fn test_repository_ids_from_gitlab_payload() {
+    let payload = serde_json::json!({
+        "project": {
+            "git_http_url": "https://gitlab.com/team/api.git",
+            "git_ssh_url": "git@gitlab.com:team/api.git",
+            "web_url": "https://gitlab.com/team/api"
+        }
+    });
+
+    assert_eq!(
+        repository_ids(ForgeProvider::Gitlab, &payload),
+        vec!["gitlab_com-team-api".to_string()]
+    );
+}
+
+#[actix_web::test]
+async fn test_push_hook_queues_matching_repository() {
+    let temp_dir = TempDir::new().unwrap();
+
+    let mut config = Config::default();
+    config.server.push_hooks = Some(PushHookConfig {
+        secret: SECRET.to_string(),
+        debounce_seconds: 3600,
+    });
+    config
+        .repositories
+        .push(repository("service", "git@github.com:acme/service.git"));
+
+    // Long debounce: the test must not actually sync
+    let app_state = app_state(config, temp_dir.path());
+
+    let app = test::init_service(
+        App::new()
+            .app_data(app_state.clone())
+            .route("/api/hooks/{provider}", web::post().to(push_hook)),
+    )
+    .await;
+
+    let body = serde_json::to_vec(&serde_json::json!({
+        "ref": "refs/heads/main",
+        "repository": {
+            "clone_url": "https://github.com/acme/service.git",
+            "ssh_url": "git@github.com:acme/service.git"
+        }
+    }))
+    .unwrap();
+
+    let req = test::TestRequest::post()
+        .uri("/api/hooks/github")
+        .insert_header(("X-GitHub-Event", "push"))
+        .insert_header(("X-Hub-Signature-256", format!("sha256={}", sign(&body))))
+        .set_payload(body.clone())
+        .to_request();
+    let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(resp["queued"], serde_json::json!(["service"]));
+
+    // Bad signature
+    let req = test::TestRequest::post()
+        .uri("/api/hooks/github")
+        .insert_header(("X-GitHub-Event", "push"))
+        .insert_header(("X-Hub-Signature-256", "sha256=00"))
+        .set_payload(body)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 401);
+
+    // Unknown repository
+    let body = br#"{"repository": {"clone_url": "https://github.com/acme/other.git"}}"#;
+    let req = test::TestRequest::post()
+        .uri("/api/hooks/github")
+        .insert_header(("X-Hub-Signature-256", format!("sha256={}", sign(body))))
+        .set_payload(body.to_vec())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+
+    // An empty secret disables push hooks instead of accepting everyone's signature
+    app_state
+        .config
+        .write()
+        .await
+        .server
+        .push_hooks
+        .as_mut()
+        .unwrap()
+        .secret = String::new();
+    let body = br#"{"repository": {"clone_url": "https://github.com/acme/service.git"}}"#;
+    let mut mac = Hmac::<Sha256>::new_from_slice(b"").unwrap();
+    mac.update(body);
+    let req = test::TestRequest::post()
+        .uri("/api/hooks/github")
+        .insert_header(("X-GitHub-Event", "push"))
+        .insert_header((
+            "X-Hub-Signature-256",
+            format!("sha256={}", hex::encode(mac.finalize().into_bytes())),
+        ))
+        .set_payload(body.to_vec())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+}
@@ -0,0 +1,216 @@
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::middleware::from_fn;
+use actix_web::{test, web, App};
+use chrono::{Duration as ChronoDuration, Utc};
+use gitsafe::config::{Config, LoginThrottleConfig, Role, User};
+use gitsafe::handlers::{health_live, login};
+use gitsafe::rate_limit::{self, LockoutTarget, RateLimiter};
+use std::net::{IpAddr, SocketAddr};
+use tempfile::TempDir;
+
+mod common;
+
+use common::app_state;
+
+fn ip(addr: &str) -> IpAddr {
+    addr.parse().unwrap()
+}
+
+#[actix_web::test]
+async fn test_request_limit_per_client() {
+    let limiter = RateLimiter::new();
+    let now = Utc::now();
+
+    for _ in 0..3 {
+        assert!(limiter.check_request(ip("10.0.0.1"), 3, now).is_ok());
+    }
+    let err = limiter
+        .check_request(ip("10.0.0.1"), 3, now + ChronoDuration::seconds(20))
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        gitsafe::error::AppError::TooManyRequests(_, 40)
+    ));
+
+    // Other clients have their own limit, and the window ends after a minute
+    assert!(limiter.check_request(ip("10.0.0.2"), 3, now).is_ok());
+    assert!(limiter
+        .check_request(ip("10.0.0.1"), 3, now + ChronoDuration::seconds(60))
+        .is_ok());
+    // 0 disables the limit
+    assert!(limiter.check_request(ip("10.0.0.2"), 0, now).is_ok());
+}
+
+#[actix_web::test]
+async fn test_login_lockout_per_user_and_address() {
+    let limiter = RateLimiter::new();
+    let settings = LoginThrottleConfig {
+        max_failures_per_user: 3,
+        max_failures_per_ip: 5,
+        window_minutes: 15,
+        lockout_minutes: 10,
+    };
+    let now = Utc::now();
+    let attacker = Some(ip("192.0.2.1"));
+
+    assert!(limiter
+        .record_login_failure(&settings, attacker, "alice", now)
+        .is_empty());
+    assert!(limiter
+        .record_login_failure(&settings, attacker, "alice", now)
+        .is_empty());
+    let lockouts = limiter.record_login_failure(&settings, attacker, "alice", now);
+    assert_eq!(lockouts.len(), 1);
+    assert_eq!(lockouts[0].target, LockoutTarget::User("alice".to_string()));
+    assert_eq!(lockouts[0].failures, 3);
+
+    // The user is locked from every address until the lockout ends
+    assert!(limiter
+        .check_login(Some(ip("198.51.100.7")), "alice", now)
+        .is_err());
+    assert!(limiter.check_login(attacker, "bob", now).is_ok());
+    assert!(limiter
+        .check_login(attacker, "alice", now + ChronoDuration::minutes(10))
+        .is_ok());
+
+    // Failures for different users add up for the address
+    limiter.record_login_failure(&settings, attacker, "bob", now);
+    let lockouts = limiter.record_login_failure(&settings, attacker, "carol", now);
+    assert_eq!(lockouts.len(), 1);
+    assert_eq!(lockouts[0].target, LockoutTarget::Address(ip("192.0.2.1")));
+    assert!(limiter.check_login(attacker, "dave", now).is_err());
+    assert!(limiter
+        .check_login(Some(ip("198.51.100.7")), "dave", now)
+        .is_ok());
+}
+
+#[actix_web::test]
+async fn test_login_success_resets_user_failures() {
+    let limiter = RateLimiter::new();
+    let settings = LoginThrottleConfig {
+        max_failures_per_user: 2,
+        ..LoginThrottleConfig::default()
+    };
+    let now = Utc::now();
+
+    limiter.record_login_failure(&settings, None, "alice", now);
+    limiter.record_login_success("alice");
+    assert!(limiter
+        .record_login_failure(&settings, None, "alice", now)
+        .is_empty());
+
+    // Failures outside the window are forgotten
+    assert!(limiter
+        .record_login_failure(&settings, None, "alice", now + ChronoDuration::minutes(15))
+        .is_empty());
+}
+
+#[actix_web::test]
+async fn test_client_ip_behind_trusted_proxy() {
+    let trusted = vec!["10.0.0.0/8".parse().unwrap()];
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        HeaderName::from_static("x-forwarded-for"),
+        HeaderValue::from_static("203.0.113.9, 198.51.100.7, 10.0.0.3"),
+    );
+
+    // The last address not added by a trusted proxy is the client
+    assert_eq!(
+        rate_limit::client_ip(Some(ip("10.0.0.2")), &headers, &trusted),
+        Some(ip("198.51.100.7"))
+    );
+    // The header of untrusted peers is ignored
+    assert_eq!(
+        rate_limit::client_ip(Some(ip("192.0.2.1")), &headers, &trusted),
+        Some(ip("192.0.2.1"))
+    );
+    assert_eq!(
+        rate_limit::client_ip(Some(ip("::ffff:10.0.0.2")), &HeaderMap::new(), &trusted),
+        Some(ip("10.0.0.2"))
+    );
+}
+
+#[actix_web::test]
+async fn test_login_rejected_during_lockout() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = Config {
+        users: vec![User {
+            username: "alice".to_string(),
+            password_hash: bcrypt::hash("correct-password", 4).unwrap(),
+            role: Role::Admin,
+            must_change_password: false,
+            totp: None,
+        }],
+        ..Config::default()
+    };
+    config.server.rate_limit.login.max_failures_per_user = 2;
+    let state = app_state(config, temp_dir.path());
+    let app = test::init_service(
+        App::new()
+            .app_data(state.clone())
+            .route("/api/login", web::post().to(login)),
+    )
+    .await;
+    let peer: SocketAddr = "192.0.2.1:40000".parse().unwrap();
+    let login_with = |password: &str| {
+        test::TestRequest::post()
+            .uri("/api/login")
+            .peer_addr(peer)
+            .set_json(serde_json::json!({ "username": "alice", "password": password }))
+            .to_request()
+    };
+
+    for _ in 0..2 {
+        let resp = test::call_service(&app, login_with("wrong-password")).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    // Even the correct password is rejected until the lockout ends
+    let resp = test::call_service(&app, login_with("correct-password")).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: u64 = resp
+        .headers()
+        .get("Retry-After")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after > 0 && retry_after <= 15 * 60);
+}
+
+#[actix_web::test]
+async fn test_rate_limit_middleware() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = Config::default();
+    config.server.rate_limit.requests_per_minute = 2;
+    let state = app_state(config, temp_dir.path());
+    let app = test::init_service(
+        App::new()
+            .app_data(state.clone())
+            .wrap(from_fn(rate_limit::limit_requests))
+            .route("/health", web::get().to(health_live))
+            .route("/api/health", web::get().to(health_live)),
+    )
+    .await;
+    let peer: SocketAddr = "192.0.2.1:40000".parse().unwrap();
+    let get = |uri: &str| {
+        test::TestRequest::get()
+            .uri(uri)
+            .peer_addr(peer)
+            .to_request()
+    };
+
+    for _ in 0..2 {
+        let resp = test::call_service(&app, get("/api/health")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+    let resp = test::call_service(&app, get("/api/health")).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(resp.headers().contains_key("Retry-After"));
+
+    // Only /api is limited
+    let resp = test::call_service(&app, get("/health")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
@@ -0,0 +1,149 @@
+use actix_web::http::{Method, StatusCode};
+use actix_web::{test, web, App};
+use chrono::Utc;
+use gitsafe::auth::{self};
+use gitsafe::config::{ApiToken, Config, Role, TokenScope, User};
+use gitsafe::handlers::{
+    delete_credential, delete_repository, list_credentials, list_repositories, sync_repository,
+};
+use gitsafe::middleware::AuthMiddleware;
+use tempfile::TempDir;
+
+#[macro_use]
+mod common;
+
+use common::app_state;
+
+fn user(username: &str, role: Role) -> User {
+    User {
+        username: username.to_string(),
+        password_hash: "hash".to_string(),
+        role,
+        must_change_password: false,
+        totp: None,
+    }
+}
+
+#[actix_web::test]
+async fn test_routes_are_restricted_by_role() {
+    let temp_dir = TempDir::new().unwrap();
+    let state = app_state(Config::default(), temp_dir.path());
+    let app = test::init_service(
+        App::new().app_data(state.clone()).service(
+            web::scope("/api")
+                .wrap(AuthMiddleware)
+                .route("/repositories", web::get().to(list_repositories))
+                .route("/repositories/{id}", web::delete().to(delete_repository))
+                .route("/sync", web::post().to(sync_repository))
+                .route("/credentials", web::get().to(list_credentials))
+                .route("/credentials/{id}", web::delete().to(delete_credential)),
+        ),
+    )
+    .await;
+    let viewer = state
+        .auth_service
+        .generate_token("v", Role::Viewer)
+        .unwrap();
+    let operator = state
+        .auth_service
+        .generate_token("o", Role::Operator)
+        .unwrap();
+    let admin = state.auth_service.generate_token("a", Role::Admin).unwrap();
+
+    // Requests passing authorization fail for the missing repository/credential
+    for (method, uri, token, expected) in [
+        (Method::GET, "/api/repositories", &viewer, StatusCode::OK),
+        (Method::POST, "/api/sync", &viewer, StatusCode::FORBIDDEN),
+        (
+            Method::GET,
+            "/api/credentials",
+            &viewer,
+            StatusCode::FORBIDDEN,
+        ),
+        (Method::POST, "/api/sync", &operator, StatusCode::NOT_FOUND),
+        (Method::GET, "/api/credentials", &operator, StatusCode::OK),
+        (
+            Method::DELETE,
+            "/api/repositories/missing",
+            &operator,
+            StatusCode::FORBIDDEN,
+        ),
+        (
+            Method::DELETE,
+            "/api/credentials/missing",
+            &operator,
+            StatusCode::FORBIDDEN,
+        ),
+        (
+            Method::DELETE,
+            "/api/repositories/missing",
+            &admin,
+            StatusCode::NOT_FOUND,
+        ),
+        (
+            Method::DELETE,
+            "/api/credentials/missing",
+            &admin,
+            StatusCode::NOT_FOUND,
+        ),
+    ] {
+        assert_eq!(
+            status!(app, method.clone(), uri, token),
+            expected,
+            "{} {}",
+            method,
+            uri
+        );
+    }
+}
+
+#[actix_web::test]
+async fn test_api_tokens_have_the_role_of_their_user() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = Config::default();
+    config.users.push(user("reader", Role::Viewer));
+    config.api_tokens.push(ApiToken {
+        id: "token-1".to_string(),
+        username: "reader".to_string(),
+        name: "script".to_string(),
+        token_hash: auth::hash_api_token("gs_reader"),
+        scopes: vec![TokenScope::Write],
+        created_at: Utc::now(),
+        expires_at: None,
+        last_used: None,
+    });
+    let state = app_state(config, temp_dir.path());
+    let app = test::init_service(
+        App::new().app_data(state.clone()).service(
+            web::scope("/api")
+                .wrap(AuthMiddleware)
+                .route("/repositories", web::get().to(list_repositories))
+                .route("/sync", web::post().to(sync_repository)),
+        ),
+    )
+    .await;
+
+    assert_eq!(
+        status!(app, Method::GET, "/api/repositories", "gs_reader"),
+        StatusCode::OK
+    );
+    // The write scope doesn't lift the role of the user
+    assert_eq!(
+        status!(app, Method::POST, "/api/sync", "gs_reader"),
+        StatusCode::FORBIDDEN
+    );
+
+    // Promoting the user takes effect for existing tokens
+    state.config.write().await.users[1].role = Role::Operator;
+    assert_eq!(
+        status!(app, Method::POST, "/api/sync", "gs_reader"),
+        StatusCode::NOT_FOUND
+    );
+
+    // Tokens of removed users are rejected
+    state.config.write().await.users.pop();
+    assert_eq!(
+        status!(app, Method::GET, "/api/repositories", "gs_reader"),
+        StatusCode::UNAUTHORIZED
+    );
+}
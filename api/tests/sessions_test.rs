@@ -0,0 +1,161 @@
+use actix_web::http::{Method, StatusCode};
+use actix_web::{test, web, App};
+use chrono::{Duration as ChronoDuration, Utc};
+use gitsafe::auth::AuthService;
+use gitsafe::config::{Config, PreviousJwtSecret, Role, SessionConfig};
+use gitsafe::handlers::{list_repositories, login, logout, refresh_token};
+use gitsafe::middleware::AuthMiddleware;
+use gitsafe::sessions::SessionStore;
+use tempfile::TempDir;
+
+#[macro_use]
+mod common;
+
+use common::app_state;
+
+#[actix_web::test]
+async fn test_refresh_and_logout() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = Config::default();
+    config.server.skip_auth = true;
+    let state = app_state(config, temp_dir.path());
+    let app = test::init_service(
+        App::new()
+            .app_data(state.clone())
+            .route("/api/login", web::post().to(login))
+            .route("/api/token/refresh", web::post().to(refresh_token))
+            .service(
+                web::scope("/api")
+                    .wrap(AuthMiddleware)
+                    .route("/repositories", web::get().to(list_repositories))
+                    .route("/logout", web::post().to(logout)),
+            ),
+    )
+    .await;
+
+    let (status, body) = send!(
+        app,
+        Method::POST,
+        "/api/login",
+        "",
+        serde_json::json!({ "username": "alice", "password": "" })
+    );
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["expires_in"], 15 * 60);
+    let first_refresh = body["refresh_token"].as_str().unwrap().to_string();
+
+    let (status, body) = send!(
+        app,
+        Method::POST,
+        "/api/token/refresh",
+        "",
+        serde_json::json!({ "refresh_token": first_refresh })
+    );
+    assert_eq!(status, StatusCode::OK);
+    let token = body["token"].as_str().unwrap().to_string();
+    let refresh = body["refresh_token"].as_str().unwrap().to_string();
+    assert_ne!(refresh, first_refresh);
+    let (status, _) = send!(
+        app,
+        Method::GET,
+        "/api/repositories",
+        token,
+        serde_json::json!({})
+    );
+    assert_eq!(status, StatusCode::OK);
+
+    // Logging out revokes the access token and the refresh token
+    let (status, _) = send!(
+        app,
+        Method::POST,
+        "/api/logout",
+        token,
+        serde_json::json!({})
+    );
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = send!(
+        app,
+        Method::GET,
+        "/api/repositories",
+        token,
+        serde_json::json!({})
+    );
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = send!(
+        app,
+        Method::POST,
+        "/api/token/refresh",
+        "",
+        serde_json::json!({ "refresh_token": refresh })
+    );
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn test_reused_refresh_token_revokes_session() {
+    let auth_service = AuthService::new("test-secret".to_string());
+    let tokens = auth_service.start_session("alice", Role::Operator).unwrap();
+
+    let refreshed = auth_service.refresh(&tokens.refresh_token, &[]).unwrap();
+    assert_eq!(
+        auth_service.verify_token(&refreshed.token).unwrap().role,
+        Role::Operator
+    );
+
+    // Replaying the first refresh token ends the session for both parties
+    assert!(auth_service.refresh(&tokens.refresh_token, &[]).is_err());
+    assert!(auth_service.verify_token(&refreshed.token).is_err());
+    assert!(auth_service.refresh(&refreshed.refresh_token, &[]).is_err());
+}
+
+#[actix_web::test]
+async fn test_sessions_survive_restarts() {
+    let temp_dir = TempDir::new().unwrap();
+    let auth_service = |store: SessionStore| {
+        AuthService::with_sessions(
+            "test-secret".to_string(),
+            None,
+            SessionConfig::default(),
+            store,
+        )
+    };
+
+    let first = auth_service(SessionStore::load(temp_dir.path()).unwrap());
+    let kept = first.start_session("alice", Role::Admin).unwrap();
+    let revoked = first.start_session("bob", Role::Viewer).unwrap();
+    assert_eq!(first.revoke_user_sessions("bob"), 1);
+
+    let restarted = auth_service(SessionStore::load(temp_dir.path()).unwrap());
+    assert!(restarted.verify_token(&kept.token).is_ok());
+    assert!(restarted.refresh(&kept.refresh_token, &[]).is_ok());
+    assert!(restarted.verify_token(&revoked.token).is_err());
+    assert!(restarted.refresh(&revoked.refresh_token, &[]).is_err());
+}
+
+#[actix_web::test]
+async fn test_previous_jwt_secret_grace_period() {
+    let old_token = AuthService::new("old-secret".to_string())
+        .generate_token("alice", Role::Admin)
+        .unwrap();
+    let rotated = |valid_until| {
+        AuthService::with_sessions(
+            "new-secret".to_string(),
+            Some(PreviousJwtSecret {
+                secret: "old-secret".to_string(),
+                valid_until,
+            }),
+            SessionConfig::default(),
+            SessionStore::in_memory(),
+        )
+    };
+
+    assert!(rotated(Utc::now() + ChronoDuration::hours(1))
+        .verify_token(&old_token)
+        .is_ok());
+    assert!(rotated(Utc::now() - ChronoDuration::seconds(1))
+        .verify_token(&old_token)
+        .is_err());
+    assert!(AuthService::new("new-secret".to_string())
+        .verify_token(&old_token)
+        .is_err());
+}
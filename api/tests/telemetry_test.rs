@@ -0,0 +1,114 @@
+use actix_web::{test, web, App};
+use gitsafe::config::TracingConfig;
+use gitsafe::encryption::EncryptionKeys;
+use gitsafe::git::GitService;
+use gitsafe::handlers::health_live;
+use gitsafe::telemetry;
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+use tracing_actix_web::TracingLogger;
+use tracing_subscriber::layer::SubscriberExt;
+
+mod common;
+
+use common::{create_remote, repository};
+
+/// Starts a fake OTLP collector and returns it with the received export requests.
+async fn collector() -> (mockito::ServerGuard, mockito::Mock, Arc<Mutex<Vec<u8>>>) {
+    let mut server = mockito::Server::new_async().await;
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let captured = Arc::clone(&received);
+    let mock = server
+        .mock("POST", "/v1/traces")
+        .match_header("content-type", "application/x-protobuf")
+        .with_body_from_request(move |request| {
+            captured
+                .lock()
+                .unwrap()
+                .extend_from_slice(request.body().unwrap());
+            Vec::new()
+        })
+        .expect_at_least(1)
+        .create_async()
+        .await;
+    (server, mock, received)
+}
+
+fn tracing_config(server: &mockito::ServerGuard) -> TracingConfig {
+    TracingConfig {
+        endpoint: format!("{}/v1/traces", server.url()),
+        service_name: "gitsafe-test".to_string(),
+        log_spans: false,
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_sync_phases_are_exported() {
+    let (server, mock, received) = collector().await;
+    let provider = telemetry::tracer_provider(&tracing_config(&server)).unwrap();
+    let subscriber = tracing_subscriber::registry().with(telemetry::otel_layer(&provider));
+
+    let temp_dir = TempDir::new().unwrap();
+    let url = create_remote(&temp_dir.path().join("remote/tool"));
+    let service = GitService::new(temp_dir.path().join("archives"), true).unwrap();
+    tracing::subscriber::with_default(subscriber, || {
+        // Initial clone
+        let mut repo = repository("tool", &url);
+        service
+            .sync_repository(&repo, None, &EncryptionKeys::new("key", &[]))
+            .unwrap();
+        // An outdated commit makes the next sync probe, unpack and pull
+        repo.last_sync_commit_hash = Some("0".repeat(40));
+        service
+            .sync_repository(&repo, None, &EncryptionKeys::new("key", &[]))
+            .unwrap();
+    });
+    provider.force_flush().unwrap();
+
+    mock.assert();
+    let body = String::from_utf8_lossy(&received.lock().unwrap()).to_string();
+    for expected in [
+        "gitsafe-test",
+        "sync_repository",
+        "probe_remote",
+        "clone",
+        "unpack",
+        "pull",
+        "archive",
+        "repository",
+        "tool",
+    ] {
+        assert!(body.contains(expected), "missing {} in export", expected);
+    }
+}
+
+#[actix_web::test]
+async fn test_request_spans_are_exported() {
+    let (server, mock, received) = collector().await;
+    let provider = telemetry::tracer_provider(&tracing_config(&server)).unwrap();
+    let subscriber = tracing_subscriber::registry().with(telemetry::otel_layer(&provider));
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let app = test::init_service(
+        App::new()
+            .wrap(TracingLogger::default())
+            .route("/health/live", web::get().to(health_live)),
+    )
+    .await;
+    let req = test::TestRequest::get().uri("/health/live").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    // The request span ends with the response body
+    test::read_body(resp).await;
+
+    // The batch exporter runs on its own thread
+    let provider_for_flush = provider.clone();
+    tokio::task::spawn_blocking(move || provider_for_flush.force_flush())
+        .await
+        .unwrap()
+        .unwrap();
+
+    mock.assert();
+    let body = String::from_utf8_lossy(&received.lock().unwrap()).to_string();
+    assert!(body.contains("GET /health/live"));
+}
@@ -0,0 +1,207 @@
+use actix_web::http::StatusCode;
+use actix_web::{test, web, App, HttpServer};
+use gitsafe::config::{ClientAuthConfig, Config, Role, TlsConfig, User};
+use gitsafe::handlers::list_repositories;
+use gitsafe::middleware::AuthMiddleware;
+use gitsafe::tls::{self, ReloadableCertificate};
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa,
+    KeyPair,
+};
+use std::path::Path;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+mod common;
+
+use common::app_state;
+
+/// Test CA issuing server and client certificates.
+struct TestCa {
+    cert: Certificate,
+    key: KeyPair,
+}
+
+impl TestCa {
+    fn new() -> Self {
+        let key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "GitSafe Test CA");
+        let cert = params.self_signed(&key).unwrap();
+        TestCa { cert, key }
+    }
+
+    /// Issues a certificate, returning its PEM and the PEM of its key.
+    fn issue(&self, common_name: &str, purpose: ExtendedKeyUsagePurpose) -> (String, String) {
+        let key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(vec!["localhost".to_string()]).unwrap();
+        params
+            .distinguished_name
+            .push(DnType::CommonName, common_name);
+        params.extended_key_usages = vec![purpose];
+        let cert = params.signed_by(&key, &self.cert, &self.key).unwrap();
+        (cert.pem(), key.serialize_pem())
+    }
+}
+
+/// Writes a server certificate and key of `ca` into `dir`, returning their paths.
+fn write_server_certificate(ca: &TestCa, dir: &Path) -> (String, String) {
+    let (cert, key) = ca.issue("localhost", ExtendedKeyUsagePurpose::ServerAuth);
+    let cert_path = dir.join("cert.pem");
+    let key_path = dir.join("key.pem");
+    std::fs::write(&cert_path, cert).unwrap();
+    std::fs::write(&key_path, key).unwrap();
+    (
+        cert_path.to_string_lossy().to_string(),
+        key_path.to_string_lossy().to_string(),
+    )
+}
+
+#[actix_web::test]
+async fn test_certificate_reloads_when_files_change() {
+    let temp_dir = TempDir::new().unwrap();
+    let ca = TestCa::new();
+    let (cert_path, key_path) = write_server_certificate(&ca, temp_dir.path());
+
+    let certificate = ReloadableCertificate::load(&cert_path, &key_path).unwrap();
+    let first = certificate.current();
+    assert!(!certificate.reload_if_changed().unwrap());
+
+    // A renewed certificate is picked up
+    write_server_certificate(&ca, temp_dir.path());
+    assert!(certificate.reload_if_changed().unwrap());
+    let second = certificate.current();
+    assert_ne!(first.cert, second.cert);
+
+    // Invalid files keep the previous certificate
+    std::fs::write(&key_path, "not a key").unwrap();
+    assert!(certificate.reload_if_changed().is_err());
+    assert_eq!(certificate.current().cert, second.cert);
+
+    // The key must belong to the certificate
+    let (_, other_key) = ca.issue("other", ExtendedKeyUsagePurpose::ServerAuth);
+    std::fs::write(&key_path, other_key).unwrap();
+    assert!(ReloadableCertificate::load(&cert_path, &key_path).is_err());
+}
+
+#[actix_web::test]
+async fn test_redirect_to_https() {
+    let req = test::TestRequest::get()
+        .uri("/api/repositories?search=api")
+        .insert_header(("Host", "backup.example.com:8080"))
+        .to_http_request();
+    let resp = tls::redirect_to_https(&req, 8443);
+    assert_eq!(resp.status(), StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(
+        resp.headers().get("Location").unwrap(),
+        "https://backup.example.com:8443/api/repositories?search=api"
+    );
+
+    let req = test::TestRequest::get()
+        .uri("/")
+        .insert_header(("Host", "[::1]:80"))
+        .to_http_request();
+    let resp = tls::redirect_to_https(&req, 443);
+    assert_eq!(resp.headers().get("Location").unwrap(), "https://[::1]/");
+}
+
+#[actix_web::test]
+async fn test_client_certificate_authenticates_user() {
+    let temp_dir = TempDir::new().unwrap();
+    let ca = TestCa::new();
+    let (cert_path, key_path) = write_server_certificate(&ca, temp_dir.path());
+    let ca_path = temp_dir.path().join("ca.pem");
+    std::fs::write(&ca_path, ca.cert.pem()).unwrap();
+
+    let tls_settings = TlsConfig {
+        cert_path: cert_path.clone(),
+        key_path: key_path.clone(),
+        reload_interval_seconds: 0,
+        redirect_http_port: None,
+        client_auth: Some(ClientAuthConfig {
+            ca_path: ca_path.to_string_lossy().to_string(),
+            required: false,
+        }),
+    };
+    let mut config = Config {
+        users: vec![User {
+            username: "alice".to_string(),
+            password_hash: String::new(),
+            role: Role::Viewer,
+            must_change_password: false,
+            totp: None,
+        }],
+        ..Config::default()
+    };
+    config.server.tls = Some(tls_settings.clone());
+    let state = app_state(config, temp_dir.path());
+
+    let certificate = Arc::new(ReloadableCertificate::load(&cert_path, &key_path).unwrap());
+    let tls_config = tls::server_config(&tls_settings, certificate).unwrap();
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = HttpServer::new(move || {
+        App::new().app_data(state.clone()).service(
+            web::scope("/api")
+                .wrap(AuthMiddleware)
+                .route("/repositories", web::get().to(list_repositories)),
+        )
+    })
+    .workers(1)
+    .on_connect(tls::store_client_certificate)
+    .listen_rustls_0_23(listener, tls_config)
+    .unwrap()
+    .run();
+    let handle = server.handle();
+    actix_web::rt::spawn(server);
+
+    let url = format!("https://localhost:{}/api/repositories", port);
+    let client = |identity: Option<(String, String)>| {
+        let mut builder = reqwest::Client::builder()
+            .use_rustls_tls()
+            .tls_built_in_root_certs(false)
+            .add_root_certificate(
+                reqwest::Certificate::from_pem(ca.cert.pem().as_bytes()).unwrap(),
+            );
+        if let Some((cert, key)) = identity {
+            builder = builder.identity(
+                reqwest::Identity::from_pem(format!("{}{}", cert, key).as_bytes()).unwrap(),
+            );
+        }
+        builder.build().unwrap()
+    };
+
+    let resp = client(Some(ca.issue("alice", ExtendedKeyUsagePurpose::ClientAuth)))
+        .get(&url)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 200);
+
+    // Without a certificate, or with one of an unknown user, a token is needed
+    let resp = client(None).get(&url).send().await.unwrap();
+    assert_eq!(resp.status().as_u16(), 401);
+    let resp = client(Some(
+        ca.issue("mallory", ExtendedKeyUsagePurpose::ClientAuth),
+    ))
+    .get(&url)
+    .send()
+    .await
+    .unwrap();
+    assert_eq!(resp.status().as_u16(), 401);
+
+    // Certificates of other CAs are rejected in the handshake
+    let other_ca = TestCa::new();
+    let result = client(Some(
+        other_ca.issue("alice", ExtendedKeyUsagePurpose::ClientAuth),
+    ))
+    .get(&url)
+    .send()
+    .await;
+    assert!(result.is_err());
+
+    handle.stop(false).await;
+}
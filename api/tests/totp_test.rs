@@ -0,0 +1,274 @@
+use actix_web::http::StatusCode;
+use actix_web::{test, web, App};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use gitsafe::audit::REDACTED;
+use gitsafe::config::{Config, Role, User};
+use gitsafe::handlers::{confirm_totp, disable_totp, login, start_totp_enrollment, update_user};
+use gitsafe::middleware::AuthMiddleware;
+use gitsafe::totp;
+use tempfile::TempDir;
+
+mod common;
+
+use common::app_state;
+
+fn user(username: &str, role: Role) -> User {
+    User {
+        username: username.to_string(),
+        password_hash: bcrypt::hash("alice-password", 4).unwrap(),
+        role,
+        must_change_password: false,
+        totp: None,
+    }
+}
+
+fn at(timestamp: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp(timestamp, 0).unwrap()
+}
+
+#[actix_web::test]
+async fn test_codes_match_rfc_6238() {
+    // Base32 of the ASCII secret "12345678901234567890" used by the RFC test vectors
+    let secret = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+    assert_eq!(totp::code_at(secret, at(59)).unwrap(), "287082");
+    assert_eq!(totp::code_at(secret, at(1111111109)).unwrap(), "081804");
+    assert_eq!(totp::code_at(secret, at(1234567890)).unwrap(), "005924");
+    assert_eq!(totp::code_at(secret, at(2000000000)).unwrap(), "279037");
+}
+
+#[actix_web::test]
+async fn test_verify_code_allows_drift_and_rejects_reuse() {
+    let secret = totp::generate_secret();
+    let now = at(1_700_000_000);
+    let step = (now.timestamp() / totp::TIME_STEP_SECONDS) as u64;
+
+    let code = totp::code_at(&secret, now).unwrap();
+    assert_eq!(
+        totp::verify_code(&secret, &code, now, None).unwrap(),
+        Some(step)
+    );
+    // Codes of the previous and next step are accepted for clock drift
+    let previous = totp::code_at(&secret, now - ChronoDuration::seconds(30)).unwrap();
+    assert_eq!(
+        totp::verify_code(&secret, &previous, now, None).unwrap(),
+        Some(step - 1)
+    );
+    let old = totp::code_at(&secret, now - ChronoDuration::seconds(90)).unwrap();
+    assert_eq!(totp::verify_code(&secret, &old, now, None).unwrap(), None);
+
+    // A code isn't accepted again, nor is one older than the last used
+    assert_eq!(
+        totp::verify_code(&secret, &code, now, Some(step)).unwrap(),
+        None
+    );
+    assert_eq!(
+        totp::verify_code(&secret, &previous, now, Some(step)).unwrap(),
+        None
+    );
+    assert_eq!(
+        totp::verify_code(&secret, "12345", now, None).unwrap(),
+        None
+    );
+}
+
+#[actix_web::test]
+async fn test_provisioning_uri() {
+    let uri = totp::provisioning_uri("alice", "JBSWY3DPEHPK3PXP");
+    assert!(uri.starts_with("otpauth://totp/GitSafe:alice?"));
+    assert!(uri.contains("secret=JBSWY3DPEHPK3PXP"));
+    assert!(uri.contains("issuer=GitSafe"));
+    assert!(uri.contains("period=30"));
+}
+
+#[actix_web::test]
+async fn test_enrollment_and_login_with_second_factor() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = Config {
+        users: vec![user("alice", Role::Viewer), user("root", Role::Admin)],
+        ..Config::default()
+    };
+    let state = app_state(config, temp_dir.path());
+    let app = test::init_service(
+        App::new()
+            .app_data(state.clone())
+            .route("/api/login", web::post().to(login))
+            .service(
+                web::scope("/api")
+                    .wrap(AuthMiddleware)
+                    .route("/account/totp", web::post().to(start_totp_enrollment))
+                    .route("/account/totp", web::delete().to(disable_totp))
+                    .route("/account/totp/confirm", web::post().to(confirm_totp))
+                    .route("/users/{username}", web::patch().to(update_user)),
+            ),
+    )
+    .await;
+    let token = state
+        .auth_service
+        .generate_token("alice", Role::Viewer)
+        .unwrap();
+    let login_with = |code: Option<&str>| {
+        test::TestRequest::post()
+            .uri("/api/login")
+            .set_json(serde_json::json!({
+                "username": "alice",
+                "password": "alice-password",
+                "totp_code": code,
+            }))
+            .to_request()
+    };
+
+    // Enrollment needs the password
+    let req = test::TestRequest::post()
+        .uri("/api/account/totp")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .set_json(serde_json::json!({ "current_password": "wrong-password" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let req = test::TestRequest::post()
+        .uri("/api/account/totp")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .set_json(serde_json::json!({ "current_password": "alice-password" }))
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let secret = body["secret"].as_str().unwrap().to_string();
+    assert!(body["provisioning_uri"]
+        .as_str()
+        .unwrap()
+        .contains(&format!("secret={}", secret)));
+    {
+        // The secret is stored encrypted
+        let config = state.config.read().await;
+        let stored = config.users[0].totp.as_ref().unwrap();
+        assert_ne!(stored.secret, secret);
+        assert_eq!(
+            config
+                .server
+                .encryption_keys()
+                .decrypt(&stored.secret)
+                .unwrap(),
+            secret
+        );
+    }
+
+    // Starting the enrollment is audited without the secret
+    let entries = state.audit_log.entries();
+    let started = entries
+        .iter()
+        .find(|e| e.action == "user.start_totp")
+        .unwrap();
+    assert_eq!(started.target.as_deref(), Some("alice"));
+    assert_eq!(started.changes["totp"].after, REDACTED);
+
+    // Until the enrollment is confirmed, logins don't need a code
+    let resp = test::call_service(&app, login_with(None)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let now = Utc::now();
+    let req = test::TestRequest::post()
+        .uri("/api/account/totp/confirm")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .set_json(serde_json::json!({ "code": totp::code_at(&secret, now).unwrap() }))
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let recovery_codes: Vec<String> =
+        serde_json::from_value(body["recovery_codes"].clone()).unwrap();
+    assert_eq!(recovery_codes.len(), totp::RECOVERY_CODE_COUNT);
+
+    let resp = test::call_service(&app, login_with(None)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp = test::call_service(&app, login_with(Some("000000"))).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let code = totp::code_at(&secret, now + ChronoDuration::seconds(30)).unwrap();
+    let resp = test::call_service(&app, login_with(Some(&code))).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    // The same code can't be used twice
+    let resp = test::call_service(&app, login_with(Some(&code))).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    // Recovery codes work once, regardless of case and dashes
+    let recovery_code = recovery_codes[0].replace('-', "").to_uppercase();
+    let resp = test::call_service(&app, login_with(Some(&recovery_code))).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let resp = test::call_service(&app, login_with(Some(&recovery_codes[0]))).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    // Admins can remove the second factor of a user
+    let admin = state
+        .auth_service
+        .generate_token("root", Role::Admin)
+        .unwrap();
+    let req = test::TestRequest::patch()
+        .uri("/api/users/alice")
+        .insert_header(("Authorization", format!("Bearer {}", admin)))
+        .set_json(serde_json::json!({ "disable_totp": true }))
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["totp_enabled"], false);
+    let resp = test::call_service(&app, login_with(None)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn test_disable_totp_needs_password_and_code() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = Config {
+        users: vec![user("alice", Role::Viewer)],
+        ..Config::default()
+    };
+    let state = app_state(config, temp_dir.path());
+    let (secret, recovery_codes) = {
+        let mut config = state.config.write().await;
+        let keys = config.server.encryption_keys();
+        let (mut user_totp, secret) = totp::enroll(&keys).unwrap();
+        let recovery_codes = totp::confirm(
+            &mut user_totp,
+            &totp::code_at(&secret, Utc::now()).unwrap(),
+            &keys,
+            Utc::now(),
+        )
+        .unwrap();
+        config.users[0].totp = Some(user_totp);
+        (secret, recovery_codes)
+    };
+    let app = test::init_service(
+        App::new().app_data(state.clone()).service(
+            web::scope("/api")
+                .wrap(AuthMiddleware)
+                .route("/account/totp", web::delete().to(disable_totp)),
+        ),
+    )
+    .await;
+    let token = state
+        .auth_service
+        .generate_token("alice", Role::Viewer)
+        .unwrap();
+    let disable_with = |password: &str, code: Option<&str>| {
+        test::TestRequest::delete()
+            .uri("/api/account/totp")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .set_json(serde_json::json!({ "current_password": password, "code": code }))
+            .to_request()
+    };
+
+    let code = totp::code_at(&secret, Utc::now() + ChronoDuration::seconds(30)).unwrap();
+    let resp = test::call_service(&app, disable_with("wrong-password", Some(&code))).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    // The password alone isn't enough
+    let resp = test::call_service(&app, disable_with("alice-password", None)).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let resp = test::call_service(&app, disable_with("alice-password", Some("000000"))).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert!(state.config.read().await.users[0].has_totp());
+
+    // A recovery code works in place of a one-time password
+    let resp = test::call_service(
+        &app,
+        disable_with("alice-password", Some(&recovery_codes[0])),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    assert!(state.config.read().await.users[0].totp.is_none());
+}
@@ -0,0 +1,241 @@
+use actix_web::http::{Method, StatusCode};
+use actix_web::{test, web, App};
+use chrono::Utc;
+use gitsafe::auth::{self};
+use gitsafe::config::{ApiToken, Config, Role, TokenScope};
+use gitsafe::handlers::{
+    add_user, change_password, delete_user, list_repositories, list_users, login, update_user,
+};
+use gitsafe::middleware::AuthMiddleware;
+use tempfile::TempDir;
+
+#[macro_use]
+mod common;
+
+use common::app_state;
+
+macro_rules! init_app {
+    ($state:expr) => {
+        test::init_service(
+            App::new()
+                .app_data($state.clone())
+                .route("/api/login", web::post().to(login))
+                .service(
+                    web::scope("/api")
+                        .wrap(AuthMiddleware)
+                        .route("/repositories", web::get().to(list_repositories))
+                        .route("/account/password", web::post().to(change_password))
+                        .route("/users", web::get().to(list_users))
+                        .route("/users", web::post().to(add_user))
+                        .route("/users/{username}", web::patch().to(update_user))
+                        .route("/users/{username}", web::delete().to(delete_user)),
+                ),
+        )
+        .await
+    };
+}
+
+#[actix_web::test]
+async fn test_default_credentials_force_password_change() {
+    let temp_dir = TempDir::new().unwrap();
+    let state = app_state(Config::default(), temp_dir.path());
+    let app = init_app!(state);
+
+    let (status, body) = send!(
+        app,
+        Method::POST,
+        "/api/login",
+        "",
+        serde_json::json!({ "username": "admin", "password": "admin" })
+    );
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["password_change_required"], true);
+    let restricted = body["token"].as_str().unwrap().to_string();
+
+    // The restricted token only allows changing the password
+    let (status, _) = send!(
+        app,
+        Method::GET,
+        "/api/repositories",
+        restricted,
+        serde_json::json!({})
+    );
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    for (current, new) in [("wrong-password", "new-password"), ("admin", "short")] {
+        let (status, _) = send!(
+            app,
+            Method::POST,
+            "/api/account/password",
+            restricted,
+            serde_json::json!({ "current_password": current, "new_password": new })
+        );
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    let (status, body) = send!(
+        app,
+        Method::POST,
+        "/api/account/password",
+        restricted,
+        serde_json::json!({ "current_password": "admin", "new_password": "new-password" })
+    );
+    assert_eq!(status, StatusCode::OK);
+    let token = body["token"].as_str().unwrap().to_string();
+    let (status, _) = send!(
+        app,
+        Method::GET,
+        "/api/repositories",
+        token,
+        serde_json::json!({})
+    );
+    assert_eq!(status, StatusCode::OK);
+    assert!(!state.config.read().await.users[0].must_change_password);
+
+    // The old password no longer works, the new one isn't restricted
+    let (status, _) = send!(
+        app,
+        Method::POST,
+        "/api/login",
+        "",
+        serde_json::json!({ "username": "admin", "password": "admin" })
+    );
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, body) = send!(
+        app,
+        Method::POST,
+        "/api/login",
+        "",
+        serde_json::json!({ "username": "admin", "password": "new-password" })
+    );
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.get("password_change_required").is_none());
+}
+
+#[actix_web::test]
+async fn test_manage_users() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = Config::default();
+    config.users[0].must_change_password = false;
+    let state = app_state(config, temp_dir.path());
+    let admin = state
+        .auth_service
+        .generate_token("admin", Role::Admin)
+        .unwrap();
+    let app = init_app!(state);
+
+    let (status, body) = send!(
+        app,
+        Method::POST,
+        "/api/users",
+        admin,
+        serde_json::json!({ "username": "alice", "password": "alice-password" })
+    );
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(body["role"], "viewer");
+    let (status, _) = send!(
+        app,
+        Method::POST,
+        "/api/users",
+        admin,
+        serde_json::json!({ "username": "alice", "password": "other-password" })
+    );
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, body) = send!(app, Method::GET, "/api/users", admin, serde_json::json!({}));
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.as_array().unwrap().len(), 2);
+    assert!(body[1].get("password_hash").is_none());
+
+    // The new user logs in with the role set by the admin
+    let (status, _) = send!(
+        app,
+        Method::PATCH,
+        "/api/users/alice",
+        admin,
+        serde_json::json!({ "role": "operator" })
+    );
+    assert_eq!(status, StatusCode::OK);
+    let (status, body) = send!(
+        app,
+        Method::POST,
+        "/api/login",
+        "",
+        serde_json::json!({ "username": "alice", "password": "alice-password" })
+    );
+    assert_eq!(status, StatusCode::OK);
+    let alice = body["token"].as_str().unwrap().to_string();
+    let claims = state.auth_service.verify_token(&alice).unwrap();
+    assert_eq!(claims.role, Role::Operator);
+
+    // Only admins manage users
+    let (status, _) = send!(app, Method::GET, "/api/users", alice, serde_json::json!({}));
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // Changing the role ends the sessions issued with the old one
+    let (status, _) = send!(
+        app,
+        Method::GET,
+        "/api/repositories",
+        alice,
+        serde_json::json!({})
+    );
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send!(
+        app,
+        Method::PATCH,
+        "/api/users/alice",
+        admin,
+        serde_json::json!({ "role": "viewer" })
+    );
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send!(
+        app,
+        Method::GET,
+        "/api/repositories",
+        alice,
+        serde_json::json!({})
+    );
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // The last admin can't be demoted or deleted
+    let (status, _) = send!(
+        app,
+        Method::PATCH,
+        "/api/users/admin",
+        admin,
+        serde_json::json!({ "role": "viewer" })
+    );
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send!(
+        app,
+        Method::DELETE,
+        "/api/users/admin",
+        admin,
+        serde_json::json!({})
+    );
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Deleting a user revokes the user's API tokens
+    state.config.write().await.api_tokens.push(ApiToken {
+        id: "token-1".to_string(),
+        username: "alice".to_string(),
+        name: "script".to_string(),
+        token_hash: auth::hash_api_token("gs_alice"),
+        scopes: vec![TokenScope::Read],
+        created_at: Utc::now(),
+        expires_at: None,
+        last_used: None,
+    });
+    let (status, _) = send!(
+        app,
+        Method::DELETE,
+        "/api/users/alice",
+        admin,
+        serde_json::json!({})
+    );
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let config = state.config.read().await;
+    assert_eq!(config.users.len(), 1);
+    assert!(config.api_tokens.is_empty());
+}
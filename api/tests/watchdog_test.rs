@@ -0,0 +1,136 @@
+use actix_web::{test, web, App};
+use chrono::{Duration, Utc};
+use gitsafe::config::{
+    Config, NotificationEvent, Subscription, UpstreamStatus, Webhook, WebhookDeliveryConfig,
+};
+use gitsafe::handlers::health_check;
+use gitsafe::watchdog::Watchdog;
+use gitsafe::webhooks::WebhookService;
+use mockito::Matcher;
+use std::sync::Arc;
+use tempfile::TempDir;
+use tokio::sync::RwLock;
+
+mod common;
+
+use common::repository;
+
+#[tokio::test]
+async fn test_watchdog_alerts_once_per_stale_backup() {
+    let mut server = mockito::Server::new_async().await;
+    let mock = server
+        .mock("POST", "/events")
+        .match_body(Matcher::PartialJson(serde_json::json!({
+            "event": "backup_stale",
+            "repo": {"id": "stale"},
+            "details": {"max_backup_age_hours": 24},
+        })))
+        .expect(1)
+        .create_async()
+        .await;
+
+    let now = Utc::now();
+    let mut config = Config::default();
+    config.scheduler.max_backup_age_hours = Some(24);
+    config.server.notifications.push(Subscription {
+        webhook: Webhook::new(format!("{}/events", server.url())),
+        events: vec![NotificationEvent::BackupStale],
+        repositories: Vec::new(),
+        tags: Vec::new(),
+        exclude_repositories: Vec::new(),
+        exclude_tags: Vec::new(),
+    });
+    let mut fresh = repository("fresh", "https://github.com/acme/fresh.git");
+    fresh.last_sync = Some(now - Duration::hours(2));
+    let mut stale = repository("stale", "https://github.com/acme/stale.git");
+    stale.last_sync = Some(now - Duration::hours(30));
+    // The repository limit overrides the global one
+    let mut relaxed = repository("relaxed", "https://github.com/acme/relaxed.git");
+    relaxed.last_sync = Some(now - Duration::hours(30));
+    relaxed.max_backup_age_hours = Some(72);
+    let mut disabled = repository("disabled", "https://github.com/acme/disabled.git");
+    disabled.enabled = false;
+    let mut deleted = repository("deleted", "https://github.com/acme/deleted.git");
+    deleted.upstream_status = Some(UpstreamStatus::Deleted);
+    config.repositories = vec![
+        fresh,
+        stale,
+        relaxed,
+        disabled,
+        deleted,
+        repository("never-synced", "https://github.com/acme/never-synced.git"),
+    ];
+    let config = Arc::new(RwLock::new(config));
+
+    let temp_dir = TempDir::new().unwrap();
+    let webhook_service =
+        WebhookService::new(WebhookDeliveryConfig::default(), temp_dir.path()).unwrap();
+    let watchdog = Watchdog::new();
+
+    let stale: Vec<_> = watchdog
+        .check(&config, &webhook_service, now)
+        .await
+        .into_iter()
+        .map(|s| s.id)
+        .collect();
+    assert_eq!(stale, ["stale"]);
+
+    // Repositories that never synced are measured from when the watchdog first saw them
+    let later = now + Duration::hours(25);
+    config.write().await.repositories[0].last_sync = Some(later);
+    let stale: Vec<_> = watchdog
+        .check(&config, &webhook_service, later)
+        .await
+        .into_iter()
+        .map(|s| s.id)
+        .collect();
+    assert_eq!(stale, ["stale", "never-synced"]);
+
+    // A successful sync clears the alert
+    config.write().await.repositories[1].last_sync = Some(later);
+    let stale = watchdog.check(&config, &webhook_service, later).await;
+    assert_eq!(stale.len(), 1);
+    assert_eq!(watchdog.stale(), stale);
+
+    // Wait for the deliveries running in the background
+    for _ in 0..100 {
+        if webhook_service.deliveries().len() == 2 && mock.matched_async().await {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    mock.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_health_reports_stale_backups() {
+    let mut config = Config::default();
+    let mut repo = repository("tool", "https://github.com/acme/tool.git");
+    repo.max_backup_age_hours = Some(1);
+    repo.last_sync = Some(Utc::now() - Duration::hours(2));
+    config.repositories.push(repo);
+    let config = Arc::new(RwLock::new(config));
+
+    let temp_dir = TempDir::new().unwrap();
+    let webhook_service =
+        WebhookService::new(WebhookDeliveryConfig::default(), temp_dir.path()).unwrap();
+    let watchdog = Watchdog::new();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(watchdog.clone()))
+            .route("/health", web::get().to(health_check)),
+    )
+    .await;
+
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/health").to_request()).await;
+    assert!(resp.status().is_success());
+
+    watchdog.check(&config, &webhook_service, Utc::now()).await;
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/health").to_request()).await;
+    // Still 200, so orchestrators don't restart a server whose remotes are down
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["status"], "degraded");
+    assert_eq!(body["stale_backups"][0]["id"], "tool");
+    assert_eq!(body["stale_backups"][0]["max_backup_age_hours"], 1);
+}
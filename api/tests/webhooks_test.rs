@@ -1,6 +1,41 @@
 use chrono::Utc;
-use gitsafe::config::Repository;
-use gitsafe::webhooks::{ErrorWebhookPayload, RepoInfo};
+use gitsafe::config::{
+    Config, NotificationEvent, Repository, Subscription, Webhook, WebhookDeliveryConfig,
+    WebhookFormat,
+};
+use gitsafe::git::GitService;
+use gitsafe::sync;
+use gitsafe::webhooks::{
+    self, sign_payload, DeliveryStatus, ErrorWebhookPayload, RepoInfo, WebhookDelivery,
+    WebhookService,
+};
+use mockito::Matcher;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::sync::RwLock;
+
+fn delivery_settings() -> WebhookDeliveryConfig {
+    WebhookDeliveryConfig {
+        max_attempts: 3,
+        // No waiting between attempts in tests
+        initial_backoff_seconds: 0,
+        ..WebhookDeliveryConfig::default()
+    }
+}
+
+/// Waits until a delivery is no longer pending.
+async fn wait_for_delivery(service: &WebhookService, id: &str) -> WebhookDelivery {
+    for _ in 0..100 {
+        if let Some(delivery) = service.delivery(id) {
+            if delivery.status != DeliveryStatus::Pending {
+                return delivery;
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("delivery {} did not finish", id);
+}
 
 #[test]
 fn test_error_webhook_payload_serialization() {
@@ -15,6 +50,11 @@ fn test_error_webhook_payload_serialization() {
         error: None,
         size: Some(1024),
         attempts_left: None,
+        source_id: None,
+        upstream_status: None,
+        metadata: None,
+        tags: Vec::new(),
+        max_backup_age_hours: None,
     };
 
     let payload = ErrorWebhookPayload {
@@ -54,6 +94,11 @@ fn test_error_webhook_payload_without_credential() {
         error: None,
         size: None,
         attempts_left: None,
+        source_id: None,
+        upstream_status: None,
+        metadata: None,
+        tags: Vec::new(),
+        max_backup_age_hours: None,
     };
 
     let payload = ErrorWebhookPayload {
@@ -108,8 +153,598 @@ async fn test_notify_error_webhooks_empty_list() {
         error: None,
         size: None,
         attempts_left: None,
+        source_id: None,
+        upstream_status: None,
+        metadata: None,
+        tags: Vec::new(),
+        max_backup_age_hours: None,
     };
 
+    let temp_dir = TempDir::new().unwrap();
+    let service = WebhookService::new(delivery_settings(), temp_dir.path()).unwrap();
+
     // Should not panic or error with empty webhook list
-    gitsafe::webhooks::notify_error_webhooks(&[], &repo, "sync", None, "test error").await;
+    gitsafe::webhooks::notify_error_webhooks(&service, &[], &repo, "sync", None, "test error");
+    assert!(service.deliveries().is_empty());
+}
+
+#[tokio::test]
+async fn test_webhook_delivery_retries_until_success() {
+    let mut server = mockito::Server::new_async().await;
+    let unavailable = server
+        .mock("POST", "/hook")
+        .with_status(503)
+        .with_body("restarting")
+        .expect(2)
+        .create_async()
+        .await;
+    let ok = server
+        .mock("POST", "/hook")
+        .with_status(200)
+        .with_body("thanks")
+        .expect(1)
+        .create_async()
+        .await;
+
+    let temp_dir = TempDir::new().unwrap();
+    let service = WebhookService::new(delivery_settings(), temp_dir.path()).unwrap();
+    let id = service.send(
+        &Webhook::new(format!("{}/hook", server.url())),
+        "error",
+        &serde_json::json!({"error_message": "boom"}),
+    );
+    let delivery = wait_for_delivery(&service, &id).await;
+
+    unavailable.assert_async().await;
+    ok.assert_async().await;
+    assert_eq!(delivery.status, DeliveryStatus::Delivered);
+    assert_eq!(delivery.attempts, 3);
+    assert_eq!(delivery.status_code, Some(200));
+    assert_eq!(delivery.response.as_deref(), Some("thanks"));
+    assert!(delivery.latency_ms.is_some());
+}
+
+#[tokio::test]
+async fn test_webhook_delivery_gives_up() {
+    let mut server = mockito::Server::new_async().await;
+    let rejected = server
+        .mock("POST", "/rejected")
+        .with_status(400)
+        .expect(1)
+        .create_async()
+        .await;
+    server
+        .mock("POST", "/down")
+        .with_status(500)
+        .expect(3)
+        .create_async()
+        .await;
+
+    let temp_dir = TempDir::new().unwrap();
+    let service = WebhookService::new(delivery_settings(), temp_dir.path()).unwrap();
+
+    // Client errors are not retried
+    let id = service.send(
+        &Webhook::new(format!("{}/rejected", server.url())),
+        "error",
+        &"x",
+    );
+    let delivery = wait_for_delivery(&service, &id).await;
+    rejected.assert_async().await;
+    assert_eq!(delivery.status, DeliveryStatus::Failed);
+    assert_eq!(delivery.attempts, 1);
+
+    let id = service.send(
+        &Webhook::new(format!("{}/down", server.url())),
+        "error",
+        &"x",
+    );
+    let delivery = wait_for_delivery(&service, &id).await;
+    assert_eq!(delivery.status, DeliveryStatus::Failed);
+    assert_eq!(delivery.attempts, 3);
+    assert_eq!(delivery.status_code, Some(500));
+}
+
+#[tokio::test]
+async fn test_webhook_delivery_log_persists_and_redelivers() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("POST", "/hook")
+        .with_status(500)
+        .create_async()
+        .await;
+
+    let temp_dir = TempDir::new().unwrap();
+    let webhook = Webhook::new(format!("{}/hook", server.url()));
+    let id = {
+        let service = WebhookService::new(delivery_settings(), temp_dir.path()).unwrap();
+        let id = service.send(&webhook, "error", &serde_json::json!({"n": 1}));
+        wait_for_delivery(&service, &id).await;
+        id
+    };
+
+    // The log survives a restart
+    let service = WebhookService::new(delivery_settings(), temp_dir.path()).unwrap();
+    let deliveries = service.deliveries();
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0].id, id);
+    assert_eq!(deliveries[0].status, DeliveryStatus::Failed);
+
+    // The receiver is back
+    server.reset();
+    server
+        .mock("POST", "/hook")
+        .match_body(mockito::Matcher::Json(serde_json::json!({"n": 1})))
+        .with_status(204)
+        .create_async()
+        .await;
+
+    let redelivery = service
+        .redeliver(&id, std::slice::from_ref(&webhook))
+        .unwrap();
+    assert_eq!(redelivery.redelivery_of.as_deref(), Some(id.as_str()));
+    let redelivery = wait_for_delivery(&service, &redelivery.id).await;
+    assert_eq!(redelivery.status, DeliveryStatus::Delivered);
+    assert_eq!(service.deliveries().len(), 2);
+
+    assert!(service.redeliver("unknown", &[webhook]).is_err());
+    // The receiver was removed from the configuration
+    assert!(service.redeliver(&id, &[]).is_err());
+}
+
+#[tokio::test]
+async fn test_pending_deliveries_fail_after_restart() {
+    let temp_dir = TempDir::new().unwrap();
+    let now = Utc::now();
+    let pending = serde_json::json!({
+        "id": "interrupted",
+        "url": "https://example.com/hook",
+        "event": "error",
+        "payload": {"n": 1},
+        "status": "pending",
+        "attempts": 1,
+        "created_at": now,
+        "updated_at": now,
+    });
+    std::fs::write(
+        temp_dir.path().join("webhook_deliveries.jsonl"),
+        format!("{}\n", pending),
+    )
+    .unwrap();
+
+    let service = WebhookService::new(delivery_settings(), temp_dir.path()).unwrap();
+    let delivery = service.delivery("interrupted").unwrap();
+    assert_eq!(delivery.status, DeliveryStatus::Failed);
+    assert!(delivery.error.unwrap().contains("restart"));
+
+    // The new status is persisted
+    let service = WebhookService::new(delivery_settings(), temp_dir.path()).unwrap();
+    assert_eq!(
+        service.delivery("interrupted").unwrap().status,
+        DeliveryStatus::Failed
+    );
+}
+
+#[tokio::test]
+async fn test_signed_webhook_with_custom_headers() {
+    let mut server = mockito::Server::new_async().await;
+    let body = br#"{"error_message":"boom"}"#;
+    let signature = Arc::new(Mutex::new(None));
+    let captured = Arc::clone(&signature);
+    let mock = server
+        .mock("POST", "/signed")
+        .match_header("authorization", "Bearer receiver-token")
+        .match_header("content-type", "application/json")
+        .match_request(move |request| {
+            let header = |name: &str| {
+                request
+                    .header(name)
+                    .first()
+                    .and_then(|v| v.to_str().ok())
+                    .map(str::to_string)
+            };
+            *captured.lock().unwrap() = header("x-gitsafe-timestamp")
+                .zip(header("x-gitsafe-signature"))
+                .zip(request.utf8_lossy_body().ok().map(|body| body.into_owned()));
+            true
+        })
+        .with_status(200)
+        .create_async()
+        .await;
+
+    let temp_dir = TempDir::new().unwrap();
+    let service = WebhookService::new(delivery_settings(), temp_dir.path()).unwrap();
+    let mut webhook = Webhook::new(format!("{}/signed", server.url()));
+    webhook.secret = Some("shared-secret".to_string());
+    webhook.headers.insert(
+        "Authorization".to_string(),
+        "Bearer receiver-token".to_string(),
+    );
+
+    let id = service.send(
+        &webhook,
+        "error",
+        &serde_json::json!({"error_message": "boom"}),
+    );
+    let delivery = wait_for_delivery(&service, &id).await;
+    mock.assert_async().await;
+    assert_eq!(delivery.status, DeliveryStatus::Delivered);
+
+    let ((timestamp, signature), received_body) = signature.lock().unwrap().clone().unwrap();
+    assert_eq!(received_body.as_bytes(), body);
+    let timestamp: i64 = timestamp.parse().unwrap();
+    assert!((Utc::now().timestamp() - timestamp).abs() < 60);
+    assert_eq!(
+        signature,
+        format!("sha256={}", sign_payload("shared-secret", timestamp, body))
+    );
+}
+
+fn error_payload() -> ErrorWebhookPayload {
+    ErrorWebhookPayload {
+        time: "2024-01-01T12:00:00+00:00".to_string(),
+        repo: RepoInfo {
+            id: "github_com-acme-tool".to_string(),
+            url: "https://github.com/acme/tool.git".to_string(),
+            enabled: true,
+        },
+        operation: "sync".to_string(),
+        credential_id: None,
+        error_message: "authentication failed".to_string(),
+    }
+}
+
+#[tokio::test]
+async fn test_chat_webhook_formats() {
+    let mut server = mockito::Server::new_async().await;
+    let slack = server
+        .mock("POST", "/slack")
+        .match_body(Matcher::PartialJson(serde_json::json!({
+            "text": "GitSafe: sync failed for github_com-acme-tool",
+            "blocks": [
+                {"type": "header"},
+                {"type": "section", "text": {"text": "```authentication failed```"}},
+            ],
+        })))
+        .create_async()
+        .await;
+    let discord = server
+        .mock("POST", "/discord")
+        .match_body(Matcher::PartialJson(serde_json::json!({
+            "embeds": [{
+                "description": "authentication failed",
+                "fields": [{"name": "Repository", "value": "https://github.com/acme/tool.git"}],
+                "timestamp": "2024-01-01T12:00:00+00:00",
+            }],
+        })))
+        .create_async()
+        .await;
+    let teams = server
+        .mock("POST", "/teams")
+        .match_body(Matcher::PartialJson(serde_json::json!({
+            "type": "message",
+            "attachments": [{"contentType": "application/vnd.microsoft.card.adaptive"}],
+        })))
+        .create_async()
+        .await;
+
+    let temp_dir = TempDir::new().unwrap();
+    let service = WebhookService::new(delivery_settings(), temp_dir.path()).unwrap();
+    for (path, format) in [
+        ("slack", WebhookFormat::Slack),
+        ("discord", WebhookFormat::Discord),
+        ("teams", WebhookFormat::Teams),
+    ] {
+        let mut webhook = Webhook::new(format!("{}/{}", server.url(), path));
+        webhook.format = format;
+        let id = service.send(&webhook, "error", &error_payload());
+        let delivery = wait_for_delivery(&service, &id).await;
+        assert_eq!(delivery.status, DeliveryStatus::Delivered, "{}", path);
+        // The log keeps the generic payload
+        assert_eq!(delivery.payload["error_message"], "authentication failed");
+    }
+
+    slack.assert_async().await;
+    discord.assert_async().await;
+    teams.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_push_webhook_formats() {
+    let mut server = mockito::Server::new_async().await;
+    let ntfy = server
+        .mock("POST", "/gitsafe-alerts")
+        .match_header("title", "GitSafe: github_com-acme-tool was disabled")
+        .match_header("priority", "5")
+        .match_header("tags", "rotating_light")
+        .match_body(Matcher::Regex(
+            "^Ran out of sync attempts.*\nRepository: https://github.com/acme/tool.git".into(),
+        ))
+        .create_async()
+        .await;
+    let gotify = server
+        .mock("POST", "/message")
+        .match_query(Matcher::UrlEncoded("token".into(), "app-token".into()))
+        .match_body(Matcher::PartialJson(serde_json::json!({
+            "title": "GitSafe: sync failed for github_com-acme-tool",
+            "priority": 2,
+        })))
+        .create_async()
+        .await;
+
+    let temp_dir = TempDir::new().unwrap();
+    let service = WebhookService::new(delivery_settings(), temp_dir.path()).unwrap();
+
+    let mut webhook = Webhook::new(format!("{}/gitsafe-alerts", server.url()));
+    webhook.format = WebhookFormat::Ntfy;
+    let payload = serde_json::json!({
+        "repo": {"id": "github_com-acme-tool", "url": "https://github.com/acme/tool.git"},
+        "error_message": "authentication failed",
+        "sync_attempts": 5,
+    });
+    let id = service.send(&webhook, "out_of_attempts", &payload);
+    assert_eq!(
+        wait_for_delivery(&service, &id).await.status,
+        DeliveryStatus::Delivered
+    );
+
+    let mut webhook = Webhook::new(format!("{}/message?token=app-token", server.url()));
+    webhook.format = WebhookFormat::Gotify;
+    webhook.priority = Some(2);
+    let id = service.send(&webhook, "error", &error_payload());
+    assert_eq!(
+        wait_for_delivery(&service, &id).await.status,
+        DeliveryStatus::Delivered
+    );
+
+    ntfy.assert_async().await;
+    gotify.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_templated_webhook() {
+    let mut server = mockito::Server::new_async().await;
+    let mock = server
+        .mock("POST", "/custom")
+        .match_header("content-type", "application/vnd.example+json")
+        .match_body(Matcher::Json(serde_json::json!({
+            "kind": "error",
+            "summary": "GitSafe: sync failed for github_com-acme-tool",
+            "repository": "github_com-acme-tool",
+            "error": "authentication failed",
+            "severity": "warning",
+            "at": 1704110400,
+        })))
+        .create_async()
+        .await;
+
+    let temp_dir = TempDir::new().unwrap();
+    let service = WebhookService::new(delivery_settings(), temp_dir.path()).unwrap();
+    let mut webhook = Webhook::new(format!("{}/custom", server.url()));
+    // The template takes precedence over the format
+    webhook.format = WebhookFormat::Slack;
+    webhook.content_type = Some("application/vnd.example+json".to_string());
+    webhook.template = Some(
+        r#"{"kind": "{{ event }}", "summary": {{ title | tojson }},
+            "repository": {{ repo.id | tojson }}, "error": {{ error_message | tojson }},
+            "severity": "{{ severity }}", "at": {{ timestamp }}}"#
+            .to_string(),
+    );
+
+    let id = service.send(&webhook, "error", &error_payload());
+    let delivery = wait_for_delivery(&service, &id).await;
+    assert_eq!(delivery.status, DeliveryStatus::Delivered);
+    mock.assert_async().await;
+
+    // Syntax errors fail the delivery without sending it
+    webhook.template = Some("{{ repo.id ".to_string());
+    let id = service.send(&webhook, "error", &error_payload());
+    let delivery = wait_for_delivery(&service, &id).await;
+    assert_eq!(delivery.status, DeliveryStatus::Failed);
+    assert_eq!(delivery.attempts, 0);
+    assert!(delivery.error.unwrap().starts_with("Template error"));
+}
+
+#[tokio::test]
+async fn test_send_test_event() {
+    let mut server = mockito::Server::new_async().await;
+    let mock = server
+        .mock("POST", "/hook")
+        .match_body("storage_low: Only 512 MB free on the archive volume, below the minimum of 1024 MB (repository: none)")
+        .with_status(500)
+        .expect(1)
+        .create_async()
+        .await;
+
+    let temp_dir = TempDir::new().unwrap();
+    let service = WebhookService::new(delivery_settings(), temp_dir.path()).unwrap();
+    let mut webhook = Webhook::new(format!("{}/hook", server.url()));
+    webhook.content_type = Some("text/plain".to_string());
+    // Events without a repository render undefined values as empty
+    webhook.template =
+        Some("{{ event }}: {{ message }} (repository: {{ repo.id or 'none' }})".to_string());
+
+    let result = service
+        .send_test(&webhook, NotificationEvent::StorageLow)
+        .await
+        .unwrap();
+    assert_eq!(result.content_type, "text/plain");
+    assert!(result.body.starts_with("storage_low: Only 512 MB free"));
+    // Test events are sent once, without retries
+    assert_eq!(result.delivery.status, DeliveryStatus::Failed);
+    assert_eq!(result.delivery.status_code, Some(500));
+    assert_eq!(service.deliveries().len(), 1);
+    mock.assert_async().await;
+
+    webhook.template = Some("{% if %}".to_string());
+    let error = service
+        .send_test(&webhook, NotificationEvent::Error)
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("Invalid webhook template"));
+}
+
+#[test]
+fn test_sample_payloads() {
+    let error = webhooks::sample_payload(NotificationEvent::Error);
+    assert_eq!(error["operation"], "sync");
+    assert!(error["error_message"].is_string());
+
+    let disabled = webhooks::sample_payload(NotificationEvent::OutOfAttempts);
+    assert_eq!(disabled["repo"]["enabled"], false);
+    assert!(disabled["sync_attempts"].is_u64());
+
+    let credential = webhooks::sample_payload(NotificationEvent::CredentialChanged);
+    assert_eq!(credential["event"], "credential_changed");
+    assert!(credential.get("repo").is_none());
+    assert_eq!(credential["details"]["action"], "updated");
+}
+
+/// Waits until `count` deliveries were recorded and none of them is pending.
+async fn wait_for_deliveries(service: &WebhookService, count: usize) -> Vec<WebhookDelivery> {
+    for _ in 0..100 {
+        let deliveries = service.deliveries();
+        if deliveries.len() >= count
+            && deliveries
+                .iter()
+                .all(|d| d.status != DeliveryStatus::Pending)
+        {
+            return deliveries;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("{} deliveries did not finish", count);
+}
+
+fn subscription(url: String, events: Vec<NotificationEvent>) -> Subscription {
+    Subscription {
+        webhook: Webhook::new(url),
+        events,
+        repositories: Vec::new(),
+        tags: Vec::new(),
+        exclude_repositories: Vec::new(),
+        exclude_tags: Vec::new(),
+    }
+}
+
+#[tokio::test]
+async fn test_events_are_routed_to_matching_subscriptions() {
+    let mut server = mockito::Server::new_async().await;
+    let main = server
+        .mock("POST", "/main")
+        .match_body(Matcher::PartialJson(serde_json::json!({
+            "event": "sync_succeeded",
+            "repo": {"id": "tool"},
+            "details": {"commit_hash": "abc123"},
+        })))
+        .expect(1)
+        .create_async()
+        .await;
+    let noisy = server
+        .mock("POST", "/noisy")
+        .match_body(Matcher::PartialJson(
+            serde_json::json!({"repo": {"id": "chatty"}}),
+        ))
+        .expect(1)
+        .create_async()
+        .await;
+
+    let mut main_subscription = subscription(
+        format!("{}/main", server.url()),
+        vec![NotificationEvent::SyncSucceeded],
+    );
+    main_subscription.exclude_tags = vec!["noisy".to_string()];
+    let mut noisy_subscription = subscription(
+        format!("{}/noisy", server.url()),
+        vec![NotificationEvent::SyncSucceeded],
+    );
+    noisy_subscription.tags = vec!["noisy".to_string()];
+    let subscriptions = [main_subscription, noisy_subscription];
+
+    let temp_dir = TempDir::new().unwrap();
+    let service = WebhookService::new(delivery_settings(), temp_dir.path()).unwrap();
+    let mut tool = Repository {
+        id: "tool".to_string(),
+        url: "https://github.com/acme/tool.git".to_string(),
+        credential_id: None,
+        enabled: true,
+        last_sync: None,
+        last_sync_commit_hash: None,
+        last_sync_message: None,
+        error: None,
+        size: None,
+        attempts_left: None,
+        source_id: None,
+        upstream_status: None,
+        metadata: None,
+        tags: Vec::new(),
+        max_backup_age_hours: None,
+    };
+    let details = serde_json::json!({"commit_hash": "abc123"});
+    for event in [
+        NotificationEvent::SyncSucceeded,
+        NotificationEvent::Recovered,
+    ] {
+        webhooks::notify_event(
+            &service,
+            &subscriptions,
+            event,
+            Some(&tool),
+            "Synced new commits",
+            details.clone(),
+        );
+    }
+    tool.id = "chatty".to_string();
+    tool.tags = vec!["noisy".to_string()];
+    webhooks::notify_event(
+        &service,
+        &subscriptions,
+        NotificationEvent::SyncSucceeded,
+        Some(&tool),
+        "Synced new commits",
+        details,
+    );
+
+    let deliveries = wait_for_deliveries(&service, 2).await;
+    assert_eq!(deliveries.len(), 2);
+    assert!(deliveries.iter().all(|d| d.event == "sync_succeeded"));
+    main.assert_async().await;
+    noisy.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_storage_low_is_notified_once() {
+    let mut server = mockito::Server::new_async().await;
+    let mock = server
+        .mock("POST", "/storage")
+        .match_body(Matcher::PartialJson(
+            serde_json::json!({"event": "storage_low"}),
+        ))
+        .expect(1)
+        .create_async()
+        .await;
+
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = Config::default();
+    // More than any volume provides
+    config.storage.min_free_space_mb = Some(u64::MAX / (1024 * 1024));
+    config.server.notifications.push(subscription(
+        format!("{}/storage", server.url()),
+        vec![NotificationEvent::StorageLow],
+    ));
+    let config = Arc::new(RwLock::new(config));
+    let git_service = GitService::new(temp_dir.path().join("archives"), true).unwrap();
+    let service = WebhookService::new(delivery_settings(), temp_dir.path()).unwrap();
+
+    let is_low = sync::check_free_space(&config, &git_service, &service, false).await;
+    assert!(is_low);
+    // Still low: no second notification
+    assert!(sync::check_free_space(&config, &git_service, &service, is_low).await);
+
+    wait_for_deliveries(&service, 1).await;
+    mock.assert_async().await;
+    assert_eq!(service.deliveries().len(), 1);
+
+    config.write().await.storage.min_free_space_mb = Some(0);
+    assert!(!sync::check_free_space(&config, &git_service, &service, true).await);
 }